@@ -1,37 +1,129 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+// Kernels compiled ahead of time: (file stem in src/kernels, env var holding the PTX path)
+const KERNELS: [(&str, &str); 2] = [("boids", "BOIDS_PTX"), ("sph", "SPH_PTX")];
+// Compute capability targeted when neither CUDA_ARCH nor the installed GPU says otherwise
+const DEFAULT_ARCH: u32 = 61;
+
 fn main() {
-    // Always tell Cargo to rerun if the kernel changes
-    println!("cargo:rerun-if-changed=src/kernels/boids.cu");
+    // Always tell Cargo to rerun if a kernel changes
+    for (name, _) in KERNELS {
+        println!("cargo:rerun-if-changed=src/kernels/{}.cu", name);
+    }
+
+    // Embed the git revision for /api/build-info (GIT_SHA env overrides, e.g. in Docker builds)
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    let git_sha = env::var("GIT_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .and_then(|out| String::from_utf8(out.stdout).ok())
+            .map(|sha| sha.trim().to_string())
+    });
+    println!("cargo:rustc-env=GIT_SHA={}", git_sha.unwrap_or_else(|| "unknown".to_string()));
 
-    // Try to compile the CUDA kernel with nvcc if available
-    let nvcc = which::which("nvcc");
-    if nvcc.is_err() {
-        println!("cargo:warning=nvcc not found; building without CUDA boids kernel");
+    // CPU-only builds never load the kernels
+    if env::var_os("CARGO_FEATURE_NO_CUDA").is_some() {
         return;
     }
 
+    // Try to compile the CUDA kernels with nvcc if available
+    let nvcc = match which::which("nvcc") {
+        Ok(nvcc) => nvcc,
+        Err(_) => {
+            println!("cargo:warning=nvcc not found; building without CUDA boids/SPH kernels");
+            return;
+        }
+    };
+
+    println!("cargo:rerun-if-env-changed=CUDA_ARCH");
+    let (arch, source) = kernel_arch(&nvcc);
+    println!("cargo:warning=compiling CUDA kernels for sm_{} ({})", arch, source);
+
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let ptx_out = out_dir.join("boids.ptx");
-
-    let status = Command::new(nvcc.unwrap())
-        .args([
-            "-ptx",
-            "-arch=sm_61",
-            "-allow-unsupported-compiler",
-            "src/kernels/boids.cu",
-            "-o",
-        ])
-        .arg(&ptx_out)
-        .status()
-        .expect("failed to invoke nvcc");
-
-    if !status.success() {
-        println!("cargo:warning=nvcc failed to compile boids kernel; CPU fallback will be used");
-        return;
+    for (name, env_var) in KERNELS {
+        let ptx_out = out_dir.join(format!("{}.ptx", name));
+        let status = Command::new(&nvcc)
+            .args(["-ptx", &format!("-arch=sm_{}", arch), "-allow-unsupported-compiler"])
+            .arg(format!("src/kernels/{}.cu", name))
+            .arg("-o")
+            .arg(&ptx_out)
+            .status()
+            .expect("failed to invoke nvcc");
+
+        if !status.success() {
+            println!("cargo:warning=nvcc failed to compile {} kernel; CPU fallback will be used", name);
+            continue;
+        }
+        println!("cargo:rustc-env={}={}", env_var, ptx_out.display());
+    }
+}
+
+/// Architecture to compile for, and where it came from. `CUDA_ARCH` (`sm_86`, `86` or
+/// `8.6`) wins; otherwise the installed GPU's compute capability, capped at the newest
+/// nvcc supports; otherwise `DEFAULT_ARCH`. PTX for a newer arch than the device won't
+/// load, so detection never picks above the GPU.
+fn kernel_arch(nvcc: &Path) -> (u32, String) {
+    if let Ok(value) = env::var("CUDA_ARCH") {
+        match parse_arch(&value) {
+            Some(arch) => return (arch, "CUDA_ARCH".to_string()),
+            None => println!("cargo:warning=ignoring unrecognised CUDA_ARCH={:?}", value),
+        }
     }
+    let supported = nvcc_archs(nvcc);
+    if let Some(gpu) = gpu_arch() {
+        let arch = match supported.iter().copied().filter(|&a| a <= gpu).max() {
+            Some(arch) => arch,
+            // Older nvcc without --list-gpu-arch: trust the device
+            None if supported.is_empty() => gpu,
+            None => return fallback(&supported, "detected GPU is older than nvcc supports"),
+        };
+        return (arch, format!("detected GPU sm_{}", gpu));
+    }
+    fallback(&supported, "no GPU detected")
+}
+
+/// `DEFAULT_ARCH`, or nvcc's oldest arch if it has dropped the default
+fn fallback(supported: &[u32], reason: &str) -> (u32, String) {
+    match supported.iter().copied().min() {
+        Some(oldest) if !supported.contains(&DEFAULT_ARCH) => (oldest, format!("{}; oldest nvcc arch", reason)),
+        _ => (DEFAULT_ARCH, format!("{}; default", reason)),
+    }
+}
+
+fn parse_arch(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let digits = value
+        .strip_prefix("sm_")
+        .or_else(|| value.strip_prefix("compute_"))
+        .unwrap_or(value)
+        .replace('.', "");
+    digits.parse().ok().filter(|&arch| (10..=999).contains(&arch))
+}
+
+/// Compute capability of the first installed GPU, via nvidia-smi
+fn gpu_arch() -> Option<u32> {
+    let out = Command::new("nvidia-smi")
+        .args(["--query-gpu=compute_cap", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    String::from_utf8(out.stdout).ok()?.lines().next().and_then(parse_arch)
+}
 
-    println!("cargo:rustc-env=BOIDS_PTX={}", ptx_out.display());
+/// Virtual architectures nvcc can target (`nvcc --list-gpu-arch`, CUDA 11+); empty if unknown
+fn nvcc_archs(nvcc: &Path) -> Vec<u32> {
+    Command::new(nvcc)
+        .arg("--list-gpu-arch")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|list| list.lines().filter_map(parse_arch).collect())
+        .unwrap_or_default()
 }
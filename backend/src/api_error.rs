@@ -0,0 +1,169 @@
+// Error replies for the simulate endpoints: a `SimulationResponse` with `success: false`,
+// the reason in `error` and its kind in `error_kind`, under a matching status
+use crate::physics::accelerator;
+use crate::SimulationResponse;
+use axum::extract::{FromRequest, Request};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Display;
+use tracing::warn;
+
+/// What went wrong, so clients can tell a bad request from a server that can't run it
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Parameters out of range or malformed (400)
+    BadRequest,
+    /// The request needs the control token (401)
+    Unauthorized,
+    /// No such simulation instance (404)
+    NotFound,
+    /// The worker has no usable CUDA context (503)
+    CudaInit,
+    /// Buffers for the requested size couldn't be allocated (507)
+    Allocation,
+    /// The run failed part way, or the worker pool is gone (500)
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::CudaInit => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorKind::Allocation => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(kind: ErrorKind, message: impl Display) -> Self {
+        Self { kind, message: message.to_string() }
+    }
+
+    pub fn bad_request(message: impl Display) -> Self {
+        Self::new(ErrorKind::BadRequest, message)
+    }
+
+    pub fn unauthorized(message: impl Display) -> Self {
+        Self::new(ErrorKind::Unauthorized, message)
+    }
+
+    pub fn not_found(message: impl Display) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn cuda_init(message: impl Display) -> Self {
+        Self::new(ErrorKind::CudaInit, message)
+    }
+
+    pub fn allocation(message: impl Display) -> Self {
+        Self::new(ErrorKind::Allocation, message)
+    }
+
+    pub fn internal(message: impl Display) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// A simulation that couldn't be created: `Allocation` only if the device ran out
+    /// of memory, `Internal` for anything else
+    pub fn creation(error: anyhow::Error) -> Self {
+        let kind = if accelerator::is_out_of_memory(&error) { ErrorKind::Allocation } else { ErrorKind::Internal };
+        Self::new(kind, format!("{:#}", error))
+    }
+
+    /// Plain-text form for handlers that reply with `(StatusCode, String)`
+    pub fn into_text(self, context: &str) -> (StatusCode, String) {
+        if self.kind.status().is_server_error() {
+            warn!("{} ({:?}): {}", context, self.kind, self.message);
+        }
+        (self.kind.status(), format!("{}: {}", context, self.message))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.kind.status();
+        if status.is_server_error() {
+            warn!("Simulation request failed ({:?}): {}", self.kind, self.message);
+        }
+        let body = SimulationResponse {
+            success: false,
+            data: None,
+            forces: None,
+            fields: None,
+            warnings: Vec::new(),
+            metadata: None,
+            error: Some(self.message),
+            error_kind: Some(self.kind),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// `Json` whose rejections (bad syntax, wrong content type, ...) are `ApiError`s, so
+/// clients get the same error body as for every other failure
+pub struct ApiJson<T>(pub T);
+
+#[axum::async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for ApiJson<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(request, state)
+            .await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|rejection| ApiError::bad_request(rejection.body_text()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_reply_carries_the_reason() {
+        let response = ApiError::allocation("Failed to allocate particles: OutOfMemory").into_response();
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "Failed to allocate particles: OutOfMemory");
+        assert_eq!(json["error_kind"], "allocation");
+
+        assert_eq!(ApiError::bad_request("x").kind.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(ApiError::unauthorized("x").kind.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ApiError::cuda_init("x").kind.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ApiError::creation(anyhow::anyhow!("no kernel")).kind, ErrorKind::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_json_rejection_is_an_api_error() {
+        #[derive(serde::Deserialize)]
+        struct Body {
+            #[allow(dead_code)]
+            steps: usize,
+        }
+        let request = Request::builder()
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from("{\"steps\": -1}"))
+            .unwrap();
+        let Err(error) = ApiJson::<Body>::from_request(request, &()).await else {
+            panic!("a negative step count should be rejected");
+        };
+        assert_eq!(error.kind, ErrorKind::BadRequest);
+        assert!(error.message.contains("steps"), "{}", error.message);
+    }
+}
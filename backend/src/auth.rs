@@ -0,0 +1,159 @@
+// Bearer-token gate for the routes that change the shared simulation
+// Enabled by setting CONTROL_TOKEN; read and stream routes always stay open
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const CONTROL_TOKEN_ENV: &str = "CONTROL_TOKEN";
+
+/// Token the control routes expect in `Authorization: Bearer <token>`
+#[derive(Clone)]
+pub struct ControlToken(Arc<str>);
+
+impl ControlToken {
+    /// `None` when `CONTROL_TOKEN` is unset or empty
+    pub fn from_env() -> Option<Self> {
+        std::env::var(CONTROL_TOKEN_ENV).ok().and_then(|token| Self::new(&token))
+    }
+
+    pub fn new(token: &str) -> Option<Self> {
+        let token = token.trim();
+        (!token.is_empty()).then(|| Self(token.into()))
+    }
+
+    /// Whether `headers` carry this token as a bearer credential
+    pub fn accepts(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| self.matches(given))
+    }
+
+    /// Whether `given` (e.g. from `?token=`) is this token
+    pub fn matches(&self, given: &str) -> bool {
+        constant_time_eq(given.trim().as_bytes(), self.0.as_bytes())
+    }
+}
+
+/// A token passed as a query param; `Debug` hides it so request logs don't leak it
+#[derive(Deserialize, Clone)]
+#[serde(transparent)]
+pub struct QueryToken(String);
+
+impl QueryToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for QueryToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueryToken(..)")
+    }
+}
+
+/// Whether a client may make changes: always without a token, otherwise only with it
+/// in `headers` or `query`
+pub fn authorized(token: Option<&ControlToken>, headers: &HeaderMap, query: Option<&QueryToken>) -> bool {
+    token.is_none_or(|token| {
+        token.accepts(headers) || query.is_some_and(|given| token.matches(given.as_str()))
+    })
+}
+
+// Compares every byte so the response time doesn't reveal how much of a guess matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `from_fn_with_state` middleware: 401 unless the request carries the control token
+pub async fn require_token(State(token): State<ControlToken>, request: Request, next: Next) -> Response {
+    if token.accepts(request.headers()) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid control token",
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{delete, get, post};
+    use axum::Router;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Status code of a bodyless `method path` request to `addr`
+    async fn status(addr: std::net::SocketAddr, method: &str, path: &str, auth: Option<&str>) -> u16 {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let auth = auth.map(|a| format!("Authorization: {}\r\n", a)).unwrap_or_default();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+            method, path, auth
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.split(' ').nth(1).unwrap().parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_protected_route_needs_the_token() {
+        let token = ControlToken::new("s3cret").unwrap();
+        let app = Router::new()
+            .route("/stats", get(|| async { "open" }))
+            .route(
+                "/reset",
+                post(|| async { "reset" })
+                    .route_layer(axum::middleware::from_fn_with_state(token.clone(), require_token)),
+            )
+            .route(
+                "/instances",
+                get(|| async { "list" }).merge(
+                    delete(|| async { "deleted" })
+                        .route_layer(axum::middleware::from_fn_with_state(token, require_token)),
+                ),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        assert_eq!(status(addr, "POST", "/reset", None).await, 401);
+        assert_eq!(status(addr, "POST", "/reset", Some("Bearer wrong")).await, 401);
+        assert_eq!(status(addr, "POST", "/reset", Some("Basic s3cret")).await, 401);
+        assert_eq!(status(addr, "POST", "/reset", Some("Bearer s3cret")).await, 200);
+        assert_eq!(status(addr, "GET", "/stats", None).await, 200, "read routes stay open");
+        // Gating one method of a route leaves the others open, as for `/api/simulations`
+        assert_eq!(status(addr, "DELETE", "/instances", None).await, 401);
+        assert_eq!(status(addr, "DELETE", "/instances", Some("Bearer s3cret")).await, 200);
+        assert_eq!(status(addr, "GET", "/instances", None).await, 200);
+    }
+
+    #[test]
+    fn test_blank_token_disables_the_gate() {
+        assert!(ControlToken::new("").is_none());
+        assert!(ControlToken::new("  ").is_none());
+    }
+
+    #[test]
+    fn test_query_token_authorizes() {
+        let token = ControlToken::new("s3cret").unwrap();
+        let none = HeaderMap::new();
+        let given = |t: &str| QueryToken(t.to_string());
+        assert!(authorized(None, &none, None), "no token configured, no gate");
+        assert!(!authorized(Some(&token), &none, None));
+        assert!(!authorized(Some(&token), &none, Some(&given("wrong"))));
+        assert!(authorized(Some(&token), &none, Some(&given("s3cret"))));
+        let mut bearer = HeaderMap::new();
+        bearer.insert(header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert!(authorized(Some(&token), &bearer, None));
+        assert_eq!(format!("{:?}", given("s3cret")), "QueryToken(..)");
+    }
+}
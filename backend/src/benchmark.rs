@@ -0,0 +1,282 @@
+// Headless boids benchmark, shared by the `--bench` CLI mode and `POST /api/benchmark`
+use crate::cuda::CudaContext;
+use crate::physics::BoidsSimulation;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+pub const DEFAULT_BENCH_BOIDS: usize = 100_000;
+pub const DEFAULT_BENCH_STEPS: usize = 1000;
+/// Fixed so runs on different commits start from the same flock
+pub const DEFAULT_BENCH_SEED: u64 = 0;
+const BENCH_DT: f32 = 0.016;
+/// Sweep `POST /api/benchmark` runs when the request gives no counts
+pub const DEFAULT_SWEEP_COUNTS: [usize; 4] = [1_000, 10_000, 50_000, 100_000];
+pub const DEFAULT_SWEEP_STEPS: usize = 100;
+// Keep one sweep from holding a CUDA worker for minutes
+pub const MAX_SWEEP_COUNTS: usize = 8;
+pub const MAX_SWEEP_BOIDS: usize = 1_000_000;
+pub const MAX_SWEEP_STEPS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchAccelerator {
+    Cpu,
+    Cuda,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    pub num_boids: usize,
+    pub steps: usize,
+    pub accelerator: BenchAccelerator,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            num_boids: DEFAULT_BENCH_BOIDS,
+            steps: DEFAULT_BENCH_STEPS,
+            accelerator: BenchAccelerator::Both,
+            seed: DEFAULT_BENCH_SEED,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Parse `--bench [--boids N] [--steps N] [--accelerator cpu|cuda|both] [--seed N]`.
+    /// Returns `Ok(None)` when `--bench` is absent so the server starts normally.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>> {
+        let mut args = args.into_iter().skip(1);
+        let mut bench = false;
+        let mut config = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bench" => bench = true,
+                "--boids" => config.num_boids = parse_value(&arg, args.next())?,
+                "--steps" => config.steps = parse_value(&arg, args.next())?,
+                "--seed" => config.seed = parse_value(&arg, args.next())?,
+                "--accelerator" => {
+                    config.accelerator = match args.next().as_deref() {
+                        Some("cpu") => BenchAccelerator::Cpu,
+                        Some("cuda") => BenchAccelerator::Cuda,
+                        Some("both") => BenchAccelerator::Both,
+                        other => anyhow::bail!("--accelerator expects cpu|cuda|both, got {:?}", other),
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !bench {
+            return Ok(None);
+        }
+        if config.num_boids == 0 || config.steps == 0 {
+            anyhow::bail!("--boids and --steps must be greater than zero");
+        }
+        Ok(Some(config))
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T> {
+    value
+        .as_deref()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("{} expects a non-negative integer", flag))
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchResult {
+    pub num_boids: usize,
+    pub steps: usize,
+    pub seed: u64,
+    pub accelerator: String,
+    pub total_ms: f64,
+    pub ms_per_step: f64,
+    pub steps_per_sec: f64,
+}
+
+/// Time `steps` boids steps on a throwaway simulation started from `seed`, or
+/// `None` if `cancelled` turned true first.
+/// The caller must have a CUDA context current on this thread.
+pub fn run_boids(
+    context: &Arc<CudaContext>,
+    num_boids: usize,
+    steps: usize,
+    seed: u64,
+    force_cpu: bool,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Option<BenchResult>> {
+    let mut sim = BoidsSimulation::new_seeded(context, num_boids, seed)?;
+    sim.set_force_cpu(force_cpu);
+    time_steps(&mut sim, steps, seed, cancelled)
+}
+
+fn time_steps(
+    sim: &mut BoidsSimulation,
+    steps: usize,
+    seed: u64,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<Option<BenchResult>> {
+    let num_boids = sim.num_boids();
+    let start = Instant::now();
+    for _ in 0..steps {
+        if cancelled() {
+            return Ok(None);
+        }
+        sim.step(BENCH_DT)?;
+    }
+    // Pull the state back so asynchronous GPU work is included in the timing
+    sim.get_boids()?;
+    let total = start.elapsed().as_secs_f64();
+
+    Ok(Some(BenchResult {
+        num_boids,
+        steps,
+        seed,
+        accelerator: sim.accelerator().to_string(),
+        total_ms: total * 1000.0,
+        ms_per_step: total * 1000.0 / steps as f64,
+        steps_per_sec: if total > 0.0 { steps as f64 / total } else { 0.0 },
+    }))
+}
+
+/// Body of `POST /api/benchmark`
+#[derive(Deserialize, Debug, Default)]
+pub struct SweepRequest {
+    pub counts: Option<Vec<usize>>,
+    pub steps: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl SweepRequest {
+    /// Boid counts and steps to run, defaults filled in and limits checked
+    pub fn resolve(&self) -> Result<(Vec<usize>, usize)> {
+        let counts = self.counts.clone().unwrap_or_else(|| DEFAULT_SWEEP_COUNTS.to_vec());
+        let steps = self.steps.unwrap_or(DEFAULT_SWEEP_STEPS);
+        if counts.is_empty() || counts.len() > MAX_SWEEP_COUNTS {
+            anyhow::bail!("counts must list 1..={} boid counts, got {}", MAX_SWEEP_COUNTS, counts.len());
+        }
+        if let Some(&count) = counts.iter().find(|&&c| c == 0 || c > MAX_SWEEP_BOIDS) {
+            anyhow::bail!("each count must be 1..={}, got {}", MAX_SWEEP_BOIDS, count);
+        }
+        if steps == 0 || steps > MAX_SWEEP_STEPS {
+            anyhow::bail!("steps must be 1..={}, got {}", MAX_SWEEP_STEPS, steps);
+        }
+        Ok((counts, steps))
+    }
+}
+
+/// Time `steps` steps at each of `counts` on throwaway simulations, on whichever
+/// accelerator each one picks. Stops early, returning what finished, once `cancelled`.
+pub fn run_sweep(
+    context: &Arc<CudaContext>,
+    counts: &[usize],
+    steps: usize,
+    seed: u64,
+    cancelled: impl Fn() -> bool,
+) -> Result<Vec<BenchResult>> {
+    let mut results = Vec::with_capacity(counts.len());
+    for &count in counts {
+        match run_boids(context, count, steps, seed, false, &cancelled)? {
+            Some(result) => results.push(result),
+            None => break,
+        }
+    }
+    Ok(results)
+}
+
+/// Run the configured benchmark suite, skipping the CUDA pass when no kernel is loaded
+pub fn run_suite(context: &Arc<CudaContext>, config: &BenchConfig) -> Result<Vec<BenchResult>> {
+    let mut results = Vec::new();
+    let never = || false;
+    if matches!(config.accelerator, BenchAccelerator::Cuda | BenchAccelerator::Both) {
+        let result = run_boids(context, config.num_boids, config.steps, config.seed, false, &never)?;
+        if let Some(result) = result {
+            if result.accelerator == "cuda" || config.accelerator == BenchAccelerator::Cuda {
+                results.push(result);
+            } else {
+                tracing::warn!("CUDA boids kernel unavailable; skipping GPU benchmark pass");
+            }
+        }
+    }
+    if matches!(config.accelerator, BenchAccelerator::Cpu | BenchAccelerator::Both) {
+        results.extend(run_boids(context, config.num_boids, config.steps, config.seed, true, &never)?);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("physics-backend")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_bench_args_absent() {
+        assert_eq!(BenchConfig::from_args(args(&[])).unwrap(), None);
+    }
+
+    #[test]
+    fn test_bench_args_parsed() {
+        let config = BenchConfig::from_args(args(&[
+            "--bench", "--boids", "5000", "--steps", "10", "--accelerator", "cpu", "--seed", "42",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.seed, 42);
+        assert_eq!(config.num_boids, 5000);
+        assert_eq!(config.steps, 10);
+        assert_eq!(config.accelerator, BenchAccelerator::Cpu);
+    }
+
+    #[test]
+    fn test_bench_args_invalid() {
+        assert!(BenchConfig::from_args(args(&["--bench", "--steps", "abc"])).is_err());
+        assert!(BenchConfig::from_args(args(&["--bench", "--boids", "0"])).is_err());
+        assert!(BenchConfig::from_args(args(&["--bench", "--seed", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_sweep_request_resolve() {
+        let (counts, steps) = SweepRequest::default().resolve().unwrap();
+        assert_eq!(counts, DEFAULT_SWEEP_COUNTS.to_vec());
+        assert_eq!(steps, DEFAULT_SWEEP_STEPS);
+
+        let request = SweepRequest { counts: Some(vec![500, 2000]), steps: Some(10), seed: None };
+        assert_eq!(request.resolve().unwrap(), (vec![500, 2000], 10));
+
+        let invalid = [
+            SweepRequest { counts: Some(vec![]), ..Default::default() },
+            SweepRequest { counts: Some(vec![1000; MAX_SWEEP_COUNTS + 1]), ..Default::default() },
+            SweepRequest { counts: Some(vec![1000, 0]), ..Default::default() },
+            SweepRequest { counts: Some(vec![MAX_SWEEP_BOIDS + 1]), ..Default::default() },
+            SweepRequest { steps: Some(0), ..Default::default() },
+            SweepRequest { steps: Some(MAX_SWEEP_STEPS + 1), ..Default::default() },
+        ];
+        for request in invalid {
+            assert!(request.resolve().is_err(), "{:?} should be rejected", request);
+        }
+    }
+
+    #[test]
+    fn test_cancel_stops_mid_run() {
+        let mut sim = BoidsSimulation::new_host_seeded(100, 1).unwrap();
+        let result = time_steps(&mut sim, 5, 1, &|| false).unwrap().unwrap();
+        assert_eq!((result.num_boids, result.steps), (100, 5));
+
+        let checks = std::cell::Cell::new(0);
+        let cancel_after_three = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 3
+        };
+        assert!(time_steps(&mut sim, 1000, 1, &cancel_after_three).unwrap().is_none());
+        assert_eq!(checks.get(), 4, "checked before every step, not just between runs");
+    }
+}
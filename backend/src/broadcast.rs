@@ -1,22 +1,236 @@
 // Efficient state broadcasting with binary serialization
-use crate::simulation_engine::SimulationEngine;
+use crate::physics::boids::DEFAULT_MAX_SPEED;
+use crate::physics::BoidsSimulation;
+use crate::simulation_engine::{EngineSnapshot, SimulationEngine};
 use anyhow::Result;
-use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, UNIX_EPOCH};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::Receiver;
+
+/// First byte of every WebSocket frame, identifying its layout
+pub const FRAME_FULL: u8 = 0;
+pub const FRAME_OCCUPANCY: u8 = 1;
+pub const FRAME_DELTA: u8 = 2;
+/// Binary REST body from the simulate endpoints (`values_frame`)
+pub const FRAME_VALUES: u8 = 3;
+/// Set on a full or delta frame's kind byte when per-boid force magnitudes follow the boid data
+pub const FRAME_FLAG_FORCES: u8 = 0x80;
+/// Set on a full frame's kind byte when earlier positions follow for interpolation
+pub const FRAME_FLAG_INTERP: u8 = 0x40;
+/// Set on a full or delta frame's kind byte for `?withspeed=1` clients. One u8 per boid
+/// follows the position/velocity block (before any forces): `|v| / max_speed` clamped
+/// to `[0, 1]` and scaled by `SPEED_SCALE`, using each boid's own speed cap.
+pub const FRAME_FLAG_SPEED: u8 = 0x20;
+/// Full speed (`|v| == max_speed`) in a speed byte
+pub const SPEED_SCALE: f32 = 255.0;
+
+/// How many broadcasts back the positions in `?interp=1` frames are taken from
+pub const INTERP_HISTORY: usize = 4;
+
+/// Delta frames sent between full keyframes, bounding float drift on the client
+pub const KEYFRAME_INTERVAL: u32 = 60;
+/// Largest per-frame position change still sent as a delta; bigger jumps
+/// (wrap-around, resets) send that boid's absolute values instead
+pub const MAX_POSITION_DELTA: f32 = 0.25;
+/// Delta frames carry each change as an i16 count of `1 / DELTA_SCALE` units
+pub const DELTA_SCALE: f32 = 32768.0;
+/// Leading i16 of a boid sent as four absolute f32 values instead of deltas
+pub const DELTA_ESCAPE: i16 = i16::MIN;
+
+/// Side length of the occupancy grid sent to `?occupancy=1` clients
+pub const OCCUPANCY_GRID_SIZE: usize = 128;
+
+/// Largest per-boid frame sent to a client before falling back to occupancy (~260K boids)
+pub const DEFAULT_MAX_FRAME_BYTES: usize = 4 * 1024 * 1024;
+
+/// Every Nth boid is sent to `?format=json` clients unless they pass `stride`
+pub const DEFAULT_JSON_STRIDE: usize = 10;
+
+/// Encoding of `/ws` frames
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    /// Little-endian binary frames (see `full_frame`)
+    #[default]
+    Binary,
+    /// Human-readable text frames for debugging (see `json_frame`)
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonFrame {
+    timestamp: u64,
+    encode_ms: u64,
+    num_boids: usize,
+    stride: usize,
+    boids: Vec<JsonBoid>,
+}
+
+#[derive(Serialize)]
+struct JsonBoid {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+}
+
+/// What a client asked to receive
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// Append each boid's stable id
+    pub ids: bool,
+    /// Append each boid's steering force magnitude
+    pub forces: bool,
+    /// Send the occupancy grid instead of per-boid data
+    pub occupancy: bool,
+    /// Send per-boid changes since the previous frame where possible
+    pub delta: bool,
+    /// Append each boid's position from a few broadcasts back
+    pub interp: bool,
+    /// Append each boid's normalized speed as one byte
+    pub speed: bool,
+}
+
+/// Viewport rectangle a `/ws` client subscribed to; bounds are inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub xmin: f32,
+    pub xmax: f32,
+    pub ymin: f32,
+    pub ymax: f32,
+}
+
+impl Region {
+    /// Omitted bounds are unbounded, so boids outside the unit square still match
+    pub fn new(
+        xmin: Option<f32>,
+        xmax: Option<f32>,
+        ymin: Option<f32>,
+        ymax: Option<f32>,
+    ) -> Result<Self> {
+        let region = Self {
+            xmin: xmin.unwrap_or(f32::NEG_INFINITY),
+            xmax: xmax.unwrap_or(f32::INFINITY),
+            ymin: ymin.unwrap_or(f32::NEG_INFINITY),
+            ymax: ymax.unwrap_or(f32::INFINITY),
+        };
+        if [xmin, xmax, ymin, ymax].iter().flatten().any(|v| !v.is_finite()) {
+            anyhow::bail!("Region bounds must be finite");
+        }
+        if region.xmin >= region.xmax || region.ymin >= region.ymax {
+            anyhow::bail!("Region must have xmin < xmax and ymin < ymax, got {:?}", region);
+        }
+        Ok(region)
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.xmin..=self.xmax).contains(&x) && (self.ymin..=self.ymax).contains(&y)
+    }
+}
 
 #[derive(Clone)]
 pub struct BroadcastState {
+    /// Unix milliseconds when the simulation state was produced
     pub timestamp: u64,
+    /// How long encoding took, for diagnostics; not part of the binary frames
+    pub encode_ms: u64,
     pub num_boids: usize,
     pub data: Vec<u8>,
+    /// Stable boid ids, in the same order as `data`
+    pub ids: Vec<u32>,
+    /// Little-endian f32 force magnitude per boid, in the same order as `data`
+    pub forces: Vec<u8>,
+    /// Quantized speed per boid (see `FRAME_FLAG_SPEED`), in the same order as `data`
+    pub speeds: Vec<u8>,
+    /// Row-major `OCCUPANCY_GRID_SIZE`² boid counts, saturating at 255
+    pub occupancy: Vec<u8>,
+    /// Little-endian f32 `x, y` per boid from `history_frames` broadcasts ago, in
+    /// the same order as `data`; empty until a `StateHistory` fills it in
+    pub previous: Vec<u8>,
+    pub history_frames: u32,
+}
+
+/// Binary simulate response: [kind u8][computation ms u64][num_values u32]
+/// [type length u8][simulation type utf8][f32 values]. The first 13 bytes match
+/// the `/ws` frame header, with the duration in the timestamp slot.
+pub fn values_frame(simulation_type: &str, computation_ms: u64, values: &[f32]) -> Vec<u8> {
+    let name = &simulation_type.as_bytes()[..simulation_type.len().min(u8::MAX as usize)];
+    let mut frame = Vec::with_capacity(14 + name.len() + values.len() * 4);
+    frame.push(FRAME_VALUES);
+    frame.extend_from_slice(&computation_ms.to_le_bytes());
+    frame.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    frame.push(name.len() as u8);
+    frame.extend_from_slice(name);
+    for value in values {
+        frame.extend_from_slice(&value.to_le_bytes());
+    }
+    frame
+}
+
+/// Rasterize positions in the unit square into a `size`×`size` grid of counts
+pub fn occupancy_grid(state: &[f32], size: usize) -> Vec<u8> {
+    let mut grid = vec![0u8; size * size];
+    let scale = size as f32;
+    for boid in state.chunks_exact(4) {
+        if !(boid[0].is_finite() && boid[1].is_finite()) {
+            continue;
+        }
+        // Clamp so x == 1.0 lands in the last cell rather than out of range
+        let cx = ((boid[0] * scale) as usize).min(size - 1);
+        let cy = ((boid[1] * scale) as usize).min(size - 1);
+        let cell = &mut grid[cy * size + cx];
+        *cell = cell.saturating_add(1);
+    }
+    grid
+}
+
+/// One speed byte per boid in `state` (see `FRAME_FLAG_SPEED`); boids without an
+/// entry in `max_speeds` are measured against `DEFAULT_MAX_SPEED`
+pub fn speed_bytes(state: &[f32], max_speeds: &[f32]) -> Vec<u8> {
+    state
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(i, boid)| {
+            let max_speed = max_speeds.get(i).copied().unwrap_or(DEFAULT_MAX_SPEED);
+            let speed = (boid[2] * boid[2] + boid[3] * boid[3]).sqrt() / max_speed;
+            // NaN (a zero cap at rest) casts to 0
+            (speed.clamp(0.0, 1.0) * SPEED_SCALE).round() as u8
+        })
+        .collect()
 }
 
 impl BroadcastState {
-    pub fn encode(engine: &SimulationEngine) -> Result<Self> {
+    /// Encode the engine's latest published snapshot; doesn't wait on the
+    /// simulation or need a CUDA context on this thread. Speed bytes are only
+    /// computed when `speeds` is set.
+    pub fn encode(engine: &SimulationEngine, speeds: bool) -> Result<Self> {
         let start = Instant::now();
-        
-        // Get simulation state
-        let state = engine.get_state()?;
-        let num_boids = engine.num_boids();
+        let (_, snapshot) = engine.latest_snapshot();
+        Ok(Self::from_snapshot(&snapshot, start, speeds))
+    }
+
+    /// Encode a standalone simulation (one not driven by an engine)
+    pub fn encode_simulation(sim: &mut BoidsSimulation, forces: bool, speeds: bool) -> Result<Self> {
+        let start = Instant::now();
+        Ok(Self::from_snapshot(&EngineSnapshot::of(sim, forces)?, start, speeds))
+    }
+
+    fn from_snapshot(snapshot: &EngineSnapshot, start: Instant, speeds: bool) -> Self {
+        // Speeds are relative to each boid's cap, so world units are fine
+        let speeds = if speeds { speed_bytes(&snapshot.state, &snapshot.max_speeds) } else { Vec::new() };
+        // Clients always get the unit square, whatever the world size
+        let state: Cow<[f32]> = if snapshot.world_size == 1.0 {
+            Cow::Borrowed(&snapshot.state)
+        } else {
+            Cow::Owned(snapshot.state.iter().map(|v| v / snapshot.world_size).collect())
+        };
+        // Derived from the snapshot itself since the population can change between calls
+        let num_boids = state.len() / 4;
         
         // Binary encode: [x1, y1, vx1, vy1, x2, y2, vx2, vy2, ...]
         // Each float is 4 bytes, so total size is num_boids * 4 * 4 = num_boids * 16
@@ -30,16 +244,235 @@ impl BroadcastState {
             data.extend_from_slice(&chunk[3].to_le_bytes()); // vy
         }
         
-        let timestamp = start.elapsed().as_millis() as u64;
+        let forces = snapshot.forces.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let occupancy = occupancy_grid(&state, OCCUPANCY_GRID_SIZE);
+        let timestamp = snapshot
+            .produced_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
         
-        Ok(Self {
+        Self {
             timestamp,
+            encode_ms: start.elapsed().as_millis() as u64,
             num_boids,
             data,
-        })
+            ids: snapshot.ids.clone(),
+            forces,
+            speeds,
+            occupancy,
+            previous: Vec::new(),
+            history_frames: 0,
+        }
+    }
+
+    /// Just the boids inside `region`, with `num_boids` and the occupancy grid
+    /// counting only those
+    pub fn in_region(&self, region: &Region) -> Self {
+        let mut data = Vec::new();
+        let mut ids = Vec::new();
+        let mut forces = Vec::new();
+        let mut speeds = Vec::new();
+        let mut previous = Vec::new();
+        let mut visible = Vec::new();
+        for (i, boid) in self.data.chunks_exact(16).enumerate() {
+            let x = f32::from_le_bytes(boid[0..4].try_into().unwrap());
+            let y = f32::from_le_bytes(boid[4..8].try_into().unwrap());
+            if !region.contains(x, y) {
+                continue;
+            }
+            data.extend_from_slice(boid);
+            ids.extend(self.ids.get(i));
+            forces.extend_from_slice(self.forces.get(i * 4..i * 4 + 4).unwrap_or_default());
+            speeds.extend(self.speeds.get(i));
+            previous.extend_from_slice(self.previous.get(i * 8..i * 8 + 8).unwrap_or_default());
+            visible.extend_from_slice(&[x, y, 0.0, 0.0]);
+        }
+        Self {
+            timestamp: self.timestamp,
+            encode_ms: self.encode_ms,
+            num_boids: data.len() / 16,
+            data,
+            ids,
+            forces,
+            speeds,
+            occupancy: occupancy_grid(&visible, OCCUPANCY_GRID_SIZE),
+            previous,
+            history_frames: self.history_frames,
+        }
+    }
+
+    /// Per-boid frame: [kind u8][timestamp u64][num_boids u32][16 bytes per boid]
+    /// [speed u8 each, if requested][force f32 each, if requested][ids u32 each, if requested]
+    /// [history_frames u32, then earlier x, y f32 per boid, if requested]
+    pub fn full_frame(&self, options: &FrameOptions) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(self.full_frame_len(options));
+        let forces = self.sends_forces(options);
+        let speed = self.sends_speeds(options);
+        let mut kind = FRAME_FULL;
+        if forces {
+            kind |= FRAME_FLAG_FORCES;
+        }
+        if options.interp {
+            kind |= FRAME_FLAG_INTERP;
+        }
+        if speed {
+            kind |= FRAME_FLAG_SPEED;
+        }
+        frame.push(kind);
+        frame.extend_from_slice(&self.timestamp.to_le_bytes());
+        frame.extend_from_slice(&(self.num_boids as u32).to_le_bytes());
+        frame.extend_from_slice(&self.data);
+        if speed {
+            frame.extend_from_slice(&self.speeds);
+        }
+        if forces {
+            frame.extend_from_slice(&self.forces);
+        }
+        if options.ids {
+            frame.extend_from_slice(&self.encode_ids());
+        }
+        if options.interp {
+            self.encode_previous(&mut frame);
+        }
+        frame
+    }
+
+    /// Earlier positions, or the current ones with `history_frames` 0 when there is
+    /// no (complete) history yet
+    fn encode_previous(&self, frame: &mut Vec<u8>) {
+        if self.previous.len() == self.num_boids * 8 {
+            frame.extend_from_slice(&self.history_frames.to_le_bytes());
+            frame.extend_from_slice(&self.previous);
+        } else {
+            frame.extend_from_slice(&0u32.to_le_bytes());
+            for boid in self.data.chunks_exact(16) {
+                frame.extend_from_slice(&boid[..8]);
+            }
+        }
+    }
+
+    /// Whether frames for `options` carry forces; snapshots taken before any client
+    /// asked for them have none, and those frames leave `FRAME_FLAG_FORCES` unset
+    fn sends_forces(&self, options: &FrameOptions) -> bool {
+        options.forces && self.forces.len() == self.num_boids * 4
+    }
+
+    /// Like `sends_forces`, for speed bytes
+    fn sends_speeds(&self, options: &FrameOptions) -> bool {
+        options.speed && self.speeds.len() == self.num_boids
+    }
+
+    /// Size of `full_frame(options)` without building it
+    pub fn full_frame_len(&self, options: &FrameOptions) -> usize {
+        13 + self.data.len()
+            + if self.sends_speeds(options) { self.speeds.len() } else { 0 }
+            + if self.sends_forces(options) { self.forces.len() } else { 0 }
+            + if options.ids { self.ids.len() * 4 } else { 0 }
+            + if options.interp { 4 + self.num_boids * 8 } else { 0 }
+    }
+
+    /// The frame a client asked for, switching to the occupancy grid when the
+    /// per-boid frame would exceed `max_frame_bytes`
+    pub fn client_frame(&self, options: &FrameOptions, max_frame_bytes: usize) -> Vec<u8> {
+        if options.occupancy || self.full_frame_len(options) > max_frame_bytes {
+            self.occupancy_frame()
+        } else {
+            self.full_frame(options)
+        }
+    }
+
+    /// Occupancy frame: [kind u8][timestamp u64][num_boids u32][grid size u16][size² u8 counts]
+    pub fn occupancy_frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(15 + self.occupancy.len());
+        frame.push(FRAME_OCCUPANCY);
+        frame.extend_from_slice(&self.timestamp.to_le_bytes());
+        frame.extend_from_slice(&(self.num_boids as u32).to_le_bytes());
+        frame.extend_from_slice(&(OCCUPANCY_GRID_SIZE as u16).to_le_bytes());
+        frame.extend_from_slice(&self.occupancy);
+        frame
+    }
+
+    /// Delta frame: [kind u8][timestamp u64][num_boids u32][quantized deltas, see
+    /// `DeltaState`][speed u8 each, if requested][force f32 each, if requested]. Ids are not resent; they match the
+    /// base frame. Also returns the state the client reconstructs, which the next delta
+    /// must be taken against so quantization error doesn't accumulate.
+    /// `None` when a full frame is needed (or would be no larger).
+    pub fn delta_frame(
+        &self,
+        previous: &BroadcastState,
+        options: &FrameOptions,
+    ) -> Option<(Vec<u8>, BroadcastState)> {
+        // Differing ids mean boids moved slots (respawns, region changes), so slot deltas are wrong
+        if self.num_boids != previous.num_boids || self.ids != previous.ids {
+            return None;
+        }
+        let delta = DeltaState::encode_delta(self, previous).ok()?;
+        if delta.deltas.len() >= self.data.len() {
+            return None;
+        }
+        let reconstructed = delta.decode_delta(&BroadcastState::decode(&previous.data).ok()?).ok()?;
+
+        let forces = self.sends_forces(options);
+        let speed = self.sends_speeds(options);
+        let forces_len = if forces { self.forces.len() } else { 0 };
+        let speeds_len = if speed { self.speeds.len() } else { 0 };
+        let mut frame = Vec::with_capacity(13 + delta.deltas.len() + speeds_len + forces_len);
+        let mut kind = FRAME_DELTA;
+        if forces {
+            kind |= FRAME_FLAG_FORCES;
+        }
+        if speed {
+            kind |= FRAME_FLAG_SPEED;
+        }
+        frame.push(kind);
+        frame.extend_from_slice(&self.timestamp.to_le_bytes());
+        frame.extend_from_slice(&(self.num_boids as u32).to_le_bytes());
+        frame.extend_from_slice(&delta.deltas);
+        if speed {
+            frame.extend_from_slice(&self.speeds);
+        }
+        if forces {
+            frame.extend_from_slice(&self.forces);
+        }
+        let client_state = BroadcastState {
+            data: reconstructed.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..self.clone()
+        };
+        Some((frame, client_state))
+    }
+
+    /// Text frame for `?format=json` clients: every `stride`-th boid, with
+    /// `num_boids` still counting the whole flock
+    pub fn json_frame(&self, stride: usize) -> Result<String> {
+        let boids = self
+            .data
+            .chunks_exact(16)
+            .step_by(stride.max(1))
+            .map(|boid| {
+                let f = |i: usize| f32::from_le_bytes(boid[i * 4..i * 4 + 4].try_into().unwrap());
+                JsonBoid { x: f(0), y: f(1), vx: f(2), vy: f(3) }
+            })
+            .collect();
+        let frame = JsonFrame {
+            timestamp: self.timestamp,
+            encode_ms: self.encode_ms,
+            num_boids: self.num_boids,
+            stride: stride.max(1),
+            boids,
+        };
+        serde_json::to_string(&frame).map_err(|e| anyhow::anyhow!("Failed to encode JSON frame: {:?}", e))
+    }
+
+    /// Little-endian u32 ids appended after the boid data for `?ids=1` clients
+    pub fn encode_ids(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ids.len() * 4);
+        for id in &self.ids {
+            out.extend_from_slice(&id.to_le_bytes());
+        }
+        out
     }
     
-    #[allow(dead_code)]
     pub fn decode(data: &[u8]) -> Result<Vec<f32>> {
         let mut result = Vec::new();
         
@@ -59,7 +492,150 @@ impl BroadcastState {
     }
 }
 
-// Delta compression for position updates
+/// Drain `rx` down to its newest frame, for a client that lagged behind the channel.
+/// Every frame carries the full state, so the skipped ones are never needed; a
+/// `ClientFrames` delta is taken against what the client last got, not the previous frame.
+pub fn skip_to_latest<T: Clone>(rx: &mut Receiver<T>) -> Result<T, TryRecvError> {
+    let mut latest = None;
+    loop {
+        match rx.try_recv() {
+            Ok(frame) => latest = Some(frame),
+            // The sender overtook us again while draining; keep going
+            Err(TryRecvError::Lagged(_)) => {}
+            Err(e) => return latest.ok_or(e),
+        }
+    }
+}
+
+/// Per-connection frame encoder that remembers what the client last received,
+/// so `?delta=1` clients get deltas against it and everything else full frames
+#[derive(Default)]
+pub struct ClientFrames {
+    // Last per-boid frame sent; `None` after connecting or an occupancy frame
+    last_sent: Option<BroadcastState>,
+    deltas_since_keyframe: u32,
+}
+
+impl ClientFrames {
+    pub fn next_frame(
+        &mut self,
+        state: BroadcastState,
+        options: &FrameOptions,
+        max_frame_bytes: usize,
+    ) -> Vec<u8> {
+        let per_boid = !options.occupancy && state.full_frame_len(options) <= max_frame_bytes;
+        if !(options.delta && per_boid) {
+            self.last_sent = None;
+            return state.client_frame(options, max_frame_bytes);
+        }
+
+        let delta = match &self.last_sent {
+            Some(previous) if self.deltas_since_keyframe < KEYFRAME_INTERVAL => {
+                state.delta_frame(previous, options)
+            }
+            _ => None,
+        };
+        match delta {
+            Some((frame, client_state)) => {
+                self.deltas_since_keyframe += 1;
+                self.last_sent = Some(client_state);
+                frame
+            }
+            None => {
+                self.deltas_since_keyframe = 0;
+                let frame = state.full_frame(options);
+                self.last_sent = Some(state);
+                frame
+            }
+        }
+    }
+}
+
+/// The last few broadcasts, used to give each new one the positions its boids had
+/// `INTERP_HISTORY` broadcasts earlier
+pub struct StateHistory {
+    // Oldest first, without their own `previous` to keep the copies small
+    states: VecDeque<BroadcastState>,
+    depth: usize,
+}
+
+impl StateHistory {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(depth),
+            depth: depth.max(1),
+        }
+    }
+
+    /// Fill in `state.previous` from the oldest remembered broadcast, then remember `state`
+    pub fn push(&mut self, mut state: BroadcastState) -> BroadcastState {
+        if let Some(oldest) = self.states.front() {
+            state.previous = previous_positions(&state, oldest);
+            state.history_frames = self.states.len() as u32;
+        }
+        if self.states.len() == self.depth {
+            self.states.pop_front();
+        }
+        self.states.push_back(BroadcastState {
+            previous: Vec::new(),
+            history_frames: 0,
+            ..state.clone()
+        });
+        state
+    }
+
+    /// Forget every remembered broadcast, e.g. once no client wants interpolation
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+/// Counts live clients of an optional stream, e.g. `?interp=1` so the broadcast
+/// task only keeps a `StateHistory` while someone reads it
+#[derive(Default)]
+pub struct Subscribers(AtomicUsize);
+
+impl Subscribers {
+    /// Count a client until the returned guard is dropped
+    pub fn subscribe(self: &Arc<Self>) -> SubscriberGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        SubscriberGuard(Arc::clone(self))
+    }
+
+    pub fn any(&self) -> bool {
+        self.0.load(Ordering::Relaxed) > 0
+    }
+}
+
+/// Held by a subscribed client for as long as it's connected
+pub struct SubscriberGuard(Arc<Subscribers>);
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// `oldest`'s x, y for each boid in `current`, matched by id; boids that didn't
+/// exist yet get their current position
+fn previous_positions(current: &BroadcastState, oldest: &BroadcastState) -> Vec<u8> {
+    let mut out = Vec::with_capacity(current.num_boids * 8);
+    if current.ids == oldest.ids {
+        for boid in oldest.data.chunks_exact(16) {
+            out.extend_from_slice(&boid[..8]);
+        }
+        return out;
+    }
+    let by_id: HashMap<u32, &[u8]> = oldest.ids.iter().copied().zip(oldest.data.chunks_exact(16)).collect();
+    for (id, boid) in current.ids.iter().zip(current.data.chunks_exact(16)) {
+        out.extend_from_slice(&by_id.get(id).copied().unwrap_or(boid)[..8]);
+    }
+    out
+}
+
+// Delta compression for position updates. Each boid is either four i16 LE
+// deltas (`x, y, vx, vy`) in `1 / DELTA_SCALE` units, or `DELTA_ESCAPE`
+// followed by its four absolute f32 values when a delta won't fit.
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct DeltaState {
@@ -69,7 +645,6 @@ pub struct DeltaState {
     pub deltas: Vec<u8>, // Packed delta values
 }
 
-#[allow(dead_code)]
 impl DeltaState {
     pub fn encode_delta(current: &BroadcastState, previous: &BroadcastState) -> Result<Self> {
         if current.num_boids != previous.num_boids {
@@ -82,16 +657,19 @@ impl DeltaState {
             });
         }
         
-        let mut deltas = Vec::with_capacity(current.data.len());
+        let curr = BroadcastState::decode(&current.data)?;
+        let prev = BroadcastState::decode(&previous.data)?;
+        let mut deltas = Vec::with_capacity(current.num_boids * 8);
         
         // Calculate deltas (current - previous)
-        for (curr, prev) in current.data.chunks_exact(4).zip(previous.data.chunks_exact(4)) {
-            let curr_val = f32::from_le_bytes(curr.try_into().unwrap());
-            let prev_val = f32::from_le_bytes(prev.try_into().unwrap());
-            let delta = curr_val - prev_val;
-            
-            // Quantize delta to reduce size (optional)
-            deltas.extend_from_slice(&delta.to_le_bytes());
+        for (c, p) in curr.chunks_exact(4).zip(prev.chunks_exact(4)) {
+            match quantize_boid(c, p) {
+                Some(q) => q.iter().for_each(|v| deltas.extend_from_slice(&v.to_le_bytes())),
+                None => {
+                    deltas.extend_from_slice(&DELTA_ESCAPE.to_le_bytes());
+                    c.iter().for_each(|v| deltas.extend_from_slice(&v.to_le_bytes()));
+                }
+            }
         }
         
         Ok(Self {
@@ -101,15 +679,65 @@ impl DeltaState {
             deltas,
         })
     }
+
+    /// Apply the deltas to `previous` (`num_boids * 4` values), giving exactly what a
+    /// client holds after receiving them
+    pub fn decode_delta(&self, previous: &[f32]) -> Result<Vec<f32>> {
+        if previous.len() != self.num_boids * 4 {
+            anyhow::bail!("Delta has {} boids, previous state {} values", self.num_boids, previous.len());
+        }
+        let truncated = || anyhow::anyhow!("Truncated delta data");
+        let mut out = Vec::with_capacity(previous.len());
+        let mut bytes = self.deltas.as_slice();
+        for p in previous.chunks_exact(4) {
+            let head = bytes.get(..2).ok_or_else(truncated)?;
+            if i16::from_le_bytes([head[0], head[1]]) == DELTA_ESCAPE {
+                let values = bytes.get(2..18).ok_or_else(truncated)?;
+                out.extend(BroadcastState::decode(values)?);
+                bytes = &bytes[18..];
+            } else {
+                let values = bytes.get(..8).ok_or_else(truncated)?;
+                for (prev, q) in p.iter().zip(values.chunks_exact(2)) {
+                    out.push(prev + i16::from_le_bytes([q[0], q[1]]) as f32 / DELTA_SCALE);
+                }
+                bytes = &bytes[8..];
+            }
+        }
+        if !bytes.is_empty() {
+            anyhow::bail!("{} trailing bytes after delta data", bytes.len());
+        }
+        Ok(out)
+    }
+}
+
+/// One boid's deltas in `1 / DELTA_SCALE` units, or `None` when it has to be sent
+/// absolute: position jumps past `MAX_POSITION_DELTA` (wrap-around, respawns),
+/// velocity changes outside the i16 range and non-finite values
+fn quantize_boid(current: &[f32], previous: &[f32]) -> Option<[i16; 4]> {
+    let mut q = [0i16; 4];
+    for (k, (c, p)) in current.iter().zip(previous).enumerate() {
+        let delta = c - p;
+        let limit = if k < 2 { MAX_POSITION_DELTA } else { 1.0 };
+        if delta.is_nan() || delta.abs() > limit {
+            return None;
+        }
+        // i16::MIN is the escape marker, so clamp to the symmetric range
+        q[k] = (delta * DELTA_SCALE).round().clamp(-32767.0, 32767.0) as i16;
+    }
+    Some(q)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::{CudaContext, init_cuda_in_thread};
+    #[cfg(not(feature = "no-cuda"))]
     use crate::simulation_engine::SimulationEngine;
+    #[cfg(not(feature = "no-cuda"))]
     use std::sync::Arc;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -124,6 +752,7 @@ mod tests {
         )
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_broadcast_state_encode_decode() {
         let (context, _context_guard) = setup_test_context();
@@ -134,9 +763,11 @@ mod tests {
         std::thread::sleep(std::time::Duration::from_millis(100));
         
         // Encode state
-        let encoded = BroadcastState::encode(&engine).unwrap();
+        let encoded = BroadcastState::encode(&engine, false).unwrap();
         assert_eq!(encoded.num_boids, 10);
         assert_eq!(encoded.data.len(), 10 * 16); // 10 boids * 4 floats * 4 bytes
+        assert_eq!(encoded.ids.len(), 10);
+        assert_eq!(encoded.encode_ids().len(), 10 * 4);
         
         // Decode state
         let decoded = BroadcastState::decode(&encoded.data).unwrap();
@@ -145,6 +776,7 @@ mod tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_broadcast_state_size() {
         let (context, _context_guard) = setup_test_context();
@@ -153,12 +785,13 @@ mod tests {
         
         std::thread::sleep(std::time::Duration::from_millis(100));
         
-        let encoded = BroadcastState::encode(&engine).unwrap();
+        let encoded = BroadcastState::encode(&engine, false).unwrap();
         assert_eq!(encoded.size_bytes(), 100 * 16); // 100 boids * 16 bytes per boid
         
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_delta_state_encode() {
         let (context, _context_guard) = setup_test_context();
@@ -167,16 +800,17 @@ mod tests {
         
         std::thread::sleep(std::time::Duration::from_millis(100));
         
-        let state1 = BroadcastState::encode(&engine).unwrap();
+        let state1 = BroadcastState::encode(&engine, false).unwrap();
         
         // Wait a bit and get second state
         std::thread::sleep(std::time::Duration::from_millis(50));
-        let state2 = BroadcastState::encode(&engine).unwrap();
+        let state2 = BroadcastState::encode(&engine, false).unwrap();
         
         // Encode delta
         let delta = DeltaState::encode_delta(&state2, &state1).unwrap();
         assert_eq!(delta.num_boids, 10);
-        assert_eq!(delta.deltas.len(), state2.data.len());
+        let previous = BroadcastState::decode(&state1.data).unwrap();
+        assert_eq!(delta.decode_delta(&previous).unwrap().len(), 10 * 4);
         
         engine.stop();
     }
@@ -186,14 +820,28 @@ mod tests {
         // Test delta encoding when particle count changes
         let state1 = BroadcastState {
             timestamp: 100,
+            encode_ms: 0,
             num_boids: 10,
             data: vec![0u8; 10 * 16],
+            ids: (0..10).collect(),
+            forces: Vec::new(),
+            speeds: Vec::new(),
+            occupancy: Vec::new(),
+            previous: Vec::new(),
+            history_frames: 0,
         };
         
         let state2 = BroadcastState {
             timestamp: 200,
+            encode_ms: 0,
             num_boids: 20, // Different count
             data: vec![0u8; 20 * 16],
+            ids: (0..20).collect(),
+            forces: Vec::new(),
+            speeds: Vec::new(),
+            occupancy: Vec::new(),
+            previous: Vec::new(),
+            history_frames: 0,
         };
         
         let delta = DeltaState::encode_delta(&state2, &state1).unwrap();
@@ -221,4 +869,330 @@ mod tests {
             assert!((orig - dec).abs() < 0.0001, "Values should match");
         }
     }
+
+    #[test]
+    fn test_occupancy_grid_counts() {
+        let state = [
+            0.0, 0.0, 0.0, 0.0, //
+            0.01, 0.01, 0.0, 0.0, //
+            1.0, 1.0, 0.0, 0.0, //
+            0.5, f32::NAN, 0.0, 0.0,
+        ];
+        let grid = occupancy_grid(&state, 4);
+        assert_eq!(grid.len(), 16);
+        assert_eq!(grid[0], 2, "Both boids near the origin share a cell");
+        assert_eq!(grid[15], 1, "Edge positions clamp into the last cell");
+        assert_eq!(grid.iter().map(|&c| c as u32).sum::<u32>(), 3, "NaN positions are skipped");
+
+        let crowded: Vec<f32> = (0..300).flat_map(|_| [0.2, 0.2, 0.0, 0.0]).collect();
+        assert_eq!(occupancy_grid(&crowded, 4)[0], 255, "Counts saturate");
+    }
+
+    #[test]
+    fn test_frame_headers() {
+        let state = BroadcastState {
+            timestamp: 7,
+            encode_ms: 0,
+            num_boids: 2,
+            data: vec![0u8; 2 * 16],
+            ids: vec![0, 1],
+            forces: vec![0u8; 2 * 4],
+            speeds: vec![0u8; 2],
+            occupancy: vec![0u8; OCCUPANCY_GRID_SIZE * OCCUPANCY_GRID_SIZE],
+            previous: Vec::new(),
+            history_frames: 0,
+        };
+        let full = state.full_frame(&FrameOptions { ids: true, ..Default::default() });
+        assert_eq!(full[0], FRAME_FULL);
+        assert_eq!(full.len(), 13 + 2 * 16 + 2 * 4);
+
+        let with_forces = state.full_frame(&FrameOptions { forces: true, ..Default::default() });
+        assert_eq!(with_forces[0], FRAME_FULL | FRAME_FLAG_FORCES);
+        assert_eq!(with_forces.len(), 13 + 2 * 16 + 2 * 4);
+        // Snapshots taken before anyone asked for forces go out without them
+        let no_forces = BroadcastState { forces: Vec::new(), ..state.clone() };
+        let frame = no_forces.full_frame(&FrameOptions { forces: true, ..Default::default() });
+        assert_eq!(frame[0], FRAME_FULL);
+        assert_eq!(frame.len(), 13 + 2 * 16);
+
+        let occ = state.occupancy_frame();
+        assert_eq!(occ[0], FRAME_OCCUPANCY);
+        assert_eq!(u16::from_le_bytes([occ[13], occ[14]]) as usize, OCCUPANCY_GRID_SIZE);
+        assert_eq!(occ.len(), 15 + OCCUPANCY_GRID_SIZE * OCCUPANCY_GRID_SIZE);
+    }
+
+    #[test]
+    fn test_speed_bytes_follow_the_boid_block() {
+        let values = [
+            0.1, 0.1, 0.0, 0.0, //
+            0.2, 0.2, 0.03, 0.04, //
+            0.3, 0.3, 0.3, 0.4, //
+            0.4, 0.4, 0.03, 0.04,
+        ];
+        // The last boid has no cap of its own and is measured against the default
+        let speeds = speed_bytes(&values, &[0.05, 0.1, 0.05]);
+        assert_eq!(speeds, [0, 128, 255, 255]);
+
+        let state = BroadcastState { speeds, forces: vec![9u8; 4 * 4], ..state_from(&values) };
+        let frame = state.full_frame(&FrameOptions { speed: true, forces: true, ..Default::default() });
+        assert_eq!(frame[0], FRAME_FULL | FRAME_FLAG_SPEED | FRAME_FLAG_FORCES);
+        assert_eq!(frame.len(), state.full_frame_len(&FrameOptions { speed: true, forces: true, ..Default::default() }));
+        assert_eq!(&frame[13 + 4 * 16..13 + 4 * 16 + 4], [0, 128, 255, 255]);
+        assert_eq!(frame[13 + 4 * 16 + 4], 9, "forces follow the speed bytes");
+
+        let plain = state.full_frame(&FrameOptions::default());
+        assert_eq!(plain[0], FRAME_FULL);
+        assert_eq!(plain.len(), 13 + 4 * 16, "default frames are unchanged");
+
+        // States encoded while nobody asked for speeds have none, and frames say so
+        let unmeasured = BroadcastState { speeds: Vec::new(), ..state };
+        let frame = unmeasured.full_frame(&FrameOptions { speed: true, ..Default::default() });
+        assert_eq!(frame[0], FRAME_FULL);
+        assert_eq!(frame.len(), 13 + 4 * 16);
+    }
+
+    #[test]
+    fn test_oversized_frame_falls_back_to_occupancy() {
+        let state = BroadcastState {
+            timestamp: 7,
+            encode_ms: 0,
+            num_boids: 1_000_000,
+            data: vec![0u8; 1_000_000 * 16],
+            ids: (0..1_000_000).collect(),
+            forces: Vec::new(),
+            speeds: Vec::new(),
+            occupancy: vec![0u8; OCCUPANCY_GRID_SIZE * OCCUPANCY_GRID_SIZE],
+            previous: Vec::new(),
+            history_frames: 0,
+        };
+        let frame = state.client_frame(&FrameOptions::default(), DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_OCCUPANCY, "16MB frame should degrade to occupancy");
+        assert!(frame.len() <= DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(u32::from_le_bytes(frame[9..13].try_into().unwrap()), 1_000_000);
+
+        // Under the cap the client gets what it asked for; ids count toward the size
+        let cap = state.full_frame_len(&FrameOptions::default());
+        let with_ids = FrameOptions { ids: true, ..Default::default() };
+        assert_eq!(state.client_frame(&FrameOptions::default(), cap)[0], FRAME_FULL);
+        assert_eq!(state.client_frame(&with_ids, cap)[0], FRAME_OCCUPANCY);
+    }
+
+    fn state_from(values: &[f32]) -> BroadcastState {
+        BroadcastState {
+            timestamp: 0,
+            encode_ms: 0,
+            num_boids: values.len() / 4,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ids: (0..values.len() as u32 / 4).collect(),
+            forces: Vec::new(),
+            speeds: Vec::new(),
+            occupancy: vec![0u8; OCCUPANCY_GRID_SIZE * OCCUPANCY_GRID_SIZE],
+            previous: Vec::new(),
+            history_frames: 0,
+        }
+    }
+
+    #[test]
+    fn test_client_frames_send_deltas_between_full_frames() {
+        let options = FrameOptions { delta: true, ..Default::default() };
+        let mut frames = ClientFrames::default();
+        let first = [0.5, 0.5, 0.1, 0.0, 0.2, 0.2, 0.0, 0.1];
+        let second = [0.51, 0.5, 0.1, 0.0, 0.2, 0.21, 0.0, 0.1];
+
+        let frame = frames.next_frame(state_from(&first), &options, DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_FULL, "A new connection starts with a full frame");
+
+        let frame = frames.next_frame(state_from(&second), &options, DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_DELTA);
+        assert_eq!(frame.len(), 13 + 2 * 8, "Four i16 deltas per boid");
+        for (i, (prev, curr)) in first.iter().zip(second).enumerate() {
+            let q = i16::from_le_bytes([frame[13 + i * 2], frame[14 + i * 2]]);
+            assert!((prev + q as f32 / DELTA_SCALE - curr).abs() <= 0.5 / DELTA_SCALE + 1e-6);
+        }
+
+        let frame = frames.next_frame(state_from(&second[..4]), &options, DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_FULL, "num_boids changed");
+
+        let wrapped = [0.99, 0.5, 0.1, 0.0];
+        let frame = frames.next_frame(state_from(&wrapped), &options, DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_FULL, "Wrap-around jumps get a full frame");
+
+        for _ in 0..KEYFRAME_INTERVAL {
+            let frame = frames.next_frame(state_from(&wrapped), &options, DEFAULT_MAX_FRAME_BYTES);
+            assert_eq!(frame[0], FRAME_DELTA);
+        }
+        let frame = frames.next_frame(state_from(&wrapped), &options, DEFAULT_MAX_FRAME_BYTES);
+        assert_eq!(frame[0], FRAME_FULL, "Periodic keyframe");
+
+        let mut full_only = ClientFrames::default();
+        let options = FrameOptions::default();
+        for _ in 0..2 {
+            let frame = full_only.next_frame(state_from(&first), &options, DEFAULT_MAX_FRAME_BYTES);
+            assert_eq!(frame[0], FRAME_FULL, "Deltas are opt-in");
+        }
+    }
+
+    #[test]
+    fn test_quantized_delta_roundtrip() {
+        let previous = [0.5, 0.5, 0.1, -0.2, 0.01, 0.3, 0.0, 0.0, 0.7, 0.7, 0.0, 0.0];
+        let current = [
+            0.5123, 0.4871, 0.1004, -0.3, // small moves
+            0.99, 0.3, 5.0, 0.0, // wrap-around jump and a huge velocity change
+            0.7, 0.7, f32::NAN, 0.0, // non-finite
+        ];
+        let delta = DeltaState::encode_delta(&state_from(&current), &state_from(&previous)).unwrap();
+        assert_eq!(delta.deltas.len(), 8 + 18 + 18);
+
+        let decoded = delta.decode_delta(&previous).unwrap();
+        for (i, (c, d)) in current.iter().zip(&decoded).enumerate().take(4) {
+            assert!((c - d).abs() <= 0.5 / DELTA_SCALE + 1e-6, "value {}: {} vs {}", i, c, d);
+        }
+        assert_eq!(&decoded[4..8], &current[4..8], "Escaped boids are exact");
+        assert!(decoded[10].is_nan());
+
+        assert!(delta.decode_delta(&previous[..8]).is_err());
+        let truncated = DeltaState { deltas: delta.deltas[..20].to_vec(), ..delta.clone() };
+        assert!(truncated.decode_delta(&previous).is_err());
+
+        // Repeated small moves don't drift: each delta is taken against the reconstruction
+        let options = FrameOptions { delta: true, ..Default::default() };
+        let mut frames = ClientFrames::default();
+        let first = frames.next_frame(state_from(&previous[..4]), &options, usize::MAX);
+        let mut client = BroadcastState::decode(&first[13..]).unwrap();
+        for step in 1..=50 {
+            let target = [0.5 + step as f32 * 1e-5, 0.5, 0.1, -0.2];
+            let frame = frames.next_frame(state_from(&target), &options, usize::MAX);
+            assert_eq!(frame[0], FRAME_DELTA);
+            let delta = DeltaState {
+                base_timestamp: 0,
+                delta_timestamp: 0,
+                num_boids: 1,
+                deltas: frame[13..].to_vec(),
+            };
+            client = delta.decode_delta(&client).unwrap();
+            assert!((client[0] - target[0]).abs() <= 0.5 / DELTA_SCALE + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_interp_frames_carry_earlier_positions() {
+        let options = FrameOptions { interp: true, ..Default::default() };
+        let at = |x: f32| state_from(&[x, 0.5, 0.01, 0.0, 0.2, x, 0.0, 0.01]);
+        let mut history = StateHistory::new(2);
+
+        // No history yet: the current positions, 0 broadcasts back
+        let frame = history.push(at(0.1)).full_frame(&options);
+        assert_eq!(frame[0], FRAME_FULL | FRAME_FLAG_INTERP);
+        assert_eq!(frame.len(), 13 + 2 * 16 + 4 + 2 * 8);
+        assert_eq!(u32::from_le_bytes(frame[45..49].try_into().unwrap()), 0);
+        assert_eq!(BroadcastState::decode(&frame[49..]).unwrap(), [0.1, 0.5, 0.2, 0.1]);
+
+        history.push(at(0.2));
+        let frame = history.push(at(0.3)).full_frame(&options);
+        assert_eq!(u32::from_le_bytes(frame[45..49].try_into().unwrap()), 2);
+        assert_eq!(BroadcastState::decode(&frame[49..]).unwrap(), [0.1, 0.5, 0.2, 0.1]);
+
+        // Reordered and new boids are matched by id
+        let mut reordered = state_from(&[0.6, 0.6, 0.0, 0.0, 0.4, 0.5, 0.0, 0.0, 0.9, 0.9, 0.0, 0.0]);
+        reordered.ids = vec![1, 0, 7];
+        let state = history.push(reordered);
+        assert_eq!(
+            BroadcastState::decode(&state.previous).unwrap(),
+            [0.2, 0.2, 0.2, 0.5, 0.9, 0.9]
+        );
+        assert_eq!(state.in_region(&Region::new(Some(0.5), None, None, None).unwrap()).previous.len(), 16);
+
+        // A cleared history starts over from the current positions
+        history.clear();
+        assert_eq!(history.push(at(0.4)).history_frames, 0);
+
+        let subscribers = Arc::new(Subscribers::default());
+        let guard = subscribers.subscribe();
+        assert!(subscribers.any());
+        drop(guard);
+        assert!(!subscribers.any());
+    }
+
+    #[test]
+    fn test_in_region_keeps_only_visible_boids() {
+        let mut state = state_from(&[0.3, 0.2, 1.0, 2.0, 0.9, 0.9, 0.0, 0.0, 0.4, 0.4, 3.0, 4.0]);
+        state.forces = [0.5f32, 1.5, 2.5].iter().flat_map(|f| f.to_le_bytes()).collect();
+        let region = Region::new(Some(0.2), Some(0.5), Some(0.1), Some(0.4)).unwrap();
+
+        let visible = state.in_region(&region);
+        assert_eq!(visible.num_boids, 2);
+        assert_eq!(visible.ids, [0, 2]);
+        assert_eq!(
+            BroadcastState::decode(&visible.data).unwrap(),
+            [0.3, 0.2, 1.0, 2.0, 0.4, 0.4, 3.0, 4.0]
+        );
+        assert_eq!(BroadcastState::decode(&visible.forces).unwrap(), [0.5, 2.5]);
+        assert_eq!(visible.occupancy.iter().map(|&c| c as u32).sum::<u32>(), 2);
+
+        assert!(Region::new(Some(0.5), Some(0.5), None, None).is_err());
+        assert!(Region::new(Some(f32::NAN), None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_values_frame_layout() {
+        let frame = values_frame("grayscott", 12, &[0.5, -1.0]);
+        assert_eq!(frame[0], FRAME_VALUES);
+        assert_eq!(u64::from_le_bytes(frame[1..9].try_into().unwrap()), 12);
+        assert_eq!(u32::from_le_bytes(frame[9..13].try_into().unwrap()), 2);
+        assert_eq!(frame[13] as usize, "grayscott".len());
+        assert_eq!(&frame[14..23], b"grayscott");
+        assert_eq!(BroadcastState::decode(&frame[23..]).unwrap(), [0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_timestamp_is_when_the_snapshot_was_produced() {
+        let snapshot = EngineSnapshot {
+            state: vec![0.5, 0.5, 0.0, 0.0],
+            ids: vec![0],
+            forces: vec![0.0],
+            max_speeds: vec![0.05],
+            world_size: 1.0,
+            produced_at: UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123),
+        };
+        let state = BroadcastState::from_snapshot(&snapshot, Instant::now(), false);
+        assert_eq!(state.timestamp, 1_700_000_000_123);
+        assert!(state.speeds.is_empty(), "Speeds are only computed when asked for");
+        assert_eq!(BroadcastState::from_snapshot(&snapshot, Instant::now(), true).speeds, [0]);
+        let frame = state.full_frame(&FrameOptions::default());
+        assert_eq!(u64::from_le_bytes(frame[1..9].try_into().unwrap()), 1_700_000_000_123);
+        let json: serde_json::Value = serde_json::from_str(&state.json_frame(1).unwrap()).unwrap();
+        assert_eq!(json["timestamp"], 1_700_000_000_123u64);
+    }
+
+    #[test]
+    fn test_lagging_receiver_skips_ahead_and_keeps_receiving() {
+        let (tx, mut rx) = tokio::sync::broadcast::channel(4);
+        for frame in 0..10 {
+            tx.send(frame).unwrap();
+        }
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Lagged(6))));
+        assert_eq!(skip_to_latest(&mut rx), Ok(9));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        tx.send(10).unwrap();
+        assert_eq!(rx.try_recv(), Ok(10));
+        assert_eq!(skip_to_latest(&mut rx), Err(TryRecvError::Empty));
+        drop(tx);
+        assert_eq!(skip_to_latest(&mut rx), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn test_json_frame_downsamples_by_stride() {
+        let state = state_from(&[0.1, 0.2, 1.0, 2.0, 0.3, 0.4, 3.0, 4.0, 0.5, 0.6, 5.0, 6.0]);
+        let frame: serde_json::Value = serde_json::from_str(&state.json_frame(2).unwrap()).unwrap();
+        assert_eq!(frame["num_boids"], 3);
+        assert_eq!(frame["stride"], 2);
+        let boids = frame["boids"].as_array().unwrap();
+        assert_eq!(boids.len(), 2);
+        assert_eq!(boids[1]["x"].as_f64().unwrap() as f32, 0.5);
+        assert_eq!(boids[1]["vy"].as_f64().unwrap() as f32, 6.0);
+
+        let every: serde_json::Value = serde_json::from_str(&state.json_frame(1).unwrap()).unwrap();
+        assert_eq!(every["boids"].as_array().unwrap().len(), 3);
+    }
 }
@@ -0,0 +1,141 @@
+// Cooperative cancellation for long-running simulation requests
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag checked between simulation steps
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Guard that cancels the token when dropped. Held by a request handler, it fires
+    /// when axum drops the handler future because the client disconnected.
+    pub fn drop_guard(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            token: self.clone(),
+            armed: true,
+        }
+    }
+
+    /// Run up to `steps` iterations of `step`, stopping early once cancelled
+    pub fn run_steps<T, E>(
+        &self,
+        steps: usize,
+        mut step: impl FnMut() -> Result<T, E>,
+    ) -> Result<StepProgress, E> {
+        let mut completed = 0;
+        while completed < steps && !self.is_cancelled() {
+            step()?;
+            completed += 1;
+        }
+        Ok(StepProgress {
+            completed,
+            requested: steps,
+        })
+    }
+}
+
+pub struct CancelOnDrop {
+    token: CancelToken,
+    armed: bool,
+}
+
+impl CancelOnDrop {
+    /// Let the guard drop without cancelling (the request finished normally)
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            self.token.cancel();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepProgress {
+    pub completed: usize,
+    pub requested: usize,
+}
+
+impl StepProgress {
+    pub fn cancelled(&self) -> bool {
+        self.completed < self.requested
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_steps_completes_without_cancel() {
+        let token = CancelToken::new();
+        let mut count = 0;
+        let progress = token
+            .run_steps(10, || {
+                count += 1;
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(count, 10);
+        assert!(!progress.cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_stops_step_loop() {
+        let token = CancelToken::new();
+        let worker = {
+            let token = token.clone();
+            tokio::task::spawn_blocking(move || {
+                token.run_steps(100_000, || {
+                    std::thread::sleep(Duration::from_millis(1));
+                    Ok::<_, ()>(())
+                })
+            })
+        };
+
+        // Stand-in for a handler future that axum drops when the client goes away
+        let guard = token.drop_guard();
+        let request = tokio::spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        request.abort();
+
+        let progress = tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .expect("Step loop should stop promptly")
+            .unwrap()
+            .unwrap();
+        assert!(progress.cancelled());
+        assert!(progress.completed > 0 && progress.completed < 100_000);
+    }
+
+    #[test]
+    fn test_disarmed_guard_does_not_cancel() {
+        let token = CancelToken::new();
+        token.drop_guard().disarm();
+        assert!(!token.is_cancelled());
+        drop(token.drop_guard());
+        assert!(token.is_cancelled());
+    }
+}
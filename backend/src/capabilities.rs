@@ -0,0 +1,233 @@
+// Startup probe of the compiled CUDA kernels against the active GPU
+// Makes the silent GPU -> CPU fallback visible via /api/capabilities
+use crate::cuda::Device;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::device::DeviceAttribute;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::prelude::*;
+use serde::Serialize;
+#[cfg(not(feature = "no-cuda"))]
+use std::ffi::CString;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct KernelStatus {
+    pub entry_point: &'static str,
+    /// PTX was produced by build.rs
+    pub compiled: bool,
+    /// `.target` architecture declared in the PTX, e.g. `sm_61`
+    pub ptx_target: Option<String>,
+    /// Module loaded and entry point resolved on this device
+    pub loaded: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Capabilities {
+    pub device_name: Option<String>,
+    pub compute_capability: Option<String>,
+    /// PTX target is not newer than the device, so the driver can JIT it
+    pub arch_compatible: Option<bool>,
+    pub boids_kernel: KernelStatus,
+}
+
+/// PTX a simulation is running with right now, for `/api/debug/kernels`
+#[derive(Serialize, Clone, Debug)]
+pub struct LoadedKernel {
+    pub simulation: &'static str,
+    pub entry_points: &'static [&'static str],
+    /// The simulation holds PTX; false means it steps on the CPU
+    pub loaded: bool,
+    pub ptx_len: Option<usize>,
+    /// `.target` architecture declared in the PTX, e.g. `sm_61`
+    pub ptx_target: Option<String>,
+}
+
+impl LoadedKernel {
+    pub fn new(simulation: &'static str, entry_points: &'static [&'static str], ptx: Option<&str>) -> Self {
+        Self {
+            simulation,
+            entry_points,
+            loaded: ptx.is_some(),
+            ptx_len: ptx.map(str::len),
+            ptx_target: ptx.and_then(ptx_target),
+        }
+    }
+}
+
+/// Compile-time configuration, for diagnosing deployments that behave differently
+#[derive(Serialize, Clone, Debug)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub cuda_kernel: bool,
+    pub gpu_stats: bool,
+    /// build.rs compiled the boids kernel with nvcc
+    pub boids_ptx: bool,
+    /// Built with `no-cuda`: every simulation runs on the CPU
+    pub no_cuda: bool,
+    pub debug_assertions: bool,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        cuda_kernel: cfg!(feature = "cuda-kernel"),
+        gpu_stats: cfg!(feature = "gpu-stats"),
+        boids_ptx: option_env!("BOIDS_PTX").is_some(),
+        no_cuda: cfg!(feature = "no-cuda"),
+        debug_assertions: cfg!(debug_assertions),
+    }
+}
+
+/// Probe the boids kernel on `device`. Requires a CUDA context on this thread.
+/// `None` (a no-cuda build) reports the kernel as unavailable without touching the driver.
+pub fn probe(device: Option<&Device>) -> Capabilities {
+    match device {
+        #[cfg(not(feature = "no-cuda"))]
+        Some(device) => probe_device(device),
+        _ => Capabilities {
+            device_name: None,
+            compute_capability: None,
+            arch_compatible: None,
+            boids_kernel: KernelStatus {
+                entry_point: "boids_step",
+                compiled: false,
+                ptx_target: None,
+                loaded: false,
+                error: Some("built with the no-cuda feature".to_string()),
+            },
+        },
+    }
+}
+
+#[cfg(not(feature = "no-cuda"))]
+fn probe_device(device: &Device) -> Capabilities {
+    let device_name = device.name().ok();
+    let compute = compute_capability(device);
+    let boids_kernel = probe_boids_kernel();
+
+    let arch_compatible = match (compute, boids_kernel.ptx_target.as_deref()) {
+        (Some((major, minor)), Some(target)) => {
+            parse_sm_arch(target).map(|arch| arch <= (major * 10 + minor) as u32)
+        }
+        _ => None,
+    };
+
+    Capabilities {
+        device_name,
+        compute_capability: compute.map(|(major, minor)| format!("{}.{}", major, minor)),
+        arch_compatible,
+        boids_kernel,
+    }
+}
+
+#[cfg(not(feature = "no-cuda"))]
+fn compute_capability(device: &Device) -> Option<(i32, i32)> {
+    let major = device.get_attribute(DeviceAttribute::ComputeCapabilityMajor).ok()?;
+    let minor = device.get_attribute(DeviceAttribute::ComputeCapabilityMinor).ok()?;
+    Some((major, minor))
+}
+
+#[cfg(not(feature = "no-cuda"))]
+fn probe_boids_kernel() -> KernelStatus {
+    let mut status = KernelStatus {
+        entry_point: "boids_step",
+        compiled: false,
+        ptx_target: None,
+        loaded: false,
+        error: None,
+    };
+
+    let ptx = match option_env!("BOIDS_PTX") {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(ptx) => ptx,
+            Err(e) => {
+                status.error = Some(format!("Failed to read PTX at {}: {}", path, e));
+                return status;
+            }
+        },
+        None => {
+            status.error = Some("nvcc was not available at build time".to_string());
+            return status;
+        }
+    };
+    status.compiled = true;
+    status.ptx_target = ptx_target(&ptx);
+
+    let result = CString::new(ptx)
+        .map_err(|e| format!("PTX contains a NUL byte: {}", e))
+        .and_then(|ptx_c| {
+            Module::load_from_string(&ptx_c).map_err(|e| format!("Module load failed: {:?}", e))
+        })
+        .and_then(|module| {
+            module
+                .get_function(&CString::new(status.entry_point).unwrap())
+                .map(|_| ())
+                .map_err(|e| format!("Entry point lookup failed: {:?}", e))
+        });
+    match result {
+        Ok(()) => status.loaded = true,
+        Err(e) => status.error = Some(e),
+    }
+    status
+}
+
+/// Extract the architecture from the PTX `.target` directive
+pub fn ptx_target(ptx: &str) -> Option<String> {
+    ptx.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix(".target"))
+        .and_then(|rest| rest.split(|c: char| c == ',' || c.is_whitespace()).find(|s| !s.is_empty()))
+        .map(str::to_string)
+}
+
+/// `sm_86` -> 86
+pub fn parse_sm_arch(arch: &str) -> Option<u32> {
+    arch.strip_prefix("sm_")
+        .or_else(|| arch.strip_prefix("compute_"))
+        .and_then(|n| n.trim_end_matches(|c: char| c.is_ascii_alphabetic()).parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ptx_target_parsing() {
+        let ptx = "//\n.version 8.6\n.target sm_61\n.address_size 64\n";
+        assert_eq!(ptx_target(ptx).as_deref(), Some("sm_61"));
+        assert_eq!(ptx_target(".target sm_90a, debug"), Some("sm_90a".to_string()));
+        assert_eq!(ptx_target(".version 8.6"), None);
+    }
+
+    #[test]
+    fn test_build_info_reflects_features() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_sha.is_empty());
+        assert_eq!(info.cuda_kernel, cfg!(feature = "cuda-kernel"));
+        assert_eq!(info.gpu_stats, cfg!(feature = "gpu-stats"));
+    }
+
+    #[test]
+    fn test_loaded_kernel_describes_the_ptx() {
+        let ptx = ".version 8.6\n.target sm_75\n";
+        let kernel = LoadedKernel::new("boids", &["boids_step"], Some(ptx));
+        assert!(kernel.loaded);
+        assert_eq!(kernel.ptx_len, Some(ptx.len()));
+        assert_eq!(kernel.ptx_target.as_deref(), Some("sm_75"));
+
+        let fallback = LoadedKernel::new("boids", &["boids_step"], None);
+        assert!(!fallback.loaded);
+        assert_eq!((fallback.ptx_len, fallback.ptx_target), (None, None));
+    }
+
+    #[test]
+    fn test_parse_sm_arch() {
+        assert_eq!(parse_sm_arch("sm_61"), Some(61));
+        assert_eq!(parse_sm_arch("sm_90a"), Some(90));
+        assert_eq!(parse_sm_arch("compute_75"), Some(75));
+        assert_eq!(parse_sm_arch("bogus"), None);
+    }
+}
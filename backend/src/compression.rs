@@ -0,0 +1,79 @@
+// Optional zstd compression of simulate responses and streamed fields.
+// Needs the `compression` feature; without it `?compress=zstd` is rejected.
+use anyhow::Result;
+use serde::Deserialize;
+
+/// Header carrying the body length before compression, so clients can preallocate
+pub const UNCOMPRESSED_LENGTH_HEADER: &str = "x-uncompressed-length";
+#[cfg(feature = "compression")]
+const ZSTD_LEVEL: i32 = 3;
+const UNAVAILABLE: &str = "zstd compression needs a server built with the `compression` feature";
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl Compression {
+    /// Fail if this build can't produce the requested compression
+    pub fn check_available(self) -> Result<()> {
+        if self == Self::Zstd && !cfg!(feature = "compression") {
+            anyhow::bail!(UNAVAILABLE);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compression")]
+pub fn compress(bytes: &[u8]) -> Result<Vec<u8>> {
+    zstd::bulk::compress(bytes, ZSTD_LEVEL).map_err(|e| anyhow::anyhow!("zstd compression failed: {:?}", e))
+}
+
+#[cfg(not(feature = "compression"))]
+pub fn compress(_bytes: &[u8]) -> Result<Vec<u8>> {
+    anyhow::bail!(UNAVAILABLE)
+}
+
+/// Little-endian bytes of `values`, the payload compressed field frames carry
+pub fn f32_le_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_availability_matches_feature() {
+        assert!(Compression::None.check_available().is_ok());
+        assert_eq!(Compression::Zstd.check_available().is_ok(), cfg!(feature = "compression"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_zstd_roundtrip_reproduces_field_exactly() {
+        // A smooth, Gray-Scott-like 512x512 field
+        let size = 512;
+        let field: Vec<f32> = (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f32 / size as f32, (i / size) as f32 / size as f32);
+                0.5 + 0.25 * (x * 12.0).sin() * (y * 9.0).cos()
+            })
+            .collect();
+        let bytes = f32_le_bytes(&field);
+        let compressed = compress(&bytes).unwrap();
+        assert!(compressed.len() < bytes.len(), "a smooth field should compress");
+
+        // What a client does with the uncompressed length header
+        let restored: Vec<f32> = zstd::bulk::decompress(&compressed, bytes.len())
+            .unwrap()
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        assert_eq!(restored.len(), field.len());
+        assert!(restored.iter().zip(&field).all(|(a, b)| a.to_bits() == b.to_bits()));
+    }
+}
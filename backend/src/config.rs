@@ -0,0 +1,257 @@
+// Server configuration from a TOML file, environment variables and CLI flags
+// Precedence, highest first: CLI > env > file > defaults
+use crate::broadcast::DEFAULT_MAX_FRAME_BYTES;
+use crate::cuda_pool::DEFAULT_CUDA_WORKERS;
+use crate::gpu_stats::DEFAULT_CACHE_INTERVAL_MS;
+use crate::physics::boids::{BoundaryMode, MAX_SPECIES};
+use crate::rate_limit::{DEFAULT_BURST, DEFAULT_RATE_PER_SEC};
+use crate::simulation_engine::{EngineMode, DEFAULT_THERMAL_LIMIT_C};
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// HTTP/WebSocket listen port
+    pub port: u16,
+    /// CUDA device index
+    pub device: u32,
+    /// Continuous background loop, or client-driven steps
+    pub mode: EngineMode,
+    /// Boids in the streamed simulation
+    pub num_boids: usize,
+    /// Species in the streamed flock, 1..=8
+    pub num_species: u8,
+    /// Internal simulation update rate in Hz
+    pub target_fps: f32,
+    /// Milliseconds between WebSocket broadcasts
+    pub broadcast_interval_ms: u64,
+    /// Per-boid frames above this size are sent as occupancy grids
+    pub max_frame_bytes: usize,
+    pub boundary: BoundaryMode,
+    /// Enable the separation auto-tuner
+    pub auto_tune: bool,
+    pub target_density: Option<f32>,
+    /// Threads in the CUDA worker pool the simulate endpoints run on
+    pub cuda_workers: usize,
+    /// Milliseconds a GPU stats reading is reused before querying the device again
+    pub gpu_stats_interval_ms: u64,
+    /// GPU temperature in °C the engine halves its target FPS at; 0 disables throttling
+    pub gpu_temp_limit_c: u32,
+    /// Where `/api/record/start` writes recordings and `/ws?replay=` reads them
+    pub recording_dir: PathBuf,
+    /// `/api/simulate/*` runs each client may start per second; 0 disables the limit
+    pub simulate_rate_limit: f32,
+    /// Runs a client may start at once before the rate applies
+    pub simulate_burst: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 3001,
+            device: 0,
+            mode: EngineMode::Continuous,
+            num_boids: 100_000,
+            num_species: 4,
+            target_fps: 500.0,
+            broadcast_interval_ms: 16,
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            boundary: BoundaryMode::Wrap,
+            auto_tune: false,
+            target_density: None,
+            cuda_workers: DEFAULT_CUDA_WORKERS,
+            gpu_stats_interval_ms: DEFAULT_CACHE_INTERVAL_MS,
+            gpu_temp_limit_c: DEFAULT_THERMAL_LIMIT_C,
+            recording_dir: PathBuf::from("recordings"),
+            simulate_rate_limit: DEFAULT_RATE_PER_SEC,
+            simulate_burst: DEFAULT_BURST,
+        }
+    }
+}
+
+impl Config {
+    /// Load from `--config path.toml` (if given), the process environment and `args`
+    pub fn load(args: &[String]) -> Result<Self> {
+        let file = match flag_value(args, "--config") {
+            Some(path) => Some(
+                std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read config {}: {:?}", path, e))?,
+            ),
+            None => None,
+        };
+        Self::from_sources(file.as_deref(), |key| std::env::var(key).ok(), args)
+    }
+
+    /// Layer `file` contents, `env` lookups and CLI `args` over the defaults
+    pub fn from_sources(
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+        args: &[String],
+    ) -> Result<Self> {
+        let mut config = match file {
+            Some(contents) => toml::from_str(contents)
+                .map_err(|e| anyhow::anyhow!("Invalid config file: {}", e))?,
+            None => Self::default(),
+        };
+
+        let env_flag = |key: &str| env(key).map(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        override_with(&mut config.port, "PORT", env("PORT"))?;
+        override_with(&mut config.device, "CUDA_DEVICE", env("CUDA_DEVICE"))?;
+        override_with(&mut config.mode, "ENGINE_MODE", env("ENGINE_MODE"))?;
+        override_with(&mut config.num_boids, "BOIDS_COUNT", env("BOIDS_COUNT"))?;
+        override_with(&mut config.num_species, "BOIDS_SPECIES", env("BOIDS_SPECIES"))?;
+        override_with(&mut config.target_fps, "BOIDS_TARGET_FPS", env("BOIDS_TARGET_FPS"))?;
+        override_with(&mut config.broadcast_interval_ms, "BROADCAST_INTERVAL_MS", env("BROADCAST_INTERVAL_MS"))?;
+        override_with(&mut config.max_frame_bytes, "WS_MAX_FRAME_BYTES", env("WS_MAX_FRAME_BYTES"))?;
+        override_with(&mut config.boundary, "BOIDS_BOUNDARY", env("BOIDS_BOUNDARY"))?;
+        override_with(&mut config.cuda_workers, "CUDA_WORKERS", env("CUDA_WORKERS"))?;
+        override_with(&mut config.gpu_stats_interval_ms, "GPU_STATS_INTERVAL_MS", env("GPU_STATS_INTERVAL_MS"))?;
+        override_with(&mut config.gpu_temp_limit_c, "GPU_TEMP_LIMIT_C", env("GPU_TEMP_LIMIT_C"))?;
+        override_with(&mut config.recording_dir, "RECORDING_DIR", env("RECORDING_DIR"))?;
+        override_with(&mut config.simulate_rate_limit, "SIMULATE_RATE_LIMIT", env("SIMULATE_RATE_LIMIT"))?;
+        override_with(&mut config.simulate_burst, "SIMULATE_BURST", env("SIMULATE_BURST"))?;
+        if let Some(auto_tune) = env_flag("BOIDS_AUTO_TUNE") {
+            config.auto_tune = auto_tune;
+        }
+        if let Some(value) = env("BOIDS_TARGET_DENSITY") {
+            config.target_density = Some(parse("BOIDS_TARGET_DENSITY", &value)?);
+        }
+
+        override_with(&mut config.port, "--port", flag_value(args, "--port"))?;
+        override_with(&mut config.device, "--device", flag_value(args, "--device"))?;
+        override_with(&mut config.mode, "--mode", flag_value(args, "--mode"))?;
+        override_with(&mut config.num_boids, "--boids", flag_value(args, "--boids"))?;
+        override_with(&mut config.num_species, "--species", flag_value(args, "--species"))?;
+        override_with(&mut config.target_fps, "--fps", flag_value(args, "--fps"))?;
+        override_with(
+            &mut config.broadcast_interval_ms,
+            "--broadcast-interval-ms",
+            flag_value(args, "--broadcast-interval-ms"),
+        )?;
+        override_with(&mut config.max_frame_bytes, "--max-frame-bytes", flag_value(args, "--max-frame-bytes"))?;
+        override_with(&mut config.boundary, "--boundary", flag_value(args, "--boundary"))?;
+        override_with(&mut config.cuda_workers, "--cuda-workers", flag_value(args, "--cuda-workers"))?;
+        override_with(
+            &mut config.gpu_stats_interval_ms,
+            "--gpu-stats-interval-ms",
+            flag_value(args, "--gpu-stats-interval-ms"),
+        )?;
+        override_with(&mut config.gpu_temp_limit_c, "--gpu-temp-limit-c", flag_value(args, "--gpu-temp-limit-c"))?;
+        override_with(&mut config.recording_dir, "--recording-dir", flag_value(args, "--recording-dir"))?;
+        override_with(
+            &mut config.simulate_rate_limit,
+            "--simulate-rate-limit",
+            flag_value(args, "--simulate-rate-limit"),
+        )?;
+        override_with(&mut config.simulate_burst, "--simulate-burst", flag_value(args, "--simulate-burst"))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.num_boids == 0 {
+            anyhow::bail!("num_boids must be at least 1");
+        }
+        if self.num_species == 0 || self.num_species > MAX_SPECIES {
+            anyhow::bail!("num_species must be 1..={}, got {}", MAX_SPECIES, self.num_species);
+        }
+        if !self.target_fps.is_finite() || self.target_fps <= 0.0 {
+            anyhow::bail!("target_fps must be positive, got {}", self.target_fps);
+        }
+        if self.broadcast_interval_ms == 0 {
+            anyhow::bail!("broadcast_interval_ms must be at least 1");
+        }
+        if self.max_frame_bytes == 0 {
+            anyhow::bail!("max_frame_bytes must be positive");
+        }
+        if self.cuda_workers == 0 {
+            anyhow::bail!("cuda_workers must be at least 1");
+        }
+        if !self.simulate_rate_limit.is_finite() || self.simulate_rate_limit < 0.0 {
+            anyhow::bail!("simulate_rate_limit must be non-negative, got {}", self.simulate_rate_limit);
+        }
+        if self.simulate_burst == 0 {
+            anyhow::bail!("simulate_burst must be at least 1");
+        }
+        if let Some(density) = self.target_density {
+            if !density.is_finite() || density <= 0.0 {
+                anyhow::bail!("target_density must be positive, got {}", density);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Value following `flag` in `args`; later occurrences win
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.windows(2).rev().find(|w| w[0] == flag).map(|w| w[1].clone())
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid value for {}: {:?}", name, value))
+}
+
+fn override_with<T: FromStr>(field: &mut T, name: &str, value: Option<String>) -> Result<()> {
+    if let Some(value) = value {
+        *field = parse(name, &value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("physics-backend")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_precedence_cli_env_file_default() {
+        let file = r#"
+            num_boids = 1000
+            target_fps = 120.0
+            port = 4000
+            boundary = "bounce"
+        "#;
+        let env: HashMap<&str, &str> = [("BOIDS_TARGET_FPS", "240"), ("PORT", "5000")].into();
+        let config = Config::from_sources(
+            Some(file),
+            |key| env.get(key).map(|v| v.to_string()),
+            &args(&["--port", "6000", "--mode", "pull"]),
+        )
+        .unwrap();
+        assert_eq!(config.port, 6000, "CLI beats env and file");
+        assert_eq!(config.target_fps, 240.0, "Env beats file");
+        assert_eq!(config.num_boids, 1000, "File beats default");
+        assert_eq!(config.boundary, BoundaryMode::Bounce);
+        assert_eq!(config.mode, EngineMode::Pull);
+        assert_eq!(config.device, 0, "Default when unset everywhere");
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let no_env = |_: &str| None;
+        assert!(Config::from_sources(Some("num_boids = 0"), no_env, &[]).is_err());
+        assert!(Config::from_sources(Some("unknown_knob = 1"), no_env, &[]).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--fps", "fast"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--boundary", "sideways"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--mode", "manual"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--species", "0"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--species", "9"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--cuda-workers", "0"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--simulate-rate-limit", "-1"])).is_err());
+        assert!(Config::from_sources(None, no_env, &args(&["--simulate-burst", "0"])).is_err());
+        assert_eq!(Config::from_sources(None, no_env, &[]).unwrap(), Config::default());
+    }
+}
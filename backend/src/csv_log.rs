@@ -0,0 +1,242 @@
+// Optional CSV log of aggregate server metrics for offline analysis
+// Enabled by setting METRICS_CSV_PATH; one row is appended per interval
+use crate::metrics::ServerMetrics;
+use crate::physics::Accelerator;
+use crate::simulation_engine::SimulationEngine;
+use crate::telemetry::FpsMeter;
+use anyhow::Result;
+use crate::cuda::Device;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+pub const CSV_HEADER: &str =
+    "timestamp_ms,fps,avg_frame_ms,p99_frame_ms,num_boids,accelerator,gpu_util_pct,connections";
+
+const DEFAULT_INTERVAL_SECS: u64 = 1;
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvLogConfig {
+    pub path: PathBuf,
+    pub interval: Duration,
+    /// The file is rotated to `<path>.1` once the next row would exceed this size
+    pub max_bytes: u64,
+}
+
+impl CsvLogConfig {
+    /// Read METRICS_CSV_PATH, METRICS_CSV_INTERVAL_SECS and METRICS_CSV_MAX_BYTES.
+    /// Returns `None` (logging disabled) when no path is set.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("METRICS_CSV_PATH").ok().filter(|p| !p.is_empty())?;
+        let interval = std::env::var("METRICS_CSV_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&secs| secs > 0)
+            .unwrap_or(DEFAULT_INTERVAL_SECS);
+        let max_bytes = std::env::var("METRICS_CSV_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&bytes| bytes > 0)
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        Some(Self {
+            path: PathBuf::from(path),
+            interval: Duration::from_secs(interval),
+            max_bytes,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsRow {
+    pub timestamp_ms: u64,
+    pub fps: f32,
+    pub avg_frame_ms: f32,
+    pub p99_frame_ms: f32,
+    pub num_boids: usize,
+    pub accelerator: &'static str,
+    pub gpu_util_pct: Option<u32>,
+    pub connections: usize,
+}
+
+impl MetricsRow {
+    /// One CSV line, matching `CSV_HEADER`; unknown GPU utilization is left empty
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{:.1},{:.3},{:.3},{},{},{},{}\n",
+            self.timestamp_ms,
+            self.fps,
+            self.avg_frame_ms,
+            self.p99_frame_ms,
+            self.num_boids,
+            self.accelerator,
+            self.gpu_util_pct.map(|u| u.to_string()).unwrap_or_default(),
+            self.connections,
+        )
+    }
+}
+
+pub struct CsvLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    size: u64,
+}
+
+impl CsvLogger {
+    /// Open `path` for appending, writing the header if the file is new or empty
+    pub fn open(path: &Path, max_bytes: u64) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open metrics CSV {:?}: {:?}", path, e))?;
+        let size = file
+            .metadata()
+            .map_err(|e| anyhow::anyhow!("Failed to stat metrics CSV {:?}: {:?}", path, e))?
+            .len();
+        let mut logger = Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            file,
+            size,
+        };
+        if logger.size == 0 {
+            logger.write_line(&format!("{}\n", CSV_HEADER))?;
+        }
+        Ok(logger)
+    }
+
+    pub fn append(&mut self, row: &MetricsRow) -> Result<()> {
+        let line = row.to_csv();
+        if self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.write_line(&line)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        // Flushed per row so the log survives a crash
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.flush())
+            .map_err(|e| anyhow::anyhow!("Failed to write metrics CSV {:?}: {:?}", self.path, e))?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Move the current file to `<path>.1` (replacing any older one) and start afresh
+    fn rotate(&mut self) -> Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&self.path, &rotated)
+            .map_err(|e| anyhow::anyhow!("Failed to rotate metrics CSV {:?}: {:?}", self.path, e))?;
+        *self = Self::open(&self.path, self.max_bytes)?;
+        Ok(())
+    }
+}
+
+/// Append a row every `config.interval` on a background thread; GPU utilization
+/// comes from the stats cache, reused for up to `gpu_stats_interval`
+pub fn spawn(
+    config: CsvLogConfig,
+    engine: Arc<SimulationEngine>,
+    metrics: Arc<ServerMetrics>,
+    device: Option<Device>,
+    gpu_stats_interval: Duration,
+) -> Result<()> {
+    let mut logger = CsvLogger::open(&config.path, config.max_bytes)?;
+    info!(
+        "Logging metrics to {:?} every {:?} (rotating at {} bytes)",
+        config.path, config.interval, config.max_bytes
+    );
+
+    std::thread::spawn(move || {
+        let mut fps_meter = FpsMeter::new(engine.get_frame_count());
+        loop {
+            std::thread::sleep(config.interval);
+
+            let stats = engine.frame_stats();
+            let fps = fps_meter.sample(stats.frame_count);
+
+            let row = MetricsRow {
+                timestamp_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                fps,
+                avg_frame_ms: stats.avg_frame_ms,
+                p99_frame_ms: stats.p99_frame_ms,
+                num_boids: engine.num_boids(),
+                accelerator: Accelerator::from_used_cuda(stats.used_cuda).as_str(),
+                gpu_util_pct: crate::gpu_stats::get_gpu_stats(device.as_ref(), gpu_stats_interval)
+                    .ok()
+                    .and_then(|s| s.gpu_utilization),
+                connections: metrics.active_connections(),
+            };
+            if let Err(e) = logger.append(&row) {
+                warn!("Metrics CSV logging failed: {:?}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(connections: usize) -> MetricsRow {
+        MetricsRow {
+            timestamp_ms: 1_700_000_000_000,
+            fps: 499.96,
+            avg_frame_ms: 1.25,
+            p99_frame_ms: 3.5,
+            num_boids: 10_000,
+            accelerator: "cuda",
+            gpu_util_pct: None,
+            connections,
+        }
+    }
+
+    #[test]
+    fn test_row_matches_header() {
+        let line = row(2).to_csv();
+        assert_eq!(line, "1700000000000,500.0,1.250,3.500,10000,cuda,,2\n");
+        assert_eq!(
+            line.trim_end().split(',').count(),
+            CSV_HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    fn test_logger_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("csv-log-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metrics.csv");
+        let _ = std::fs::remove_file(&path);
+
+        let line_len = row(0).to_csv().len() as u64;
+        let max_bytes = CSV_HEADER.len() as u64 + 1 + 2 * line_len;
+        let mut logger = CsvLogger::open(&path, max_bytes).unwrap();
+        for i in 0..3 {
+            logger.append(&row(i)).unwrap();
+        }
+
+        let rotated = std::fs::read_to_string(dir.join("metrics.csv.1")).unwrap();
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(rotated.lines().count(), 3, "Header plus two rows before rotating");
+        assert!(current.starts_with(CSV_HEADER), "Rotated file starts with a header");
+        assert!(current.trim_end().ends_with(",2"));
+
+        // Reopening an existing file appends without a second header
+        drop(logger);
+        CsvLogger::open(&path, max_bytes).unwrap().append(&row(3)).unwrap();
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(current.matches("timestamp_ms").count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,22 +1,71 @@
 // CUDA context and device management - Thread-safe version
-use anyhow::{Context as AnyhowContext, Result};
+use anyhow::Result;
+#[cfg(not(feature = "no-cuda"))]
+use anyhow::Context as AnyhowContext;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::device::DeviceAttribute;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::prelude::*;
+use serde::Serialize;
+#[cfg(not(feature = "no-cuda"))]
+use std::cell::RefCell;
 use std::sync::Arc;
 use std::sync::Mutex;
+#[cfg(not(feature = "no-cuda"))]
 use tracing::warn;
 
+#[cfg(all(feature = "no-cuda", feature = "cuda-kernel"))]
+compile_error!("the `cuda-kernel` feature needs CUDA; it can't be combined with `no-cuda`");
+#[cfg(not(any(feature = "cuda", feature = "no-cuda")))]
+compile_error!("enable the `cuda` feature (on by default) or build with `no-cuda`");
+
+#[cfg(not(feature = "no-cuda"))]
+pub use rustacuda::device::Device;
+
+/// Stand-in for the GPU in `no-cuda` builds. It has no values, so
+/// `CudaContext::device` is always `None` there.
+#[cfg(feature = "no-cuda")]
+#[derive(Debug, Clone, Copy)]
+pub enum Device {}
+
+#[cfg(not(feature = "no-cuda"))]
+thread_local! {
+    // Context created by `ensure_context`; destroyed when the thread exits
+    static THREAD_CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// A context made current on its thread by `CudaContext::push_context`, until dropped
+pub struct ThreadContext {
+    #[cfg(not(feature = "no-cuda"))]
+    _context: Context,
+}
+
 pub struct CudaContext {
-    device: Arc<Device>,
+    // `None` in `no-cuda` builds, which never touch the driver
+    device: Option<Arc<Device>>,
     // Store context handle for thread-local access
     _context_handle: Arc<Mutex<()>>,
 }
 
 impl CudaContext {
     pub fn new() -> Result<Self> {
+        Self::with_device(0)
+    }
+
+    /// No device: every simulation runs on the host
+    #[cfg(feature = "no-cuda")]
+    pub fn with_device(_index: u32) -> Result<Self> {
+        tracing::info!("Built with no-cuda; every simulation runs on the CPU");
+        Ok(Self { device: None, _context_handle: Arc::new(Mutex::new(())) })
+    }
+
+    /// Context for the device at `index`
+    #[cfg(not(feature = "no-cuda"))]
+    pub fn with_device(index: u32) -> Result<Self> {
         // CUDA should already be initialized by caller
         // Get device (requires CUDA to be initialized)
-        let device = Device::get_device(0)
-            .map_err(|e| anyhow::anyhow!("Failed to get CUDA device (is CUDA initialized?): {:?}", e))?;
+        let device = Device::get_device(index)
+            .map_err(|e| anyhow::anyhow!("Failed to get CUDA device {} (is CUDA initialized?): {:?}", index, e))?;
         
         let device_name = device.name()
             .map_err(|e| anyhow::anyhow!("Failed to get device name: {:?}", e))?;
@@ -24,48 +73,125 @@ impl CudaContext {
         tracing::info!("CUDA Device: {}", device_name);
         
         Ok(Self {
-            device: Arc::new(device),
+            device: Some(Arc::new(device)),
             _context_handle: Arc::new(Mutex::new(())),
         })
     }
 
-    pub fn device(&self) -> &Arc<Device> {
-        &self.device
+    /// The GPU, or `None` in a `no-cuda` build
+    pub fn device(&self) -> Option<&Device> {
+        self.device.as_deref()
     }
 
-    /// Ensure CUDA context is active in current thread
-    /// This must be called before any CUDA operations in a new thread
+    /// Nothing to push without a device
+    #[cfg(feature = "no-cuda")]
+    pub fn push_context(&self) -> Result<Option<ThreadContext>> {
+        Ok(None)
+    }
+
+    /// Create a context and make it current on the calling thread until the returned
+    /// handle is dropped; `None` without a device (a `no-cuda` build)
+    #[cfg(not(feature = "no-cuda"))]
+    pub fn push_context(&self) -> Result<Option<ThreadContext>> {
+        self.device
+            .as_deref()
+            .map(|device| {
+                Context::create_and_push(ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO, *device)
+                    .map(|context| ThreadContext { _context: context })
+                    .map_err(|e| anyhow::anyhow!("Failed to create CUDA context: {:?}", e))
+            })
+            .transpose()
+    }
+
+    /// Nothing to set up without a device
+    #[cfg(feature = "no-cuda")]
     pub fn ensure_context(&self) -> Result<()> {
-        // Try to initialize CUDA first if not already initialized
-        // This is safe to call multiple times
-        if let Err(_) = rustacuda::init(CudaFlags::empty()) {
-            // CUDA might already be initialized, which is fine
-        }
-        
-        // In rustacuda, contexts are thread-local
-        // Try to create context if it doesn't exist
-        // If context already exists, this will return an error, which we can ignore
-        // Try to create context - if it already exists, the error is usually safe to ignore
-        // In rustacuda, creating a context when one exists returns an error, but operations
-        // can still work if a context is already active
-        match Context::create_and_push(
-            ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO,
-            *self.device
-        ) {
-            Ok(_) => Ok(()),
+        Ok(())
+    }
+
+    /// Make sure the calling thread has a current CUDA context, creating one (owned by
+    /// the thread) the first time. Must be called before CUDA operations in a new thread.
+    #[cfg(not(feature = "no-cuda"))]
+    pub fn ensure_context(&self) -> Result<()> {
+        let Some(device) = self.device.as_deref() else {
+            return Ok(());
+        };
+        THREAD_CONTEXT.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            // Already set up here, or another context was pushed on this thread
+            if slot.is_some() || CurrentContext::get_device().is_ok() {
+                return Ok(());
+            }
+            // cuInit is idempotent, so this only fails when the driver is unusable
+            rustacuda::init(CudaFlags::empty())
+                .map_err(|e| anyhow::anyhow!("Failed to initialize CUDA: {:?}", e))?;
+            let context = Context::create_and_push(
+                ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO,
+                *device,
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to create CUDA context: {:?}", e))?;
+            *slot = Some(context);
+            Ok(())
+        })
+    }
+}
+
+/// Static device properties for /api/gpu-info.
+/// Each attribute is `None` (JSON `null`) when the driver can't report it.
+#[derive(Serialize, Clone, Debug)]
+pub struct GpuInfo {
+    pub gpu: String,
+    pub status: &'static str,
+    pub cuda_context: bool,
+    pub compute_capability_major: Option<i32>,
+    pub compute_capability_minor: Option<i32>,
+    pub multiprocessor_count: Option<i32>,
+    pub total_memory_bytes: Option<usize>,
+    pub max_threads_per_block: Option<i32>,
+    pub warp_size: Option<i32>,
+}
+
+impl GpuInfo {
+    #[cfg(feature = "no-cuda")]
+    pub fn query(device: &Device) -> Result<Self> {
+        match *device {}
+    }
+
+    /// Query `device`; only the name is required
+    #[cfg(not(feature = "no-cuda"))]
+    pub fn query(device: &Device) -> Result<Self> {
+        let gpu = device.name()
+            .map_err(|e| anyhow::anyhow!("Failed to get device name: {:?}", e))?;
+        let attribute = |attribute: DeviceAttribute| match device.get_attribute(attribute) {
+            Ok(value) => Some(value),
             Err(e) => {
-                // If context creation fails, it might be because one already exists
-                // or because CUDA isn't properly initialized. Try to proceed anyway
-                // as the context might already be active from a previous call
-                // Log a warning but don't fail - let the actual CUDA operation fail if needed
-                warn!("Context creation returned error (may already exist): {:?}", e);
-                Ok(())
+                warn!("Failed to query {:?}: {:?}", attribute, e);
+                None
             }
-        }
+        };
+
+        Ok(Self {
+            gpu,
+            status: "ready",
+            cuda_context: true,
+            compute_capability_major: attribute(DeviceAttribute::ComputeCapabilityMajor),
+            compute_capability_minor: attribute(DeviceAttribute::ComputeCapabilityMinor),
+            multiprocessor_count: attribute(DeviceAttribute::MultiprocessorCount),
+            total_memory_bytes: device.total_memory().ok(),
+            max_threads_per_block: attribute(DeviceAttribute::MaxThreadsPerBlock),
+            warp_size: attribute(DeviceAttribute::WarpSize),
+        })
     }
 }
 
+// Helper function to create context in a thread; a no-op in `no-cuda` builds
+#[cfg(feature = "no-cuda")]
+pub fn init_cuda_in_thread() -> Result<()> {
+    Ok(())
+}
+
 // Helper function to create context in a thread
+#[cfg(not(feature = "no-cuda"))]
 pub fn init_cuda_in_thread() -> Result<()> {
     rustacuda::init(CudaFlags::empty())
         .context("Failed to initialize CUDA")?;
@@ -85,6 +211,7 @@ pub fn init_cuda_in_thread() -> Result<()> {
 mod tests {
     use super::*;
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_cuda_context_initialization() {
         init_cuda_in_thread().expect("Failed to init CUDA");
@@ -95,4 +222,41 @@ mod tests {
         let context = CudaContext::new();
         assert!(context.is_ok(), "CUDA context should initialize");
     }
+
+    #[test]
+    fn test_gpu_info_failed_attributes_are_null() {
+        let info = GpuInfo {
+            gpu: "Test GPU".to_string(),
+            status: "ready",
+            cuda_context: true,
+            compute_capability_major: Some(8),
+            compute_capability_minor: Some(6),
+            multiprocessor_count: None,
+            total_memory_bytes: Some(1 << 30),
+            max_threads_per_block: Some(1024),
+            warp_size: None,
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["compute_capability_major"], 8);
+        assert_eq!(json["total_memory_bytes"], 1 << 30);
+        assert!(json["multiprocessor_count"].is_null());
+        assert!(json["warp_size"].is_null());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_ensure_context_twice_reuses_thread_context() {
+        rustacuda::init(CudaFlags::empty()).expect("Failed to init CUDA");
+        let context = CudaContext::new().unwrap();
+        context.ensure_context().unwrap();
+        assert!(THREAD_CONTEXT.with(|slot| slot.borrow().is_some()));
+        // Second call finds the thread's context instead of creating another
+        context.ensure_context().unwrap();
+
+        let mut buffer = DeviceBuffer::from_slice(&[1.0f32, 2.0, 3.0]).unwrap();
+        buffer.copy_from(&[4.0f32, 5.0, 6.0][..]).unwrap();
+        let mut host = [0.0f32; 3];
+        buffer.copy_to(&mut host[..]).unwrap();
+        assert_eq!(host, [4.0, 5.0, 6.0]);
+    }
 }
@@ -0,0 +1,131 @@
+// Long-lived worker threads that each own one CUDA context for their lifetime,
+// so request handlers don't create (and leak) a context per call
+use crate::cuda::CudaContext;
+use anyhow::Result;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Workers when the config doesn't say
+pub const DEFAULT_CUDA_WORKERS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+pub struct CudaPool {
+    jobs: mpsc::Sender<Job>,
+    workers: usize,
+}
+
+impl CudaPool {
+    /// `workers` threads, each making `context` current once before taking jobs
+    pub fn with_context(context: Arc<CudaContext>, workers: usize) -> Result<Self> {
+        Self::new(workers, move || context.ensure_context())
+    }
+
+    /// `workers` threads that run `init` once at startup. A failed `init` is logged
+    /// and the worker still takes jobs, whose CUDA calls then fail (or fall back to
+    /// the CPU) the same way they would on any thread without a context.
+    pub fn new(workers: usize, init: impl Fn() -> Result<()> + Send + Sync + 'static) -> Result<Self> {
+        if workers == 0 {
+            anyhow::bail!("CUDA pool needs at least one worker");
+        }
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let init = Arc::new(init);
+        for index in 0..workers {
+            let queue = Arc::clone(&queue);
+            let init = Arc::clone(&init);
+            std::thread::Builder::new()
+                .name(format!("cuda-worker-{}", index))
+                .spawn(move || {
+                    if let Err(e) = init() {
+                        warn!("CUDA worker {} has no context: {:?}", index, e);
+                    }
+                    loop {
+                        // Holding the lock only while waiting; the job runs unlocked
+                        let job = queue.lock().unwrap().recv();
+                        let Ok(job) = job else { break };
+                        // A panicking job drops its result sender; the worker carries on
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                })
+                .map_err(|e| anyhow::anyhow!("Failed to spawn CUDA worker: {:?}", e))?;
+        }
+        Ok(Self { jobs, workers })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Run `work` on a worker thread and wait for its result. Jobs queue while
+    /// every worker is busy; workers exit once the pool is dropped.
+    pub async fn run<T, F>(&self, work: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.spawn(move || {
+            let _ = tx.send(work());
+        })?;
+        rx.await
+            .map_err(|_| anyhow::anyhow!("CUDA job panicked"))
+    }
+
+    /// Queue `work` on a worker thread without waiting for it, for long-running jobs
+    /// that report back on their own, such as a stream feeding a channel
+    pub fn spawn(&self, work: impl FnOnce() + Send + 'static) -> Result<()> {
+        self.jobs
+            .send(Box::new(work))
+            .map_err(|_| anyhow::anyhow!("CUDA worker pool has shut down"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_pool_reuses_its_workers() {
+        let inits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&inits);
+        let pool = CudaPool::new(2, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .unwrap();
+
+        let mut threads = HashSet::new();
+        for i in 0..20 {
+            let (value, name) = pool
+                .run(move || (i * 2, std::thread::current().name().unwrap().to_string()))
+                .await
+                .unwrap();
+            assert_eq!(value, i * 2);
+            threads.insert(name);
+        }
+        assert!(threads.len() <= 2 && threads.iter().all(|n| n.starts_with("cuda-worker-")));
+
+        // Two jobs that wait for each other can only finish on both workers at once
+        let barrier = Arc::new(Barrier::new(2));
+        let (a, b) = (Arc::clone(&barrier), barrier);
+        let (a, b) = tokio::join!(pool.run(move || a.wait()), pool.run(move || b.wait()));
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(inits.load(Ordering::SeqCst), 2, "each worker initializes once");
+
+        assert!(pool.run(|| -> i32 { panic!("job failed") }).await.is_err());
+        assert_eq!(pool.run(|| 7).await.unwrap(), 7, "workers survive a panicking job");
+        assert!(CudaPool::new(0, || Ok(())).is_err());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        pool.spawn(move || tx.send(std::thread::current().name().map(str::to_string)).unwrap())
+            .unwrap();
+        let name = rx.recv().unwrap().unwrap();
+        assert!(name.starts_with("cuda-worker-"), "spawned jobs run on the pool's workers");
+    }
+}
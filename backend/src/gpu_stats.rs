@@ -1,7 +1,9 @@
 // GPU statistics collection using NVML (NVIDIA Management Library)
 // Falls back to basic CUDA queries if NVML is unavailable
-use anyhow::{Context, Result};
-use rustacuda::prelude::*;
+use crate::cuda::Device;
+use anyhow::Result;
+#[cfg(not(feature = "no-cuda"))]
+use anyhow::Context;
 use serde::Serialize;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
@@ -20,12 +22,12 @@ pub struct GpuStats {
 struct StatsCache {
     stats: Option<GpuStats>,
     last_update: Instant,
-    update_interval: Duration,
 }
 
 static STATS_CACHE: Mutex<Option<StatsCache>> = Mutex::new(None);
 
-const CACHE_DURATION_MS: u64 = 500; // Cache for 500ms
+/// How long a reading is reused when the config doesn't say
+pub const DEFAULT_CACHE_INTERVAL_MS: u64 = 500;
 
 #[cfg(feature = "gpu-stats")]
 /// Initialize NVML if available
@@ -129,7 +131,13 @@ fn get_gpu_stats_nvml() -> Result<GpuStats> {
     }
 }
 
+#[cfg(feature = "no-cuda")]
+fn get_gpu_stats_cuda(device: &Device) -> Result<GpuStats> {
+    match *device {}
+}
+
 /// Get basic GPU stats using CUDA runtime (fallback)
+#[cfg(not(feature = "no-cuda"))]
 fn get_gpu_stats_cuda(device: &Device) -> Result<GpuStats> {
     // CUDA runtime doesn't provide utilization directly
     // We can only get memory info
@@ -153,13 +161,23 @@ fn get_gpu_stats_cuda(device: &Device) -> Result<GpuStats> {
     })
 }
 
-/// Get GPU stats with caching
-pub fn get_gpu_stats(device: Option<&Device>) -> Result<GpuStats> {
-    let mut cache_guard = STATS_CACHE.lock().unwrap();
+/// Get GPU stats, reusing a reading younger than `max_age`; `Duration::ZERO`
+/// always queries the device (and refreshes the cache for everyone else)
+pub fn get_gpu_stats(device: Option<&Device>, max_age: Duration) -> Result<GpuStats> {
+    cached_gpu_stats(&STATS_CACHE, device, max_age)
+}
+
+// `get_gpu_stats` against a given cache, so tests don't share the global one
+fn cached_gpu_stats(
+    cache: &Mutex<Option<StatsCache>>,
+    device: Option<&Device>,
+    max_age: Duration,
+) -> Result<GpuStats> {
+    let mut cache_guard = cache.lock().unwrap();
     
     // Check cache
     if let Some(ref cache) = *cache_guard {
-        if cache.last_update.elapsed() < cache.update_interval {
+        if cache.last_update.elapsed() < max_age {
             if let Some(ref stats) = cache.stats {
                 return Ok(stats.clone());
             }
@@ -258,9 +276,28 @@ pub fn get_gpu_stats(device: Option<&Device>) -> Result<GpuStats> {
     *cache_guard = Some(StatsCache {
         stats: Some(stats.clone()),
         last_update: Instant::now(),
-        update_interval: Duration::from_millis(CACHE_DURATION_MS),
     });
 
     Ok(stats)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_reuses_recent_reading() {
+        // Its own cache, so other tests polling the GPU can't refresh it in between
+        let cache = Mutex::new(None);
+        let interval = Duration::from_millis(DEFAULT_CACHE_INTERVAL_MS);
+        let first = cached_gpu_stats(&cache, None, interval).unwrap();
+        let second = cached_gpu_stats(&cache, None, interval).unwrap();
+        assert_eq!(first.timestamp, second.timestamp);
+
+        std::thread::sleep(Duration::from_millis(5));
+        let fresh = cached_gpu_stats(&cache, None, Duration::ZERO).unwrap();
+        assert!(fresh.timestamp > first.timestamp, "a fresh reading skips the cache");
+        assert_eq!(cached_gpu_stats(&cache, None, interval).unwrap().timestamp, fresh.timestamp);
+    }
+}
+
@@ -0,0 +1,177 @@
+// Independent boids simulations created on demand and addressed by id,
+// separate from the shared flock the engine streams on `/ws`
+use crate::broadcast::{BroadcastState, SubscriberGuard, Subscribers};
+use crate::physics::BoidsSimulation;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast as tokio_broadcast;
+use uuid::Uuid;
+
+/// Instances alive at once; each holds its own device buffers
+pub const MAX_INSTANCES: usize = 8;
+/// Boids per instance
+pub const MAX_INSTANCE_BOIDS: usize = 50_000;
+pub const DEFAULT_INSTANCE_BOIDS: usize = 1000;
+// Frames buffered per instance channel before slow subscribers lag
+const CHANNEL_CAPACITY: usize = 16;
+
+pub struct Instance {
+    pub simulation: Arc<Mutex<BoidsSimulation>>,
+    broadcast_tx: tokio_broadcast::Sender<BroadcastState>,
+    // `?forces=1` and `?withspeed=1` subscribers; published states only carry
+    // forces and speeds while there are any
+    force_subscribers: Arc<Subscribers>,
+    speed_subscribers: Arc<Subscribers>,
+}
+
+impl Instance {
+    pub fn subscribe(&self) -> tokio_broadcast::Receiver<BroadcastState> {
+        self.broadcast_tx.subscribe()
+    }
+
+    /// Include force magnitudes in published states until the guard is dropped
+    pub fn subscribe_forces(&self) -> SubscriberGuard {
+        self.force_subscribers.subscribe()
+    }
+
+    /// Include speed bytes in published states until the guard is dropped
+    pub fn subscribe_speeds(&self) -> SubscriberGuard {
+        self.speed_subscribers.subscribe()
+    }
+
+    /// Send the current state to `/ws?sim=<id>` subscribers, if there are any
+    pub fn publish(&self, sim: &mut BoidsSimulation) -> Result<()> {
+        if self.broadcast_tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        let _ = self.broadcast_tx.send(BroadcastState::encode_simulation(
+            sim,
+            self.force_subscribers.any(),
+            self.speed_subscribers.any(),
+        )?);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum CreateError {
+    /// `MAX_INSTANCES` are already alive
+    LimitReached,
+    InvalidSize(usize),
+}
+
+impl fmt::Display for CreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreateError::LimitReached => {
+                write!(f, "instance limit reached ({} max); delete one first", MAX_INSTANCES)
+            }
+            CreateError::InvalidSize(n) => {
+                write!(f, "num must be 1..={}, got {}", MAX_INSTANCE_BOIDS, n)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InstanceRegistry {
+    instances: RwLock<HashMap<Uuid, Arc<Instance>>>,
+}
+
+impl InstanceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve room for a `num_boids` instance, checked before allocating it
+    pub fn check_create(&self, num_boids: usize) -> Result<(), CreateError> {
+        if num_boids == 0 || num_boids > MAX_INSTANCE_BOIDS {
+            return Err(CreateError::InvalidSize(num_boids));
+        }
+        if self.len() >= MAX_INSTANCES {
+            return Err(CreateError::LimitReached);
+        }
+        Ok(())
+    }
+
+    /// Register `simulation` under a fresh id. Re-checks the limit under the write
+    /// lock, since concurrent creates may have filled it since `check_create`.
+    pub fn insert(&self, simulation: BoidsSimulation) -> Result<(Uuid, Arc<Instance>), CreateError> {
+        let mut instances = self.instances.write().unwrap();
+        if instances.len() >= MAX_INSTANCES {
+            return Err(CreateError::LimitReached);
+        }
+        let (broadcast_tx, _) = tokio_broadcast::channel(CHANNEL_CAPACITY);
+        let instance = Arc::new(Instance {
+            simulation: Arc::new(Mutex::new(simulation)),
+            broadcast_tx,
+            force_subscribers: Arc::new(Subscribers::default()),
+            speed_subscribers: Arc::new(Subscribers::default()),
+        });
+        let id = Uuid::new_v4();
+        instances.insert(id, Arc::clone(&instance));
+        Ok((id, instance))
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Arc<Instance>> {
+        self.instances.read().unwrap().get(id).cloned()
+    }
+
+    /// Drop the instance; its subscribers see the channel close once in-flight work ends
+    pub fn remove(&self, id: &Uuid) -> bool {
+        self.instances.write().unwrap().remove(id).is_some()
+    }
+
+    pub fn ids(&self) -> Vec<Uuid> {
+        self.instances.read().unwrap().keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.read().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_enforces_limits() {
+        let registry = InstanceRegistry::new();
+        assert!(matches!(registry.check_create(0), Err(CreateError::InvalidSize(0))));
+        assert!(registry.check_create(MAX_INSTANCE_BOIDS + 1).is_err());
+
+        let mut ids = Vec::new();
+        for _ in 0..MAX_INSTANCES {
+            registry.check_create(10).unwrap();
+            let (id, _) = registry.insert(BoidsSimulation::new_host(10).unwrap()).unwrap();
+            ids.push(id);
+        }
+        assert!(matches!(registry.check_create(10), Err(CreateError::LimitReached)));
+        assert!(registry.insert(BoidsSimulation::new_host(10).unwrap()).is_err());
+
+        assert!(registry.get(&ids[0]).is_some());
+        assert!(registry.remove(&ids[0]));
+        assert!(!registry.remove(&ids[0]));
+        assert!(registry.get(&ids[0]).is_none());
+        registry.check_create(10).unwrap();
+    }
+
+    #[test]
+    fn test_publish_reaches_subscribers() {
+        let registry = InstanceRegistry::new();
+        let (_, instance) = registry.insert(BoidsSimulation::new_host(5).unwrap()).unwrap();
+        let mut rx = instance.subscribe();
+        let mut sim = instance.simulation.lock().unwrap();
+        instance.publish(&mut sim).unwrap();
+        let state = rx.try_recv().unwrap();
+        assert_eq!(state.num_boids, 5);
+        assert!(state.forces.is_empty(), "Forces are only read for clients that asked");
+
+        let _forces = instance.subscribe_forces();
+        instance.publish(&mut sim).unwrap();
+        assert_eq!(rx.try_recv().unwrap().forces.len(), 5 * 4);
+    }
+}
@@ -1,20 +1,80 @@
+// Integer hash shared with `hash_u32` in boids.rs so both paths jitter identically
+__device__ unsigned int hashU32(unsigned int x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+__device__ float unitNoise(unsigned int h) {
+    return (float)(h >> 8) / 16777216.0f * 2.0f - 1.0f;
+}
+
+// Matches MAX_PUSH in obstacles.rs
+#define OBSTACLE_MAX_PUSH 3.0f
+// Matches OBSTACLE_WEIGHT in boids.rs, as a multiple of maxForce. Above
+// sepWeight + alignWeight + cohWeight so boids inside an obstacle always head out
+#define OBSTACLE_WEIGHT 4.0f
+
+// Match BOUNCE_MIN_SPEED and OPEN_RESPAWN_SALT in boids.rs
+#define BOUNCE_MIN_SPEED 1e-3f
+#define OPEN_RESPAWN_SALT 0x9e3779b9u
+
+// Match predators.rs
+#define PREDATOR_SPECIES 255
+#define FLEE_RADIUS 0.15f
+#define FLEE_WEIGHT 3.0f
+#define PURSUIT_WEIGHT 1.5f
+
+// Signed distance to capsule `o` (x0, y0, x1, y1, radius) with the outward normal
+__device__ float capsuleDistance(const float* o, float px, float py, float* nx, float* ny) {
+    float sx = o[2] - o[0];
+    float sy = o[3] - o[1];
+    float len2 = sx*sx + sy*sy;
+    float s = len2 > 0.0f ? ((px - o[0]) * sx + (py - o[1]) * sy) / len2 : 0.0f;
+    s = fminf(fmaxf(s, 0.0f), 1.0f);
+    float dx = px - (o[0] + s * sx);
+    float dy = py - (o[1] + s * sy);
+    float d = sqrtf(dx*dx + dy*dy);
+    if (d > 0.0f) {
+        *nx = dx / d; *ny = dy / d;
+    } else {
+        *nx = 1.0f; *ny = 0.0f;
+    }
+    return d - o[4];
+}
+
 extern "C" __global__ void boids_step(
     int n,
     float dt,
     float sepRadius,
     float alignRadius,
     float cohRadius,
-    float sepWeight,
+    float sepWeight,    // steering weights, as multiples of maxForce
     float alignWeight,
     float cohWeight,
-    float maxSpeed,
     const unsigned char* species,
+    const float* mass,
+    const float* maxSpeeds,  // per-boid speed cap
     float* x,
     float* y,
     float* vx,
     float* vy,
-    int width,
-    int height
+    float width,   // world_size; positions live in [0, width] x [0, height]
+    float height,
+    float jitter,
+    unsigned int jitterSeed,
+    unsigned int stepIndex,
+    int boundaryMode,  // 0 = wrap, 1 = bounce, 2 = open
+    float* forceMag,
+    const float* obstacles,  // numObstacles capsules, 5 floats each
+    int numObstacles,
+    float avoidMargin,
+    const float* attractors,  // numAttractors points, 4 floats each (x, y, strength, radius)
+    int numAttractors,
+    float maxForce
 ) {
     int i = blockIdx.x * blockDim.x + threadIdx.x;
     if (i >= n) return;
@@ -23,23 +83,38 @@ extern "C" __global__ void boids_step(
     float yi = y[i];
     float vxi = vx[i];
     float vyi = vy[i];
+    float maxSpeed = maxSpeeds[i];
     unsigned char si = species[i];
 
     float sepX = 0.0f, sepY = 0.0f; int sepC = 0;
     float aliX = 0.0f, aliY = 0.0f; int aliC = 0;
     float cohX = 0.0f, cohY = 0.0f; int cohC = 0;
-    float chaseX = 0.0f, chaseY = 0.0f; int chaseC = 0;
     float fleeX = 0.0f, fleeY = 0.0f; int fleeC = 0;
-
-    const float predatorRadius = cohRadius * 1.5f;
-    const float preyFearRadius = sepRadius * 2.0f;
+    int prey = -1; float preyD2 = 0.0f;
+    bool predator = si == PREDATOR_SPECIES;
 
     for (int j = 0; j < n; ++j) {
         if (j == i) continue;
         float dx = x[j] - xi;
         float dy = y[j] - yi;
         float d2 = dx*dx + dy*dy;
-        unsigned char sj = species[j];
+        bool predatorJ = species[j] == PREDATOR_SPECIES;
+
+        // Predators hunt the flock rather than flocking with it
+        if (predator && !predatorJ) {
+            if (prey < 0 || d2 < preyD2) { prey = j; preyD2 = d2; }
+            continue;
+        }
+        if (!predator && predatorJ) {
+            float d = sqrtf(d2);
+            if (d < FLEE_RADIUS && d > 0.0f) {
+                float closeness = 1.0f - d / FLEE_RADIUS;
+                fleeX -= dx / d * closeness;
+                fleeY -= dy / d * closeness;
+                fleeC++;
+            }
+            continue;
+        }
 
         if (d2 < sepRadius*sepRadius) {
             float d = sqrtf(d2) + 1e-6f;
@@ -57,48 +132,53 @@ extern "C" __global__ void boids_step(
             cohY += y[j];
             cohC++;
         }
-
-        if (si == 2 && sj == 1 && d2 < predatorRadius * predatorRadius) {
-            chaseX += -dx;
-            chaseY += -dy;
-            chaseC++;
-        }
-        if (si == 1 && sj == 2 && d2 < preyFearRadius * preyFearRadius) {
-            float d = sqrtf(d2) + 1e-6f;
-            fleeX -= dx / d;
-            fleeY -= dy / d;
-            fleeC++;
-        }
     }
 
     float ax = 0.0f;
     float ay = 0.0f;
 
+    // Each rule steers along its unit direction, scaled by maxForce as in boids.rs
     if (sepC > 0) {
-        ax += (sepX / (float)sepC) * sepWeight;
-        ay += (sepY / (float)sepC) * sepWeight;
+        float m = sqrtf(sepX*sepX + sepY*sepY);
+        if (m > 0.0f) {
+            ax += sepX / m * maxForce * sepWeight;
+            ay += sepY / m * maxForce * sepWeight;
+        }
     }
     if (aliC > 0) {
         float tx = (aliX / (float)aliC) - vxi;
         float ty = (aliY / (float)aliC) - vyi;
-        ax += tx * alignWeight;
-        ay += ty * alignWeight;
+        float m = sqrtf(tx*tx + ty*ty);
+        if (m > 0.0f) {
+            ax += tx / m * maxForce * alignWeight;
+            ay += ty / m * maxForce * alignWeight;
+        }
     }
-    if (cohC > 0) {
+    float fleeMag = sqrtf(fleeX*fleeX + fleeY*fleeY);
+    // Fleeing prey drop cohesion so the flock actually breaks apart
+    if (cohC > 0 && !(fleeC > 0 && fleeMag > 0.0f)) {
         float tx = (cohX / (float)cohC) - xi;
         float ty = (cohY / (float)cohC) - yi;
-        ax += tx * cohWeight;
-        ay += ty * cohWeight;
+        float m = sqrtf(tx*tx + ty*ty);
+        if (m > 0.0f) {
+            ax += tx / m * maxForce * cohWeight;
+            ay += ty / m * maxForce * cohWeight;
+        }
     }
-    if (chaseC > 0 && si == 2) {
-        float tx = (chaseX / (float)chaseC);
-        float ty = (chaseY / (float)chaseC);
-        ax += tx * 0.8f;
-        ay += ty * 0.8f;
+    if (fleeC > 0 && fleeMag > 0.0f) {
+        ax += fleeX / fleeMag * maxForce * FLEE_WEIGHT;
+        ay += fleeY / fleeMag * maxForce * FLEE_WEIGHT;
     }
-    if (fleeC > 0 && si == 1) {
-        ax += (fleeX / (float)fleeC) * 2.0f;
-        ay += (fleeY / (float)fleeC) * 2.0f;
+    if (prey >= 0 && preyD2 > 0.0f) {
+        // Seek: desired velocity toward the nearest prey minus current velocity
+        float d = sqrtf(preyD2);
+        float sx = (x[prey] - xi) / d * maxSpeed - vxi;
+        float sy = (y[prey] - yi) / d * maxSpeed - vyi;
+        float sm = sqrtf(sx*sx + sy*sy);
+        if (sm > 0.0f) {
+            ax += sx / sm * maxForce * PURSUIT_WEIGHT;
+            ay += sy / sm * maxForce * PURSUIT_WEIGHT;
+        }
     }
     if (si == 0) {
         float centerX = width * 0.5f;
@@ -107,8 +187,43 @@ extern "C" __global__ void boids_step(
         ay += (centerY - yi) * 0.02f;
     }
 
-    vxi += ax * dt;
-    vyi += ay * dt;
+    // Steer away from nearby obstacles; the push keeps growing inside one
+    for (int o = 0; o < numObstacles; ++o) {
+        float nx, ny;
+        float d = capsuleDistance(obstacles + o * 5, xi, yi, &nx, &ny);
+        if (d < avoidMargin) {
+            float push = fminf((avoidMargin - d) / avoidMargin, OBSTACLE_MAX_PUSH);
+            ax += nx * push * maxForce * OBSTACLE_WEIGHT;
+            ay += ny * push * maxForce * OBSTACLE_WEIGHT;
+        }
+    }
+
+    // Pull toward interactive attractors (away when strength < 0), fading to nothing at their radius
+    for (int a = 0; a < numAttractors; ++a) {
+        const float* at = attractors + a * 4;
+        float dx = at[0] - xi;
+        float dy = at[1] - yi;
+        float d = sqrtf(dx*dx + dy*dy);
+        if (d < at[3] && d > 0.0f) {
+            float pull = at[2] * (1.0f - d / at[3]) * maxForce;
+            ax += dx / d * pull;
+            ay += dy / d * pull;
+        }
+    }
+
+    forceMag[i] = sqrtf(ax*ax + ay*ay);
+
+    // a = F / m so heavier boids respond more sluggishly
+    float invMass = 1.0f / fmaxf(mass[i], 0.01f);
+    vxi += ax * invMass * dt;
+    vyi += ay * invMass * dt;
+
+    if (jitter > 0.0f) {
+        unsigned int h1 = hashU32(jitterSeed ^ hashU32(stepIndex ^ hashU32((unsigned int)i)));
+        unsigned int h2 = hashU32(h1);
+        vxi += unitNoise(h1) * jitter;
+        vyi += unitNoise(h2) * jitter;
+    }
 
     float sp = sqrtf(vxi*vxi + vyi*vyi);
     if (sp > maxSpeed) {
@@ -116,12 +231,150 @@ extern "C" __global__ void boids_step(
         vyi = vyi / sp * maxSpeed;
     }
 
+    float x0 = xi;
+    float y0 = yi;
     xi += vxi * dt;
     yi += vyi * dt;
 
-    // Wrap around boundaries
-    if (xi < 0.0f) xi += width; if (xi >= width) xi -= width;
-    if (yi < 0.0f) yi += height; if (yi >= height) yi -= height;
+    // Boids entering an obstacle this step are pushed back to its surface and bounce;
+    // ones that started inside are left to the avoidance push
+    for (int o = 0; o < numObstacles; ++o) {
+        float nx, ny, nx0, ny0;
+        float d = capsuleDistance(obstacles + o * 5, xi, yi, &nx, &ny);
+        if (d < 0.0f && capsuleDistance(obstacles + o * 5, x0, y0, &nx0, &ny0) >= 0.0f) {
+            xi += nx * (1e-4f - d);
+            yi += ny * (1e-4f - d);
+            float vn = vxi * nx + vyi * ny;
+            if (vn < 0.0f) {
+                vxi -= 2.0f * vn * nx;
+                vyi -= 2.0f * vn * ny;
+            }
+        }
+    }
+
+    if (boundaryMode == 0) {
+        if (xi < 0.0f) xi += width; if (xi >= width) xi -= width;
+        if (yi < 0.0f) yi += height; if (yi >= height) yi -= height;
+    } else if (boundaryMode == 1) {
+        // Minimum inward speed so steering can't pin a boid to the edge
+        float minSpeed = BOUNCE_MIN_SPEED * width;
+        if (xi <= 0.0f) { xi = -xi; vxi = fmaxf(fabsf(vxi), minSpeed); }
+        else if (xi >= width) { xi = 2.0f * width - xi; vxi = -fmaxf(fabsf(vxi), minSpeed); }
+        if (yi <= 0.0f) { yi = -yi; vyi = fmaxf(fabsf(vyi), minSpeed); }
+        else if (yi >= height) { yi = 2.0f * height - yi; vyi = -fmaxf(fabsf(vyi), minSpeed); }
+        xi = fminf(fmaxf(xi, 0.0f), width);
+        yi = fminf(fmaxf(yi, 0.0f), height);
+    } else if (xi < 0.0f || xi > width || yi < 0.0f || yi > height) {
+        // Open: respawn on the opposite edge with a fresh heading, as in boids.rs
+        unsigned int h1 = hashU32((jitterSeed ^ OPEN_RESPAWN_SALT) ^ hashU32(stepIndex ^ hashU32((unsigned int)i)));
+        unsigned int h2 = hashU32(h1);
+        vxi = unitNoise(h1) * maxSpeed;
+        vyi = unitNoise(h2) * maxSpeed;
+        if (xi < 0.0f) { xi = width; vxi = -fabsf(vxi); }
+        else if (xi > width) { xi = 0.0f; vxi = fabsf(vxi); }
+        if (yi < 0.0f) { yi = height; vyi = -fabsf(vyi); }
+        else if (yi > height) { yi = 0.0f; vyi = fabsf(vyi); }
+    }
 
     x[i] = xi; y[i] = yi; vx[i] = vxi; vy[i] = vyi;
 }
+
+// Must match `Boid3D` in boids3d.rs (#[repr(C)])
+struct Boid3D {
+    float x, y, z;
+    float vx, vy, vz;
+    float mass;
+    unsigned int id;
+    unsigned char species;
+};
+
+__device__ float wrapUnit(float v) {
+    float w = v - floorf(v);
+    return w >= 1.0f ? 0.0f : w;
+}
+
+// Add `weight * maxForce` along (dx, dy, dz), if it has a direction
+__device__ void steerToward(float dx, float dy, float dz, float weight, float maxForce,
+                            float* fx, float* fy, float* fz) {
+    float mag = sqrtf(dx*dx + dy*dy + dz*dz);
+    if (mag > 0.0f) {
+        *fx += dx / mag * maxForce * weight;
+        *fy += dy / mag * maxForce * weight;
+        *fz += dz / mag * maxForce * weight;
+    }
+}
+
+// 3D flocking in a wrapping unit cube, mirroring the CPU path in boids3d.rs.
+// Reads `in` and writes `out` so every boid sees the same previous state.
+extern "C" __global__ void boids_step_3d(
+    int n,
+    float dt,
+    float sepRadius,
+    float alignRadius,
+    float cohRadius,
+    float maxSpeed,
+    float maxForce,
+    const Boid3D* in,
+    Boid3D* out,
+    float* forceMag
+) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n) return;
+
+    Boid3D b = in[i];
+    float sepX = 0.0f, sepY = 0.0f, sepZ = 0.0f; int sepC = 0;
+    float aliX = 0.0f, aliY = 0.0f, aliZ = 0.0f; int aliC = 0;
+    float cohX = 0.0f, cohY = 0.0f, cohZ = 0.0f; int cohC = 0;
+
+    for (int j = 0; j < n; ++j) {
+        if (j == i) continue;
+        Boid3D o = in[j];
+        if (o.species != b.species) continue;
+        float dx = b.x - o.x;
+        float dy = b.y - o.y;
+        float dz = b.z - o.z;
+        float dist = sqrtf(dx*dx + dy*dy + dz*dz);
+        if (dist < sepRadius && dist > 0.0f) {
+            sepX += dx / dist; sepY += dy / dist; sepZ += dz / dist;
+            sepC++;
+        }
+        if (dist < alignRadius) {
+            aliX += o.vx; aliY += o.vy; aliZ += o.vz;
+            aliC++;
+        }
+        if (dist < cohRadius) {
+            cohX += o.x; cohY += o.y; cohZ += o.z;
+            cohC++;
+        }
+    }
+
+    float fx = 0.0f, fy = 0.0f, fz = 0.0f;
+    if (sepC > 0) {
+        steerToward(sepX, sepY, sepZ, 1.0f, maxForce, &fx, &fy, &fz);
+    }
+    if (aliC > 0) {
+        float c = (float)aliC;
+        steerToward(aliX / c - b.vx, aliY / c - b.vy, aliZ / c - b.vz, 0.5f, maxForce, &fx, &fy, &fz);
+    }
+    if (cohC > 0) {
+        float c = (float)cohC;
+        steerToward(cohX / c - b.x, cohY / c - b.y, cohZ / c - b.z, 0.3f, maxForce, &fx, &fy, &fz);
+    }
+
+    float invMass = 1.0f / fmaxf(b.mass, 0.01f);
+    b.vx += fx * invMass * dt;
+    b.vy += fy * invMass * dt;
+    b.vz += fz * invMass * dt;
+    float sp = sqrtf(b.vx*b.vx + b.vy*b.vy + b.vz*b.vz);
+    if (sp > maxSpeed) {
+        b.vx = b.vx / sp * maxSpeed;
+        b.vy = b.vy / sp * maxSpeed;
+        b.vz = b.vz / sp * maxSpeed;
+    }
+
+    b.x = wrapUnit(b.x + b.vx * dt);
+    b.y = wrapUnit(b.y + b.vy * dt);
+    b.z = wrapUnit(b.z + b.vz * dt);
+    forceMag[i] = sqrtf(fx*fx + fy*fy + fz*fz);
+    out[i] = b;
+}
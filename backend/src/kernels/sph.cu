@@ -0,0 +1,149 @@
+// SPH step in three passes, mirroring the CPU path in sph.rs:
+// density/pressure, then pressure + viscosity + cohesion accelerations, then integration.
+// The *Scale arguments come from `kernel_scales` and keep W normalized for any h.
+// Passes are separate launches so every particle reads a consistent state.
+
+// Must match `Particle` in sph.rs (#[repr(C)])
+struct Particle {
+    float x;
+    float y;
+    float vx;
+    float vy;
+    float density;
+    float pressure;
+};
+
+// Cubic spline kernel W(q), q = r / h
+__device__ float splineW(float q) {
+    if (q < 1.0f) {
+        float q2 = q * q;
+        return 1.0f - 1.5f * q2 + 0.75f * q2 * q;
+    }
+    if (q < 2.0f) {
+        float t = 2.0f - q;
+        return 0.25f * t * t * t;
+    }
+    return 0.0f;
+}
+
+__device__ float splineDw(float q) {
+    if (q < 1.0f) return -3.0f * q + 2.25f * q * q;
+    if (q < 2.0f) return -0.75f * (2.0f - q) * (2.0f - q);
+    return 0.0f;
+}
+
+__device__ float splineLaplacian(float q) {
+    if (q < 1.0f) return 3.0f - 4.5f * q;
+    if (q < 2.0f) return 1.5f * (2.0f - q);
+    return 0.0f;
+}
+
+extern "C" __global__ void sph_density(
+    int n,
+    Particle* p,
+    float mass,
+    float h,
+    float gasConstant,
+    float restDensity,
+    float wScale,
+    float minPressure
+) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n) return;
+
+    float xi = p[i].x;
+    float yi = p[i].y;
+    float density = 0.0f;
+    for (int j = 0; j < n; ++j) {
+        float dx = xi - p[j].x;
+        float dy = yi - p[j].y;
+        float dist = sqrtf(dx * dx + dy * dy);
+        if (dist < h) {
+            density += mass * wScale * splineW(dist / h);
+        }
+    }
+    p[i].density = density;
+    // minPressure is 0 with gravity on (no tension, which would clump particles)
+    p[i].pressure = fmaxf(gasConstant * (density - restDensity), minPressure);
+}
+
+extern "C" __global__ void sph_forces(
+    int n,
+    const Particle* p,
+    float mass,
+    float h,
+    float viscosity,
+    float surfaceTension,
+    float wScale,
+    float gradScale,
+    float lapScale,
+    float* ax,
+    float* ay
+) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n) return;
+
+    Particle pi = p[i];
+    float fx = 0.0f;
+    float fy = 0.0f;
+    for (int j = 0; j < n; ++j) {
+        if (j == i) continue;
+        Particle pj = p[j];
+        float dx = pi.x - pj.x;
+        float dy = pi.y - pj.y;
+        float dist = fmaxf(sqrtf(dx * dx + dy * dy), 0.0001f);
+        if (dist >= h) continue;
+
+        float q = dist / h;
+        float pressureForce = -(pi.pressure + pj.pressure) / (2.0f * pj.density);
+        float dw = gradScale * splineDw(q);
+        fx += pressureForce * mass * dw * (dx / dist);
+        fy += pressureForce * mass * dw * (dy / dist);
+
+        float lap = lapScale * splineLaplacian(q);
+        fx += viscosity * mass * lap * (pi.vx - pj.vx) / pj.density;
+        fy += viscosity * mass * lap * (pi.vy - pj.vy) / pj.density;
+
+        float cohesion = surfaceTension * mass * wScale * splineW(q);
+        fx -= cohesion * (dx / dist);
+        fy -= cohesion * (dy / dist);
+    }
+    ax[i] = fx;
+    ay[i] = fy;
+}
+
+extern "C" __global__ void sph_integrate(
+    int n,
+    Particle* p,
+    const float* ax,
+    const float* ay,
+    float dt,
+    float gx,
+    float gy,
+    float max_speed
+) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i >= n) return;
+
+    Particle q = p[i];
+    q.vx += (ax[i] + gx) * dt;
+    q.vy += (ay[i] + gy) * dt;
+    float speed = sqrtf(q.vx * q.vx + q.vy * q.vy);
+    if (speed > max_speed) {
+        q.vx *= max_speed / speed;
+        q.vy *= max_speed / speed;
+    }
+    q.x += q.vx * dt;
+    q.y += q.vy * dt;
+
+    // Damped bounce off the unit box
+    if (q.x < 0.0f || q.x > 1.0f) {
+        q.vx *= -0.5f;
+        q.x = fminf(fmaxf(q.x, 0.0f), 1.0f);
+    }
+    if (q.y < 0.0f || q.y > 1.0f) {
+        q.vy *= -0.5f;
+        q.y = fminf(fmaxf(q.y, 0.0f), 1.0f);
+    }
+    p[i] = q;
+}
@@ -2,50 +2,240 @@
 #![allow(dead_code, unused_variables)]
 
 use axum::{
-    extract::{State, ws::WebSocketUpgrade},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    extract::{Path, Query, State, ws::WebSocketUpgrade},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
+    routing::{delete, get, post, put, MethodRouter},
     Router,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast as tokio_broadcast;
-use tracing::{info, warn, Level};
+use tokio::sync::watch;
+use tracing::{debug, info, warn, Level};
 use tracing_subscriber;
 
+mod api_error;
+mod auth;
+mod benchmark;
 mod broadcast;
+mod cancellation;
+mod capabilities;
+mod compression;
+mod config;
+mod csv_log;
 mod cuda;
+mod cuda_pool;
 mod gpu_stats;
+mod instances;
+mod metrics;
+mod openapi;
 mod physics;
+mod prometheus;
+mod rate_limit;
+mod recorder;
 mod simulation_engine;
+mod telemetry;
 #[cfg(test)]
 mod tests;
 
+use api_error::{ApiError, ApiJson};
+
 #[derive(Clone)]
 struct AppState {
     cuda_context: Arc<cuda::CudaContext>,
+    /// Threads with a long-lived CUDA context that run the simulate handlers' work
+    cuda_pool: Arc<cuda_pool::CudaPool>,
+    /// Separate context-owning workers for Gray-Scott streams, which hold one for the whole run
+    stream_pool: Arc<cuda_pool::CudaPool>,
+    /// One permit per stream worker; a stream that can't get one is turned away
+    grayscott_streams: Arc<tokio::sync::Semaphore>,
     boids_simulation: Arc<Mutex<physics::BoidsSimulation>>,
+    // Separate flock for `dimensions: 3` requests
+    boids3d_simulation: Arc<Mutex<physics::Boids3DSimulation>>,
     #[allow(dead_code)]
     simulation_engine: Arc<simulation_engine::SimulationEngine>,
     broadcast_tx: tokio_broadcast::Sender<broadcast::BroadcastState>,
+    capabilities: Arc<capabilities::Capabilities>,
+    metrics: Arc<metrics::ServerMetrics>,
+    /// Per-boid frames larger than this are replaced by occupancy frames
+    max_frame_bytes: usize,
+    /// How long a GPU stats reading is reused
+    gpu_stats_interval: std::time::Duration,
+    /// Flips to true once the server starts shutting down
+    shutdown: watch::Receiver<bool>,
+    /// Simulations created through `/api/simulations`
+    instances: Arc<instances::InstanceRegistry>,
+    /// Captures broadcast frames between `/api/record/start` and `/api/record/stop`
+    recorder: Arc<recorder::Recorder>,
+    /// Live `?interp=1` clients; the broadcast task keeps history only while there are any
+    interp_subscribers: Arc<broadcast::Subscribers>,
+    /// Live `?withspeed=1` clients of the shared flock; speeds are computed only while there are any
+    speed_subscribers: Arc<broadcast::Subscribers>,
+    /// `CONTROL_TOKEN`, which `/ws` steering commands need when set
+    control_token: Option<auth::ControlToken>,
 }
 
 #[derive(Deserialize, Debug)]
-struct SimulationRequest {
+struct SimulationRequest<P = serde::de::IgnoredAny> {
     #[allow(dead_code)]
     simulation_type: String,
     #[allow(dead_code)]
     num_particles: Option<usize>,
     steps: Option<usize>,
+    // Decimal places to round returned values to (full precision when omitted)
+    round_to: Option<u8>,
+    // Simulation-specific tunables (e.g. `physics::BoidsParams` for boids)
+    params: Option<P>,
+    // Boids only: also return each boid's steering force magnitude
+    #[serde(default)]
+    include_forces: bool,
+    // Boids only: 2 (default) or 3 for the 3D flock
+    dimensions: Option<u8>,
+    // SPH, MD and Gray-Scott: seed for the initial noise, so runs can be repeated (random when omitted)
+    seed: Option<u64>,
+    // SPH and Gray-Scott: integration steps per step, each with dt / substeps (default 1)
+    substeps: Option<u32>,
 }
 
 #[derive(Serialize)]
 struct SimulationResponse {
     success: bool,
     data: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forces: Option<Vec<f32>>,
+    // Gray-Scott `?fields=both`, in place of `data`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<GrayScottFields>,
+    // Accepted-but-suspicious params, e.g. out-of-order radii
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
     metadata: Option<SimulationMetadata>,
     error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<api_error::ErrorKind>,
+}
+
+#[derive(Serialize)]
+struct GrayScottFields {
+    u: Vec<f32>,
+    v: Vec<f32>,
+}
+
+/// How the simulate endpoints encode a successful response: JSON by default,
+/// `values_frame` bytes for `?encoding=binary` or `Accept: application/octet-stream`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ResponseEncoding {
+    #[default]
+    Json,
+    Binary,
+}
+
+impl ResponseEncoding {
+    fn parse(uri: &axum::http::Uri, accept: Option<&str>) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Params {
+            encoding: Option<String>,
+        }
+        let Query(params) = Query::<Params>::try_from_uri(uri).map_err(|e| e.body_text())?;
+        match params.encoding.as_deref() {
+            Some("binary") => return Ok(Self::Binary),
+            Some("json") => return Ok(Self::Json),
+            Some(other) => return Err(format!("encoding must be json or binary, got {}", other)),
+            None => {}
+        }
+        let wants_binary = accept.is_some_and(|accept| {
+            accept
+                .split(',')
+                .any(|media| media.split(';').next().unwrap_or("").trim() == "application/octet-stream")
+        });
+        Ok(if wants_binary { Self::Binary } else { Self::Json })
+    }
+
+    /// Content type and body of a successful response
+    fn encode(self, response: SimulationResponse) -> Result<(&'static str, Vec<u8>), serde_json::Error> {
+        match self {
+            Self::Json => Ok(("application/json", serde_json::to_vec(&response)?)),
+            Self::Binary => {
+                let (simulation_type, computation_ms) = response
+                    .metadata
+                    .as_ref()
+                    .map_or(("", 0), |m| (m.simulation_type.as_str(), m.computation_time_ms as u64));
+                // Both Gray-Scott fields go out as u followed by v
+                let values = match &response.fields {
+                    Some(fields) => [fields.u.as_slice(), fields.v.as_slice()].concat(),
+                    None => response.data.unwrap_or_default(),
+                };
+                Ok(("application/octet-stream", broadcast::values_frame(simulation_type, computation_ms, &values)))
+            }
+        }
+    }
+}
+
+/// `ResponseEncoding` plus `?compress=zstd`, which compresses the encoded body and
+/// reports its original length in `compression::UNCOMPRESSED_LENGTH_HEADER`
+#[derive(Debug, Default, Clone, Copy)]
+struct ResponseFormat {
+    encoding: ResponseEncoding,
+    compression: compression::Compression,
+}
+
+impl ResponseFormat {
+    /// Successful responses only; errors stay uncompressed JSON so clients can read them
+    fn reply(self, response: SimulationResponse) -> axum::response::Response {
+        use axum::http::{header, HeaderName, HeaderValue};
+        use axum::response::IntoResponse;
+        let (content_type, body) = match self.encoding.encode(response) {
+            Ok(encoded) => encoded,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let content_type = HeaderValue::from_static(content_type);
+        match self.compression {
+            compression::Compression::None => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+            compression::Compression::Zstd => match compression::compress(&body) {
+                Ok(compressed) => (
+                    [
+                        (header::CONTENT_TYPE, content_type),
+                        (header::CONTENT_ENCODING, HeaderValue::from_static("zstd")),
+                        (HeaderName::from_static(compression::UNCOMPRESSED_LENGTH_HEADER), HeaderValue::from(body.len())),
+                    ],
+                    compressed,
+                )
+                    .into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            },
+        }
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for ResponseFormat {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        #[derive(Deserialize)]
+        struct Params {
+            compress: Option<compression::Compression>,
+        }
+        let accept = parts
+            .headers
+            .get(axum::http::header::ACCEPT)
+            .and_then(|value| value.to_str().ok());
+        let encoding = ResponseEncoding::parse(&parts.uri, accept).map_err(ApiError::bad_request)?;
+        let Query(params) = Query::<Params>::try_from_uri(&parts.uri)
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        let compression = params.compress.unwrap_or_default();
+        compression
+            .check_available()
+            .map_err(ApiError::bad_request)?;
+        Ok(Self { encoding, compression })
+    }
 }
 
 #[derive(Serialize)]
@@ -55,56 +245,470 @@ struct SimulationMetadata {
     #[allow(dead_code)]
     num_particles: usize,
     computation_time_ms: u128,
-    accelerator: String,
+    accelerator: physics::Accelerator,
+    // Fewer than requested if the client disconnected mid-run
+    steps_completed: usize,
+    // Original (min, max) a rescaled field was mapped from, so clients can invert it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_range: Option<(f32, f32)>,
+    // Boids only: floats per boid in `data` are 4 in 2D (x, y, vx, vy), 6 in 3D
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<u8>,
+    // Seed the initial state came from, for simulations built per request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    // SPH only: false if the fluid blew up to non-finite values and was reset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stable: Option<bool>,
+    // SPH only: fluid properties the run used, defaults filled in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<physics::sph::FluidParams>,
+}
+
+// Step the simulate endpoints take before the engine's time scale is applied
+const SIMULATE_DT: f32 = 0.016;
+
+/// `SIMULATE_DT` scaled by the engine's time scale, so `/api/sim/time-scale` covers these runs too
+fn simulate_dt(state: &AppState) -> f32 {
+    SIMULATE_DT * state.simulation_engine.time_scale()
+}
+
+// f32 carries ~7 significant digits, so rounding beyond this is a no-op
+const MAX_ROUND_DECIMALS: u8 = 7;
+
+/// Round values in place to `decimals` places to shrink the JSON encoding
+fn round_values(values: &mut [f32], decimals: u8) {
+    let scale = 10f32.powi(decimals.min(MAX_ROUND_DECIMALS) as i32);
+    for v in values.iter_mut() {
+        *v = (*v * scale).round() / scale;
+    }
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    running: bool,
+    paused: bool,
+    last_update_age_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Liveness probe: 503 when the simulation loop has stopped, died or stalled
+async fn health(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let liveness = state.simulation_engine.liveness();
+    let status = if liveness.problem.is_some() {
+        warn!("Health check failing: {}", liveness.problem.as_deref().unwrap_or_default());
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (
+        status,
+        Json(HealthResponse {
+            status: if status == StatusCode::OK { "ok" } else { "unhealthy" },
+            running: liveness.running,
+            paused: liveness.paused,
+            last_update_age_ms: liveness.last_update_age.as_millis(),
+            error: liveness.problem,
+        }),
+    )
+}
+
+/// Accept `1`/`0` as well as `true`/`false` for query-string flags
+fn deserialize_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match value.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" | "" => Ok(false),
+        other => Err(serde::de::Error::custom(format!("invalid flag value: {}", other))),
+    }
+}
+
+/// Per-connection options for `/ws`
+#[derive(Deserialize, Debug, Default, Clone)]
+struct WsParams {
+    /// Append each boid's stable u32 id after the position/velocity block
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    ids: bool,
+    /// Send a coarse occupancy grid instead of per-boid data
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    occupancy: bool,
+    /// Append each boid's steering force magnitude, for coloring by force
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    forces: bool,
+    /// Send position/velocity changes since the previous frame instead of full frames
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    delta: bool,
+    /// Append each boid's position from a few broadcasts back, for smooth interpolation
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    interp: bool,
+    /// Append each boid's speed relative to its cap as one byte, for heat coloring
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    withspeed: bool,
+    /// `json` sends downsampled text frames for debugging instead of binary
+    #[serde(default)]
+    format: broadcast::FrameFormat,
+    /// Every Nth boid in JSON frames (default `broadcast::DEFAULT_JSON_STRIDE`)
+    stride: Option<usize>,
+    /// Only send boids inside this viewport; omitted bounds are unbounded
+    xmin: Option<f32>,
+    xmax: Option<f32>,
+    ymin: Option<f32>,
+    ymax: Option<f32>,
+    /// Stream an instance created through `/api/simulations` instead of the shared flock
+    sim: Option<uuid::Uuid>,
+    /// Play back a file from `/api/record/start` at its recorded pace instead of a live flock
+    replay: Option<String>,
+    /// `CONTROL_TOKEN`, for `/ws` clients that steer but can't set an `Authorization` header
+    token: Option<auth::QueryToken>,
+}
+
+impl WsParams {
+    fn frame_options(&self) -> broadcast::FrameOptions {
+        broadcast::FrameOptions {
+            ids: self.ids,
+            forces: self.forces,
+            occupancy: self.occupancy,
+            delta: self.delta,
+            interp: self.interp,
+            speed: self.withspeed,
+        }
+    }
+
+    fn json_stride(&self) -> anyhow::Result<usize> {
+        match self.stride {
+            Some(0) => anyhow::bail!("stride must be at least 1"),
+            Some(stride) => Ok(stride),
+            None => Ok(broadcast::DEFAULT_JSON_STRIDE),
+        }
+    }
+
+    /// `None` when no bound was given, so unfiltered clients skip the copy
+    fn region(&self) -> anyhow::Result<Option<broadcast::Region>> {
+        if [self.xmin, self.xmax, self.ymin, self.ymax].iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        broadcast::Region::new(self.xmin, self.xmax, self.ymin, self.ymax).map(Some)
+    }
 }
 
-async fn health() -> &'static str {
-    "OK"
+// A replay file and the channel it plays into, started once the client is listening
+type PendingReplay = (recorder::Replay, tokio_broadcast::Sender<broadcast::BroadcastState>);
+
+/// A checked `/ws` or SSE subscription
+struct FlockStream {
+    flock: WsFlock,
+    region: Option<broadcast::Region>,
+    replay: Option<PendingReplay>,
+}
+
+/// Check a `/ws` or SSE stream's params and pick the flock it follows
+fn open_flock(state: &AppState, params: &WsParams) -> Result<FlockStream, (StatusCode, String)> {
+    let region = params
+        .region()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    params
+        .json_stride()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if params.interp && params.delta {
+        return Err((StatusCode::BAD_REQUEST, "interp and delta can't be combined".to_string()));
+    }
+    // Only the shared flock's broadcasts carry earlier positions
+    if params.interp && (params.sim.is_some() || params.replay.is_some()) {
+        return Err((StatusCode::BAD_REQUEST, "interp is only available on the shared flock".to_string()));
+    }
+    let mut replay = None;
+    let flock = match (&params.replay, params.sim) {
+        (Some(_), Some(_)) => {
+            return Err((StatusCode::BAD_REQUEST, "replay and sim can't be combined".to_string()));
+        }
+        (Some(file), None) => {
+            let (tx, rx) = tokio_broadcast::channel(REPLAY_CHANNEL_CAPACITY);
+            let opened = state.recorder.open_replay(file).map_err(record_error)?;
+            replay = Some((opened.with_speeds(params.withspeed), tx));
+            WsFlock { rx, simulation: None, subscriptions: Vec::new(), can_steer: true }
+        }
+        (None, Some(id)) => {
+            let instance = state
+                .instances
+                .get(&id)
+                .ok_or((StatusCode::NOT_FOUND, format!("No simulation {}", id)))?;
+            WsFlock {
+                rx: instance.subscribe(),
+                simulation: Some(Arc::clone(&instance.simulation)),
+                subscriptions: [
+                    params.forces.then(|| instance.subscribe_forces()),
+                    params.withspeed.then(|| instance.subscribe_speeds()),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+                can_steer: true,
+            }
+        }
+        (None, None) => WsFlock {
+            rx: state.broadcast_tx.subscribe(),
+            simulation: Some(state.simulation_engine.simulation()),
+            subscriptions: [
+                params.interp.then(|| state.interp_subscribers.subscribe()),
+                params.forces.then(|| state.simulation_engine.subscribe_forces()),
+                params.withspeed.then(|| state.speed_subscribers.subscribe()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            can_steer: true,
+        },
+    };
+    Ok(FlockStream { flock, region, replay })
 }
 
 async fn websocket_handler(
     ws: WebSocketUpgrade,
+    Query(params): Query<WsParams>,
     State(state): State<AppState>,
-) -> axum::response::Response {
-    let rx = state.broadcast_tx.subscribe();
+    headers: HeaderMap,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let FlockStream { mut flock, region, replay } = open_flock(&state, &params)?;
+    // Watching stays open; steering the flock is a control action
+    flock.can_steer = auth::authorized(state.control_token.as_ref(), &headers, params.token.as_ref());
     
-    info!("New WebSocket connection request");
+    info!("New WebSocket connection request: {:?}", params);
     
-    ws.on_upgrade(|socket| async move {
-        info!("WebSocket connection upgraded");
-        handle_websocket(socket, rx).await;
-        info!("WebSocket connection closed");
-    })
+    Ok(ws.on_upgrade(move |socket| async move {
+        let guard = metrics::ConnectionGuard::new(&state.metrics);
+        // Playback starts once the client is connected, so it sees the first frame
+        if let Some((replay, tx)) = replay {
+            let shutdown = state.shutdown.clone();
+            tokio::task::spawn_blocking(move || replay.play(tx, shutdown));
+        }
+        info!("WebSocket client {} connected", guard.id());
+        handle_websocket(
+            socket,
+            flock,
+            params,
+            region,
+            guard,
+            state.max_frame_bytes,
+            state.shutdown.clone(),
+        )
+        .await;
+    }))
+}
+
+/// The `/ws` stream as server-sent events, for clients behind proxies that block
+/// WebSockets. Takes the same query params; each binary frame is sent base64-encoded,
+/// `format=json` frames as they are. Steering commands need `/ws`.
+async fn stream_boids(
+    Query(params): Query<WsParams>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>>, (StatusCode, String)> {
+    use base64::Engine;
+    use metrics::DisconnectReason;
+
+    let FlockStream { flock, region, replay } = open_flock(&state, &params)?;
+    info!("New SSE stream request: {:?}", params);
+    if let Some((replay, tx)) = replay {
+        let shutdown = state.shutdown.clone();
+        tokio::task::spawn_blocking(move || replay.play(tx, shutdown));
+    }
+
+    let frame_options = params.frame_options();
+    let json = params.format == broadcast::FrameFormat::Json;
+    let json_stride = params.json_stride().unwrap_or(broadcast::DEFAULT_JSON_STRIDE);
+    let max_frame_bytes = state.max_frame_bytes;
+    let frames = broadcast::ClientFrames::default();
+    // Counted like a /ws connection; the stream is dropped when the client goes away
+    let guard = metrics::ConnectionGuard::new(&state.metrics).with_fallback(DisconnectReason::ClientClosed);
+    info!("SSE client {} connected", guard.id());
+    // The guards ride along so they drop with the stream
+    let stream = (flock.rx, frames, state.shutdown.clone(), guard, flock.subscriptions);
+    let events = futures_util::stream::unfold(stream, move |(mut rx, mut frames, mut shutdown, mut guard, subscriptions)| async move {
+        let state = loop {
+            let result = tokio::select! {
+                result = rx.recv() => result,
+                // End the stream on shutdown rather than holding the server open
+                _ = shutdown.wait_for(|stopping| *stopping) => {
+                    guard.set_reason(DisconnectReason::ChannelClosed);
+                    return None;
+                }
+            };
+            match result {
+                Ok(state) => break state,
+                // Full-state stream: the newest frame supersedes the ones missed
+                Err(tokio_broadcast::error::RecvError::Lagged(skipped)) => {
+                    guard.record_lagged(skipped);
+                    if let Ok(state) = broadcast::skip_to_latest(&mut rx) {
+                        break state;
+                    }
+                }
+                Err(tokio_broadcast::error::RecvError::Closed) => {
+                    guard.set_reason(DisconnectReason::ChannelClosed);
+                    return None;
+                }
+            }
+        };
+        let state = match &region {
+            Some(region) => state.in_region(region),
+            None => state,
+        };
+        let event = if json {
+            state
+                .json_frame(json_stride)
+                .map(|json| Event::default().event("frame").data(json))
+                .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+        } else {
+            let frame = frames.next_frame(state, &frame_options, max_frame_bytes);
+            Event::default()
+                .event("frame")
+                .data(base64::engine::general_purpose::STANDARD.encode(frame))
+        };
+        Some((Ok(event), (rx, frames, shutdown, guard, subscriptions)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Frames a replay can run ahead of its client; matches the live broadcast channel
+const REPLAY_CHANNEL_CAPACITY: usize = 100;
+
+/// The flock a `/ws` client watches, and steers with its commands (`None` for replays)
+struct WsFlock {
+    rx: tokio_broadcast::Receiver<broadcast::BroadcastState>,
+    simulation: Option<Arc<Mutex<physics::BoidsSimulation>>>,
+    /// Held for the optional streams this client asked for (`?interp=1`,
+    /// `?forces=1`, `?withspeed=1`), so they're only computed while wanted
+    subscriptions: Vec<broadcast::SubscriberGuard>,
+    /// False when `CONTROL_TOKEN` is set and the client didn't present it
+    can_steer: bool,
+}
+
+/// Control messages a `/ws` client can send as JSON text frames. Positions and radii
+/// are in the unit square the frames use, whatever the simulation's world size.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum WsCommand {
+    /// Pull nearby boids toward (x, y) (push when `strength` < 0) until cleared
+    /// or displaced by newer attractors
+    AddAttractor { x: f32, y: f32, strength: Option<f32>, radius: Option<f32> },
+    /// Replace every attractor, e.g. each frame to follow the pointer
+    SetAttractors { attractors: Vec<physics::attractors::Attractor> },
+    ClearAttractors,
+}
+
+impl WsCommand {
+    fn apply(self, simulation: &Mutex<physics::BoidsSimulation>) -> anyhow::Result<()> {
+        let mut sim = simulation
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Simulation unavailable"))?;
+        let world_size = sim.world_size();
+        let to_world = |a: physics::attractors::Attractor| physics::attractors::Attractor {
+            x: a.x * world_size,
+            y: a.y * world_size,
+            radius: a.radius * world_size,
+            ..a
+        };
+        match self {
+            WsCommand::AddAttractor { x, y, strength, radius } => sim.add_attractor(to_world(physics::attractors::Attractor {
+                x,
+                y,
+                strength: strength.unwrap_or(physics::attractors::DEFAULT_ATTRACTOR_STRENGTH),
+                radius: radius.unwrap_or(physics::attractors::DEFAULT_ATTRACTOR_RADIUS),
+            })),
+            WsCommand::SetAttractors { attractors } => sim.set_attractors(attractors.into_iter().map(to_world).collect()),
+            WsCommand::ClearAttractors => {
+                sim.clear_attractors();
+                Ok(())
+            }
+        }
+    }
 }
 
 async fn handle_websocket(
     socket: axum::extract::ws::WebSocket,
-    mut rx: tokio_broadcast::Receiver<broadcast::BroadcastState>,
+    flock: WsFlock,
+    params: WsParams,
+    region: Option<broadcast::Region>,
+    mut guard: metrics::ConnectionGuard,
+    max_frame_bytes: usize,
+    mut shutdown: watch::Receiver<bool>,
 ) {
     use axum::extract::ws::Message;
     use futures_util::{SinkExt, StreamExt};
+    use metrics::DisconnectReason;
     
     let (mut sender, mut receiver) = socket.split();
+    let WsFlock { mut rx, simulation, subscriptions, can_steer } = flock;
     
-    // Spawn task to send simulation updates
+    // Spawn task to send simulation updates. The guard lives in the task so the
+    // connection is released and its disconnect reason recorded however it ends.
     let send_task = tokio::spawn(async move {
+        let _subscriptions = subscriptions;
+        let client = guard.id();
         let mut interval = tokio::time::interval(std::time::Duration::from_millis(16)); // ~60 FPS
         let mut last_successful_send = std::time::Instant::now();
         let mut consecutive_empty = 0;
+        let mut oversized = false;
+        let frame_options = params.frame_options();
+        let json_stride = params.json_stride().unwrap_or(broadcast::DEFAULT_JSON_STRIDE);
+        let mut frames = broadcast::ClientFrames::default();
         
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    match rx.try_recv() {
+                    let next = match rx.try_recv() {
+                        Err(tokio_broadcast::error::TryRecvError::Lagged(skipped)) => {
+                            // Full-state stream: the newest frame supersedes the ones missed
+                            debug!("WebSocket client {} lagged behind by {} frames, skipping ahead", client, skipped);
+                            guard.record_lagged(skipped);
+                            broadcast::skip_to_latest(&mut rx)
+                        }
+                        next => next,
+                    };
+                    match next {
                         Ok(state) => {
-                            // Send binary data: [timestamp (u64), num_boids (u32), data...]
-                            let mut message = Vec::with_capacity(12 + state.data.len());
-                            message.extend_from_slice(&state.timestamp.to_le_bytes());
-                            message.extend_from_slice(&(state.num_boids as u32).to_le_bytes());
-                            message.extend_from_slice(&state.data);
+                            // Each connection filters to its own viewport
+                            let state = match &region {
+                                Some(region) => state.in_region(region),
+                                None => state,
+                            };
+                            // Debug clients get text frames; binary clients never touch JSON
+                            if params.format == broadcast::FrameFormat::Json {
+                                let json = match state.json_frame(json_stride) {
+                                    Ok(json) => json,
+                                    Err(e) => {
+                                        warn!("WebSocket client {}: {:?}", client, e);
+                                        continue;
+                                    }
+                                };
+                                if let Err(e) = sender.send(Message::Text(json)).await {
+                                    warn!("WebSocket client {}: send failed: {:?}", client, e);
+                                    guard.set_reason(DisconnectReason::SendError);
+                                    break;
+                                }
+                                last_successful_send = std::time::Instant::now();
+                                consecutive_empty = 0;
+                                continue;
+                            }
+                            let num_boids = state.num_boids;
+                            let message = frames.next_frame(state, &frame_options, max_frame_bytes);
+                            // Log transitions only, not every frame
+                            let fell_back = !params.occupancy && message[0] == broadcast::FRAME_OCCUPANCY;
+                            if fell_back != oversized {
+                                oversized = fell_back;
+                                if oversized {
+                                    warn!(
+                                        "WebSocket client {}: {} boids exceed the {} byte frame cap, sending occupancy grid",
+                                        client, num_boids, max_frame_bytes
+                                    );
+                                } else {
+                                    info!("WebSocket client {}: frames back under the size cap", client);
+                                }
+                            }
                             
-                            if sender.send(Message::Binary(message)).await.is_err() {
-                                warn!("Failed to send WebSocket message, connection closed");
+                            if let Err(e) = sender.send(Message::Binary(message)).await {
+                                warn!("WebSocket client {}: send failed: {:?}", client, e);
+                                guard.set_reason(DisconnectReason::SendError);
                                 break;
                             }
                             last_successful_send = std::time::Instant::now();
@@ -115,8 +719,9 @@ async fn handle_websocket(
                             // If no data for too long, send a keepalive ping
                             if consecutive_empty > 60 && last_successful_send.elapsed().as_secs() > 1 {
                                 // Send a ping to keep connection alive
-                                if sender.send(Message::Ping(vec![])).await.is_err() {
-                                    warn!("Failed to send WebSocket ping, connection closed");
+                                if let Err(e) = sender.send(Message::Ping(vec![])).await {
+                                    warn!("WebSocket client {}: ping failed: {:?}", client, e);
+                                    guard.set_reason(DisconnectReason::SendError);
                                     break;
                                 }
                                 consecutive_empty = 0;
@@ -124,35 +729,65 @@ async fn handle_websocket(
                         }
                         Err(tokio_broadcast::error::TryRecvError::Closed) => {
                             warn!("Broadcast channel closed");
+                            guard.set_reason(DisconnectReason::ChannelClosed);
                             break;
                         }
-                        Err(e) => {
-                            warn!("Broadcast receive error: {:?}", e);
-                            break;
+                        Err(tokio_broadcast::error::TryRecvError::Lagged(_)) => {
+                            // skip_to_latest only returns Empty or Closed
                         }
                     }
                 }
+                _ = shutdown.changed() => {
+                    // Tell the client we're going away rather than dropping the socket
+                    let _ = sender.send(Message::Close(Some(shutdown_close_frame()))).await;
+                    guard.set_reason(DisconnectReason::ChannelClosed);
+                    break;
+                }
                 result = receiver.next() => {
                     match result {
                         Some(Ok(Message::Close(_))) => {
-                            info!("WebSocket client closed connection");
+                            guard.set_reason(DisconnectReason::ClientClosed);
                             break;
                         }
                         Some(Ok(Message::Ping(data))) => {
                             // Respond to ping with pong
                             if sender.send(Message::Pong(data)).await.is_err() {
+                                guard.set_reason(DisconnectReason::SendError);
                                 break;
                             }
                         }
+                        Some(Ok(Message::Text(text))) => {
+                            // A bad command is logged and skipped; the stream carries on
+                            let parsed = serde_json::from_str::<WsCommand>(&text)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|command| match &simulation {
+                                    _ if !can_steer => Err(anyhow::anyhow!("commands need the control token")),
+                                    Some(simulation) => Ok((command, Arc::clone(simulation))),
+                                    None => Err(anyhow::anyhow!("a replay can't be steered")),
+                                });
+                            // The lock can be held for a whole step, so wait for it off the async workers
+                            let applied = match parsed {
+                                Ok((command, simulation)) => {
+                                    tokio::task::spawn_blocking(move || command.apply(&simulation))
+                                        .await
+                                        .unwrap_or_else(|e| Err(e.into()))
+                                }
+                                Err(e) => Err(e),
+                            };
+                            if let Err(e) = applied {
+                                warn!("WebSocket client {}: ignoring command {:?}: {}", client, text, e);
+                            }
+                        }
                         Some(Ok(_)) => {
-                            // Ignore other incoming messages (read-only)
+                            // Ignore other incoming messages
                         }
                         Some(Err(e)) => {
-                            warn!("WebSocket receive error: {:?}", e);
+                            warn!("WebSocket client {}: receive error: {:?}", client, e);
+                            guard.set_reason(DisconnectReason::ReceiveError);
                             break;
                         }
                         None => {
-                            info!("WebSocket receiver closed");
+                            guard.set_reason(DisconnectReason::ClientClosed);
                             break;
                         }
                     }
@@ -164,247 +799,1759 @@ async fn handle_websocket(
     send_task.await.ok();
 }
 
-async fn gpu_info(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
-    let device_name = state.cuda_context.device().name()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+#[derive(Deserialize, Debug)]
+struct TelemetryParams {
+    /// Frames per second (default 2)
+    rate: Option<f32>,
+}
+
+async fn telemetry_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<TelemetryParams>,
+    State(state): State<AppState>,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let interval = telemetry::interval_for_rate(params.rate)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("New telemetry connection, every {:?}", interval);
+    Ok(ws.on_upgrade(move |socket| handle_telemetry(socket, state, interval)))
+}
+
+async fn handle_telemetry(
+    socket: axum::extract::ws::WebSocket,
+    state: AppState,
+    interval: std::time::Duration,
+) {
+    use axum::extract::ws::Message;
+    use futures_util::{SinkExt, StreamExt};
+
+    let (mut sender, mut receiver) = socket.split();
+    let mut ticker = tokio::time::interval(interval);
+    let mut shutdown = state.shutdown.clone();
+    let engine = &state.simulation_engine;
+    let mut fps_meter = telemetry::FpsMeter::new(engine.get_frame_count());
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let stats = engine.frame_stats();
+                let frame = telemetry::TelemetryFrame::new(
+                    &stats,
+                    fps_meter.sample(stats.frame_count),
+                    engine.num_boids(),
+                    state.metrics.active_connections(),
+                    gpu_stats::get_gpu_stats(state.cuda_context.device(), state.gpu_stats_interval).ok(),
+                );
+                let json = match serde_json::to_string(&frame) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        warn!("Failed to encode telemetry: {:?}", e);
+                        continue;
+                    }
+                };
+                if sender.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            _ = shutdown.changed() => {
+                let _ = sender.send(Message::Close(Some(shutdown_close_frame()))).await;
+                break;
+            }
+            result = receiver.next() => {
+                match result {
+                    Some(Ok(Message::Close(_))) | None => {
+                        // Finish the close handshake; the client may already be gone
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        if sender.send(Message::Pong(data)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        warn!("Telemetry client receive error: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    info!("Telemetry client disconnected");
+}
+
+async fn gpu_info(State(state): State<AppState>) -> Result<Json<cuda::GpuInfo>, StatusCode> {
+    // A no-cuda build has no GPU to describe
+    let device = state.cuda_context.device().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let info = cuda::GpuInfo::query(device)
+        .map_err(|e| {
+            warn!("Failed to get GPU info: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
     
-    Ok(Json(serde_json::json!({
-        "gpu": device_name,
-        "status": "ready",
-        "cuda_context": true
-    })))
+    Ok(Json(info))
+}
+
+/// OpenAPI description of the simulate and GPU endpoints
+async fn openapi_document() -> Json<serde_json::Value> {
+    Json(openapi::document())
+}
+
+async fn get_capabilities(State(state): State<AppState>) -> Json<capabilities::Capabilities> {
+    Json((*state.capabilities).clone())
+}
+
+async fn build_info() -> Json<capabilities::BuildInfo> {
+    Json(capabilities::build_info())
+}
+
+/// The PTX each CUDA simulation is using right now, to spot a silent CPU fallback.
+/// Boids and 3D boids report their live simulations; SPH and Gray-Scott, which build
+/// one per request, report the PTX new runs would load.
+async fn debug_kernels(State(state): State<AppState>) -> Result<Json<Vec<capabilities::LoadedKernel>>, StatusCode> {
+    use capabilities::LoadedKernel;
+    // The simulation locks are held across steps and the PTX may be read from disk or
+    // compiled, so none of it runs on the async workers
+    let boids = state.simulation_engine.simulation();
+    let boids3d = Arc::clone(&state.boids3d_simulation);
+    let kernels = tokio::task::spawn_blocking(move || -> Result<Vec<LoadedKernel>, StatusCode> {
+        let boids = boids.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let boids3d = boids3d.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let sph = physics::sph::kernel_ptx();
+        let gray_scott = physics::grayscott::kernel_ptx();
+        Ok(vec![
+            LoadedKernel::new("boids", &["boids_step"], boids.ptx()),
+            LoadedKernel::new("boids_3d", &["boids_step_3d"], boids3d.ptx()),
+            LoadedKernel::new("sph", &["sph_density", "sph_forces", "sph_integrate"], sph.as_deref()),
+            LoadedKernel::new("gray_scott", &["gray_scott_step"], gray_scott.as_deref().map(String::as_str)),
+        ])
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    Ok(Json(kernels))
+}
+
+/// GPU counters plus how the simulation loop is keeping up
+#[derive(Serialize)]
+struct GpuStatsResponse {
+    #[serde(flatten)]
+    gpu: gpu_stats::GpuStats,
+    achieved_fps: f32,
+    avg_frame_time_ms: f32,
+    target_fps: f32,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GpuStatsParams {
+    /// Query the device now instead of reusing a cached reading
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    fresh: bool,
 }
 
-async fn gpu_stats(State(state): State<AppState>) -> Result<Json<gpu_stats::GpuStats>, StatusCode> {
-    let device = state.cuda_context.device();
-    let stats = gpu_stats::get_gpu_stats(Some(device))
+async fn gpu_stats(
+    State(state): State<AppState>,
+    Query(params): Query<GpuStatsParams>,
+) -> Result<Json<GpuStatsResponse>, StatusCode> {
+    let max_age = if params.fresh { std::time::Duration::ZERO } else { state.gpu_stats_interval };
+    let stats = gpu_stats::get_gpu_stats(state.cuda_context.device(), max_age)
         .map_err(|e| {
             tracing::warn!("Failed to get GPU stats: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+    let frames = state.simulation_engine.frame_stats();
     
-    Ok(Json(stats))
+    Ok(Json(GpuStatsResponse {
+        gpu: stats,
+        achieved_fps: frames.achieved_fps,
+        avg_frame_time_ms: frames.avg_frame_ms,
+        target_fps: frames.target_fps,
+    }))
+}
+
+/// Prometheus scrape endpoint; GPU gauges come from the same cache as /api/gpu-stats
+async fn prometheus_metrics(State(state): State<AppState>) -> impl axum::response::IntoResponse {
+    let gpu = gpu_stats::get_gpu_stats(state.cuda_context.device(), state.gpu_stats_interval)
+        .map_err(|e| tracing::debug!("GPU stats unavailable for /metrics: {:?}", e))
+        .ok();
+    let body = prometheus::render(
+        &state.simulation_engine.frame_stats(),
+        &state.metrics,
+        gpu.as_ref(),
+    );
+    ([(axum::http::header::CONTENT_TYPE, prometheus::CONTENT_TYPE)], body)
+}
+
+/// Run simulation work on the CUDA worker pool, failing with `CudaInit` if the worker
+/// has no usable context. The work is cancelled if the handler future is dropped,
+/// i.e. the client disconnected.
+async fn run_cancellable<T, F>(state: &AppState, work: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&cancellation::CancelToken) -> Result<T, ApiError> + Send + 'static,
+{
+    let token = cancellation::CancelToken::new();
+    let cancel_on_drop = token.drop_guard();
+    let context = Arc::clone(&state.cuda_context);
+    let result = state.cuda_pool
+        .run(move || {
+            context.ensure_context().map_err(ApiError::cuda_init)?;
+            work(&token)
+        })
+        .await
+        .map_err(|e| ApiError::internal(format!("Simulation job failed: {}", e)))?;
+    cancel_on_drop.disarm();
+    result
+}
+
+fn log_progress(simulation_type: &str, progress: &cancellation::StepProgress) {
+    if progress.cancelled() {
+        info!(
+            "{} request cancelled after {}/{} steps",
+            simulation_type, progress.completed, progress.requested
+        );
+    }
 }
 
 async fn simulate_sph(
     State(state): State<AppState>,
-    Json(request): Json<SimulationRequest>,
-) -> Result<Json<SimulationResponse>, StatusCode> {
+    format: ResponseFormat,
+    ApiJson(request): ApiJson<SimulationRequest<physics::sph::SphParams>>,
+) -> Result<axum::response::Response, ApiError> {
     info!("SPH simulation request: {:?}", request);
     
-    // Initialize CUDA in this thread
-    cuda::init_cuda_in_thread()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    // Create context for this thread
-    let device_clone = *state.cuda_context.device().clone();
-    let _ctx = rustacuda::prelude::Context::create_and_push(
-        rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
-        device_clone
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
+    let num_particles = request.num_particles.unwrap_or(physics::sph::DEFAULT_NUM_PARTICLES);
+    physics::sph::check_num_particles(num_particles)
+        .map_err(ApiError::bad_request)?;
+    let params = request.params.unwrap_or_default();
+    params.validate()
+        .map_err(ApiError::bad_request)?;
+    let substeps = request.substeps.unwrap_or(physics::substeps::DEFAULT_SUBSTEPS);
+    physics::substeps::check_substeps(substeps)
+        .map_err(ApiError::bad_request)?;
     let start = std::time::Instant::now();
-    
-    // Create simulation
-    let mut sim = physics::SphSimulation::new(&state.cuda_context)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    // Run simulation steps
     let steps = request.steps.unwrap_or(1);
-    for _ in 0..steps {
-        sim.step(0.016)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    }
+    let seed = request.seed;
+    let context = Arc::clone(&state.cuda_context);
     
-    // Get results
-    let particles = sim.get_particles()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let dt = simulate_dt(&state);
+    let (mut particles, progress, accelerator, resets, fluid) = run_cancellable(&state, move |cancel| {
+        // Create simulation
+        let mut sim = physics::SphSimulation::with_seed(&context, num_particles, seed)
+            .map_err(ApiError::creation)?;
+        sim.set_params(&params)
+            .map_err(ApiError::bad_request)?;
+        sim.set_substeps(substeps)
+            .map_err(ApiError::bad_request)?;
+        
+        // Run simulation steps
+        let progress = cancel.run_steps(steps, || sim.step(dt))
+            .map_err(ApiError::internal)?;
+        
+        // Get results
+        let particles = sim.get_particles()
+            .map_err(ApiError::internal)?;
+        Ok((particles, progress, sim.accelerator(), sim.resets(), sim.fluid_params()))
+    }).await?;
+    log_progress("SPH", &progress);
+    let mut warnings = Vec::new();
+    if resets > 0 {
+        warnings.push(format!(
+            "fluid went non-finite and was reset to its initial layout {} time(s)",
+            resets
+        ));
+    }
+    if let Some(decimals) = request.round_to {
+        round_values(&mut particles, decimals);
+    }
     
     let duration = start.elapsed();
     
-    Ok(Json(SimulationResponse {
+    Ok(format.reply(SimulationResponse {
         success: true,
         data: Some(particles),
+        forces: None,
+        fields: None,
+        warnings,
         metadata: Some(SimulationMetadata {
             simulation_type: "sph".to_string(),
-            num_particles: 1000,
+            num_particles,
             computation_time_ms: duration.as_millis(),
-            accelerator: "cpu".to_string(),
+            accelerator,
+            steps_completed: progress.completed,
+            value_range: None,
+            dimensions: None,
+            seed,
+            stable: Some(resets == 0),
+            params: Some(fluid),
+        }),
+        error: None,
+        error_kind: None,
+    }))
+}
+
+async fn simulate_md(
+    State(state): State<AppState>,
+    format: ResponseFormat,
+    ApiJson(request): ApiJson<SimulationRequest>,
+) -> Result<axum::response::Response, ApiError> {
+    info!("MD simulation request: {:?}", request);
+
+    let num_particles = request.num_particles.unwrap_or(physics::md::DEFAULT_NUM_PARTICLES);
+    physics::md::check_num_particles(num_particles)
+        .map_err(ApiError::bad_request)?;
+    let start = std::time::Instant::now();
+    let steps = request.steps.unwrap_or(1);
+    let seed = request.seed.unwrap_or_else(rand::random);
+    let context = Arc::clone(&state.cuda_context);
+
+    let (mut particles, progress, accelerator) = run_cancellable(&state, move |cancel| {
+        let mut sim = physics::MdSimulation::new_seeded(&context, num_particles, seed)
+            .map_err(ApiError::creation)?;
+        let progress = cancel.run_steps(steps, || sim.step(physics::md::DEFAULT_DT))
+            .map_err(ApiError::internal)?;
+        let particles = sim.get_particles()
+            .map_err(ApiError::internal)?;
+        Ok((particles, progress, sim.accelerator()))
+    }).await?;
+    log_progress("MD", &progress);
+    if let Some(decimals) = request.round_to {
+        round_values(&mut particles, decimals);
+    }
+
+    Ok(format.reply(SimulationResponse {
+        success: true,
+        data: Some(particles),
+        forces: None,
+        fields: None,
+        warnings: Vec::new(),
+        metadata: Some(SimulationMetadata {
+            simulation_type: "md".to_string(),
+            num_particles,
+            computation_time_ms: start.elapsed().as_millis(),
+            accelerator,
+            steps_completed: progress.completed,
+            value_range: None,
+            dimensions: None,
+            seed: Some(seed),
+            stable: None,
+            params: None,
         }),
         error: None,
+        error_kind: None,
     }))
 }
 
 async fn simulate_boids(
     State(state): State<AppState>,
-    Json(request): Json<SimulationRequest>,
-) -> Result<Json<SimulationResponse>, StatusCode> {
+    headers: HeaderMap,
+    format: ResponseFormat,
+    ApiJson(request): ApiJson<SimulationRequest<physics::BoidsParams>>,
+) -> Result<axum::response::Response, ApiError> {
     info!("Boids simulation request: {:?}", request);
+    // Params persist on the shared flock, so they need the control token like its other settings
+    if request.params.is_some() && !auth::authorized(state.control_token.as_ref(), &headers, None) {
+        return Err(ApiError::unauthorized("Changing the shared flock's params needs the control token"));
+    }
     
-    // Initialize CUDA in this thread
-    cuda::init_cuda_in_thread()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let device = *state.cuda_context.device().clone();
-    let _ctx = rustacuda::prelude::Context::create_and_push(
-        rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
-        device
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
+    match request.dimensions {
+        None | Some(2) => {
+            let simulation = Arc::clone(&state.boids_simulation);
+            run_flock(&state, format, request, simulation, 2).await
+        }
+        Some(3) => {
+            let simulation = Arc::clone(&state.boids3d_simulation);
+            run_flock(&state, format, request, simulation, 3).await
+        }
+        Some(d) => Err(ApiError::bad_request(format!("dimensions must be 2 or 3, got {}", d))),
+    }
+}
+
+/// What `/api/simulate/boids` needs from a flock, so the 2D and 3D flocks share one runner
+trait SimulatedFlock: Send + 'static {
+    fn validate_params(&self, params: &physics::BoidsParams) -> anyhow::Result<()>;
+    fn set_params(&mut self, params: &physics::BoidsParams) -> anyhow::Result<Vec<String>>;
+    fn num_boids(&self) -> usize;
+    fn step(&mut self, dt: f32) -> anyhow::Result<physics::Accelerator>;
+    fn get_boids(&mut self) -> anyhow::Result<Vec<f32>>;
+    fn force_magnitudes(&mut self) -> anyhow::Result<Vec<f32>>;
+    fn accelerator(&self) -> physics::Accelerator;
+}
+
+impl SimulatedFlock for physics::BoidsSimulation {
+    fn validate_params(&self, params: &physics::BoidsParams) -> anyhow::Result<()> {
+        physics::BoidsSimulation::validate_params(self, params).map(drop)
+    }
+    fn set_params(&mut self, params: &physics::BoidsParams) -> anyhow::Result<Vec<String>> {
+        physics::BoidsSimulation::set_params(self, params)
+    }
+    fn num_boids(&self) -> usize {
+        physics::BoidsSimulation::num_boids(self)
+    }
+    fn step(&mut self, dt: f32) -> anyhow::Result<physics::Accelerator> {
+        physics::BoidsSimulation::step(self, dt)
+    }
+    fn get_boids(&mut self) -> anyhow::Result<Vec<f32>> {
+        physics::BoidsSimulation::get_boids(self)
+    }
+    fn force_magnitudes(&mut self) -> anyhow::Result<Vec<f32>> {
+        physics::BoidsSimulation::force_magnitudes(self)
+    }
+    fn accelerator(&self) -> physics::Accelerator {
+        physics::BoidsSimulation::accelerator(self)
+    }
+}
+
+impl SimulatedFlock for physics::Boids3DSimulation {
+    fn validate_params(&self, params: &physics::BoidsParams) -> anyhow::Result<()> {
+        self.rules().with_params(params).map(drop)
+    }
+    fn set_params(&mut self, params: &physics::BoidsParams) -> anyhow::Result<Vec<String>> {
+        physics::Boids3DSimulation::set_params(self, params)
+    }
+    fn num_boids(&self) -> usize {
+        physics::Boids3DSimulation::num_boids(self)
+    }
+    fn step(&mut self, dt: f32) -> anyhow::Result<physics::Accelerator> {
+        physics::Boids3DSimulation::step(self, dt)
+    }
+    fn get_boids(&mut self) -> anyhow::Result<Vec<f32>> {
+        physics::Boids3DSimulation::get_boids(self)
+    }
+    fn force_magnitudes(&mut self) -> anyhow::Result<Vec<f32>> {
+        physics::Boids3DSimulation::force_magnitudes(self)
+    }
+    fn accelerator(&self) -> physics::Accelerator {
+        physics::Boids3DSimulation::accelerator(self)
+    }
+}
+
+/// Step a shared flock for a simulate request and build the reply
+async fn run_flock<S: SimulatedFlock>(
+    state: &AppState,
+    format: ResponseFormat,
+    request: SimulationRequest<physics::BoidsParams>,
+    simulation: Arc<Mutex<S>>,
+    dimensions: u8,
+) -> Result<axum::response::Response, ApiError> {
     let steps = request.steps.unwrap_or(1);
+    let params = request.params;
+    let rejected = move |e: anyhow::Error| {
+        warn!("Rejected {}D boids params: {:?}", dimensions, e);
+        ApiError::bad_request(e)
+    };
+    // Validate up front; applying needs the CUDA context of the blocking thread
+    if let Some(params) = &params {
+        let sim = simulation.lock()
+            .map_err(|_| ApiError::internal("Simulation unavailable"))?;
+        sim.validate_params(params).map_err(rejected)?;
+    }
+    let include_forces = request.include_forces;
     
-    let (boids, duration, num_boids, accelerator) = {
-        let mut sim = state.boids_simulation
+    let dt = simulate_dt(state);
+    let (mut boids, forces, warnings, duration, num_boids, accelerator, progress) = run_cancellable(state, move |cancel| {
+        let mut sim = simulation
             .lock()
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|_| ApiError::internal("Simulation unavailable"))?;
+        let warnings = match &params {
+            Some(params) => sim.set_params(params).map_err(rejected)?,
+            None => Vec::new(),
+        };
         let num_boids = sim.num_boids();
         let start = std::time::Instant::now();
-        for _ in 0..steps {
-            sim.step(0.016)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        }
+        let progress = cancel.run_steps(steps, || sim.step(dt))
+            .map_err(ApiError::internal)?;
         let boids = sim.get_boids()
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let acc = if sim.used_cuda() { "cuda" } else { "cpu" };
-        (boids, start.elapsed(), num_boids, acc.to_string())
-    };
+            .map_err(ApiError::internal)?;
+        let forces = if include_forces {
+            Some(sim.force_magnitudes().map_err(ApiError::internal)?)
+        } else {
+            None
+        };
+        Ok((boids, forces, warnings, start.elapsed(), num_boids, sim.accelerator(), progress))
+    }).await?;
+    log_progress(&format!("Boids {}D", dimensions), &progress);
+    if let Some(decimals) = request.round_to {
+        round_values(&mut boids, decimals);
+    }
     
-    Ok(Json(SimulationResponse {
+    Ok(format.reply(SimulationResponse {
         success: true,
         data: Some(boids),
+        forces,
+        fields: None,
+        warnings,
         metadata: Some(SimulationMetadata {
             simulation_type: "boids".to_string(),
             num_particles: num_boids,
             computation_time_ms: duration.as_millis(),
             accelerator,
+            steps_completed: progress.completed,
+            value_range: None,
+            dimensions: Some(dimensions),
+            seed: None,
+            stable: None,
+            params: None,
         }),
         error: None,
+        error_kind: None,
     }))
 }
 
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum GrayScottFieldSelection {
+    #[default]
+    U,
+    Both,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GrayScottQuery {
+    #[serde(default)]
+    fields: GrayScottFieldSelection,
+}
+
 async fn simulate_grayscott(
     State(state): State<AppState>,
-    Json(request): Json<SimulationRequest>,
-) -> Result<Json<SimulationResponse>, StatusCode> {
+    format: ResponseFormat,
+    Query(query): Query<GrayScottQuery>,
+    ApiJson(request): ApiJson<SimulationRequest<physics::GrayScottParams>>,
+) -> Result<axum::response::Response, ApiError> {
     info!("Gray-Scott simulation request: {:?}", request);
     
-    cuda::init_cuda_in_thread()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let device_clone = *state.cuda_context.device().clone();
-    let _ctx = rustacuda::prelude::Context::create_and_push(
-        rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
-        device_clone
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let output = request.params.unwrap_or_default();
+    output.validate()
+        .map_err(|e| {
+            warn!("Rejected Gray-Scott params: {:?}", e);
+            ApiError::bad_request(e)
+        })?;
+    let (width, height) = output.size();
+    let substeps = request.substeps.unwrap_or(physics::substeps::DEFAULT_SUBSTEPS);
+    physics::substeps::check_substeps(substeps)
+        .map_err(ApiError::bad_request)?;
     
     let start = std::time::Instant::now();
-    
-    let mut sim = physics::GrayScottSimulation::new(&state.cuda_context, 512, 512)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
     let steps = request.steps.unwrap_or(1);
-    for _ in 0..steps {
-        sim.step(0.016)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    }
+    let seed = request.seed;
+    let context = Arc::clone(&state.cuda_context);
+    let params = output.clone();
+    let both = query.fields == GrayScottFieldSelection::Both;
     
-    let field = sim.get_field()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let dt = simulate_dt(&state);
+    let ((mut u, v), progress, accelerator) = run_cancellable(&state, move |cancel| {
+        let mut sim = physics::GrayScottSimulation::with_seed(&context, width, height, seed)
+            .map_err(ApiError::creation)?;
+        sim.set_params(&params)
+            .map_err(ApiError::bad_request)?;
+        sim.set_substeps(substeps)
+            .map_err(ApiError::bad_request)?;
+        let progress = cancel.run_steps(steps, || sim.step(dt))
+            .map_err(ApiError::internal)?;
+        let fields = if both {
+            sim.get_fields().map(|(u, v)| (u, Some(v)))
+        } else {
+            sim.get_field().map(|u| (u, None))
+        }
+        .map_err(ApiError::internal)?;
+        Ok((fields, progress, sim.accelerator()))
+    }).await?;
+    log_progress("Gray-Scott", &progress);
+    // Each field is rescaled from its own range; metadata reports u's
+    let value_range = output.output_range(&u);
+    if let Some(range) = value_range {
+        physics::grayscott::rescale_field(&mut u, range);
+    }
+    let mut v = v.map(|mut v| {
+        if let Some(range) = output.output_range(&v) {
+            physics::grayscott::rescale_field(&mut v, range);
+        }
+        v
+    });
+    if let Some(decimals) = request.round_to {
+        round_values(&mut u, decimals);
+        if let Some(v) = v.as_mut() {
+            round_values(v, decimals);
+        }
+    }
+    let (data, fields) = match v {
+        Some(v) => (None, Some(GrayScottFields { u, v })),
+        None => (Some(u), None),
+    };
     
     let duration = start.elapsed();
     
-    let accelerator = if cfg!(feature = "cuda-kernel") { "cuda" } else { "cpu" };
-    Ok(Json(SimulationResponse {
+    Ok(format.reply(SimulationResponse {
         success: true,
-        data: Some(field),
+        data,
+        forces: None,
+        fields,
+        warnings: Vec::new(),
         metadata: Some(SimulationMetadata {
             simulation_type: "grayscott".to_string(),
-            num_particles: 512 * 512,
+            num_particles: width * height,
             computation_time_ms: duration.as_millis(),
-            accelerator: accelerator.to_string(),
+            accelerator,
+            steps_completed: progress.completed,
+            value_range,
+            dimensions: None,
+            seed,
+            stable: None,
+            params: None,
         }),
         error: None,
+        error_kind: None,
     }))
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+// Upper bounds for /api/simulate/grayscott/stream
+const MAX_STREAM_STEPS: usize = 1_000_000;
+const MAX_STREAM_FRAMES: usize = 500;
+/// Gray-Scott streams that may run at once; later ones get a 503
+const GRAYSCOTT_STREAM_WORKERS: usize = 2;
+const DEFAULT_STREAM_EVERY: usize = 100;
 
-    info!("Initializing CUDA context...");
-    
-    // Initialize CUDA in main thread
-    cuda::init_cuda_in_thread()?;
-    
-    let cuda_context = Arc::new(cuda::CudaContext::new()?);
-    // Create a CUDA context on this thread for initial allocations
-    let device_clone = *cuda_context.device().clone();
-    let _ctx = rustacuda::prelude::Context::create_and_push(
-        rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
-        device_clone
-    )?;
-    let boids_simulation = Arc::new(Mutex::new(
-        physics::BoidsSimulation::new(&cuda_context, 1000)?
-    ));
-    
-    // Create persistent simulation engine with larger particle count
-    // Try to maximize - start with 100K, fall back if needed
-    let num_boids = 100_000;
-    info!("Creating simulation engine with {} boids", num_boids);
-    let simulation_engine = Arc::new(
-        simulation_engine::SimulationEngine::new(&cuda_context, num_boids)
-            .map_err(|e| {
-                warn!("Failed to create simulation engine with {} boids: {:?}, falling back to 10K", num_boids, e);
-                e
-            })
-            .or_else(|_| simulation_engine::SimulationEngine::new(&cuda_context, 10_000))?
-    );
-    
-    // Start the persistent simulation loop
-    simulation_engine.start()?;
-    info!("Simulation engine started");
+#[derive(Deserialize, Debug)]
+struct GrayScottStreamParams {
+    steps: Option<usize>,
+    // Emit the field every this many steps
+    every: Option<usize>,
+    round_to: Option<u8>,
+    // Grid size, as in `GrayScottParams` (default 512x512)
+    width: Option<usize>,
+    height: Option<usize>,
+    // `zstd` sends `field-zstd` events instead of `field`
+    compress: Option<compression::Compression>,
+}
+
+#[derive(Serialize)]
+struct GrayScottFrame {
+    step: usize,
+    // Set on the last frame so EventSource clients know not to reconnect
+    done: bool,
+    data: Vec<f32>,
+}
+
+/// A `GrayScottFrame` whose `data` is base64 of the zstd-compressed little-endian f32 field
+#[derive(Serialize)]
+struct CompressedGrayScottFrame {
+    step: usize,
+    done: bool,
+    // Bytes `data` decompresses to, so clients can preallocate
+    uncompressed_len: usize,
+    data: String,
+}
+
+impl GrayScottFrame {
+    fn into_event(self, compression: compression::Compression) -> anyhow::Result<Event> {
+        use base64::Engine;
+        let event = match compression {
+            compression::Compression::None => Event::default().event("field").json_data(&self)?,
+            compression::Compression::Zstd => {
+                let bytes = compression::f32_le_bytes(&self.data);
+                let frame = CompressedGrayScottFrame {
+                    step: self.step,
+                    done: self.done,
+                    uncompressed_len: bytes.len(),
+                    data: base64::engine::general_purpose::STANDARD.encode(compression::compress(&bytes)?),
+                };
+                Event::default().event("field-zstd").json_data(&frame)?
+            }
+        };
+        Ok(event)
+    }
+}
+
+/// Emission interval for a run of `steps`, widened so at most `MAX_STREAM_FRAMES` are sent
+fn stream_interval(steps: usize, every: Option<usize>) -> usize {
+    let min_every = steps.div_ceil(MAX_STREAM_FRAMES).max(1);
+    every.unwrap_or(DEFAULT_STREAM_EVERY).max(min_every)
+}
+
+/// Run Gray-Scott and stream the intermediate field as server-sent events
+async fn stream_grayscott(
+    State(state): State<AppState>,
+    Query(params): Query<GrayScottStreamParams>,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>>, StatusCode>
+{
+    info!("Gray-Scott stream request: {:?}", params);
+
+    let steps = params.steps.unwrap_or(10_000);
+    if steps == 0 || steps > MAX_STREAM_STEPS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let every = stream_interval(steps, params.every);
+    let round_to = params.round_to;
+    let width = params.width.unwrap_or(physics::grayscott::DEFAULT_GRID_SIZE);
+    let height = params.height.unwrap_or(physics::grayscott::DEFAULT_GRID_SIZE);
+    physics::grayscott::check_grid_size(width, height).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let compression = params.compress.unwrap_or_default();
+    compression.check_available().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let permit = Arc::clone(&state.grayscott_streams).try_acquire_owned().map_err(|_| {
+        warn!("Rejected Gray-Scott stream: {} already running", GRAYSCOTT_STREAM_WORKERS);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    // A small bounded channel applies back-pressure: the step loop waits for the client.
+    // Frames are encoded (and compressed) here, off the async runtime.
+    let (tx, rx) = tokio::sync::mpsc::channel::<Event>(2);
+    let context = Arc::clone(&state.cuda_context);
+    let dt = simulate_dt(&state);
+    // Runs for the whole stream, so it takes a stream worker rather than a simulate one
+    let job = move || {
+        // Held until the run ends, freeing the slot for the next stream
+        let _permit = permit;
+        let run = || -> anyhow::Result<()> {
+            let mut sim = physics::GrayScottSimulation::new(&context, width, height)?;
+            for step in 1..=steps {
+                sim.step(dt)?;
+                if step % every != 0 && step != steps {
+                    continue;
+                }
+                let mut data = sim.get_field()?;
+                if let Some(decimals) = round_to {
+                    round_values(&mut data, decimals);
+                }
+                let frame = GrayScottFrame { step, done: step == steps, data };
+                let event = frame
+                    .into_event(compression)
+                    .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()));
+                if tx.blocking_send(event).is_err() {
+                    info!("Gray-Scott stream client disconnected at step {}", step);
+                    break;
+                }
+            }
+            Ok(())
+        };
+        if let Err(e) = run() {
+            warn!("Gray-Scott stream failed: {:?}", e);
+        }
+    };
+    state.stream_pool.spawn(job).map_err(|e| {
+        warn!("Failed to start Gray-Scott stream: {:?}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    let events = futures_util::stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        Some((Ok(event), rx))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn get_emitter(State(state): State<AppState>) -> Json<Option<physics::emitter::EmitterConfig>> {
+    Json(state.simulation_engine.emitter_config())
+}
+
+/// Replace the streamed simulation's emitter; a `null` body disables it
+async fn put_emitter(
+    State(state): State<AppState>,
+    Json(config): Json<Option<physics::emitter::EmitterConfig>>,
+) -> Result<Json<Option<physics::emitter::EmitterConfig>>, StatusCode> {
+    info!("Emitter update: {:?}", config);
+    state.simulation_engine.set_emitter(config)
+        .map_err(|e| {
+            warn!("Rejected emitter config: {:?}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    Ok(Json(state.simulation_engine.emitter_config()))
+}
+
+#[derive(Serialize)]
+struct SimulationMetricsResponse {
+    running: bool,
+    paused: bool,
+    frame_count: u64,
+    avg_frame_ms: f32,
+    p99_frame_ms: f32,
+    target_fps: f32,
+    achieved_fps: f32,
+    time_scale: f32,
+    accelerator: physics::Accelerator,
+    num_boids: usize,
+    connections: usize,
+    /// The flock has been at rest for the stasis window
+    stasis: bool,
+}
+
+async fn get_simulation_metrics(State(state): State<AppState>) -> Json<SimulationMetricsResponse> {
+    let engine = &state.simulation_engine;
+    let stats = engine.frame_stats();
+    Json(SimulationMetricsResponse {
+        running: engine.is_running(),
+        paused: engine.is_paused(),
+        frame_count: stats.frame_count,
+        avg_frame_ms: stats.avg_frame_ms,
+        p99_frame_ms: stats.p99_frame_ms,
+        target_fps: stats.target_fps,
+        achieved_fps: stats.achieved_fps,
+        time_scale: stats.time_scale,
+        accelerator: physics::Accelerator::from_used_cuda(stats.used_cuda),
+        num_boids: engine.num_boids(),
+        connections: state.metrics.active_connections(),
+        stasis: engine.in_stasis(),
+    })
+}
+
+#[derive(Serialize)]
+struct StepResponse {
+    frame: u64,
+    num_boids: usize,
+    /// [x, y, vx, vy] per boid
+    data: Vec<f32>,
+    ids: Vec<u32>,
+}
+
+/// Advance a pull-mode engine by one fixed step and return the new state
+async fn step_simulation(
+    State(state): State<AppState>,
+) -> Result<Json<StepResponse>, (StatusCode, String)> {
+    if state.simulation_engine.is_running() {
+        return Err((
+            StatusCode::CONFLICT,
+            "Engine is running continuously; start the server with mode = \"pull\"".to_string(),
+        ));
+    }
+    let engine = Arc::clone(&state.simulation_engine);
+    let result = tokio::task::spawn_blocking(move || {
+        let frame = engine.tick()?;
+        let snapshot = engine.snapshot()?;
+        Ok::<_, anyhow::Error>((frame, snapshot))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (frame, snapshot) = result.map_err(|e| {
+        warn!("Pull-mode step failed: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Simulation step failed".to_string())
+    })?;
+    Ok(Json(StepResponse {
+        frame,
+        num_boids: snapshot.ids.len(),
+        data: snapshot.state,
+        ids: snapshot.ids,
+    }))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct BoundaryRequest {
+    mode: physics::boids::BoundaryMode,
+}
+
+async fn get_boundary(State(state): State<AppState>) -> Json<BoundaryRequest> {
+    Json(BoundaryRequest { mode: state.simulation_engine.boundary_mode() })
+}
+
+/// Switch the streamed simulation between wrap, bounce and open boundaries
+async fn put_boundary(
+    State(state): State<AppState>,
+    Json(request): Json<BoundaryRequest>,
+) -> Result<Json<BoundaryRequest>, StatusCode> {
+    info!("Boundary mode update: {:?}", request.mode);
+    state.simulation_engine.set_boundary_mode(request.mode)
+        .map_err(|e| {
+            warn!("Failed to set boundary mode: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(BoundaryRequest { mode: state.simulation_engine.boundary_mode() }))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct ObstaclesRequest {
+    obstacles: Vec<physics::obstacles::Obstacle>,
+}
+
+/// Replace the obstacles the streamed flock steers around
+async fn post_obstacles(
+    State(state): State<AppState>,
+    Json(request): Json<ObstaclesRequest>,
+) -> Result<Json<ObstaclesRequest>, (StatusCode, String)> {
+    info!("Obstacles update: {} obstacles", request.obstacles.len());
+    state.simulation_engine.set_obstacles(request.obstacles)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(ObstaclesRequest { obstacles: state.simulation_engine.obstacles() }))
+}
+
+/// Largest flock `/api/simulation/graph` will enumerate
+const MAX_GRAPH_BOIDS: usize = 500;
+
+/// Interaction network of the streamed flock, for drawing connection lines
+async fn get_neighbor_graph(
+    State(state): State<AppState>,
+) -> Result<Json<physics::boids::NeighborGraph>, (StatusCode, String)> {
+    match state.simulation_engine.neighbor_graph(MAX_GRAPH_BOIDS) {
+        Ok(Some(graph)) => Ok(Json(graph)),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Neighbor graph is limited to {} boids, simulation has {}",
+                MAX_GRAPH_BOIDS,
+                state.simulation_engine.num_boids()
+            ),
+        )),
+        Err(e) => {
+            warn!("Failed to build neighbor graph: {:?}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to build neighbor graph".to_string()))
+        }
+    }
+}
+
+/// Mean speed and its variance, kinetic energy, nearest-neighbour spacing and
+/// per-species centroids of the streamed flock
+async fn get_boids_stats(
+    State(state): State<AppState>,
+) -> Result<Json<physics::stats::BoidStats>, (StatusCode, String)> {
+    let engine = Arc::clone(&state.simulation_engine);
+    tokio::task::spawn_blocking(move || engine.statistics())
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Stats task failed".to_string()))?
+        .map(Json)
+        .map_err(|e| {
+            warn!("Failed to compute flock statistics: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute flock statistics".to_string())
+        })
+}
+
+/// Population and suggested colour of each species in the streamed flock
+async fn get_boids_species(
+    State(state): State<AppState>,
+) -> Result<Json<physics::stats::SpeciesSummary>, (StatusCode, String)> {
+    let engine = Arc::clone(&state.simulation_engine);
+    tokio::task::spawn_blocking(move || engine.species_summary())
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Species task failed".to_string()))?
+        .map(Json)
+        .map_err(|e| {
+            warn!("Failed to count species: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to count species".to_string())
+        })
+}
+
+#[derive(Deserialize, Debug)]
+struct DensityParams {
+    res: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct DensityResponse {
+    resolution: usize,
+    /// Row-major `resolution`² boid counts, row 0 at y = 0
+    counts: Vec<u32>,
+}
+
+/// Coarse histogram of where the streamed flock is, for heatmaps
+async fn get_boids_density(
+    State(state): State<AppState>,
+    Query(params): Query<DensityParams>,
+) -> Result<Json<DensityResponse>, (StatusCode, String)> {
+    let resolution = params.res.unwrap_or(physics::stats::DEFAULT_DENSITY_RESOLUTION);
+    physics::stats::check_density_resolution(resolution)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let engine = Arc::clone(&state.simulation_engine);
+    let counts = tokio::task::spawn_blocking(move || engine.density_grid(resolution))
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Density task failed".to_string()))?
+        .map_err(|e| {
+            warn!("Failed to compute flock density: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to compute flock density".to_string())
+        })?;
+    Ok(Json(DensityResponse { resolution, counts }))
+}
+
+#[derive(Deserialize, Debug)]
+struct SnapshotParams {
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+/// PNG preview of the running flock, for thumbnails
+async fn get_boids_snapshot(
+    State(state): State<AppState>,
+    Query(params): Query<SnapshotParams>,
+) -> Result<([(axum::http::header::HeaderName, &'static str); 1], Vec<u8>), (StatusCode, String)> {
+    let width = params.width.unwrap_or(physics::thumbnail::DEFAULT_THUMBNAIL_SIZE);
+    let height = params.height.unwrap_or(physics::thumbnail::DEFAULT_THUMBNAIL_SIZE);
+    physics::thumbnail::check_size(width, height)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let engine = Arc::clone(&state.simulation_engine);
+    let png = tokio::task::spawn_blocking(move || {
+        let rgba = engine.render_thumbnail(width, height)?;
+        physics::sdf::encode_png(rgba, width, height)
+    })
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Snapshot task failed".to_string()))?
+    .map_err(|e| {
+        warn!("Failed to render flock snapshot: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render flock snapshot".to_string())
+    })?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "image/png")], png))
+}
+
+#[derive(Deserialize, Debug)]
+struct ImageInitParams {
+    count: usize,
+    /// Sample bright pixels instead of dark ones
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    bright: bool,
+    /// Seed for the sampled positions, for a reproducible layout
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ImageInitResponse {
+    num_boids: usize,
+    width: u32,
+    height: u32,
+}
+
+/// Re-seed the streamed flock from a grayscale PNG body, weighted by luminance
+async fn init_boids_from_image(
+    State(state): State<AppState>,
+    Query(params): Query<ImageInitParams>,
+    body: axum::body::Bytes,
+) -> Result<Json<ImageInitResponse>, (StatusCode, String)> {
+    info!("Image init request: {:?} ({} bytes)", params, body.len());
+    let (positions, width, height) = tokio::task::spawn_blocking(move || {
+        let map = physics::image_init::LuminanceMap::from_png(&body, params.bright)?;
+        let mut rng = params
+            .seed
+            .map_or_else(physics::rng::SimRng::from_entropy, physics::rng::SimRng::new);
+        let positions = map.sample(params.count, &mut rng)?;
+        Ok::<_, anyhow::Error>((positions, map.width, map.height))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    state.simulation_engine.reset_positions(&positions).map_err(|e| {
+        warn!("Failed to reset boids from image: {:?}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to reset boids".to_string())
+    })?;
+    Ok(Json(ImageInitResponse {
+        num_boids: positions.len(),
+        width,
+        height,
+    }))
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ResetRequest {
+    /// Re-seed deterministically; a random seed is used when omitted
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ResetResponse {
+    seed: u64,
+    num_boids: usize,
+}
+
+/// Re-seed the streamed flock without restarting the server or dropping clients
+async fn reset_boids(
+    State(state): State<AppState>,
+    body: axum::body::Bytes,
+) -> Result<Json<ResetResponse>, (StatusCode, String)> {
+    // The body is optional, so an empty POST resets with a random seed
+    let request: ResetRequest = if body.is_empty() {
+        ResetRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    };
+    info!("Boids reset request: {:?}", request);
+
+    let engine = Arc::clone(&state.simulation_engine);
+    let seed = tokio::task::spawn_blocking(move || engine.reset(request.seed))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| {
+            warn!("Failed to reset boids: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to reset boids".to_string())
+        })?;
+    Ok(Json(ResetResponse {
+        seed,
+        num_boids: state.simulation_engine.num_boids(),
+    }))
+}
+
+#[derive(Deserialize, Debug)]
+struct ResizeRequest {
+    num: usize,
+}
+
+#[derive(Serialize)]
+struct ResizeResponse {
+    num_boids: usize,
+}
+
+/// Grow or shrink the streamed flock in place; clients see the new count in the next frame
+async fn resize_boids(
+    State(state): State<AppState>,
+    Json(request): Json<ResizeRequest>,
+) -> Result<Json<ResizeResponse>, (StatusCode, String)> {
+    info!("Boids resize request: {:?}", request);
+    physics::boids::check_num_boids(request.num).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let engine = Arc::clone(&state.simulation_engine);
+    tokio::task::spawn_blocking(move || engine.resize(request.num))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| {
+            warn!("Failed to resize boids: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resize boids".to_string())
+        })?;
+    Ok(Json(ResizeResponse {
+        num_boids: state.simulation_engine.num_boids(),
+    }))
+}
+
+/// Snapshot the streamed flock as a binary blob that `/restore` accepts later
+async fn checkpoint_boids(
+    State(state): State<AppState>,
+) -> Result<([(axum::http::header::HeaderName, &'static str); 1], Vec<u8>), (StatusCode, String)> {
+    let engine = Arc::clone(&state.simulation_engine);
+    let bytes = tokio::task::spawn_blocking(move || engine.checkpoint())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| {
+            warn!("Failed to checkpoint boids: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to checkpoint boids".to_string())
+        })?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/octet-stream")], bytes))
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    num_boids: usize,
+}
+
+/// Replace the streamed flock with a blob from `/checkpoint`
+async fn restore_boids(
+    State(state): State<AppState>,
+    body: axum::body::Bytes,
+) -> Result<Json<RestoreResponse>, (StatusCode, String)> {
+    let checkpoint = physics::boids::decode_checkpoint(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid checkpoint: {}", e)))?;
+    info!("Boids restore request: {} boids", checkpoint.boids.len());
+
+    let engine = Arc::clone(&state.simulation_engine);
+    tokio::task::spawn_blocking(move || engine.restore(checkpoint))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| {
+            warn!("Failed to restore boids: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore boids".to_string())
+        })?;
+    Ok(Json(RestoreResponse {
+        num_boids: state.simulation_engine.num_boids(),
+    }))
+}
+
+#[derive(Serialize)]
+struct PauseResponse {
+    paused: bool,
+    frame_count: u64,
+}
+
+async fn pause_boids(State(state): State<AppState>) -> Json<PauseResponse> {
+    let engine = &state.simulation_engine;
+    engine.pause();
+    Json(PauseResponse {
+        paused: true,
+        frame_count: engine.get_frame_count(),
+    })
+}
+
+async fn resume_boids(State(state): State<AppState>) -> Json<PauseResponse> {
+    let engine = &state.simulation_engine;
+    engine.resume();
+    Json(PauseResponse {
+        paused: false,
+        frame_count: engine.get_frame_count(),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct TargetFpsRequest {
+    fps: Option<f32>,
+    /// Floor for the adaptive timer, which lowers the target when steps fall behind
+    min_fps: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct TargetFpsResponse {
+    target_fps: f32,
+    min_fps: f32,
+}
+
+/// Set the engine's update rate and/or adaptive floor; replies with the clamped values
+async fn set_target_fps(
+    State(state): State<AppState>,
+    Json(request): Json<TargetFpsRequest>,
+) -> Result<Json<TargetFpsResponse>, (StatusCode, String)> {
+    let engine = &state.simulation_engine;
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    if let Some(fps) = request.fps {
+        let applied = engine.set_target_fps(fps).map_err(bad_request)?;
+        info!("Target FPS set to {} (requested {})", applied, fps);
+    }
+    if let Some(fps) = request.min_fps {
+        let applied = engine.set_min_fps(fps).map_err(bad_request)?;
+        info!("Minimum FPS set to {} (requested {})", applied, fps);
+    }
+    Ok(Json(TargetFpsResponse {
+        target_fps: engine.target_fps(),
+        min_fps: engine.min_fps(),
+    }))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TimeScale {
+    time_scale: f32,
+}
+
+async fn get_time_scale(State(state): State<AppState>) -> Json<TimeScale> {
+    Json(TimeScale { time_scale: state.simulation_engine.time_scale() })
+}
+
+/// Set the multiplier on every step's `dt`; replies with the clamped value
+async fn set_time_scale(
+    State(state): State<AppState>,
+    Json(request): Json<TimeScale>,
+) -> Result<Json<TimeScale>, (StatusCode, String)> {
+    let applied = state.simulation_engine
+        .set_time_scale(request.time_scale)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    info!("Time scale set to {} (requested {})", applied, request.time_scale);
+    Ok(Json(TimeScale { time_scale: applied }))
+}
+
+#[derive(Serialize)]
+struct BenchmarkResponse {
+    steps: usize,
+    seed: u64,
+    results: Vec<benchmark::BenchResult>,
+}
+
+/// Time a sweep of boid counts on throwaway simulations; the running engine is untouched
+async fn run_benchmark(
+    State(state): State<AppState>,
+    Json(request): Json<benchmark::SweepRequest>,
+) -> Result<Json<BenchmarkResponse>, (StatusCode, String)> {
+    let (counts, steps) = request.resolve().map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let seed = request.seed.unwrap_or(benchmark::DEFAULT_BENCH_SEED);
+    info!("Benchmark request: counts {:?}, {} steps", counts, steps);
+
+    let context = Arc::clone(&state.cuda_context);
+    let results = run_cancellable(&state, move |cancel| {
+        benchmark::run_sweep(&context, &counts, steps, seed, || cancel.is_cancelled())
+            .map_err(ApiError::internal)
+    })
+    .await
+    .map_err(|e| e.into_text("Benchmark failed"))?;
+
+    Ok(Json(BenchmarkResponse { steps, seed, results }))
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateInstanceRequest {
+    #[serde(rename = "type")]
+    simulation_type: String,
+    /// Boids in the new flock (default `instances::DEFAULT_INSTANCE_BOIDS`)
+    num: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct InstanceInfo {
+    id: uuid::Uuid,
+    #[serde(rename = "type")]
+    simulation_type: &'static str,
+    num_boids: usize,
+}
+
+#[derive(Serialize)]
+struct InstanceList {
+    instances: Vec<uuid::Uuid>,
+    max_instances: usize,
+}
+
+fn instance_error(e: instances::CreateError) -> (StatusCode, String) {
+    let status = match e {
+        instances::CreateError::LimitReached => StatusCode::SERVICE_UNAVAILABLE,
+        instances::CreateError::InvalidSize(_) => StatusCode::BAD_REQUEST,
+    };
+    (status, e.to_string())
+}
+
+async fn create_instance(
+    State(state): State<AppState>,
+    Json(request): Json<CreateInstanceRequest>,
+) -> Result<(StatusCode, Json<InstanceInfo>), (StatusCode, String)> {
+    if request.simulation_type != "boids" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Only boids instances are supported, got {:?}", request.simulation_type),
+        ));
+    }
+    let num_boids = request.num.unwrap_or(instances::DEFAULT_INSTANCE_BOIDS);
+    state.instances.check_create(num_boids).map_err(instance_error)?;
+
+    let context = Arc::clone(&state.cuda_context);
+    let simulation = run_cancellable(&state, move |_| {
+        physics::BoidsSimulation::new(&context, num_boids).map_err(ApiError::creation)
+    })
+    .await
+    .map_err(|e| e.into_text("Failed to create simulation"))?;
+    let (id, _) = state.instances.insert(simulation).map_err(instance_error)?;
+    info!("Created boids instance {} with {} boids", id, num_boids);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(InstanceInfo {
+            id,
+            simulation_type: "boids",
+            num_boids,
+        }),
+    ))
+}
+
+async fn list_instances(State(state): State<AppState>) -> Json<InstanceList> {
+    Json(InstanceList {
+        instances: state.instances.ids(),
+        max_instances: instances::MAX_INSTANCES,
+    })
+}
+
+async fn delete_instance(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+) -> StatusCode {
+    if state.instances.remove(&id) {
+        info!("Deleted boids instance {}", id);
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RecordStartRequest {
+    /// File name in the recording directory (`.rec` appended); timestamped when omitted
+    name: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct RecordStartResponse {
+    file: String,
+}
+
+fn record_error(e: recorder::RecordError) -> (StatusCode, String) {
+    let status = match e {
+        recorder::RecordError::AlreadyRecording(_) | recorder::RecordError::NotRecording => StatusCode::CONFLICT,
+        recorder::RecordError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        recorder::RecordError::NotFound(_) => StatusCode::NOT_FOUND,
+        recorder::RecordError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
+
+/// Start appending every broadcast frame to a file for later `/ws?replay=` playback
+async fn start_recording(
+    State(state): State<AppState>,
+    body: axum::body::Bytes,
+) -> Result<Json<RecordStartResponse>, (StatusCode, String)> {
+    // The body is optional; an empty POST records under a timestamped name
+    let request: RecordStartRequest = if body.is_empty() {
+        RecordStartRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    };
+    let file = state.recorder.start(request.name.as_deref()).map_err(record_error)?;
+    Ok(Json(RecordStartResponse { file }))
+}
+
+async fn stop_recording(
+    State(state): State<AppState>,
+) -> Result<Json<recorder::RecordingSummary>, (StatusCode, String)> {
+    let recorder = Arc::clone(&state.recorder);
+    // Waits for the writer to flush queued frames
+    tokio::task::spawn_blocking(move || recorder.stop())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .map_err(record_error)
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct InstanceStepRequest {
+    steps: Option<usize>,
+    params: Option<physics::BoidsParams>,
+}
+
+/// Current state of an instance without stepping it
+async fn get_instance(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+    format: ResponseFormat,
+) -> Result<axum::response::Response, ApiError> {
+    run_instance(state, id, 0, None, format).await
+}
+
+/// Step an instance (once by default) and publish the result to its `/ws?sim=<id>` subscribers
+async fn step_instance(
+    State(state): State<AppState>,
+    Path(id): Path<uuid::Uuid>,
+    format: ResponseFormat,
+    body: axum::body::Bytes,
+) -> Result<axum::response::Response, ApiError> {
+    // The body is optional, so an empty POST takes a single step
+    let request: InstanceStepRequest = if body.is_empty() {
+        InstanceStepRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(ApiError::bad_request)?
+    };
+    run_instance(state, id, request.steps.unwrap_or(1), request.params, format).await
+}
+
+async fn run_instance(
+    state: AppState,
+    id: uuid::Uuid,
+    steps: usize,
+    params: Option<physics::BoidsParams>,
+    format: ResponseFormat,
+) -> Result<axum::response::Response, ApiError> {
+    let instance = state
+        .instances
+        .get(&id)
+        .ok_or_else(|| ApiError::not_found(format!("No simulation {}", id)))?;
+    if let Some(params) = &params {
+        let sim = instance.simulation.lock()
+            .map_err(|_| ApiError::internal("Simulation unavailable"))?;
+        sim.validate_params(params)
+            .map_err(ApiError::bad_request)?;
+    }
+
+    let dt = simulate_dt(&state);
+    let (boids, warnings, duration, num_boids, accelerator, progress) = run_cancellable(&state, move |cancel| {
+        let mut sim = instance.simulation
+            .lock()
+            .map_err(|_| ApiError::internal("Simulation unavailable"))?;
+        let warnings = match &params {
+            Some(params) => sim.set_params(params).map_err(ApiError::bad_request)?,
+            None => Vec::new(),
+        };
+        let start = std::time::Instant::now();
+        let progress = cancel.run_steps(steps, || sim.step(dt))
+            .map_err(ApiError::internal)?;
+        if progress.completed > 0 {
+            if let Err(e) = instance.publish(&mut sim) {
+                warn!("Failed to publish instance {}: {:?}", id, e);
+            }
+        }
+        let boids = sim.get_boids()
+            .map_err(ApiError::internal)?;
+        Ok((boids, warnings, start.elapsed(), sim.num_boids(), sim.accelerator(), progress))
+    }).await?;
+    log_progress("Boids instance", &progress);
+
+    Ok(format.reply(SimulationResponse {
+        success: true,
+        data: Some(boids),
+        forces: None,
+        fields: None,
+        warnings,
+        metadata: Some(SimulationMetadata {
+            simulation_type: "boids".to_string(),
+            num_particles: num_boids,
+            computation_time_ms: duration.as_millis(),
+            accelerator,
+            steps_completed: progress.completed,
+            value_range: None,
+            dimensions: Some(2),
+            seed: None,
+            stable: None,
+            params: None,
+        }),
+        error: None,
+        error_kind: None,
+    }))
+}
+
+#[derive(Deserialize, Debug)]
+struct SdfSampleRequest {
+    scene: String,
+    x: f32,
+    y: f32,
+}
+
+#[derive(Serialize)]
+struct SdfSampleResponse {
+    distance: f32,
+    inside: bool,
+}
+
+/// Evaluate the signed distance of a scene at a single point
+async fn sample_sdf(
+    Json(request): Json<SdfSampleRequest>,
+) -> Result<Json<SdfSampleResponse>, (StatusCode, String)> {
+    let shape = physics::sdf::Shape::parse(&request.scene)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid scene: {}", e)))?;
+    let distance = shape.distance(request.x, request.y);
+    Ok(Json(SdfSampleResponse {
+        distance,
+        inside: distance < 0.0,
+    }))
+}
+
+#[derive(Deserialize, Debug)]
+struct SdfRenderRequest {
+    /// `circle`, `box` or `rounded_box`
+    sdf_function: String,
+    /// Fraction of half the shorter side the shape spans, (0, 1]
+    size: Option<f32>,
+    width: Option<usize>,
+    height: Option<usize>,
+    /// `png` (default) or `rgba` for raw row-major RGBA bytes
+    format: Option<String>,
+}
+
+/// Render an antialiased SDF primitive to an image
+async fn render_sdf(
+    State(state): State<AppState>,
+    Json(request): Json<SdfRenderRequest>,
+) -> Result<([(axum::http::header::HeaderName, &'static str); 1], Vec<u8>), (StatusCode, String)> {
+    info!("SDF render request: {:?}", request);
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let primitive = request.sdf_function.clone();
+    primitive.parse::<physics::sdf::Primitive>().map_err(bad_request)?;
+    let size = request.size.unwrap_or(0.8);
+    let (width, height) = (request.width.unwrap_or(256), request.height.unwrap_or(256));
+    physics::sdf::check_render_size(width, height, size).map_err(bad_request)?;
+    let png = match request.format.as_deref() {
+        None | Some("png") => true,
+        Some("rgba") => false,
+        Some(other) => {
+            return Err((StatusCode::BAD_REQUEST, format!("format must be png or rgba, got {}", other)))
+        }
+    };
+
+    let context = Arc::clone(&state.cuda_context);
+    let rgba = run_cancellable(&state, move |_| {
+        let mut renderer = physics::sdf::SdfRenderer::new(&context, width, height)
+            .map_err(ApiError::creation)?;
+        renderer.render(&primitive, size)
+            .map_err(ApiError::internal)
+    })
+    .await
+    .map_err(|e| e.into_text("SDF render failed"))?;
+
+    if png {
+        let png = physics::sdf::encode_png(rgba, width, height)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        Ok(([(axum::http::header::CONTENT_TYPE, "image/png")], png))
+    } else {
+        Ok(([(axum::http::header::CONTENT_TYPE, "application/octet-stream")], rgba))
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let bench_config = benchmark::BenchConfig::from_args(std::env::args())?;
+    let args: Vec<String> = std::env::args().collect();
+    let config = config::Config::load(&args)?;
+    info!("Configuration: {:?}", config);
+
+    info!("Initializing CUDA context...");
+    
+    // Initialize CUDA in main thread
+    cuda::init_cuda_in_thread()?;
+    
+    let cuda_context = Arc::new(cuda::CudaContext::with_device(config.device)?);
+    // Create a CUDA context on this thread for initial allocations
+    let device_clone = cuda_context.device().copied();
+    let _ctx = cuda_context.push_context()?;
+
+    // Headless benchmark mode: print JSON results and exit without serving
+    if let Some(config) = bench_config {
+        info!("Running headless benchmark: {:?}", config);
+        let results = benchmark::run_suite(&cuda_context, &config)?;
+        println!("{}", serde_json::to_string(&serde_json::json!({ "results": results }))?);
+        return Ok(());
+    }
+
+    // Check the compiled kernel actually loads on this GPU so a CPU fallback is visible
+    let capabilities = Arc::new(capabilities::probe(cuda_context.device()));
+    if capabilities.boids_kernel.loaded {
+        info!("Boids CUDA kernel loaded (compute capability {:?})", capabilities.compute_capability);
+    } else {
+        warn!(
+            "Boids CUDA kernel unavailable, CPU fallback will be used: {}",
+            capabilities.boids_kernel.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    // Build NVRTC kernels now so the first request doesn't pay the compile latency.
+    // NVRTC_PRECOMPILE=0 skips this for faster dev startup.
+    #[cfg(feature = "cuda-kernel")]
+    {
+        let precompile = std::env::var("NVRTC_PRECOMPILE")
+            .map(|v| v != "0" && !v.eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+        if precompile {
+            let threads = std::env::var("NVRTC_COMPILE_THREADS")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            if let Err(e) = physics::kernel_cache::precompile_all(threads) {
+                warn!("NVRTC precompilation failed, kernels will compile on first use: {:?}", e);
+            }
+        } else {
+            info!("Skipping NVRTC precompilation (NVRTC_PRECOMPILE=0)");
+        }
+    }
+
+    let boids_simulation = Arc::new(Mutex::new(
+        physics::BoidsSimulation::new(&cuda_context, 1000)?
+    ));
+    let boids3d_simulation = Arc::new(Mutex::new(
+        physics::Boids3DSimulation::new(&cuda_context, 1000)?
+    ));
+    
+    // Create persistent simulation engine, halving the configured count until it fits in GPU memory
+    let simulation_engine = Arc::new(simulation_engine::SimulationEngine::best_effort(
+        &cuda_context,
+        config.num_boids,
+        MIN_ENGINE_BOIDS.min(config.num_boids),
+        config.num_species,
+    )?);
+    let num_boids = simulation_engine.num_boids();
+    if num_boids < config.num_boids {
+        warn!("Simulation engine created with {} of the configured {} boids", num_boids, config.num_boids);
+    } else {
+        info!("Simulation engine created with {} boids", num_boids);
+    }
+    
+    let target_fps = simulation_engine.set_target_fps(config.target_fps)?;
+    if target_fps != config.target_fps {
+        warn!("target_fps {} is out of range, using {}", config.target_fps, target_fps);
+    }
+    simulation_engine.set_params(&physics::BoidsParams {
+        boundary: Some(config.boundary),
+        ..Default::default()
+    })?;
+
+    // Optional separation auto-tuner
+    if config.auto_tune {
+        simulation_engine.set_params(&physics::BoidsParams {
+            auto_tune: Some(true),
+            target_density: config.target_density,
+            ..Default::default()
+        })?;
+        info!("Separation auto-tuner enabled (target density {:?})", config.target_density);
+    }
+
+    // Thermal throttling (gpu_temp_limit_c = 0 disables it)
+    let thermal_limit = (config.gpu_temp_limit_c > 0).then_some(config.gpu_temp_limit_c);
+    simulation_engine.set_thermal_limit(
+        thermal_limit,
+        std::time::Duration::from_millis(config.gpu_stats_interval_ms),
+    );
+
+    // Start the persistent simulation loop unless clients drive it
+    match config.mode {
+        simulation_engine::EngineMode::Continuous => {
+            simulation_engine.start()?;
+            info!("Simulation engine started");
+        }
+        simulation_engine::EngineMode::Pull => {
+            info!("Simulation engine in pull mode; POST /api/simulation/step to advance");
+        }
+    }
+
+    let metrics = Arc::new(metrics::ServerMetrics::new());
+
+    let gpu_stats_interval = std::time::Duration::from_millis(config.gpu_stats_interval_ms);
+    // Optional CSV metrics log (METRICS_CSV_PATH)
+    if let Some(config) = csv_log::CsvLogConfig::from_env() {
+        if let Err(e) = csv_log::spawn(
+            config,
+            Arc::clone(&simulation_engine),
+            Arc::clone(&metrics),
+            device_clone,
+            gpu_stats_interval,
+        ) {
+            warn!("Metrics CSV logging disabled: {:?}", e);
+        }
+    }
     
     // Create broadcast channel for WebSocket clients
     let (broadcast_tx, _) = tokio_broadcast::channel::<broadcast::BroadcastState>(100);
     
+    // Set by the shutdown signal; stops the broadcast task and closes WebSockets
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
     // Spawn broadcast task
     let engine_clone = Arc::clone(&simulation_engine);
     let tx_clone = broadcast_tx.clone();
-    tokio::spawn(async move {
-        // Initialize CUDA in this async task's thread
-        // Note: CUDA contexts are thread-local, so we need to initialize
-        // when the task first runs on a thread
-        if let Err(e) = cuda::init_cuda_in_thread() {
-            warn!("Failed to initialize CUDA in broadcast task thread: {:?}", e);
-        }
-        
-        let mut interval = tokio::time::interval(std::time::Duration::from_millis(16)); // 60 FPS broadcast
+    let broadcast_interval = std::time::Duration::from_millis(config.broadcast_interval_ms);
+    let mut broadcast_shutdown = shutdown_rx.clone();
+    let broadcast_metrics = Arc::clone(&metrics);
+    let recorder = Arc::new(recorder::Recorder::new(&config.recording_dir));
+    let broadcast_recorder = Arc::clone(&recorder);
+    let interp_subscribers = Arc::new(broadcast::Subscribers::default());
+    let broadcast_interp = Arc::clone(&interp_subscribers);
+    let speed_subscribers = Arc::new(broadcast::Subscribers::default());
+    let broadcast_speeds = Arc::clone(&speed_subscribers);
+    // Encodes the snapshots the simulation thread publishes, so it needs no CUDA
+    // context and never waits on a step
+    let broadcast_task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(broadcast_interval);
         let mut consecutive_failures = 0;
         let mut last_success = std::time::Instant::now();
+        let mut last_frame = None;
+        // Earlier positions for `?interp=1` clients
+        let mut history = broadcast::StateHistory::new(broadcast::INTERP_HISTORY);
         
         loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = broadcast_shutdown.changed() => {
+                    info!("Broadcast task stopping");
+                    break;
+                }
+            }
+
+            // Nothing new to send (e.g. pull mode between steps); while paused the
+            // frozen state keeps flowing so clients don't see a stalled stream
+            let (frame, _) = engine_clone.latest_snapshot();
+            if last_frame == Some(frame) && !engine_clone.is_paused() {
+                last_success = std::time::Instant::now();
+                continue;
+            }
             
-            match broadcast::BroadcastState::encode(&engine_clone) {
+            match broadcast::BroadcastState::encode(&engine_clone, broadcast_speeds.any()) {
                 Ok(state) => {
+                    broadcast_recorder.record(&state);
+                    // History is only worth copying while someone interpolates
+                    let state = if broadcast_interp.any() {
+                        history.push(state)
+                    } else {
+                        history.clear();
+                        state
+                    };
                     // Send to all subscribers (non-blocking)
                     let _ = tx_clone.send(state);
+                    last_frame = Some(frame);
                     consecutive_failures = 0;
                     last_success = std::time::Instant::now();
                 }
                 Err(e) => {
                     consecutive_failures += 1;
-                    // If we get InvalidContext error, try to reinitialize CUDA context
-                    let error_str = format!("{:?}", e);
-                    if error_str.contains("InvalidContext") || error_str.contains("context") {
-                        // Try to reinitialize CUDA context
-                        if let Err(init_err) = cuda::init_cuda_in_thread() {
-                            warn!("Failed to reinitialize CUDA context: {:?}", init_err);
-                        }
-                    }
+                    broadcast_metrics.record_encode_failure();
                     
                     // If encoding fails repeatedly, log warning
                     if consecutive_failures % 100 == 0 {
@@ -420,36 +2567,219 @@ async fn main() -> anyhow::Result<()> {
         }
     });
     
+    let engine = Arc::clone(&simulation_engine);
+    let connection_metrics = Arc::clone(&metrics);
+    let cuda_pool = Arc::new(cuda_pool::CudaPool::with_context(Arc::clone(&cuda_context), config.cuda_workers)?);
+    info!("CUDA worker pool: {} threads", cuda_pool.workers());
+    let stream_pool = Arc::new(cuda_pool::CudaPool::with_context(Arc::clone(&cuda_context), GRAYSCOTT_STREAM_WORKERS)?);
+
+    // Routes that change the shared simulation need `Authorization: Bearer $CONTROL_TOKEN`
+    let control_token = auth::ControlToken::from_env();
+    if control_token.is_none() {
+        warn!("{} is not set; control endpoints are open to anyone", auth::CONTROL_TOKEN_ENV);
+    }
+
     let state = AppState { 
         cuda_context, 
+        cuda_pool,
+        stream_pool,
+        grayscott_streams: Arc::new(tokio::sync::Semaphore::new(GRAYSCOTT_STREAM_WORKERS)),
         boids_simulation,
+        boids3d_simulation,
         simulation_engine,
         broadcast_tx,
+        capabilities,
+        metrics,
+        max_frame_bytes: config.max_frame_bytes,
+        gpu_stats_interval,
+        shutdown: shutdown_rx,
+        instances: Arc::new(instances::InstanceRegistry::new()),
+        recorder,
+        interp_subscribers,
+        speed_subscribers,
+        control_token: control_token.clone(),
+    };
+
+    let control = |route: MethodRouter<AppState>| match &control_token {
+        Some(token) => route.route_layer(axum::middleware::from_fn_with_state(token.clone(), auth::require_token)),
+        None => route,
+    };
+
+    // Each /api/simulate/* run takes a CUDA worker, so clients are limited to a token bucket
+    let limiter = (config.simulate_rate_limit > 0.0)
+        .then(|| rate_limit::RateLimiter::new(config.simulate_rate_limit, config.simulate_burst));
+    let limited = |route: MethodRouter<AppState>| match &limiter {
+        Some(limiter) => route.route_layer(axum::middleware::from_fn_with_state(limiter.clone(), rate_limit::limit)),
+        None => route,
     };
 
     // Build application
     let app = Router::new()
         .route("/health", get(health))
+        .route("/metrics", get(prometheus_metrics))
         .route("/api/gpu-info", get(gpu_info))
         .route("/api/gpu-stats", get(gpu_stats))
-        .route("/api/simulate/sph", post(simulate_sph))
-        .route("/api/simulate/boids", post(simulate_boids))
-        .route("/api/simulate/grayscott", post(simulate_grayscott))
+        .route("/api/capabilities", get(get_capabilities))
+        .route("/api/build-info", get(build_info))
+        .route("/api/debug/kernels", get(debug_kernels))
+        .route("/api/openapi.json", get(openapi_document))
+        .route("/api/simulate/sph", limited(post(simulate_sph)))
+        .route("/api/simulate/md", limited(post(simulate_md)))
+        .route("/api/simulate/boids", limited(post(simulate_boids)))
+        .route("/api/simulate/boids/reset", control(post(reset_boids)))
+        .route("/api/simulate/boids/resize", control(post(resize_boids)))
+        .route("/api/simulate/boids/checkpoint", post(checkpoint_boids))
+        .route("/api/simulate/boids/restore", control(post(restore_boids)))
+        .route("/api/simulate/boids/pause", control(post(pause_boids)))
+        .route("/api/simulate/boids/resume", control(post(resume_boids)))
+        .route("/api/simulate/boids/obstacles", control(post(post_obstacles)))
+        .route("/api/simulate/boids/stats", get(get_boids_stats))
+        .route("/api/simulate/boids/species", get(get_boids_species))
+        .route("/api/simulate/boids/density", get(get_boids_density))
+        .route("/api/simulate/boids/snapshot", get(get_boids_snapshot))
+        .route("/api/simulate/grayscott", limited(post(simulate_grayscott)))
+        .route("/api/simulate/grayscott/stream", limited(get(stream_grayscott)))
+        .route("/api/sdf/sample", post(sample_sdf))
+        .route("/api/render/sdf", post(render_sdf))
+        .route("/api/emitter", get(get_emitter).merge(control(put(put_emitter))))
+        .route("/api/boids/init-image", control(post(init_boids_from_image)))
+        .route("/api/simulation/graph", get(get_neighbor_graph))
+        .route("/api/simulation/boundary", get(get_boundary).merge(control(put(put_boundary))))
+        .route("/api/simulation/step", control(post(step_simulation)))
+        .route("/api/simulation/metrics", get(get_simulation_metrics))
+        .route("/api/sim/target-fps", control(post(set_target_fps)))
+        .route("/api/sim/time-scale", get(get_time_scale).merge(control(post(set_time_scale))))
+        .route("/api/benchmark", control(limited(post(run_benchmark))))
+        .route("/api/simulations", get(list_instances).merge(control(post(create_instance))))
+        .route("/api/simulations/:id", get(get_instance).merge(control(delete(delete_instance))))
+        .route("/api/simulations/:id/step", control(post(step_instance)))
+        .route("/api/record/start", control(post(start_recording)))
+        .route("/api/record/stop", control(post(stop_recording)))
         .route("/ws", get(websocket_handler))
+        .route("/api/simulate/boids/stream", get(stream_boids))
+        .route("/ws/telemetry", get(telemetry_handler))
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await?;
-    info!("Physics backend server listening on http://0.0.0.0:3001");
+    let addr = format!("0.0.0.0:{}", config.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("Physics backend server listening on http://{}", addr);
     info!("Endpoints:");
     info!("  GET  /health");
+    info!("  GET  /metrics");
     info!("  GET  /api/gpu-info");
     info!("  GET  /api/gpu-stats");
+    info!("  GET  /api/capabilities");
+    info!("  GET  /api/build-info");
+    info!("  GET  /api/debug/kernels");
+    info!("  GET  /api/openapi.json");
     info!("  POST /api/simulate/sph");
+    info!("  POST /api/simulate/md");
     info!("  POST /api/simulate/boids");
+    info!("  POST /api/simulate/boids/reset");
+    info!("  POST /api/simulate/boids/resize");
+    info!("  POST /api/simulate/boids/checkpoint");
+    info!("  POST /api/simulate/boids/restore");
+    info!("  POST /api/simulate/boids/pause");
+    info!("  POST /api/simulate/boids/resume");
+    info!("  POST /api/simulate/boids/obstacles");
+    info!("  GET  /api/simulate/boids/stats");
+    info!("  GET  /api/simulate/boids/species");
+    info!("  GET  /api/simulate/boids/density");
+    info!("  GET  /api/simulate/boids/stream");
+    info!("  GET  /api/simulate/boids/snapshot");
     info!("  POST /api/simulate/grayscott");
+    info!("  GET  /api/simulate/grayscott/stream");
+    info!("  POST /api/sdf/sample");
+    info!("  POST /api/render/sdf");
+    info!("  GET  /api/emitter");
+    info!("  PUT  /api/emitter");
+    info!("  POST /api/boids/init-image");
+    info!("  GET  /api/simulation/graph");
+    info!("  GET  /api/simulation/boundary");
+    info!("  PUT  /api/simulation/boundary");
+    info!("  POST /api/simulation/step");
+    info!("  GET  /api/simulation/metrics");
+    info!("  POST /api/sim/target-fps");
+    info!("  GET  /api/sim/time-scale");
+    info!("  POST /api/sim/time-scale");
+    info!("  POST /api/benchmark");
+    info!("  GET  /api/simulations");
+    info!("  POST /api/simulations");
+    info!("  GET  /api/simulations/:id");
+    info!("  DELETE /api/simulations/:id");
+    info!("  POST /api/simulations/:id/step");
+    info!("  POST /api/record/start");
+    info!("  POST /api/record/stop");
     info!("  WS   /ws");
+    info!("  WS   /ws/telemetry");
     
-    axum::serve(listener, app).await?;
+    // Connect info gives the rate limiter each client's address
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+        .await?;
+
+    // WebSocket tasks outlive the server future; give them a moment to send their close frames
+    let drain_deadline = std::time::Instant::now() + SHUTDOWN_DRAIN_TIMEOUT;
+    while connection_metrics.active_connections() > 0 && std::time::Instant::now() < drain_deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    if connection_metrics.active_connections() > 0 {
+        warn!("{} WebSocket clients still open at shutdown", connection_metrics.active_connections());
+    }
+    broadcast_task.await.ok();
+
+    // Joining blocks until the current step finishes
+    match tokio::task::spawn_blocking(move || engine.stop_and_join()).await {
+        Ok(true) => info!("Simulation engine stopped"),
+        _ => warn!("Simulation thread panicked during shutdown"),
+    }
+    info!("Shutdown complete");
     
     Ok(())
 }
+
+/// Smallest flock the engine shrinks to before startup gives up
+const MIN_ENGINE_BOIDS: usize = 1_000;
+
+/// How long shutdown waits for WebSocket clients to close
+const SHUTDOWN_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Resolves on Ctrl+C (or SIGTERM on Unix) after telling every task to wind down
+async fn shutdown_signal(shutdown_tx: watch::Sender<bool>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl+C: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("Shutdown signal received, draining connections");
+    let _ = shutdown_tx.send(true);
+}
+
+/// Close frame sent to WebSocket clients when the server stops
+fn shutdown_close_frame() -> axum::extract::ws::CloseFrame<'static> {
+    axum::extract::ws::CloseFrame {
+        code: axum::extract::ws::close_code::AWAY,
+        reason: "server shutting down".into(),
+    }
+}
@@ -0,0 +1,173 @@
+// Server-wide counters shared by the WebSocket handlers
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Why a WebSocket client's send task ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// Client sent a close frame or the stream ended
+    ClientClosed,
+    /// Writing to the socket failed
+    SendError,
+    /// Reading from the socket failed
+    ReceiveError,
+    /// Broadcast channel shut down (server stopping)
+    ChannelClosed,
+}
+
+impl DisconnectReason {
+    pub const ALL: [DisconnectReason; 4] = [
+        DisconnectReason::ClientClosed,
+        DisconnectReason::SendError,
+        DisconnectReason::ReceiveError,
+        DisconnectReason::ChannelClosed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectReason::ClientClosed => "client_closed",
+            DisconnectReason::SendError => "send_error",
+            DisconnectReason::ReceiveError => "receive_error",
+            DisconnectReason::ChannelClosed => "channel_closed",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Default)]
+pub struct ServerMetrics {
+    active_connections: AtomicUsize,
+    total_connections: AtomicU64,
+    disconnects: [AtomicU64; DisconnectReason::ALL.len()],
+    // Broadcast ticks whose frame couldn't be encoded
+    encode_failures: AtomicU64,
+    // Broadcasts /ws and SSE clients fell too far behind to receive
+    lagged_frames: AtomicU64,
+}
+
+impl ServerMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn total_connections(&self) -> u64 {
+        self.total_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn disconnects(&self, reason: DisconnectReason) -> u64 {
+        self.disconnects[reason.index()].load(Ordering::Relaxed)
+    }
+
+    pub fn record_encode_failure(&self) {
+        self.encode_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn encode_failures(&self) -> u64 {
+        self.encode_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn record_lagged(&self, frames: u64) {
+        self.lagged_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub fn lagged_frames(&self) -> u64 {
+        self.lagged_frames.load(Ordering::Relaxed)
+    }
+}
+
+/// Tracks one WebSocket or SSE client. Counts the connection on creation and
+/// always releases it on drop, even if the send task panics.
+pub struct ConnectionGuard {
+    metrics: Arc<ServerMetrics>,
+    id: u64,
+    reason: Option<DisconnectReason>,
+    // Recorded when the guard drops without a reason
+    fallback: DisconnectReason,
+}
+
+impl ConnectionGuard {
+    pub fn new(metrics: &Arc<ServerMetrics>) -> Self {
+        metrics.active_connections.fetch_add(1, Ordering::Relaxed);
+        let id = metrics.total_connections.fetch_add(1, Ordering::Relaxed) + 1;
+        Self {
+            metrics: Arc::clone(metrics),
+            id,
+            reason: None,
+            // A task that ended without recording a reason died unexpectedly
+            fallback: DisconnectReason::SendError,
+        }
+    }
+
+    /// Record `reason` if the guard drops without one, e.g. for SSE streams, which
+    /// are simply dropped when the client goes away
+    pub fn with_fallback(mut self, reason: DisconnectReason) -> Self {
+        self.fallback = reason;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Record why the client is going away; the first reason wins
+    pub fn set_reason(&mut self, reason: DisconnectReason) {
+        self.reason.get_or_insert(reason);
+    }
+
+    /// Count broadcasts this client skipped because it fell behind
+    pub fn record_lagged(&self, frames: u64) {
+        self.metrics.record_lagged(frames);
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let reason = self.reason.unwrap_or(self.fallback);
+        self.metrics.disconnects[reason.index()].fetch_add(1, Ordering::Relaxed);
+        self.metrics.active_connections.fetch_sub(1, Ordering::Relaxed);
+        tracing::info!("WebSocket client {} disconnected: {}", self.id, reason.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_guard_counts_and_releases() {
+        let metrics = Arc::new(ServerMetrics::new());
+        let mut a = ConnectionGuard::new(&metrics);
+        let b = ConnectionGuard::new(&metrics);
+        assert_eq!(metrics.active_connections(), 2);
+        assert_ne!(a.id(), b.id());
+
+        a.set_reason(DisconnectReason::SendError);
+        a.set_reason(DisconnectReason::ClientClosed); // ignored, first reason wins
+        drop(a);
+        assert_eq!(metrics.active_connections(), 1);
+        assert_eq!(metrics.disconnects(DisconnectReason::SendError), 1);
+        assert_eq!(metrics.disconnects(DisconnectReason::ClientClosed), 0);
+
+        b.record_lagged(3);
+        b.record_lagged(2);
+        assert_eq!(metrics.lagged_frames(), 5);
+
+        drop(b);
+        assert_eq!(metrics.active_connections(), 0);
+        assert_eq!(metrics.total_connections(), 2);
+
+        drop(ConnectionGuard::new(&metrics).with_fallback(DisconnectReason::ClientClosed));
+        assert_eq!(metrics.disconnects(DisconnectReason::ClientClosed), 1);
+        let mut closed = ConnectionGuard::new(&metrics).with_fallback(DisconnectReason::ClientClosed);
+        closed.set_reason(DisconnectReason::ChannelClosed);
+        drop(closed);
+        assert_eq!(metrics.disconnects(DisconnectReason::ChannelClosed), 1);
+    }
+}
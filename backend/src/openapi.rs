@@ -0,0 +1,413 @@
+// Handwritten OpenAPI 3.0 description of the simulate and GPU endpoints, served
+// at /api/openapi.json. The tests below check the response schemas against what
+// the serde types actually serialize, so the two can't drift apart silently.
+use crate::physics::{grayscott, md, sph, substeps, thumbnail};
+use serde_json::{json, Value};
+
+fn number() -> Value {
+    json!({ "type": "number", "format": "float" })
+}
+
+fn nullable(mut schema: Value) -> Value {
+    schema["nullable"] = json!(true);
+    schema
+}
+
+fn array_of(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn pair() -> Value {
+    json!({ "type": "array", "items": number(), "minItems": 2, "maxItems": 2 })
+}
+
+fn schemas() -> Value {
+    let defaults = sph::FluidParams::default();
+    json!({
+        "SimulationRequest": {
+            "type": "object",
+            "required": ["simulation_type"],
+            "properties": {
+                "simulation_type": { "type": "string", "description": "Informational; the path picks the simulation" },
+                "num_particles": {
+                    "type": "integer",
+                    "description": format!(
+                        "SPH or MD particles, or boids (SPH: 1..={}, default {}; MD: 1..={}, default {})",
+                        sph::MAX_NUM_PARTICLES, sph::DEFAULT_NUM_PARTICLES, md::MAX_NUM_PARTICLES, md::DEFAULT_NUM_PARTICLES,
+                    ),
+                },
+                "steps": { "type": "integer", "default": 1 },
+                "round_to": { "type": "integer", "description": "Decimal places to round returned values to" },
+                "params": { "description": "SphParams, BoidsParams or GrayScottParams, by endpoint" },
+                "include_forces": { "type": "boolean", "default": false, "description": "Boids only" },
+                "dimensions": { "type": "integer", "enum": [2, 3], "default": 2, "description": "Boids only" },
+                "substeps": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": substeps::MAX_SUBSTEPS,
+                    "default": substeps::DEFAULT_SUBSTEPS,
+                    "description": "SPH and Gray-Scott: integration steps per step, each with dt / substeps",
+                },
+                "seed": { "type": "integer", "format": "uint64", "description": "SPH, MD and Gray-Scott: seed for the initial noise" },
+            },
+        },
+        "SphParams": {
+            "type": "object",
+            "properties": {
+                "gravity": {
+                    "allOf": [pair()],
+                    "description": format!("Constant acceleration, each component within ±{}", sph::MAX_GRAVITY),
+                },
+                "rest_density": { "type": "number", "default": defaults.rest_density, "minimum": 0, "exclusiveMinimum": true, "maximum": sph::MAX_REST_DENSITY },
+                "gas_constant": { "type": "number", "default": defaults.gas_constant, "minimum": 0, "exclusiveMinimum": true, "maximum": sph::MAX_GAS_CONSTANT },
+                "viscosity": { "type": "number", "default": defaults.viscosity, "minimum": 0, "exclusiveMinimum": true, "maximum": sph::MAX_VISCOSITY },
+                "surface_tension": { "type": "number", "default": defaults.surface_tension, "minimum": 0, "maximum": sph::MAX_SURFACE_TENSION },
+                "smoothing_radius": { "type": "number", "default": defaults.smoothing_radius, "minimum": sph::MIN_SMOOTHING_RADIUS, "maximum": sph::MAX_SMOOTHING_RADIUS },
+                "mass": { "type": "number", "default": defaults.mass, "minimum": 0, "exclusiveMinimum": true, "maximum": sph::MAX_PARTICLE_MASS },
+                "vorticity_confinement": {
+                    "type": "number", "default": defaults.vorticity_confinement, "minimum": 0, "maximum": sph::MAX_VORTICITY_CONFINEMENT,
+                    "description": "Strength of the force that re-spins fading eddies; non-zero values run on the CPU"
+                },
+            },
+        },
+        "GrayScottParams": {
+            "type": "object",
+            "properties": {
+                "f": { "type": "number", "default": 0.055, "maximum": grayscott::MAX_RATE },
+                "k": { "type": "number", "default": 0.062, "maximum": grayscott::MAX_RATE },
+                "du": { "type": "number", "default": 0.16, "maximum": grayscott::MAX_DIFFUSION },
+                "dv": { "type": "number", "default": 0.08, "maximum": grayscott::MAX_DIFFUSION },
+                "width": {
+                    "type": "integer",
+                    "default": grayscott::DEFAULT_GRID_SIZE,
+                    "maximum": grayscott::MAX_GRID_SIZE,
+                    "description": format!("width * height may be at most {}", grayscott::MAX_GRID_CELLS),
+                },
+                "height": { "type": "integer", "default": grayscott::DEFAULT_GRID_SIZE, "maximum": grayscott::MAX_GRID_SIZE },
+                "normalize": { "type": "boolean", "description": "Rescale from the field's min/max to [0, 1]" },
+                "range": { "allOf": [pair()], "description": "Rescale from this (min, max) instead" },
+            },
+        },
+        "BoidsParams": {
+            "type": "object",
+            "description": "Omitted fields keep their current value",
+            "properties": {
+                "separation_radius": number(),
+                "alignment_radius": number(),
+                "cohesion_radius": number(),
+                "max_speed": number(),
+                "max_speed_range": { "allOf": [pair()], "description": "Per-boid speed caps drawn from (min, max); replaces max_speed" },
+                "max_force": number(),
+                "radius_check": { "type": "string", "enum": ["warn", "error"] },
+                "species_masses": array_of(number()),
+                "auto_tune": { "type": "boolean" },
+                "target_density": number(),
+                "neighbor_mode": { "type": "string", "enum": ["metric", "topological"] },
+                "topological_k": { "type": "integer" },
+                "jitter": number(),
+                "jitter_seed": { "type": "integer" },
+                "obstacles": array_of(json!({ "$ref": "#/components/schemas/Obstacle" })),
+                "continuous_collision": { "type": "boolean" },
+                "boundary": { "type": "string", "enum": ["wrap", "bounce", "open"] },
+                "num_predators": { "type": "integer" },
+                "genetics": { "type": "boolean" },
+                "world_size": number(),
+            },
+        },
+        "Obstacle": {
+            "oneOf": [
+                {
+                    "type": "object",
+                    "required": ["type", "x", "y", "radius"],
+                    "properties": {
+                        "type": { "type": "string", "enum": ["circle"] },
+                        "x": number(), "y": number(), "radius": number(),
+                    },
+                },
+                {
+                    "type": "object",
+                    "required": ["type", "x0", "y0", "x1", "y1", "thickness"],
+                    "properties": {
+                        "type": { "type": "string", "enum": ["wall"] },
+                        "x0": number(), "y0": number(), "x1": number(), "y1": number(), "thickness": number(),
+                    },
+                },
+            ],
+        },
+        "SimulationResponse": {
+            "type": "object",
+            "required": ["success", "data", "metadata", "error"],
+            "properties": {
+                "success": { "type": "boolean" },
+                "data": nullable(array_of(number())),
+                "forces": array_of(number()),
+                "fields": {
+                    "type": "object",
+                    "description": "Gray-Scott `?fields=both`, in place of `data`",
+                    "properties": { "u": array_of(number()), "v": array_of(number()) },
+                },
+                "warnings": array_of(json!({ "type": "string" })),
+                "metadata": nullable(json!({ "$ref": "#/components/schemas/SimulationMetadata" })),
+                "error": nullable(json!({ "type": "string" })),
+                "error_kind": {
+                    "type": "string",
+                    "enum": ["bad_request", "unauthorized", "not_found", "cuda_init", "allocation", "internal"],
+                    "description": "Set with `error`",
+                },
+            },
+        },
+        "SimulationMetadata": {
+            "type": "object",
+            "required": ["simulation_type", "num_particles", "computation_time_ms", "accelerator", "steps_completed"],
+            "properties": {
+                "simulation_type": { "type": "string", "enum": ["sph", "boids", "grayscott", "md"] },
+                "num_particles": { "type": "integer" },
+                "computation_time_ms": { "type": "integer" },
+                "accelerator": { "type": "string", "enum": ["cuda", "cpu"] },
+                "steps_completed": { "type": "integer" },
+                "value_range": pair(),
+                "dimensions": { "type": "integer", "enum": [2, 3] },
+                "seed": { "type": "integer", "format": "uint64" },
+                "stable": { "type": "boolean", "description": "SPH only" },
+                "params": { "type": "object", "description": "SPH only: the fluid properties used, as in SphParams" },
+            },
+        },
+        "GpuInfo": {
+            "type": "object",
+            "properties": {
+                "gpu": { "type": "string" },
+                "status": { "type": "string" },
+                "cuda_context": { "type": "boolean" },
+                "compute_capability_major": nullable(json!({ "type": "integer" })),
+                "compute_capability_minor": nullable(json!({ "type": "integer" })),
+                "multiprocessor_count": nullable(json!({ "type": "integer" })),
+                "total_memory_bytes": nullable(json!({ "type": "integer" })),
+                "max_threads_per_block": nullable(json!({ "type": "integer" })),
+                "warp_size": nullable(json!({ "type": "integer" })),
+            },
+        },
+        "GpuStats": {
+            "type": "object",
+            "properties": {
+                "gpu_utilization": nullable(json!({ "type": "integer", "maximum": 100 })),
+                "memory_utilization": nullable(json!({ "type": "integer", "maximum": 100 })),
+                "memory_used_mb": nullable(json!({ "type": "integer" })),
+                "memory_total_mb": nullable(json!({ "type": "integer" })),
+                "temperature_c": nullable(json!({ "type": "integer" })),
+                "timestamp": { "type": "integer", "description": "Unix milliseconds of the reading" },
+                "achieved_fps": number(),
+                "avg_frame_time_ms": number(),
+                "target_fps": number(),
+            },
+        },
+    })
+}
+
+/// POST operation taking a `SimulationRequest` whose `params` are `params_schema`, if any
+fn simulate(summary: &str, params_schema: Option<&str>, extra_parameters: Vec<Value>) -> Value {
+    let mut parameters = vec![json!({
+        "name": "encoding",
+        "in": "query",
+        "schema": { "type": "string", "enum": ["json", "binary"] },
+        "description": "`binary` (or `Accept: application/octet-stream`) returns `data` as a values frame",
+    }), json!({
+        "name": "compress",
+        "in": "query",
+        "schema": { "type": "string", "enum": ["none", "zstd"] },
+        "description": "`zstd` compresses the body and sets `X-Uncompressed-Length`; needs the `compression` feature",
+    })];
+    parameters.extend(extra_parameters);
+    let mut request = json!({ "allOf": [{ "$ref": "#/components/schemas/SimulationRequest" }] });
+    if let Some(params_schema) = params_schema {
+        request["properties"] = json!({ "params": { "$ref": format!("#/components/schemas/{}", params_schema) } });
+    }
+    json!({
+        "post": {
+            "summary": summary,
+            "parameters": parameters,
+            "requestBody": {
+                "required": true,
+                "content": { "application/json": { "schema": request } },
+            },
+            "responses": {
+                "200": {
+                    "description": "Simulation result",
+                    "content": {
+                        "application/json": { "schema": { "$ref": "#/components/schemas/SimulationResponse" } },
+                        "application/octet-stream": { "schema": { "type": "string", "format": "binary" } },
+                    },
+                },
+                "400": {
+                    "description": "Invalid parameters; `error` says which",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SimulationResponse" } } },
+                },
+                "500": {
+                    "description": "Simulation failed",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SimulationResponse" } } },
+                },
+                "503": {
+                    "description": "No usable CUDA context (`error_kind: cuda_init`)",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SimulationResponse" } } },
+                },
+                "507": {
+                    "description": "Buffers for the requested size couldn't be allocated (`error_kind: allocation`)",
+                    "content": { "application/json": { "schema": { "$ref": "#/components/schemas/SimulationResponse" } } },
+                },
+            },
+        },
+    })
+}
+
+fn get_json(summary: &str, schema: &str, parameters: Vec<Value>) -> Value {
+    json!({
+        "get": {
+            "summary": summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": summary,
+                    "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } },
+                },
+                "500": { "description": "The device couldn't be queried" },
+            },
+        },
+    })
+}
+
+/// The document served at `/api/openapi.json`
+pub fn document() -> Value {
+    let fields = json!({
+        "name": "fields",
+        "in": "query",
+        "schema": { "type": "string", "enum": ["u", "both"], "default": "u" },
+        "description": "`both` returns `fields: {u, v}` instead of `data`",
+    });
+    let fresh = json!({
+        "name": "fresh",
+        "in": "query",
+        "schema": { "type": "string", "enum": ["1", "true", "0", "false"] },
+        "description": "Query the device now instead of reusing a cached reading",
+    });
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Physics simulation backend",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/simulate/sph": simulate("Run an SPH fluid simulation", Some("SphParams"), Vec::new()),
+            "/api/simulate/md": simulate("Run a Lennard-Jones molecular dynamics simulation", None, Vec::new()),
+            "/api/simulate/boids": simulate("Step the shared boids flock", Some("BoidsParams"), Vec::new()),
+            "/api/simulate/grayscott": simulate("Run a Gray-Scott reaction-diffusion simulation", Some("GrayScottParams"), vec![fields]),
+            "/api/simulate/boids/snapshot": {
+                "get": {
+                    "summary": "PNG preview of the streamed flock",
+                    "parameters": [
+                        { "name": "width", "in": "query", "schema": { "type": "integer", "default": thumbnail::DEFAULT_THUMBNAIL_SIZE, "maximum": thumbnail::MAX_THUMBNAIL_SIZE } },
+                        { "name": "height", "in": "query", "schema": { "type": "integer", "default": thumbnail::DEFAULT_THUMBNAIL_SIZE, "maximum": thumbnail::MAX_THUMBNAIL_SIZE } },
+                    ],
+                    "responses": {
+                        "200": { "description": "PNG image", "content": { "image/png": { "schema": { "type": "string", "format": "binary" } } } },
+                        "400": { "description": "Invalid size" },
+                    },
+                },
+            },
+            "/api/gpu-info": get_json("Static device properties", "GpuInfo", Vec::new()),
+            "/api/gpu-stats": get_json("GPU counters and simulation loop timing", "GpuStats", vec![fresh]),
+        },
+        "components": { "schemas": schemas() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn documented(schema: &str) -> BTreeSet<String> {
+        document()["components"]["schemas"][schema]["properties"]
+            .as_object()
+            .unwrap_or_else(|| panic!("{} has no properties", schema))
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn serialized(value: impl serde::Serialize) -> BTreeSet<String> {
+        serde_json::to_value(value).unwrap().as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[test]
+    fn test_response_schemas_match_serde_types() {
+        let metadata = crate::SimulationMetadata {
+            simulation_type: "sph".to_string(),
+            num_particles: 1,
+            computation_time_ms: 1,
+            accelerator: crate::physics::Accelerator::Cpu,
+            steps_completed: 1,
+            value_range: Some((0.0, 1.0)),
+            dimensions: Some(2),
+            seed: Some(1),
+            stable: Some(true),
+            params: Some(crate::physics::sph::FluidParams::default()),
+        };
+        assert_eq!(serialized(&metadata), documented("SimulationMetadata"));
+
+        let response = crate::SimulationResponse {
+            success: true,
+            data: Some(vec![0.0]),
+            forces: Some(vec![0.0]),
+            fields: Some(crate::GrayScottFields { u: vec![0.0], v: vec![0.0] }),
+            warnings: vec!["w".to_string()],
+            metadata: Some(metadata),
+            error: None,
+            error_kind: Some(crate::api_error::ErrorKind::Internal),
+        };
+        assert_eq!(serialized(&response), documented("SimulationResponse"));
+
+        let stats = crate::GpuStatsResponse {
+            gpu: crate::gpu_stats::GpuStats {
+                gpu_utilization: None,
+                memory_utilization: None,
+                memory_used_mb: None,
+                memory_total_mb: None,
+                temperature_c: None,
+                timestamp: 0,
+            },
+            achieved_fps: 0.0,
+            avg_frame_time_ms: 0.0,
+            target_fps: 0.0,
+        };
+        assert_eq!(serialized(&stats), documented("GpuStats"));
+
+        let info = crate::cuda::GpuInfo {
+            gpu: "Test GPU".to_string(),
+            status: "ready",
+            cuda_context: true,
+            compute_capability_major: None,
+            compute_capability_minor: None,
+            multiprocessor_count: None,
+            total_memory_bytes: None,
+            max_threads_per_block: None,
+            warp_size: None,
+        };
+        assert_eq!(serialized(&info), documented("GpuInfo"));
+    }
+
+    #[test]
+    fn test_document_covers_simulate_endpoints() {
+        let doc = document();
+        for path in ["/api/simulate/sph", "/api/simulate/boids", "/api/simulate/grayscott", "/api/simulate/md"] {
+            assert!(doc["paths"][path]["post"]["requestBody"].is_object(), "{} missing", path);
+        }
+        assert!(documented("SphParams").contains("gravity"));
+        assert!(documented("SphParams").contains("viscosity"));
+        assert!(documented("GrayScottParams").contains("range"));
+        // Every $ref points at a defined schema
+        let text = doc.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(doc["components"]["schemas"][name].is_object(), "dangling $ref {}", name);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Which hardware actually ran a simulation step
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::error::CudaError;
+use serde::Serialize;
+use std::fmt;
+
+/// Failed CUDA steps in a row after which a simulation stops trying its kernel
+pub const MAX_CUDA_FAILURES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accelerator {
+    Cuda,
+    Cpu,
+}
+
+impl Accelerator {
+    pub fn from_used_cuda(used_cuda: bool) -> Self {
+        if used_cuda {
+            Accelerator::Cuda
+        } else {
+            Accelerator::Cpu
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Accelerator::Cuda => "cuda",
+            Accelerator::Cpu => "cpu",
+        }
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A simulation's recent CUDA step failures. A failed step falls back to the CPU
+/// on its own; the kernel is only dropped once it keeps failing or fails fatally.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CudaFailures {
+    consecutive: u32,
+}
+
+impl CudaFailures {
+    pub fn succeeded(&mut self) {
+        self.consecutive = 0;
+    }
+
+    /// Count a failed step; true once the kernel should be given up on
+    pub fn give_up(&mut self, error: &anyhow::Error) -> bool {
+        self.consecutive += 1;
+        self.consecutive >= MAX_CUDA_FAILURES || is_fatal(error)
+    }
+}
+
+/// Nothing is fatal without CUDA
+#[cfg(feature = "no-cuda")]
+pub fn is_fatal(_error: &anyhow::Error) -> bool {
+    false
+}
+
+/// Whether `error` came from CUDA failing in a way retrying can't fix: a kernel that
+/// won't load, or a fault that leaves the context unusable
+#[cfg(not(feature = "no-cuda"))]
+pub fn is_fatal(error: &anyhow::Error) -> bool {
+    error.chain().filter_map(|e| e.downcast_ref::<CudaError>()).any(|e| {
+        matches!(
+            e,
+            CudaError::InvalidPtx
+                | CudaError::InvalidImage
+                | CudaError::NoBinaryForGpu
+                | CudaError::NotFound
+                | CudaError::IllegalAddress
+                | CudaError::IllegalInstruction
+                | CudaError::MisalignedAddress
+                | CudaError::InvalidAddressSpace
+                | CudaError::InvalidProgramCounter
+                | CudaError::HardwareStackError
+                | CudaError::AssertError
+                | CudaError::LaunchFailed
+                | CudaError::EccUncorrectable
+                | CudaError::ContextIsDestroyed
+                | CudaError::Deinitialized
+        )
+    })
+}
+
+/// Nothing runs out of device memory without CUDA
+#[cfg(feature = "no-cuda")]
+pub fn is_out_of_memory(_error: &anyhow::Error) -> bool {
+    false
+}
+
+/// Whether `error` is the driver failing to allocate device memory
+#[cfg(not(feature = "no-cuda"))]
+pub fn is_out_of_memory(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<CudaError>())
+        .any(|e| matches!(e, CudaError::OutOfMemory))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-cuda"))]
+    use anyhow::Context;
+
+    #[test]
+    fn test_accelerator_serializes_lowercase() {
+        assert_eq!(serde_json::to_value(Accelerator::Cuda).unwrap(), "cuda");
+        assert_eq!(serde_json::to_value(Accelerator::from_used_cuda(false)).unwrap(), "cpu");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_cuda_failures_give_up_when_repeated_or_fatal() {
+        let transient = || Err::<(), _>(CudaError::OutOfMemory).context("launch failed").unwrap_err();
+        let mut failures = CudaFailures::default();
+        for _ in 1..MAX_CUDA_FAILURES {
+            assert!(!failures.give_up(&transient()));
+        }
+        assert!(failures.give_up(&transient()));
+        failures.succeeded();
+        assert!(!failures.give_up(&transient()), "A success resets the count");
+
+        let fatal = Err::<(), _>(CudaError::IllegalAddress).context("sync failed").unwrap_err();
+        assert!(is_fatal(&fatal));
+        assert!(CudaFailures::default().give_up(&fatal));
+        assert!(!is_fatal(&anyhow::anyhow!("IllegalAddress")), "Only the error itself counts");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_is_out_of_memory() {
+        let oom = Err::<(), _>(CudaError::OutOfMemory).context("Failed to allocate particles").unwrap_err();
+        assert!(is_out_of_memory(&oom));
+        let other = Err::<(), _>(CudaError::InvalidContext).context("Failed to allocate particles").unwrap_err();
+        assert!(!is_out_of_memory(&other));
+        assert!(!is_out_of_memory(&anyhow::anyhow!("OutOfMemory")));
+    }
+}
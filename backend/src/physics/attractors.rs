@@ -0,0 +1,111 @@
+// Point forces placed interactively (over `/ws`, e.g. following the mouse) that pull
+// nearby boids in, or push them away when `strength` is negative. Unlike obstacles
+// they are transient: the oldest is dropped once `MAX_ATTRACTORS` are live
+use serde::{Deserialize, Serialize};
+
+/// Most attractors live at once; bounds the per-boid loop and the kernel's buffer
+pub const MAX_ATTRACTORS: usize = 16;
+/// Floats per attractor in the kernel buffer: x, y, strength, radius
+pub const ATTRACTOR_FLOATS: usize = 4;
+/// Radii in a unit world; simulations scale them by their world size
+pub const DEFAULT_ATTRACTOR_RADIUS: f32 = 0.25;
+pub const MAX_ATTRACTOR_RADIUS: f32 = 0.5;
+pub const DEFAULT_ATTRACTOR_STRENGTH: f32 = 1.0;
+/// Largest `|strength|` accepted
+pub const MAX_ATTRACTOR_STRENGTH: f32 = 10.0;
+
+fn default_radius() -> f32 {
+    DEFAULT_ATTRACTOR_RADIUS
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Attractor {
+    pub x: f32,
+    pub y: f32,
+    /// Pull at the attractor itself, in units of the boids' `max_force`; negative repels
+    pub strength: f32,
+    /// Distance at which the force has faded to nothing
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+}
+
+impl Attractor {
+    /// Check the attractor fits a `world_size` world
+    pub fn validate(&self, world_size: f32) -> anyhow::Result<()> {
+        if !(self.x.is_finite() && self.y.is_finite()) {
+            anyhow::bail!("attractor position must be finite, got ({}, {})", self.x, self.y);
+        }
+        if !(self.strength != 0.0 && self.strength.abs() <= MAX_ATTRACTOR_STRENGTH) {
+            anyhow::bail!(
+                "attractor strength must be non-zero with magnitude at most {}, got {}",
+                MAX_ATTRACTOR_STRENGTH,
+                self.strength
+            );
+        }
+        let max_radius = MAX_ATTRACTOR_RADIUS * world_size;
+        if !(self.radius > 0.0 && self.radius <= max_radius) {
+            anyhow::bail!("attractor radius must be in (0, {}], got {}", max_radius, self.radius);
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_all(attractors: &[Attractor], world_size: f32) -> anyhow::Result<()> {
+    if attractors.len() > MAX_ATTRACTORS {
+        anyhow::bail!("at most {} attractors, got {}", MAX_ATTRACTORS, attractors.len());
+    }
+    attractors.iter().try_for_each(|a| a.validate(world_size))
+}
+
+/// Attractors laid out for the kernel, zero-padded to `MAX_ATTRACTORS`
+pub fn pack(attractors: &[Attractor]) -> Vec<f32> {
+    let mut packed = vec![0.0; MAX_ATTRACTORS * ATTRACTOR_FLOATS];
+    for (slot, a) in packed.chunks_exact_mut(ATTRACTOR_FLOATS).zip(attractors) {
+        slot.copy_from_slice(&[a.x, a.y, a.strength, a.radius]);
+    }
+    packed
+}
+
+/// Summed force from attractors whose radius covers (x, y), fading linearly
+/// from `strength` at the attractor to 0 at its radius
+pub fn pull(attractors: &[Attractor], x: f32, y: f32) -> (f32, f32) {
+    let (mut fx, mut fy) = (0.0, 0.0);
+    for a in attractors {
+        let (dx, dy) = (a.x - x, a.y - y);
+        let d = (dx * dx + dy * dy).sqrt();
+        if d < a.radius && d > 0.0 {
+            let pull = a.strength * (1.0 - d / a.radius);
+            fx += dx / d * pull;
+            fy += dy / d * pull;
+        }
+    }
+    (fx, fy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_fades_with_distance() {
+        let a = [Attractor { x: 0.5, y: 0.5, strength: 2.0, radius: 0.25 }];
+        let (near, _) = pull(&a, 0.45, 0.5);
+        let (far, _) = pull(&a, 0.3, 0.5);
+        assert!(near > far && far > 0.0, "Pulls toward the attractor, harder up close");
+        assert_eq!(pull(&a, 0.9, 0.5), (0.0, 0.0), "Nothing beyond the radius");
+        assert_eq!(pull(&a, 0.5, 0.5), (0.0, 0.0), "No direction at the attractor itself");
+
+        let repeller = [Attractor { strength: -2.0, radius: 0.5, ..a[0] }];
+        let (push, _) = pull(&repeller, 0.3, 0.5);
+        assert!(push < 0.0, "Negative strength pushes away, reaching further with a larger radius");
+
+        assert!(a[0].validate(1.0).is_ok() && repeller[0].validate(1.0).is_ok());
+        assert!(Attractor { strength: 0.0, ..a[0] }.validate(1.0).is_err());
+        assert!(Attractor { radius: 0.0, ..a[0] }.validate(1.0).is_err());
+        assert!(Attractor { x: f32::NAN, ..a[0] }.validate(1.0).is_err());
+        assert!(validate_all(&[a[0]; MAX_ATTRACTORS + 1], 1.0).is_err());
+        let wide = Attractor { radius: MAX_ATTRACTOR_RADIUS * 4.0, ..a[0] };
+        assert!(wide.validate(1.0).is_err() && wide.validate(10.0).is_ok(), "The cap grows with the world");
+        assert_eq!(pack(&a)[..ATTRACTOR_FLOATS], [0.5, 0.5, 2.0, 0.25]);
+    }
+}
@@ -0,0 +1,150 @@
+// Separation auto-tuner: nudges separation_radius toward a target flock density
+// so the flock neither disperses nor collapses across different boid counts
+use super::boids::Boid;
+
+/// Simulated seconds between tuner updates
+pub const TUNE_INTERVAL: f32 = 1.0;
+/// Boids sampled when estimating local density (keeps the estimate O(samples * n))
+const DENSITY_SAMPLES: usize = 256;
+/// Largest relative change applied to separation in a single update
+const MAX_STEP_RATIO: f32 = 1.25;
+/// Separation floor in the unit world
+const MIN_SEPARATION: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DensityTuner {
+    /// Desired neighbours per unit area within the cohesion radius
+    pub target_density: f32,
+    /// Exponent controlling how aggressively the error is corrected
+    pub gain: f32,
+    /// Smallest separation the tuner sets, in world units
+    pub min_separation: f32,
+}
+
+impl DensityTuner {
+    pub fn new(target_density: f32) -> Self {
+        Self {
+            target_density,
+            gain: 0.5,
+            min_separation: MIN_SEPARATION,
+        }
+    }
+
+    /// The same tuner for a world whose lengths are multiplied by `scale`: density per
+    /// unit area falls with the square of it and the separation floor grows with it
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            target_density: self.target_density / (scale * scale),
+            min_separation: self.min_separation * scale,
+            ..self
+        }
+    }
+
+    /// Next separation radius given the measured density.
+    /// Too dense (collapsing) -> grow separation; too sparse -> shrink it.
+    /// The result never exceeds `max_separation` (the cohesion radius).
+    pub fn update(&self, measured_density: f32, separation: f32, max_separation: f32) -> f32 {
+        if !measured_density.is_finite() || measured_density <= 0.0 || self.target_density <= 0.0 {
+            return separation;
+        }
+        let ratio = (measured_density / self.target_density)
+            .powf(self.gain)
+            .clamp(1.0 / MAX_STEP_RATIO, MAX_STEP_RATIO);
+        (separation * ratio).clamp(self.min_separation, max_separation.max(self.min_separation))
+    }
+}
+
+/// Mean number of neighbours per unit area within `radius`, estimated from
+/// an evenly strided sample of boids. `wrap` is the side of the torus distances
+/// wrap around, for worlds with wrapping boundaries.
+pub fn local_density(boids: &[Boid], radius: f32, wrap: Option<f32>) -> f32 {
+    if boids.len() < 2 || radius <= 0.0 {
+        return 0.0;
+    }
+    let stride = (boids.len() / DENSITY_SAMPLES).max(1);
+    let r2 = radius * radius;
+    let mut neighbours = 0usize;
+    let mut samples = 0usize;
+    for (i, bi) in boids.iter().enumerate().step_by(stride) {
+        samples += 1;
+        for (j, bj) in boids.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let mut dx = (bi.x - bj.x).abs();
+            let mut dy = (bi.y - bj.y).abs();
+            if let Some(side) = wrap {
+                dx = dx.min(side - dx);
+                dy = dy.min(side - dy);
+            }
+            if dx * dx + dy * dy < r2 {
+                neighbours += 1;
+            }
+        }
+    }
+    neighbours as f32 / samples as f32 / (std::f32::consts::PI * r2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuner_raises_separation_until_density_stabilizes() {
+        // Toy flock model: equilibrium density falls with the square of separation
+        let density_for = |sep: f32| 2.0 / (sep * sep);
+        let tuner = DensityTuner::new(800.0);
+        let mut separation = 0.01; // collapsing: density 20000 >> 800
+        let initial = separation;
+        let mut history = Vec::new();
+        for _ in 0..40 {
+            separation = tuner.update(density_for(separation), separation, 0.15);
+            history.push(density_for(separation));
+        }
+        assert!(separation > initial, "Separation should grow for a collapsing flock");
+        let last = *history.last().unwrap();
+        assert!((last - 800.0).abs() / 800.0 < 0.05, "Density should settle near target, got {}", last);
+        let prev = history[history.len() - 2];
+        assert!((last - prev).abs() / last < 0.01, "Density should be stable");
+    }
+
+    #[test]
+    fn test_tuner_respects_cohesion_cap() {
+        let tuner = DensityTuner::new(1.0);
+        let sep = tuner.update(1e9, 0.14, 0.15);
+        assert!(sep <= 0.15);
+    }
+
+    #[test]
+    fn test_local_density_clustered_vs_spread() {
+        let clustered: Vec<Boid> = (0..100)
+            .map(|i| Boid { x: 0.5 + (i % 10) as f32 * 0.001, y: 0.5 + (i / 10) as f32 * 0.001, ..Boid::default() })
+            .collect();
+        let spread: Vec<Boid> = (0..100)
+            .map(|i| Boid { x: (i % 10) as f32 * 0.1, y: (i / 10) as f32 * 0.1, ..Boid::default() })
+            .collect();
+        assert!(local_density(&clustered, 0.05, Some(1.0)) > local_density(&spread, 0.05, Some(1.0)));
+    }
+
+    #[test]
+    fn test_local_density_wraps_by_the_world_size() {
+        let at = |x: f32| Boid { x, y: 5.0, ..Boid::default() };
+        // 0.2 apart across the seam of a 10-wide world, 2.4 apart inside it
+        let across_seam = [at(0.1), at(9.9)];
+        let inside = [at(0.1), at(2.5)];
+        assert!(local_density(&across_seam, 0.5, Some(10.0)) > 0.0);
+        assert_eq!(local_density(&across_seam, 0.5, None), 0.0, "open worlds don't wrap");
+        assert_eq!(local_density(&inside, 0.5, Some(10.0)), 0.0);
+    }
+
+    #[test]
+    fn test_scaled_tuner_keeps_relative_separation() {
+        let tuner = DensityTuner::new(400.0);
+        let scaled = tuner.scaled(10.0);
+        // The same flock 10x larger has 1/100 the density per unit area
+        let unit = tuner.update(800.0, 0.02, 0.15);
+        let large = scaled.update(8.0, 0.2, 1.5);
+        assert!((large / 10.0 - unit).abs() < 1e-6);
+        assert!((scaled.update(1e9, 0.01, 1.5) - 0.05).abs() < 1e-6, "the floor scales too");
+    }
+}
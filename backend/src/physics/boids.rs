@@ -1,34 +1,436 @@
 // Boids algorithm simulation
 // Extended Reynolds rules with genetic evolution
+use super::accelerator::{self, Accelerator, CudaFailures};
+use super::attractors::{self, Attractor};
+use super::auto_tune::{self, DensityTuner};
+use super::checkpoint::FlockState;
+use super::emitter::{Emitter, EmitterConfig};
+use super::genetics::{self, Genes};
+use super::obstacles::{self, Obstacle};
+use super::predators::{self, PREDATOR_SPECIES};
+use super::rng::SimRng;
+use super::spatial_grid::SpatialGrid;
+use super::stats::{self, BoidStats};
+use super::stasis::{self, StasisDetector};
+use super::thumbnail;
+use super::storage::{Backend, DefaultBackend, Element, HostBackend, Resizable, SimBuffer, Storage};
 use crate::cuda::CudaContext;
 use anyhow::Result;
-use rand::Rng;
+use anyhow::Context as AnyhowContext;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::launch;
-use rustacuda::memory::DeviceBuffer;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::memory::DeviceCopy;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "no-cuda"))]
 use std::ffi::CString;
 use std::sync::Arc;
 
+/// Number of species spawned by `BoidsSimulation::new`
+pub const NUM_SPECIES: usize = 4;
+/// Valid species counts are `1..=MAX_SPECIES`
+pub const MAX_SPECIES: u8 = 8;
+
+fn check_num_species(num_species: u8) -> Result<()> {
+    if num_species == 0 || num_species > MAX_SPECIES {
+        anyhow::bail!("num_species must be 1..={}, got {}", MAX_SPECIES, num_species);
+    }
+    Ok(())
+}
+
+/// Largest flock `resize` grows to, so a typo can't exhaust device memory
+pub const MAX_NUM_BOIDS: usize = 1_000_000;
+
+pub fn check_num_boids(num_boids: usize) -> Result<()> {
+    if !(1..=MAX_NUM_BOIDS).contains(&num_boids) {
+        anyhow::bail!("num_boids must be 1..={}, got {}", MAX_NUM_BOIDS, num_boids);
+    }
+    Ok(())
+}
+
+fn check_world_size(world_size: f32) -> Result<()> {
+    if !(world_size.is_finite() && world_size > 0.0 && world_size <= MAX_WORLD_SIZE) {
+        anyhow::bail!("world_size must be in (0, {}], got {}", MAX_WORLD_SIZE, world_size);
+    }
+    Ok(())
+}
+
+/// Calls `create` with `desired`, halving the count after each failure `retry`
+/// accepts until it succeeds or a call at `min` fails. Other errors are returned as is.
+fn halve_until_ok<T>(
+    desired: usize,
+    min: usize,
+    retry: impl Fn(&anyhow::Error) -> bool,
+    mut create: impl FnMut(usize) -> Result<T>,
+) -> Result<T> {
+    let mut n = desired.max(min);
+    loop {
+        match create(n) {
+            Ok(value) => return Ok(value),
+            Err(e) if n <= min || !retry(&e) => return Err(e),
+            Err(e) => {
+                let next = (n / 2).max(min);
+                tracing::warn!("Allocating {} boids failed: {:?}, retrying with {}", n, e, next);
+                n = next;
+            }
+        }
+    }
+}
+
+fn check_num_predators(num_predators: usize, num_boids: usize) -> Result<()> {
+    let max = predators::MAX_PREDATORS.min(num_boids);
+    if num_predators > max {
+        anyhow::bail!("num_predators must be 0..={}, got {}", max, num_predators);
+    }
+    Ok(())
+}
+
+/// Lower bound on boid mass so `a = F / mass` stays finite
+pub const MIN_MASS: f32 = 0.01;
+/// Speed cap of every boid until `max_speed` or `max_speed_range` is set
+pub const DEFAULT_MAX_SPEED: f32 = 0.05;
+/// Side length of the square `[0, world_size]²` boids live in until `world_size` is set.
+/// Positions, radii and speeds are all in these units.
+pub const DEFAULT_WORLD_SIZE: f32 = 1.0;
+pub const MAX_WORLD_SIZE: f32 = 1000.0;
+/// Steering weights as multiples of `max_force`, shared by the CPU step and the kernel
+const SEPARATION_WEIGHT: f32 = 1.0;
+const ALIGNMENT_WEIGHT: f32 = 0.5;
+const COHESION_WEIGHT: f32 = 0.3;
+// Above the three combined so boids inside an obstacle always head out; matches boids.cu
+const OBSTACLE_WEIGHT: f32 = 4.0;
+
 #[repr(C)]
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Boid {
     pub x: f32,
     pub y: f32,
     pub vx: f32,
     pub vy: f32,
+    pub mass: f32,
+    /// This boid's speed cap, drawn from the simulation's `max_speed_range`
+    pub max_speed: f32,
+    /// Stable identity assigned at creation, preserved across steps
+    pub id: u32,
     pub species: u8,
+    /// Heritable traits; only expressed while genetics is enabled
+    pub genes: Genes,
+}
+
+impl Default for Boid {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            vx: 0.0,
+            vy: 0.0,
+            mass: 1.0,
+            max_speed: DEFAULT_MAX_SPEED,
+            id: 0,
+            species: 0,
+            genes: Genes::default(),
+        }
+    }
 }
 
+#[cfg(not(feature = "no-cuda"))]
 unsafe impl DeviceCopy for Boid {}
 
+impl Boid {
+    /// Integrate a steering force into velocity using `a = F / mass`
+    pub fn apply_force(&mut self, fx: f32, fy: f32, dt: f32) {
+        let inv_mass = 1.0 / self.mass.max(MIN_MASS);
+        self.vx += fx * inv_mass * dt;
+        self.vy += fy * inv_mass * dt;
+    }
+}
+
+/// How each boid chooses the flockmates it reacts to
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborMode {
+    /// Every same-species boid within the interaction radii (classic Reynolds)
+    #[default]
+    Metric,
+    /// The `k` nearest same-species boids regardless of distance (starling-style)
+    Topological,
+}
+
+/// How `set_params` treats radii outside `separation <= alignment <= cohesion`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RadiusCheck {
+    /// Apply the radii anyway and report a warning
+    #[default]
+    Warn,
+    /// Reject the parameters
+    Error,
+}
+
+/// Describe how the radii break the ordering classic flocking relies on, if they do
+pub fn radius_ordering_violation(separation: f32, alignment: f32, cohesion: f32) -> Option<String> {
+    // Separation beyond alignment/cohesion pushes boids apart before they can group up
+    if separation <= alignment && alignment <= cohesion {
+        return None;
+    }
+    Some(format!(
+        "radii should satisfy separation <= alignment <= cohesion, got {} / {} / {}",
+        separation, alignment, cohesion
+    ))
+}
+
+/// What happens to boids that leave the unit square
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Re-enter from the opposite edge (torus)
+    #[default]
+    Wrap,
+    /// Reflect off the edges
+    Bounce,
+    /// Boids that leave are respawned on the opposite edge with a random velocity
+    Open,
+}
+
+/// Slowest inward speed after a bounce in a unit world, so steering can't pin a boid
+/// to the edge; scaled by the world size
+pub const BOUNCE_MIN_SPEED: f32 = 1e-3;
+/// Mixed into the jitter seed for open-mode respawn velocities (matches boids.cu)
+const OPEN_RESPAWN_SALT: u32 = 0x9e37_79b9;
+
+impl BoundaryMode {
+    /// Value of the kernel's `boundaryMode` argument
+    fn kernel_code(self) -> i32 {
+        match self {
+            BoundaryMode::Wrap => 0,
+            BoundaryMode::Bounce => 1,
+            BoundaryMode::Open => 2,
+        }
+    }
+
+    /// Apply the boundary of a `world_size` square to a boid that has just moved.
+    /// `respawn_velocity` is only used by `Open`, for boids that left the square this step.
+    pub fn apply(self, b: &mut Boid, respawn_velocity: (f32, f32), world_size: f32) {
+        match self {
+            BoundaryMode::Wrap => {
+                if b.x < 0.0 {
+                    b.x += world_size;
+                }
+                if b.x > world_size {
+                    b.x -= world_size;
+                }
+                if b.y < 0.0 {
+                    b.y += world_size;
+                }
+                if b.y > world_size {
+                    b.y -= world_size;
+                }
+            }
+            BoundaryMode::Bounce => {
+                bounce_axis(&mut b.x, &mut b.vx, world_size);
+                bounce_axis(&mut b.y, &mut b.vy, world_size);
+            }
+            BoundaryMode::Open => {
+                if (0.0..=world_size).contains(&b.x) && (0.0..=world_size).contains(&b.y) {
+                    return;
+                }
+                // Fresh heading, turned inward along whichever axis the boid left by
+                (b.vx, b.vy) = respawn_velocity;
+                respawn_axis(&mut b.x, &mut b.vx, world_size);
+                respawn_axis(&mut b.y, &mut b.vy, world_size);
+            }
+        }
+    }
+}
+
+fn respawn_axis(p: &mut f32, v: &mut f32, world_size: f32) {
+    if *p < 0.0 {
+        *p = world_size;
+        *v = -v.abs();
+    } else if *p > world_size {
+        *p = 0.0;
+        *v = v.abs();
+    }
+}
+
+impl std::str::FromStr for BoundaryMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wrap" => Ok(BoundaryMode::Wrap),
+            "bounce" => Ok(BoundaryMode::Bounce),
+            "open" => Ok(BoundaryMode::Open),
+            other => anyhow::bail!("boundary mode must be wrap|bounce|open, got {:?}", other),
+        }
+    }
+}
+
+fn bounce_axis(p: &mut f32, v: &mut f32, world_size: f32) {
+    let min_speed = BOUNCE_MIN_SPEED * world_size;
+    // Boids sitting exactly on an edge bounce too; with a minimum inward speed they
+    // leave it instead of reflecting back and forth across it every step
+    if *p <= 0.0 {
+        *p = -*p;
+        *v = v.abs().max(min_speed);
+    } else if *p >= world_size {
+        *p = 2.0 * world_size - *p;
+        *v = -v.abs().max(min_speed);
+    }
+    // Far-out boids (e.g. after leaving open mode) land on the edge
+    *p = p.clamp(0.0, world_size);
+}
+
+/// Check the radii and speed limits shared by the 2D and 3D flocks.
+/// Returns warnings for out-of-order radii when `radius_check` allows them.
+pub(crate) fn validate_steering(
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    max_speed: f32,
+    max_force: f32,
+    radius_check: RadiusCheck,
+) -> Result<Vec<String>> {
+    let positive = [
+        ("separation_radius", separation),
+        ("alignment_radius", alignment),
+        ("cohesion_radius", cohesion),
+        ("max_speed", max_speed),
+        ("max_force", max_force),
+    ];
+    for (name, value) in positive {
+        if !value.is_finite() || value <= 0.0 {
+            anyhow::bail!("{} must be positive, got {}", name, value);
+        }
+    }
+    // Steering stronger than the speed cap makes boids snap between headings
+    if max_force > max_speed {
+        anyhow::bail!("max_force ({}) must not exceed max_speed ({})", max_force, max_speed);
+    }
+    match radius_ordering_violation(separation, alignment, cohesion) {
+        Some(violation) if radius_check == RadiusCheck::Error => anyhow::bail!(violation),
+        Some(violation) => Ok(vec![violation]),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Default neighbour count for topological flocking; starlings track ~7
+pub const DEFAULT_TOPOLOGICAL_K: usize = 7;
+
+/// Tunable flocking parameters accepted by the boids endpoint.
+/// Every field is optional; omitted fields keep their current value.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct BoidsParams {
+    pub separation_radius: Option<f32>,
+    pub alignment_radius: Option<f32>,
+    pub cohesion_radius: Option<f32>,
+    /// Speed cap shared by every boid
+    pub max_speed: Option<f32>,
+    /// Give each boid its own speed cap, drawn uniformly from `[min, max]`;
+    /// replaces `max_speed`, so set one or the other
+    pub max_speed_range: Option<(f32, f32)>,
+    /// Largest steering force per step; must not exceed any boid's max speed
+    pub max_force: Option<f32>,
+    /// Whether out-of-order radii warn (default) or are rejected
+    pub radius_check: Option<RadiusCheck>,
+    /// Default mass per species, indexed by species id
+    pub species_masses: Option<Vec<f32>>,
+    /// Enable the separation auto-tuner (off by default)
+    pub auto_tune: Option<bool>,
+    /// Neighbours per unit area the auto-tuner steers toward
+    pub target_density: Option<f32>,
+    pub neighbor_mode: Option<NeighborMode>,
+    /// Neighbour count used by `NeighborMode::Topological`
+    pub topological_k: Option<usize>,
+    /// Amplitude of the random velocity kick added every step (0 disables)
+    pub jitter: Option<f32>,
+    /// Seed for the jitter noise, so runs are reproducible
+    pub jitter_seed: Option<u32>,
+    /// Static obstacles; replaces the current set
+    pub obstacles: Option<Vec<Obstacle>>,
+    /// Sweep each boid's path against obstacles so fast boids can't tunnel through walls
+    pub continuous_collision: Option<bool>,
+    pub boundary: Option<BoundaryMode>,
+    /// Boids turned into predators that the rest of the flock flees from
+    pub num_predators: Option<usize>,
+    /// Let dense clusters breed mutated children over distant boids (off by default)
+    pub genetics: Option<bool>,
+    /// Side length of the square world (default `DEFAULT_WORLD_SIZE`). Changing it
+    /// rescales positions, velocities, radii, speed caps and `max_force` with it;
+    /// other values in the same request are in the new units.
+    pub world_size: Option<f32>,
+}
+
+/// Which boids are within `cohesion_radius` of each other (same species only)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NeighborGraph {
+    /// Stable id of each boid
+    pub ids: Vec<u32>,
+    /// For each boid, indices into `ids` of its neighbours
+    pub neighbors: Vec<Vec<u32>>,
+}
+
+/// A speed cap from `[min, max]`; a single-valued range draws nothing from `rng`
+fn sample_max_speed(rng: &mut SimRng, (min, max): (f32, f32)) -> f32 {
+    if min == max {
+        min
+    } else {
+        rng.range_f32(min, max)
+    }
+}
+
+/// `count` boids spread uniformly over the unit square, ids `0..count`.
+/// Species are drawn uniformly from one per entry of `species_masses`.
+fn random_flock(
+    rng: &mut SimRng,
+    count: usize,
+    species_masses: &[f32],
+    max_speed_range: (f32, f32),
+    world_size: f32,
+) -> Vec<Boid> {
+    (0..count)
+        .map(|id| {
+            let x = rng.next_f32() * world_size;
+            let y = rng.next_f32() * world_size;
+            let vx = rng.range_f32(-0.03, 0.03) * world_size;
+            let vy = rng.range_f32(-0.03, 0.03) * world_size;
+            let species = rng.below(species_masses.len() as u32) as u8;
+            Boid {
+                x,
+                y,
+                vx,
+                vy,
+                mass: species_masses[species as usize],
+                max_speed: sample_max_speed(rng, max_speed_range),
+                id: id as u32,
+                species,
+                genes: Genes::default(),
+            }
+        })
+        .collect()
+}
+
+/// Default auto-tuner target, roughly the density of a relaxed flock
+pub const DEFAULT_TARGET_DENSITY: f32 = 400.0;
+
 struct HostBuffers {
     boids: Vec<Boid>,
     x: Vec<f32>,
     y: Vec<f32>,
     vx: Vec<f32>,
     vy: Vec<f32>,
+    mass: Vec<f32>,
+    max_speed: Vec<f32>,
     species: Vec<u8>,
+    // Steering force magnitude of each boid in the last CPU step
+    force: Vec<f32>,
+    // Per-boid neighbour scratch space reused across steps
+    neighbors: Vec<usize>,
+    candidates: Vec<(f32, usize)>,
+    // Uniform grid for metric neighbour lookups, rebuilt every CPU step
+    grid: SpatialGrid,
 }
 
 impl HostBuffers {
@@ -39,7 +441,13 @@ impl HostBuffers {
             y: vec![0.0; count],
             vx: vec![0.0; count],
             vy: vec![0.0; count],
+            mass: vec![1.0; count],
+            max_speed: vec![DEFAULT_MAX_SPEED; count],
             species: vec![0; count],
+            force: vec![0.0; count],
+            neighbors: Vec::new(),
+            candidates: Vec::new(),
+            grid: SpatialGrid::default(),
         }
     }
 
@@ -47,6 +455,20 @@ impl HostBuffers {
         self.boids.len()
     }
 
+    /// Resize the scalar mirrors in place to match `boids` and refill them
+    fn fit_to_boids(&mut self) {
+        let count = self.boids.len();
+        self.x.resize(count, 0.0);
+        self.y.resize(count, 0.0);
+        self.vx.resize(count, 0.0);
+        self.vy.resize(count, 0.0);
+        self.mass.resize(count, 1.0);
+        self.max_speed.resize(count, DEFAULT_MAX_SPEED);
+        self.species.resize(count, 0);
+        self.force.resize(count, 0.0);
+        self.sync_scalars_from_boids();
+    }
+
     fn copy_from_slice(&mut self, boids: &[Boid]) {
         debug_assert_eq!(self.len(), boids.len());
         self.boids.copy_from_slice(boids);
@@ -59,333 +481,1321 @@ impl HostBuffers {
             self.y[idx] = boid.y;
             self.vx[idx] = boid.vx;
             self.vy[idx] = boid.vy;
+            self.mass[idx] = boid.mass;
+            self.max_speed[idx] = boid.max_speed;
             self.species[idx] = boid.species;
         }
     }
 
     fn rebuild_boids_from_scalars(&mut self) {
-        for i in 0..self.boids.len() {
-            self.boids[i] = Boid {
-                x: self.x[i],
-                y: self.y[i],
-                vx: self.vx[i],
-                vy: self.vy[i],
-                species: self.species[i],
-            };
+        // Ids are not mirrored in the SoA buffers, so keep the existing ones
+        for (i, boid) in self.boids.iter_mut().enumerate() {
+            boid.x = self.x[i];
+            boid.y = self.y[i];
+            boid.vx = self.vx[i];
+            boid.vy = self.vy[i];
+            boid.mass = self.mass[i];
+            boid.max_speed = self.max_speed[i];
+            boid.species = self.species[i];
         }
     }
 }
 
+/// Parse a `BoidsSimulation::serialize_state` blob and check every value in it
+pub fn decode_checkpoint(bytes: &[u8]) -> Result<FlockState> {
+    let state = FlockState::decode(bytes)?;
+    check_num_species(state.species_masses.len() as u8)?;
+    let (min_speed, max_speed) = state.max_speed_range;
+    if !(min_speed.is_finite() && max_speed.is_finite() && min_speed <= max_speed) {
+        anyhow::bail!("max_speed_range must be finite with min <= max, got ({}, {})", min_speed, max_speed);
+    }
+    // Warn: radii the saved simulation accepted stay accepted
+    validate_steering(
+        state.separation_radius,
+        state.alignment_radius,
+        state.cohesion_radius,
+        min_speed,
+        state.max_force,
+        RadiusCheck::Warn,
+    )?;
+    if state.topological_k == 0 {
+        anyhow::bail!("topological_k must be at least 1");
+    }
+    if !(state.jitter.is_finite() && state.jitter >= 0.0) {
+        anyhow::bail!("jitter must be non-negative, got {}", state.jitter);
+    }
+    check_world_size(state.world_size)?;
+    if let Some(m) = state.species_masses.iter().find(|m| !m.is_finite() || **m < MIN_MASS) {
+        anyhow::bail!("species mass must be finite and >= {}, got {}", MIN_MASS, m);
+    }
+    obstacles::validate_all(&state.obstacles)?;
+    attractors::validate_all(&state.attractors, state.world_size)?;
+    check_num_predators(state.num_predators as usize, state.boids.len())?;
+    let num_species = state.species_masses.len() as u8;
+    if let Some(b) = state.boids.iter().find(|b| {
+        !(b.x.is_finite() && b.y.is_finite() && b.vx.is_finite() && b.vy.is_finite())
+            || !(b.mass.is_finite() && b.mass >= MIN_MASS && b.max_speed.is_finite() && b.max_speed > 0.0)
+            || !(b.species < num_species || predators::is_predator(b))
+            || !genetics::valid_genes(&b.genes)
+            || b.id >= state.next_id
+    }) {
+        anyhow::bail!("checkpoint holds an invalid boid: {:?}", b);
+    }
+    Ok(state)
+}
+
 pub struct BoidsSimulation {
-    context: Arc<CudaContext>,
+    // None when running on the host backend
+    context: Option<Arc<CudaContext>>,
+    // Whether `boids` lives in device memory (vs. the host backend)
+    on_device: bool,
     num_boids: usize,
-    boids: DeviceBuffer<Boid>,
+    boids: Resizable<Boid>,
     // SoA device buffers (used if CUDA kernel is available)
-    d_x: Option<DeviceBuffer<f32>>,
-    d_y: Option<DeviceBuffer<f32>>,
-    d_vx: Option<DeviceBuffer<f32>>,
-    d_vy: Option<DeviceBuffer<f32>>,
-    d_species: Option<DeviceBuffer<u8>>,
+    d_x: Option<SimBuffer<f32>>,
+    d_y: Option<SimBuffer<f32>>,
+    d_vx: Option<SimBuffer<f32>>,
+    d_vy: Option<SimBuffer<f32>>,
+    d_mass: Option<SimBuffer<f32>>,
+    d_max_speed: Option<SimBuffer<f32>>,
+    d_species: Option<SimBuffer<u8>>,
+    // Steering force magnitudes written by the kernel
+    d_force: Option<SimBuffer<f32>>,
+    // `MAX_OBSTACLES` capsules for the kernel; reuploaded when `obstacles_dirty`
+    d_obstacles: Option<SimBuffer<f32>>,
+    obstacles_dirty: bool,
+    // `MAX_ATTRACTORS` attractors for the kernel; reuploaded when `attractors_dirty`
+    d_attractors: Option<SimBuffer<f32>>,
+    attractors_dirty: bool,
     ptx: Option<String>,
+    cuda_failures: CudaFailures,
     soa_dirty: bool,
     aos_dirty: bool,
     last_used_cuda: bool,
+    force_cpu: bool,
     // Boids parameters
     separation_radius: f32,
     alignment_radius: f32,
     cohesion_radius: f32,
+    radius_check: RadiusCheck,
+    // Upper end of `max_speed_range`; bounds how far any boid moves per step
     max_speed: f32,
+    max_speed_range: (f32, f32),
     max_force: f32,
+    species_masses: Vec<f32>,
+    // Next id handed out to a newly created boid
+    next_id: u32,
+    neighbor_mode: NeighborMode,
+    topological_k: usize,
+    density_tuner: Option<DensityTuner>,
+    // Simulated time since the last auto-tune update
+    tune_elapsed: f32,
+    stasis: StasisDetector,
+    // Simulated time since the last stasis check
+    stasis_elapsed: f32,
+    genetics: bool,
+    // Simulated time since the last breeding round
+    genetics_elapsed: f32,
+    // Children born since genetics was last enabled
+    births: u64,
+    jitter: f32,
+    jitter_seed: u32,
+    // Steps taken so far; decorrelates the jitter noise between steps
+    step_index: u32,
+    obstacles: Vec<Obstacle>,
+    attractors: Vec<Attractor>,
+    continuous_collision: bool,
+    emitter: Option<Emitter>,
+    boundary: BoundaryMode,
+    // Side length of the square world; see `DEFAULT_WORLD_SIZE`
+    world_size: f32,
+    // Predators kept in the flock; re-applied after a reset
+    num_predators: usize,
+    // Source of all randomness after construction (spawning, emitter)
+    rng: SimRng,
     host_buffers: HostBuffers,
 }
 
 impl BoidsSimulation {
     pub fn new(context: &Arc<CudaContext>, num_boids: usize) -> Result<Self> {
+        Self::new_seeded(context, num_boids, rand::random())
+    }
+
+    /// Like `new` but with `num_species` groups (1..=`MAX_SPECIES`) instead of `NUM_SPECIES`.
+    /// Boids only flock with their own species.
+    pub fn new_with_species(context: &Arc<CudaContext>, num_boids: usize, num_species: u8) -> Result<Self> {
+        Self::with_backend(
+            Some(Arc::clone(context)),
+            num_boids,
+            num_species,
+            &DefaultBackend,
+            SimRng::from_entropy(),
+        )
+    }
+
+    /// Like `new_with_species`, but halves the count whenever the GPU runs out of memory
+    /// until it fits or `min` boids also fail. `num_boids()` is the count created.
+    pub fn new_best_effort(context: &Arc<CudaContext>, desired: usize, min: usize, num_species: u8) -> Result<Self> {
+        // A bad species count would fail at every size
+        check_num_species(num_species)?;
+        halve_until_ok(desired, min, accelerator::is_out_of_memory, |n| {
+            Self::new_with_species(context, n, num_species)
+        })
+    }
+
+    /// Like `new`, but every random choice is drawn from `seed`
+    pub fn new_seeded(context: &Arc<CudaContext>, num_boids: usize, seed: u64) -> Result<Self> {
         // Context should already be initialized by caller
+        Self::with_backend(
+            Some(Arc::clone(context)),
+            num_boids,
+            NUM_SPECIES as u8,
+            &DefaultBackend,
+            SimRng::new(seed),
+        )
+    }
 
-        // Initialize boids randomly
-        let mut host_boids = Vec::new();
-        let mut rng = rand::thread_rng();
-        for _ in 0..num_boids {
-            host_boids.push(Boid {
-                x: rng.gen::<f32>(),
-                y: rng.gen::<f32>(),
-                vx: rng.gen_range(-0.03..0.03),
-                vy: rng.gen_range(-0.03..0.03),
-                species: rng.gen_range(0..=3),
-            });
-        }
+    /// Simulation backed by host memory only; runs the CPU path without a GPU
+    pub fn new_host(num_boids: usize) -> Result<Self> {
+        Self::with_backend(None, num_boids, NUM_SPECIES as u8, &HostBackend, SimRng::from_entropy())
+    }
+
+    pub fn new_host_seeded(num_boids: usize, seed: u64) -> Result<Self> {
+        Self::with_backend(None, num_boids, NUM_SPECIES as u8, &HostBackend, SimRng::new(seed))
+    }
 
-        let boids = DeviceBuffer::from_slice(&host_boids)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate boids: {:?}", e))?;
+    fn with_backend<B: Backend>(
+        context: Option<Arc<CudaContext>>,
+        num_boids: usize,
+        num_species: u8,
+        backend: &B,
+        mut rng: SimRng,
+    ) -> Result<Self> {
+        check_num_species(num_species)?;
+        let species_masses = vec![1.0; num_species as usize];
+        let max_speed_range = (DEFAULT_MAX_SPEED, DEFAULT_MAX_SPEED);
+        let host_boids = random_flock(&mut rng, num_boids, &species_masses, max_speed_range, DEFAULT_WORLD_SIZE);
+        let boids = Resizable::upload(backend, &host_boids)
+            .context("Failed to allocate boids")?;
         let mut host_buffers = HostBuffers::new(num_boids);
         host_buffers.copy_from_slice(&host_boids);
         // Try to prepare CUDA kernel (PTX provided by build.rs via BOIDS_PTX)
-        let mut d_x = None;
-        let mut d_y = None;
-        let mut d_vx = None;
-        let mut d_vy = None;
-        let mut d_species = None;
-        let mut ptx_opt = None;
-        let mut soa_dirty = true;
-
-        if let Some(ptx_path) = option_env!("BOIDS_PTX") {
-            if let Ok(ptx) = std::fs::read_to_string(ptx_path) {
-                // Initialize SoA buffers with current values now; PTX will be used on-demand
-                let dx = DeviceBuffer::from_slice(&host_buffers.x)
-                    .map_err(|e| anyhow::anyhow!("alloc d_x: {:?}", e))?;
-                let dy = DeviceBuffer::from_slice(&host_buffers.y)
-                    .map_err(|e| anyhow::anyhow!("alloc d_y: {:?}", e))?;
-                let dvx = DeviceBuffer::from_slice(&host_buffers.vx)
-                    .map_err(|e| anyhow::anyhow!("alloc d_vx: {:?}", e))?;
-                let dvy = DeviceBuffer::from_slice(&host_buffers.vy)
-                    .map_err(|e| anyhow::anyhow!("alloc d_vy: {:?}", e))?;
-                let dspec = DeviceBuffer::from_slice(&host_buffers.species)
-                    .map_err(|e| anyhow::anyhow!("alloc d_species: {:?}", e))?;
-                d_x = Some(dx);
-                d_y = Some(dy);
-                d_vx = Some(dvx);
-                d_vy = Some(dvy);
-                d_species = Some(dspec);
-                ptx_opt = Some(ptx);
-                soa_dirty = false;
-            }
-        }
-
-        Ok(Self {
-            context: Arc::clone(context),
+        let ptx = option_env!("BOIDS_PTX")
+            .filter(|_| backend.is_device())
+            .and_then(|path| std::fs::read_to_string(path).ok());
+
+        let mut sim = Self {
+            context,
+            on_device: backend.is_device(),
             num_boids,
             boids,
-            d_x,
-            d_y,
-            d_vx,
-            d_vy,
-            d_species,
-            ptx: ptx_opt,
-            soa_dirty,
+            d_x: None,
+            d_y: None,
+            d_vx: None,
+            d_vy: None,
+            d_mass: None,
+            d_max_speed: None,
+            d_species: None,
+            d_force: None,
+            d_obstacles: None,
+            obstacles_dirty: true,
+            d_attractors: None,
+            attractors_dirty: true,
+            ptx,
+            cuda_failures: CudaFailures::default(),
+            soa_dirty: true,
             aos_dirty: false,
             last_used_cuda: false,
+            force_cpu: false,
             separation_radius: 0.05,
             alignment_radius: 0.1,
             cohesion_radius: 0.15,
-            max_speed: 0.05,
+            radius_check: RadiusCheck::Warn,
+            max_speed: DEFAULT_MAX_SPEED,
+            max_speed_range,
             max_force: 0.01,
+            species_masses,
+            next_id: num_boids as u32,
+            neighbor_mode: NeighborMode::Metric,
+            topological_k: DEFAULT_TOPOLOGICAL_K,
+            density_tuner: None,
+            tune_elapsed: 0.0,
+            stasis: StasisDetector::default(),
+            stasis_elapsed: 0.0,
+            genetics: false,
+            genetics_elapsed: 0.0,
+            births: 0,
+            jitter: 0.0,
+            jitter_seed: 0,
+            step_index: 0,
+            obstacles: Vec::new(),
+            attractors: Vec::new(),
+            continuous_collision: false,
+            emitter: None,
+            boundary: BoundaryMode::Wrap,
+            world_size: DEFAULT_WORLD_SIZE,
+            num_predators: 0,
+            rng,
             host_buffers,
-        })
+        };
+        // Initialize SoA buffers with current values now; PTX will be used on-demand
+        if sim.ptx.is_some() {
+            sim.upload_soa()?;
+        }
+        Ok(sim)
     }
 
-    pub fn num_boids(&self) -> usize {
-        self.num_boids
+    /// Write the host scalars to the SoA device mirror, (re)allocating buffers that are
+    /// missing or too small with the same capacity as `boids`
+    fn upload_soa(&mut self) -> Result<()> {
+        let h = &self.host_buffers;
+        let capacity = self.boids.capacity();
+        fill_device(&mut self.d_x, &h.x, capacity)
+            .context("alloc d_x")?;
+        fill_device(&mut self.d_y, &h.y, capacity)
+            .context("alloc d_y")?;
+        fill_device(&mut self.d_vx, &h.vx, capacity)
+            .context("alloc d_vx")?;
+        fill_device(&mut self.d_vy, &h.vy, capacity)
+            .context("alloc d_vy")?;
+        fill_device(&mut self.d_mass, &h.mass, capacity)
+            .context("alloc d_mass")?;
+        fill_device(&mut self.d_max_speed, &h.max_speed, capacity)
+            .context("alloc d_max_speed")?;
+        fill_device(&mut self.d_species, &h.species, capacity)
+            .context("alloc d_species")?;
+        fill_device(&mut self.d_force, &h.force, capacity)
+            .context("alloc d_force")?;
+        self.soa_dirty = false;
+        Ok(())
     }
 
-    pub fn step(&mut self, dt: f32) -> Result<()> {
-        if self.ptx.is_some() && self.has_soa() {
-            if self.soa_dirty {
-                self.sync_soa_from_aos()?;
-            }
-            let ptx = self.ptx.as_ref().unwrap();
-            let dx = self.d_x.as_mut().unwrap();
-            let dy = self.d_y.as_mut().unwrap();
-            let dvx = self.d_vx.as_mut().unwrap();
-            let dvy = self.d_vy.as_mut().unwrap();
-            let dspecies = self.d_species.as_mut().unwrap();
-
-            let ptx_c = CString::new(ptx.as_str()).unwrap();
-            let module = Module::load_from_string(&ptx_c)
-                .map_err(|e| anyhow::anyhow!("Failed to load boids PTX: {:?}", e))?;
-            let func = module
-                .get_function(&CString::new("boids_step").unwrap())
-                .map_err(|e| anyhow::anyhow!("Failed to get boids_step: {:?}", e))?;
-            let stream = Stream::new(StreamFlags::DEFAULT, None)
-                .map_err(|e| anyhow::anyhow!("Failed to create stream: {:?}", e))?;
-
-            let n = self.num_boids as i32;
-            let block = (128u32, 1u32, 1u32);
-            let grid = (
-                ((self.num_boids as u32) + block.0 - 1) / block.0,
-                1u32,
-                1u32,
-            );
-            unsafe {
-                launch!(
-                    func<<<grid, block, 0, stream>>>(
-                        n,
-                        dt as f32,
-                        self.separation_radius as f32,
-                        self.alignment_radius as f32,
-                        self.cohesion_radius as f32,
-                        1.5f32,
-                        1.0f32,
-                        0.3f32,
-                        self.max_speed as f32,
-                        dspecies.as_device_ptr(),
-                        dx.as_device_ptr(),
-                        dy.as_device_ptr(),
-                        dvx.as_device_ptr(),
-                        dvy.as_device_ptr(),
-                        1_000i32,
-                        1_000i32
-                    )
-                )
-                .map_err(|e| anyhow::anyhow!("boids_step launch failed: {:?}", e))?;
-            }
-            stream
-                .synchronize()
-                .map_err(|e| anyhow::anyhow!("boids_step sync failed: {:?}", e))?;
-
-            self.aos_dirty = true;
-            self.last_used_cuda = true;
-            self.soa_dirty = false;
-            return Ok(());
-        }
-
-        // CPU fallback
+    /// Edit the flock's membership on the host. Buffers are resized in place and only
+    /// reallocated when the flock outgrows them.
+    fn resize_host_boids<F: FnOnce(&mut Vec<Boid>)>(&mut self, f: F) -> Result<()> {
         self.ensure_aos_current()?;
-        let host_boids = &mut self.host_buffers.boids;
         self.boids
-            .copy_to(&mut host_boids[..])
+            .copy_to(&mut self.host_buffers.boids[..])
             .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        f(&mut self.host_buffers.boids);
 
-        // Boids algorithm: Separation, Alignment, Cohesion
-        for i in 0..self.num_boids {
-            let mut sep_x = 0.0;
-            let mut sep_y = 0.0;
-            let mut align_x = 0.0;
-            let mut align_y = 0.0;
-            let mut coh_x = 0.0;
-            let mut coh_y = 0.0;
-            let mut sep_count = 0;
-            let mut align_count = 0;
-            let mut coh_count = 0;
+        let boids = &self.host_buffers.boids;
+        if self.on_device {
+            self.boids.assign(&DefaultBackend, boids)
+        } else {
+            self.boids.assign(&HostBackend, boids)
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to reallocate boids: {:?}", e))?;
+        self.num_boids = boids.len();
+        self.host_buffers.fit_to_boids();
+        self.aos_dirty = false;
+        if self.has_soa() {
+            self.upload_soa()?;
+        } else {
+            self.soa_dirty = true;
+        }
+        Ok(())
+    }
 
-            let bi = &host_boids[i];
+    /// Add boids to the flock, assigning fresh ids; returns the ids handed out
+    pub fn spawn(&mut self, new_boids: &[Boid]) -> Result<Vec<u32>> {
+        self.replace_oldest(0, new_boids)
+    }
 
-            for j in 0..self.num_boids {
-                if i == j {
-                    continue;
-                }
+    /// Remove the `count` oldest boids (earliest ids handed out)
+    pub fn remove_oldest(&mut self, count: usize) -> Result<()> {
+        self.replace_oldest(count, &[]).map(|_| ())
+    }
 
-                let bj = &host_boids[j];
-                let dx = bi.x - bj.x;
-                let dy = bi.y - bj.y;
-                let dist_sq = dx * dx + dy * dy;
-                let dist = dist_sq.sqrt();
+    /// Grow the flock by `count` random boids, keeping the existing ones; returns their ids
+    pub fn add_boids(&mut self, count: usize) -> Result<Vec<u32>> {
+        check_num_boids(self.num_boids.saturating_add(count))?;
+        let new_boids = random_flock(
+            &mut self.rng,
+            count,
+            &self.species_masses,
+            self.max_speed_range,
+            self.world_size,
+        );
+        self.spawn(&new_boids)
+    }
 
-                // Only consider same species (simplified)
-                if bi.species == bj.species {
-                    // Separation
-                    if dist < self.separation_radius && dist > 0.0 {
-                        sep_x += dx / dist;
-                        sep_y += dy / dist;
-                        sep_count += 1;
-                    }
+    /// Shrink the flock by its `count` oldest boids; at least one must remain
+    pub fn remove_boids(&mut self, count: usize) -> Result<()> {
+        if count >= self.num_boids {
+            anyhow::bail!("can't remove {} of {} boids, at least one must remain", count, self.num_boids);
+        }
+        self.remove_oldest(count)?;
+        // Predators may have been among the removed boids
+        self.assign_predators()
+    }
 
-                    // Alignment
-                    if dist < self.alignment_radius {
-                        align_x += bj.vx;
-                        align_y += bj.vy;
-                        align_count += 1;
-                    }
+    /// Add or remove boids until the flock has `num_boids`
+    pub fn resize(&mut self, num_boids: usize) -> Result<()> {
+        check_num_boids(num_boids)?;
+        if num_boids > self.num_boids {
+            self.add_boids(num_boids - self.num_boids).map(|_| ())
+        } else {
+            self.remove_boids(self.num_boids - num_boids)
+        }
+    }
 
-                    // Cohesion
-                    if dist < self.cohesion_radius {
-                        coh_x += bj.x;
-                        coh_y += bj.y;
-                        coh_count += 1;
-                    }
+    /// Re-seed the flock with fresh random positions and velocities, keeping the
+    /// current count and parameters. With a seed the new flock matches `new_seeded`.
+    pub fn reset(&mut self, seed: Option<u64>) -> Result<()> {
+        self.rng = seed.map_or_else(SimRng::from_entropy, SimRng::new);
+        let fresh = random_flock(
+            &mut self.rng,
+            self.num_boids,
+            &self.species_masses,
+            self.max_speed_range,
+            self.world_size,
+        );
+        self.next_id = fresh.len() as u32;
+        self.step_index = 0;
+        self.tune_elapsed = 0.0;
+        self.stasis = StasisDetector::new(self.stasis.threshold, self.stasis.window);
+        self.stasis_elapsed = 0.0;
+        // Rewrites the device buffer and SoA mirror, leaving neither side dirty
+        self.resize_host_boids(|boids| *boids = fresh)?;
+        self.assign_predators()
+    }
+
+    /// Replace the whole flock with boids at `positions`, with small random velocities
+    pub fn reset_positions(&mut self, positions: &[(f32, f32)]) -> Result<()> {
+        let rng = &mut self.rng;
+        let speed = 0.03 * self.world_size;
+        let boids: Vec<Boid> = positions
+            .iter()
+            .map(|&(x, y)| {
+                let species = rng.below(self.species_masses.len() as u32) as u8;
+                Boid {
+                    x,
+                    y,
+                    vx: rng.range_f32(-speed, speed),
+                    vy: rng.range_f32(-speed, speed),
+                    mass: self.species_masses[species as usize],
+                    species,
+                    ..Boid::default()
                 }
+            })
+            .collect();
+        self.replace_oldest(usize::MAX, &boids)?;
+        self.assign_predators()
+    }
+
+    // Remove the `count` oldest boids and append `new_boids` in a single resize.
+    // New boids get a fresh speed cap from `max_speed_range`.
+    fn replace_oldest(&mut self, count: usize, new_boids: &[Boid]) -> Result<Vec<u32>> {
+        let first_id = self.next_id;
+        let ids: Vec<u32> = (0..new_boids.len() as u32).map(|k| first_id.wrapping_add(k)).collect();
+        let max_speeds: Vec<f32> = new_boids
+            .iter()
+            .map(|_| sample_max_speed(&mut self.rng, self.max_speed_range))
+            .collect();
+        self.next_id = first_id.wrapping_add(new_boids.len() as u32);
+        self.resize_host_boids(|boids| {
+            if count >= boids.len() {
+                boids.clear();
+            } else if count > 0 {
+                // Ids wrap, so age is the distance back from `next_id` rather than the id itself
+                let age = |b: &Boid| first_id.wrapping_sub(b.id);
+                let mut ages: Vec<u32> = boids.iter().map(age).collect();
+                let (_, &mut cutoff, _) = ages.select_nth_unstable_by(count - 1, |a, b| b.cmp(a));
+                boids.retain(|b| age(b) < cutoff);
             }
+            boids.extend(
+                new_boids
+                    .iter()
+                    .zip(&ids)
+                    .zip(&max_speeds)
+                    .map(|((b, &id), &max_speed)| Boid { id, max_speed, ..*b }),
+            );
+        })?;
+        Ok(ids)
+    }
 
-            // Calculate forces
-            let mut fx = 0.0;
-            let mut fy = 0.0;
+    /// Configure the continuous emitter (`None` disables it)
+    pub fn set_emitter(&mut self, config: Option<EmitterConfig>) -> Result<()> {
+        if let Some(config) = &config {
+            config.validate(self.num_species())?;
+        }
+        self.emitter = config.map(Emitter::new);
+        Ok(())
+    }
 
-            // Separation force
-            if sep_count > 0 {
-                let sep_mag = (sep_x * sep_x + sep_y * sep_y).sqrt();
-                if sep_mag > 0.0 {
-                    fx += (sep_x / sep_mag) * self.max_force;
-                    fy += (sep_y / sep_mag) * self.max_force;
-                }
-            }
+    pub fn emitter_config(&self) -> Option<&EmitterConfig> {
+        self.emitter.as_ref().map(|e| &e.config)
+    }
 
-            // Alignment force
-            if align_count > 0 {
-                let align_mag = (align_x * align_x + align_y * align_y).sqrt();
-                if align_mag > 0.0 {
-                    let target_vx = (align_x / align_count as f32) - bi.vx;
-                    let target_vy = (align_y / align_count as f32) - bi.vy;
-                    let target_mag = (target_vx * target_vx + target_vy * target_vy).sqrt();
-                    if target_mag > 0.0 {
-                        fx += (target_vx / target_mag) * self.max_force * 0.5;
-                        fy += (target_vy / target_mag) * self.max_force * 0.5;
-                    }
-                }
-            }
+    /// Spawn the boids the emitter owes after `dt`, evicting the oldest past the cap.
+    /// Returns the number spawned.
+    pub fn run_emitter(&mut self, dt: f32) -> Result<usize> {
+        let Some(emitter) = self.emitter.as_mut() else {
+            return Ok(0);
+        };
+        let max_population = emitter.config.max_population;
+        // At most `max_population` boids
+        let new_boids = emitter.emit(dt, &self.species_masses, &mut self.rng);
+        if new_boids.is_empty() {
+            return Ok(0);
+        }
+        let overflow = (self.num_boids + new_boids.len()).saturating_sub(max_population);
+        self.replace_oldest(overflow, &new_boids)?;
+        Ok(new_boids.len())
+    }
 
-            // Cohesion force
-            if coh_count > 0 {
-                let avg_x = coh_x / coh_count as f32;
-                let avg_y = coh_y / coh_count as f32;
-                let target_x = avg_x - bi.x;
-                let target_y = avg_y - bi.y;
-                let target_mag = (target_x * target_x + target_y * target_y).sqrt();
-                if target_mag > 0.0 {
-                    fx += (target_x / target_mag) * self.max_force * 0.3;
-                    fy += (target_y / target_mag) * self.max_force * 0.3;
-                }
-            }
+    pub fn num_boids(&self) -> usize {
+        self.num_boids
+    }
 
-            // Update velocity
-            host_boids[i].vx += fx * dt;
-            host_boids[i].vy += fy * dt;
+    /// Seed the simulation's random stream started from
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
 
-            // Limit speed
-            let speed =
-                (host_boids[i].vx * host_boids[i].vx + host_boids[i].vy * host_boids[i].vy).sqrt();
-            if speed > self.max_speed {
-                host_boids[i].vx = (host_boids[i].vx / speed) * self.max_speed;
-                host_boids[i].vy = (host_boids[i].vy / speed) * self.max_speed;
+    /// Check `params` against the current state without applying anything.
+    /// Returns warnings about settings that are allowed but look wrong.
+    pub fn validate_params(&self, params: &BoidsParams) -> Result<Vec<String>> {
+        if let Some((min, max)) = params.max_speed_range {
+            if params.max_speed.is_some() {
+                anyhow::bail!("give max_speed or max_speed_range, not both");
             }
+            if !(min.is_finite() && max.is_finite() && min <= max) {
+                anyhow::bail!("max_speed_range must be finite with min <= max, got ({}, {})", min, max);
+            }
+        }
+        if let Some(world_size) = params.world_size {
+            check_world_size(world_size)?;
+        }
+        // Current values as they will be after a `world_size` change in the same request
+        let scale = params.world_size.map_or(1.0, |size| size / self.world_size);
+        // The slowest boid's cap is the one `max_force` must not exceed
+        let slowest = match (params.max_speed, params.max_speed_range) {
+            (Some(max_speed), _) => max_speed,
+            (None, Some((min, _))) => min,
+            (None, None) => self.max_speed_range.0 * scale,
+        };
+        let warnings = validate_steering(
+            params.separation_radius.unwrap_or(self.separation_radius * scale),
+            params.alignment_radius.unwrap_or(self.alignment_radius * scale),
+            params.cohesion_radius.unwrap_or(self.cohesion_radius * scale),
+            slowest,
+            params.max_force.unwrap_or(self.max_force * scale),
+            params.radius_check.unwrap_or(self.radius_check),
+        )?;
 
-            // Update position
-            host_boids[i].x += host_boids[i].vx * dt;
-            host_boids[i].y += host_boids[i].vy * dt;
-
-            // Wrap around boundaries
-            if host_boids[i].x < 0.0 {
-                host_boids[i].x += 1.0;
+        if params.topological_k == Some(0) {
+            anyhow::bail!("topological_k must be at least 1");
+        }
+        if let Some(jitter) = params.jitter {
+            if !jitter.is_finite() || jitter < 0.0 {
+                anyhow::bail!("jitter must be non-negative, got {}", jitter);
             }
-            if host_boids[i].x > 1.0 {
-                host_boids[i].x -= 1.0;
+        }
+        if let Some(obstacles) = &params.obstacles {
+            obstacles::validate_all(obstacles)?;
+        }
+        if let Some(target) = params.target_density {
+            if !target.is_finite() || target <= 0.0 {
+                anyhow::bail!("target_density must be positive, got {}", target);
+            }
+        }
+        if let Some(num_predators) = params.num_predators {
+            check_num_predators(num_predators, self.num_boids)?;
+        }
+        Ok(warnings)
+    }
+
+    /// Apply `params`, returning warnings about settings that were accepted but look wrong
+    pub fn set_params(&mut self, params: &BoidsParams) -> Result<Vec<String>> {
+        let warnings = self.validate_params(params)?;
+        for warning in &warnings {
+            tracing::warn!("Boids params: {}", warning);
+        }
+        // First, so the other values are taken in the new units
+        if let Some(world_size) = params.world_size {
+            self.set_world_size(world_size)?;
+        }
+        if let Some(masses) = &params.species_masses {
+            self.set_species_masses(masses)?;
+        }
+        if let Some(check) = params.radius_check {
+            self.radius_check = check;
+        }
+        self.separation_radius = params.separation_radius.unwrap_or(self.separation_radius);
+        self.alignment_radius = params.alignment_radius.unwrap_or(self.alignment_radius);
+        self.cohesion_radius = params.cohesion_radius.unwrap_or(self.cohesion_radius);
+        if let Some(range) = params.max_speed_range.or(params.max_speed.map(|v| (v, v))) {
+            self.set_max_speed_range(range)?;
+        }
+        self.max_force = params.max_force.unwrap_or(self.max_force);
+        if let Some(k) = params.topological_k {
+            self.topological_k = k;
+        }
+        if let Some(mode) = params.neighbor_mode {
+            self.neighbor_mode = mode;
+        }
+        if let Some(jitter) = params.jitter {
+            self.jitter = jitter;
+        }
+        if let Some(seed) = params.jitter_seed {
+            self.jitter_seed = seed;
+        }
+        if let Some(obstacles) = &params.obstacles {
+            self.obstacles = obstacles.clone();
+            self.obstacles_dirty = true;
+        }
+        if let Some(continuous) = params.continuous_collision {
+            self.continuous_collision = continuous;
+        }
+        if let Some(mode) = params.boundary {
+            self.set_boundary_mode(mode)?;
+        }
+        if let Some(num_predators) = params.num_predators {
+            self.set_num_predators(num_predators)?;
+        }
+        if let Some(genetics) = params.genetics {
+            if genetics && !self.genetics {
+                self.births = 0;
+                self.genetics_elapsed = 0.0;
             }
-            if host_boids[i].y < 0.0 {
-                host_boids[i].y += 1.0;
+            self.genetics = genetics;
+        }
+        match (params.auto_tune, params.target_density) {
+            (Some(false), _) => self.density_tuner = None,
+            (Some(true), target) => {
+                // The defaults are for the unit world; a given target is in current units
+                let mut tuner = DensityTuner::new(DEFAULT_TARGET_DENSITY).scaled(self.world_size);
+                if let Some(target) = target.or(self.density_tuner.map(|t| t.target_density)) {
+                    tuner.target_density = target;
+                }
+                self.density_tuner = Some(tuner);
             }
-            if host_boids[i].y > 1.0 {
-                host_boids[i].y -= 1.0;
+            (None, Some(target)) => {
+                if let Some(tuner) = self.density_tuner.as_mut() {
+                    tuner.target_density = target;
+                }
             }
+            (None, None) => {}
         }
+        Ok(warnings)
+    }
 
-        // Copy back to device
-        self.boids
-            .copy_from(&host_boids[..])
-            .map_err(|e| anyhow::anyhow!("Failed to copy boids back: {:?}", e))?;
-        self.last_used_cuda = false;
-        self.soa_dirty = true;
-        self.aos_dirty = false;
+    /// Switch boundary handling; switching to bounce pulls stray boids back inside
+    pub fn set_boundary_mode(&mut self, mode: BoundaryMode) -> Result<()> {
+        if mode == BoundaryMode::Bounce && self.boundary != BoundaryMode::Bounce {
+            let world_size = self.world_size;
+            self.update_host_boids(|boids| {
+                for b in boids {
+                    b.x = b.x.clamp(0.0, world_size);
+                    b.y = b.y.clamp(0.0, world_size);
+                }
+            })?;
+        }
+        self.boundary = mode;
         Ok(())
     }
 
-    fn has_soa(&self) -> bool {
-        self.d_x.is_some()
-            && self.d_y.is_some()
-            && self.d_vx.is_some()
-            && self.d_vy.is_some()
-            && self.d_species.is_some()
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.boundary
     }
 
-    fn sync_soa_from_aos(&mut self) -> Result<()> {
+    /// Resize the world, scaling the flock and every length and speed along with it
+    /// so it behaves the same in the new units. Obstacles and attractors stay put.
+    pub fn set_world_size(&mut self, world_size: f32) -> Result<()> {
+        check_world_size(world_size)?;
+        let scale = world_size / self.world_size;
+        if scale == 1.0 {
+            return Ok(());
+        }
+        self.update_host_boids(|boids| {
+            for b in boids {
+                b.x *= scale;
+                b.y *= scale;
+                b.vx *= scale;
+                b.vy *= scale;
+                b.max_speed *= scale;
+            }
+        })?;
+        self.separation_radius *= scale;
+        self.alignment_radius *= scale;
+        self.cohesion_radius *= scale;
+        self.max_speed *= scale;
+        self.max_speed_range = (self.max_speed_range.0 * scale, self.max_speed_range.1 * scale);
+        self.max_force *= scale;
+        self.jitter *= scale;
+        self.density_tuner = self.density_tuner.map(|tuner| tuner.scaled(scale));
+        self.world_size = world_size;
+        Ok(())
+    }
+
+    pub fn world_size(&self) -> f32 {
+        self.world_size
+    }
+
+    /// Replace the static obstacles (at most `MAX_OBSTACLES`) boids steer around
+    pub fn set_obstacles(&mut self, obstacles: Vec<Obstacle>) -> Result<()> {
+        obstacles::validate_all(&obstacles)?;
+        self.obstacles = obstacles;
+        self.obstacles_dirty = true;
+        Ok(())
+    }
+
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// Copy the obstacles into the kernel's fixed-size capsule buffer
+    fn upload_obstacles(&mut self) -> Result<()> {
+        let packed = obstacles::pack_capsules(&self.obstacles);
+        match self.d_obstacles.as_mut() {
+            Some(buffer) => buffer
+                .copy_from(&packed[..])
+                .map_err(|e| anyhow::anyhow!("upload obstacles: {:?}", e))?,
+            None => {
+                self.d_obstacles = Some(
+                    SimBuffer::from_slice(&packed)
+                        .context("alloc d_obstacles")?,
+                )
+            }
+        }
+        self.obstacles_dirty = false;
+        Ok(())
+    }
+
+    /// Place a point attractor, dropping the oldest once `MAX_ATTRACTORS` are live
+    pub fn add_attractor(&mut self, attractor: Attractor) -> Result<()> {
+        attractor.validate(self.world_size)?;
+        if self.attractors.len() == attractors::MAX_ATTRACTORS {
+            self.attractors.remove(0);
+        }
+        self.attractors.push(attractor);
+        self.attractors_dirty = true;
+        Ok(())
+    }
+
+    /// Replace every attractor at once, e.g. to follow a moving pointer each frame
+    pub fn set_attractors(&mut self, attractors: Vec<Attractor>) -> Result<()> {
+        attractors::validate_all(&attractors, self.world_size)?;
+        self.attractors = attractors;
+        self.attractors_dirty = true;
+        Ok(())
+    }
+
+    /// Remove every attractor, leaving pure flocking
+    pub fn clear_attractors(&mut self) {
+        self.attractors.clear();
+        self.attractors_dirty = true;
+    }
+
+    pub fn attractors(&self) -> &[Attractor] {
+        &self.attractors
+    }
+
+    fn upload_attractors(&mut self) -> Result<()> {
+        let packed = attractors::pack(&self.attractors);
+        match self.d_attractors.as_mut() {
+            Some(buffer) => buffer
+                .copy_from(&packed[..])
+                .map_err(|e| anyhow::anyhow!("upload attractors: {:?}", e))?,
+            None => {
+                self.d_attractors = Some(
+                    SimBuffer::from_slice(&packed)
+                        .context("alloc d_attractors")?,
+                )
+            }
+        }
+        self.attractors_dirty = false;
+        Ok(())
+    }
+
+    pub fn separation_radius(&self) -> f32 {
+        self.separation_radius
+    }
+
+    /// Periodic host-side checks that follow every step
+    fn after_step(&mut self, dt: f32) -> Result<()> {
+        self.maybe_auto_tune(dt)?;
+        self.maybe_breed(dt)?;
+        self.maybe_check_stasis(dt)
+    }
+
+    pub fn genetics_enabled(&self) -> bool {
+        self.genetics
+    }
+
+    /// Run a breeding round once per `genetics::BREED_INTERVAL` of simulated time
+    fn maybe_breed(&mut self, dt: f32) -> Result<()> {
+        if !self.genetics {
+            return Ok(());
+        }
+        self.genetics_elapsed += dt;
+        if self.genetics_elapsed < genetics::BREED_INTERVAL {
+            return Ok(());
+        }
+        self.genetics_elapsed = 0.0;
+
+        self.ensure_aos_current()?;
+        self.boids
+            .copy_to(&mut self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        let births = genetics::breed(
+            &mut self.host_buffers.boids,
+            self.cohesion_radius,
+            self.max_speed_range,
+            self.world_size,
+            &mut self.rng,
+            &mut self.next_id,
+        );
+        if births == 0 {
+            return Ok(());
+        }
+        self.births += births as u64;
+        self.boids
+            .copy_from(&self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids back: {:?}", e))?;
+        self.soa_dirty = true;
+        Ok(())
+    }
+
+    /// Whether the whole flock has been at rest for the stasis window
+    pub fn in_stasis(&self) -> bool {
+        self.stasis.in_stasis()
+    }
+
+    /// Measure mean speed once per `stasis::CHECK_INTERVAL` of simulated time
+    fn maybe_check_stasis(&mut self, dt: f32) -> Result<()> {
+        self.stasis_elapsed += dt;
+        if self.stasis_elapsed < stasis::CHECK_INTERVAL {
+            return Ok(());
+        }
+        let elapsed = std::mem::take(&mut self.stasis_elapsed);
+
+        self.ensure_aos_current()?;
+        self.boids
+            .copy_to(&mut self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        let mean = stasis::mean_speed(&self.host_buffers.boids);
+        match self.stasis.observe(mean, elapsed) {
+            Some(true) => tracing::warn!(
+                "flock stasis detected: mean speed {:.2e} below {:.2e} for {:.1}s \
+                 (boids {}, radii {}/{}/{}, max_speed {}, jitter {}, boundary {:?}, accelerator {})",
+                mean,
+                self.stasis.threshold,
+                self.stasis.window,
+                self.num_boids,
+                self.separation_radius,
+                self.alignment_radius,
+                self.cohesion_radius,
+                self.max_speed,
+                self.jitter,
+                self.boundary,
+                self.accelerator(),
+            ),
+            Some(false) => tracing::info!("Flock moving again (mean speed {:.2e})", mean),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Run the density auto-tuner once per `TUNE_INTERVAL` of simulated time
+    fn maybe_auto_tune(&mut self, dt: f32) -> Result<()> {
+        let Some(tuner) = self.density_tuner else {
+            return Ok(());
+        };
+        self.tune_elapsed += dt;
+        if self.tune_elapsed < auto_tune::TUNE_INTERVAL {
+            return Ok(());
+        }
+        self.tune_elapsed = 0.0;
+
+        self.ensure_aos_current()?;
+        self.boids
+            .copy_to(&mut self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        let wrap = (self.boundary == BoundaryMode::Wrap).then_some(self.world_size);
+        let density = auto_tune::local_density(&self.host_buffers.boids, self.cohesion_radius, wrap);
+        let separation = tuner.update(density, self.separation_radius, self.cohesion_radius);
+        if (separation - self.separation_radius).abs() > f32::EPSILON {
+            tracing::debug!(
+                "Auto-tune: density {:.1} (target {:.1}), separation {:.4} -> {:.4}",
+                density, tuner.target_density, self.separation_radius, separation
+            );
+            self.separation_radius = separation;
+        }
+        Ok(())
+    }
+
+    /// Draw every boid's speed cap afresh from `[min, max]` (a single value when equal)
+    fn set_max_speed_range(&mut self, range: (f32, f32)) -> Result<()> {
+        self.max_speed_range = range;
+        self.max_speed = range.1;
+        let caps: Vec<f32> = (0..self.num_boids)
+            .map(|_| sample_max_speed(&mut self.rng, range))
+            .collect();
+        self.update_host_boids(|boids| {
+            for (boid, cap) in boids.iter_mut().zip(caps) {
+                boid.max_speed = cap;
+            }
+        })
+    }
+
+    pub fn max_speed_range(&self) -> (f32, f32) {
+        self.max_speed_range
+    }
+
+    /// Set the default mass for each species and re-assign per-boid masses
+    pub fn set_species_masses(&mut self, masses: &[f32]) -> Result<()> {
+        if masses.len() != self.species_masses.len() {
+            anyhow::bail!(
+                "species_masses must have {} entries, got {}",
+                self.species_masses.len(),
+                masses.len()
+            );
+        }
+        if let Some(m) = masses.iter().find(|m| !m.is_finite() || **m < MIN_MASS) {
+            anyhow::bail!("species mass must be finite and >= {}, got {}", MIN_MASS, m);
+        }
+        self.species_masses = masses.to_vec();
+        let species_masses = self.species_masses.clone();
+        self.update_host_boids(|boids| {
+            for boid in boids.iter_mut().filter(|b| !predators::is_predator(b)) {
+                boid.mass = species_masses[boid.species as usize % species_masses.len()];
+            }
+        })
+    }
+
+    pub fn num_species(&self) -> u8 {
+        self.species_masses.len() as u8
+    }
+
+    pub fn species_masses(&self) -> &[f32] {
+        &self.species_masses
+    }
+
+    /// Keep `count` predators in the flock (at most `MAX_PREDATORS`). New predators are
+    /// picked at random; demoted ones rejoin a random species with its default mass.
+    pub fn set_num_predators(&mut self, count: usize) -> Result<()> {
+        check_num_predators(count, self.num_boids)?;
+        self.num_predators = count;
+        self.assign_predators()
+    }
+
+    pub fn num_predators(&self) -> usize {
+        self.num_predators
+    }
+
+    // Promote or demote boids until the flock holds `num_predators` predators
+    fn assign_predators(&mut self) -> Result<()> {
+        let target = self.num_predators.min(self.num_boids);
+        self.ensure_aos_current()?;
+        self.boids
+            .copy_to(&mut self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        let boids = &mut self.host_buffers.boids;
+        let mut current = boids.iter().filter(|b| predators::is_predator(b)).count();
+        if current == target {
+            return Ok(());
+        }
+        for b in boids.iter_mut().filter(|b| predators::is_predator(b)) {
+            if current == target {
+                break;
+            }
+            b.species = self.rng.below(self.species_masses.len() as u32) as u8;
+            b.mass = self.species_masses[b.species as usize];
+            current -= 1;
+        }
+        while current < target {
+            let b = &mut boids[self.rng.below(self.num_boids as u32) as usize];
+            if !predators::is_predator(b) {
+                b.species = PREDATOR_SPECIES;
+                current += 1;
+            }
+        }
+        self.boids
+            .copy_from(&self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids back: {:?}", e))?;
+        self.soa_dirty = true;
+        Ok(())
+    }
+
+    /// Edit the boids on the host and upload them, invalidating the SoA mirror
+    fn update_host_boids<F: FnOnce(&mut [Boid])>(&mut self, f: F) -> Result<()> {
+        self.ensure_aos_current()?;
+        self.boids
+            .copy_to(&mut self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        f(&mut self.host_buffers.boids[..]);
+        self.boids
+            .copy_from(&self.host_buffers.boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids back: {:?}", e))?;
+        self.soa_dirty = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "no-cuda")]
+    fn step_cuda(&mut self, _dt: f32, _step_index: u32) -> Result<()> {
+        anyhow::bail!("built with the no-cuda feature");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    fn step_cuda(&mut self, dt: f32, step_index: u32) -> Result<()> {
+        if self.soa_dirty {
+            self.sync_soa_from_aos()?;
+        }
+        if self.obstacles_dirty || self.d_obstacles.is_none() {
+            self.upload_obstacles()?;
+        }
+        if self.attractors_dirty || self.d_attractors.is_none() {
+            self.upload_attractors()?;
+        }
+        let ptx = self.ptx.as_ref().unwrap();
+        let dx = self.d_x.as_mut().unwrap();
+        let dy = self.d_y.as_mut().unwrap();
+        let dvx = self.d_vx.as_mut().unwrap();
+        let dvy = self.d_vy.as_mut().unwrap();
+        let dmass = self.d_mass.as_mut().unwrap();
+        let dmax_speed = self.d_max_speed.as_mut().unwrap();
+        let dspecies = self.d_species.as_mut().unwrap();
+        let dforce = self.d_force.as_mut().unwrap();
+        let dobstacles = self.d_obstacles.as_mut().unwrap();
+        let dattractors = self.d_attractors.as_mut().unwrap();
+
+        let ptx_c = CString::new(ptx.as_str()).unwrap();
+        let module = Module::load_from_string(&ptx_c)
+            .context("Failed to load boids PTX")?;
+        let func = module
+            .get_function(&CString::new("boids_step").unwrap())
+            .context("Failed to get boids_step")?;
+        let stream = Stream::new(StreamFlags::DEFAULT, None)
+            .context("Failed to create stream")?;
+
+        let n = self.num_boids as i32;
+        let block = (128u32, 1u32, 1u32);
+        let grid = (
+            ((self.num_boids as u32) + block.0 - 1) / block.0,
+            1u32,
+            1u32,
+        );
+        unsafe {
+            launch!(
+                func<<<grid, block, 0, stream>>>(
+                    n,
+                    dt as f32,
+                    self.separation_radius as f32,
+                    self.alignment_radius as f32,
+                    self.cohesion_radius as f32,
+                    SEPARATION_WEIGHT,
+                    ALIGNMENT_WEIGHT,
+                    COHESION_WEIGHT,
+                    dspecies.as_device_ptr(),
+                    dmass.as_device_ptr(),
+                    dmax_speed.as_device_ptr(),
+                    dx.as_device_ptr(),
+                    dy.as_device_ptr(),
+                    dvx.as_device_ptr(),
+                    dvy.as_device_ptr(),
+                    self.world_size,
+                    self.world_size,
+                    self.jitter,
+                    self.jitter_seed,
+                    step_index,
+                    self.boundary.kernel_code(),
+                    dforce.as_device_ptr(),
+                    dobstacles.as_device_ptr(),
+                    self.obstacles.len() as i32,
+                    obstacles::AVOID_MARGIN * self.world_size,
+                    dattractors.as_device_ptr(),
+                    self.attractors.len() as i32,
+                    self.max_force
+                )
+            )
+            .context("boids_step launch failed")?;
+        }
+        stream
+            .synchronize()
+            .context("boids_step sync failed")?;
+
+        self.aos_dirty = true;
+        self.last_used_cuda = true;
+        self.soa_dirty = false;
+        Ok(())
+    }
+
+    pub fn step(&mut self, dt: f32) -> Result<Accelerator> {
+        let step_index = self.step_index;
+        self.step_index = self.step_index.wrapping_add(1);
+
+        // The kernel only implements metric neighbourhoods and endpoint obstacle checks,
+        // and knows nothing of genes
+        let kernel_supported = self.neighbor_mode == NeighborMode::Metric
+            && (self.obstacles.is_empty() || !self.continuous_collision)
+            && !self.genetics;
+        if !self.force_cpu && kernel_supported && self.ptx.is_some() && self.has_soa() {
+            match self.step_cuda(dt, step_index) {
+                Ok(()) => {
+                    self.cuda_failures.succeeded();
+                    self.after_step(dt)?;
+                    return Ok(Accelerator::Cuda);
+                }
+                Err(e) => {
+                    tracing::warn!("Boids CUDA step failed, running this step on the CPU: {:#}", e);
+                    if self.cuda_failures.give_up(&e) {
+                        // Drop the kernel so later steps stay on the CPU path
+                        tracing::warn!("Giving up on the boids kernel");
+                        self.ptx = None;
+                    }
+                }
+            }
+        }
+
+        // CPU fallback
+        self.ensure_aos_current()?;
+        let HostBuffers {
+            boids: host_boids,
+            force,
+            neighbors,
+            candidates,
+            grid,
+            ..
+        } = &mut self.host_buffers;
+        self.boids
+            .copy_to(&mut host_boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+
+        // Boids are updated in place, so allow for one step of travel since the rebuild
+        let slack = self.max_speed * dt.abs();
+        match self.neighbor_mode {
+            NeighborMode::Metric => {
+                let reach = self
+                    .separation_radius
+                    .max(self.alignment_radius)
+                    .max(self.cohesion_radius);
+                grid.rebuild(host_boids, reach + slack);
+            }
+            NeighborMode::Topological => {
+                // Around k boids per cell, so a search usually stops within a ring or two
+                let share = self.topological_k.max(1) as f32 / self.num_boids.max(1) as f32;
+                grid.rebuild(host_boids, self.world_size * share.sqrt());
+            }
+        }
+
+        // Predator positions at the start of the step; prey flee from these
+        let predator_positions: Vec<(f32, f32)> = host_boids
+            .iter()
+            .filter(|b| predators::is_predator(b))
+            .map(|b| (b.x, b.y))
+            .collect();
+
+        // Topological neighbours are chosen by rank, so the radius checks only gate separation
+        let (alignment_radius, cohesion_radius) = match self.neighbor_mode {
+            NeighborMode::Metric => (self.alignment_radius, self.cohesion_radius),
+            NeighborMode::Topological => (f32::INFINITY, f32::INFINITY),
+        };
+
+        // Boids algorithm: Separation, Alignment, Cohesion
+        for i in 0..self.num_boids {
+            let mut sep_x = 0.0;
+            let mut sep_y = 0.0;
+            let mut align_x = 0.0;
+            let mut align_y = 0.0;
+            let mut coh_x = 0.0;
+            let mut coh_y = 0.0;
+            let mut sep_count = 0;
+            let mut align_count = 0;
+            let mut coh_count = 0;
+
+            // Only consider same species (simplified)
+            match self.neighbor_mode {
+                NeighborMode::Metric => {
+                    let species = host_boids[i].species;
+                    grid.near(i, neighbors);
+                    neighbors.retain(|&j| host_boids[j].species == species);
+                }
+                NeighborMode::Topological => {
+                    topological_neighbors(
+                        host_boids,
+                        grid,
+                        i,
+                        self.topological_k,
+                        slack,
+                        candidates,
+                        neighbors,
+                    );
+                }
+            }
+
+            let bi = &host_boids[i];
+            let is_predator = predators::is_predator(bi);
+            let flee = if is_predator {
+                None
+            } else {
+                predators::flee(&predator_positions, bi.x, bi.y)
+            };
+
+            for &j in neighbors.iter() {
+                let bj = &host_boids[j];
+                let dx = bi.x - bj.x;
+                let dy = bi.y - bj.y;
+                let dist_sq = dx * dx + dy * dy;
+                let dist = dist_sq.sqrt();
+
+                // Separation
+                if dist < self.separation_radius && dist > 0.0 {
+                    sep_x += dx / dist;
+                    sep_y += dy / dist;
+                    sep_count += 1;
+                }
+
+                // Alignment
+                if dist < alignment_radius {
+                    align_x += bj.vx;
+                    align_y += bj.vy;
+                    align_count += 1;
+                }
+
+                // Cohesion
+                if dist < cohesion_radius {
+                    coh_x += bj.x;
+                    coh_y += bj.y;
+                    coh_count += 1;
+                }
+            }
+
+            // Calculate forces
+            let mut fx = 0.0;
+            let mut fy = 0.0;
+
+            // Separation force
+            if sep_count > 0 {
+                let sep_mag = (sep_x * sep_x + sep_y * sep_y).sqrt();
+                if sep_mag > 0.0 {
+                    fx += (sep_x / sep_mag) * self.max_force * SEPARATION_WEIGHT;
+                    fy += (sep_y / sep_mag) * self.max_force * SEPARATION_WEIGHT;
+                }
+            }
+
+            // Alignment force
+            if align_count > 0 {
+                let align_mag = (align_x * align_x + align_y * align_y).sqrt();
+                if align_mag > 0.0 {
+                    let target_vx = (align_x / align_count as f32) - bi.vx;
+                    let target_vy = (align_y / align_count as f32) - bi.vy;
+                    let target_mag = (target_vx * target_vx + target_vy * target_vy).sqrt();
+                    if target_mag > 0.0 {
+                        fx += (target_vx / target_mag) * self.max_force * ALIGNMENT_WEIGHT;
+                        fy += (target_vy / target_mag) * self.max_force * ALIGNMENT_WEIGHT;
+                    }
+                }
+            }
+
+            // Cohesion force; fleeing prey drop it so the flock actually breaks apart
+            if coh_count > 0 && flee.is_none() {
+                let avg_x = coh_x / coh_count as f32;
+                let avg_y = coh_y / coh_count as f32;
+                let target_x = avg_x - bi.x;
+                let target_y = avg_y - bi.y;
+                let target_mag = (target_x * target_x + target_y * target_y).sqrt();
+                let weight = if self.genetics { bi.genes.cohesion } else { 1.0 };
+                if target_mag > 0.0 {
+                    fx += (target_x / target_mag) * self.max_force * COHESION_WEIGHT * weight;
+                    fy += (target_y / target_mag) * self.max_force * COHESION_WEIGHT * weight;
+                }
+            }
+
+            if let Some((ex, ey)) = flee {
+                fx += ex * self.max_force * predators::FLEE_WEIGHT;
+                fy += ey * self.max_force * predators::FLEE_WEIGHT;
+            }
+            if is_predator {
+                let (px, py) = predators::pursuit(host_boids, i, bi.max_speed);
+                fx += px * self.max_force;
+                fy += py * self.max_force;
+            }
+
+            // Obstacle avoidance, strong enough inside an obstacle to beat the flocking forces
+            if !self.obstacles.is_empty() {
+                let margin = obstacles::AVOID_MARGIN * self.world_size;
+                let (ax, ay) = obstacles::avoidance(&self.obstacles, bi.x, bi.y, margin);
+                fx += ax * self.max_force * OBSTACLE_WEIGHT;
+                fy += ay * self.max_force * OBSTACLE_WEIGHT;
+            }
+            if !self.attractors.is_empty() {
+                let (ax, ay) = attractors::pull(&self.attractors, bi.x, bi.y);
+                fx += ax * self.max_force;
+                fy += ay * self.max_force;
+            }
+
+            // Update velocity (a = F / mass)
+            force[i] = (fx * fx + fy * fy).sqrt();
+            host_boids[i].apply_force(fx, fy, dt);
+
+            // Jitter breaks symmetric configurations where the forces cancel exactly
+            if self.jitter > 0.0 {
+                let (jx, jy) = jitter_noise(self.jitter_seed, step_index, i as u32);
+                host_boids[i].vx += jx * self.jitter;
+                host_boids[i].vy += jy * self.jitter;
+            }
+
+            // Limit speed to this boid's own cap
+            let max_speed = host_boids[i].max_speed;
+            let speed =
+                (host_boids[i].vx * host_boids[i].vx + host_boids[i].vy * host_boids[i].vy).sqrt();
+            if speed > max_speed {
+                host_boids[i].vx = (host_boids[i].vx / speed) * max_speed;
+                host_boids[i].vy = (host_boids[i].vy / speed) * max_speed;
+            }
+
+            // Update position, colliding with any obstacles on the way
+            let b = &mut host_boids[i];
+            if self.obstacles.is_empty() {
+                b.x += b.vx * dt;
+                b.y += b.vy * dt;
+            } else {
+                obstacles::advance(
+                    &self.obstacles,
+                    self.continuous_collision,
+                    &mut b.x,
+                    &mut b.y,
+                    &mut b.vx,
+                    &mut b.vy,
+                    dt,
+                );
+            }
+
+            let respawn_velocity = if self.boundary == BoundaryMode::Open {
+                let (rx, ry) = jitter_noise(self.jitter_seed ^ OPEN_RESPAWN_SALT, step_index, i as u32);
+                (rx * b.max_speed, ry * b.max_speed)
+            } else {
+                (0.0, 0.0)
+            };
+            self.boundary.apply(b, respawn_velocity, self.world_size);
+        }
+
+        // Copy back to device
+        self.boids
+            .copy_from(&host_boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids back: {:?}", e))?;
+        self.last_used_cuda = false;
+        self.soa_dirty = true;
+        self.aos_dirty = false;
+        self.after_step(dt)?;
+        Ok(Accelerator::Cpu)
+    }
+
+    fn has_soa(&self) -> bool {
+        self.d_x.is_some()
+            && self.d_y.is_some()
+            && self.d_vx.is_some()
+            && self.d_vy.is_some()
+            && self.d_mass.is_some()
+            && self.d_max_speed.is_some()
+            && self.d_species.is_some()
+            && self.d_force.is_some()
+    }
+
+    fn sync_soa_from_aos(&mut self) -> Result<()> {
         if !self.has_soa() {
             self.soa_dirty = false;
             return Ok(());
@@ -394,27 +1804,8 @@ impl BoidsSimulation {
             .copy_to(&mut self.host_buffers.boids[..])
             .map_err(|e| anyhow::anyhow!("Failed to stage boids for SoA sync: {:?}", e))?;
         self.host_buffers.sync_scalars_from_boids();
-        if let (Some(dx), Some(dy), Some(dvx), Some(dvy), Some(dspecies)) = (
-            self.d_x.as_mut(),
-            self.d_y.as_mut(),
-            self.d_vx.as_mut(),
-            self.d_vy.as_mut(),
-            self.d_species.as_mut(),
-        ) {
-            dx.copy_from(&self.host_buffers.x[..])
-                .map_err(|e| anyhow::anyhow!("sync hx->dx: {:?}", e))?;
-            dy.copy_from(&self.host_buffers.y[..])
-                .map_err(|e| anyhow::anyhow!("sync hy->dy: {:?}", e))?;
-            dvx.copy_from(&self.host_buffers.vx[..])
-                .map_err(|e| anyhow::anyhow!("sync hvx->dvx: {:?}", e))?;
-            dvy.copy_from(&self.host_buffers.vy[..])
-                .map_err(|e| anyhow::anyhow!("sync hvy->dvy: {:?}", e))?;
-            dspecies
-                .copy_from(&self.host_buffers.species[..])
-                .map_err(|e| anyhow::anyhow!("sync species: {:?}", e))?;
-        }
-        self.soa_dirty = false;
-        Ok(())
+        // The buffers already fit, so this only copies
+        self.upload_soa()
     }
 
     fn sync_aos_from_soa(&mut self) -> Result<()> {
@@ -424,25 +1815,32 @@ impl BoidsSimulation {
         }
         
         // Ensure CUDA context is set up before accessing device memory
-        self.context.ensure_context()?;
+        if let Some(context) = &self.context {
+            context.ensure_context()?;
+        }
         
-        if let (Some(dx), Some(dy), Some(dvx), Some(dvy), Some(dspecies)) = (
+        // Device buffers may have room beyond the flock; only the front is live
+        let n = self.num_boids;
+        if let (Some(dx), Some(dy), Some(dvx), Some(dvy), Some(dmass), Some(dspecies)) = (
             self.d_x.as_ref(),
             self.d_y.as_ref(),
             self.d_vx.as_ref(),
             self.d_vy.as_ref(),
+            self.d_mass.as_ref(),
             self.d_species.as_ref(),
         ) {
-            dx.copy_to(&mut self.host_buffers.x[..])
+            dx.copy_prefix_to(&mut self.host_buffers.x[..n])
                 .map_err(|e| anyhow::anyhow!("dx->host: {:?}", e))?;
-            dy.copy_to(&mut self.host_buffers.y[..])
+            dy.copy_prefix_to(&mut self.host_buffers.y[..n])
                 .map_err(|e| anyhow::anyhow!("dy->host: {:?}", e))?;
-            dvx.copy_to(&mut self.host_buffers.vx[..])
+            dvx.copy_prefix_to(&mut self.host_buffers.vx[..n])
                 .map_err(|e| anyhow::anyhow!("dvx->host: {:?}", e))?;
-            dvy.copy_to(&mut self.host_buffers.vy[..])
+            dvy.copy_prefix_to(&mut self.host_buffers.vy[..n])
                 .map_err(|e| anyhow::anyhow!("dvy->host: {:?}", e))?;
+            dmass.copy_prefix_to(&mut self.host_buffers.mass[..n])
+                .map_err(|e| anyhow::anyhow!("dmass->host: {:?}", e))?;
             dspecies
-                .copy_to(&mut self.host_buffers.species[..])
+                .copy_prefix_to(&mut self.host_buffers.species[..n])
                 .map_err(|e| anyhow::anyhow!("species->host: {:?}", e))?;
         }
         self.host_buffers.rebuild_boids_from_scalars();
@@ -453,45 +1851,321 @@ impl BoidsSimulation {
         Ok(())
     }
 
-    fn ensure_aos_current(&mut self) -> Result<()> {
-        if self.aos_dirty {
-            self.sync_aos_from_soa()?;
-        }
-        Ok(())
+    fn ensure_aos_current(&mut self) -> Result<()> {
+        if self.aos_dirty {
+            self.sync_aos_from_soa()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_boids(&mut self) -> Result<Vec<f32>> {
+        // Ensure CUDA context is set up in current thread before accessing device memory
+        if let Some(context) = &self.context {
+            context.ensure_context()?;
+        }
+        
+        self.ensure_aos_current()?;
+        let host_boids = &mut self.host_buffers.boids;
+        self.boids
+            .copy_to(&mut host_boids[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
+        let mut result = Vec::with_capacity(self.num_boids * 4);
+        for b in host_boids.iter() {
+            result.push(b.x);
+            result.push(b.y);
+            result.push(b.vx);
+            result.push(b.vy);
+        }
+
+        Ok(result)
+    }
+
+    /// Steering force magnitude applied to each boid in the last step, in `get_boids` order
+    pub fn force_magnitudes(&mut self) -> Result<Vec<f32>> {
+        if self.last_used_cuda {
+            if let Some(d_force) = self.d_force.as_ref() {
+                if let Some(context) = &self.context {
+                    context.ensure_context()?;
+                }
+                d_force
+                    .copy_prefix_to(&mut self.host_buffers.force[..self.num_boids])
+                    .map_err(|e| anyhow::anyhow!("d_force->host: {:?}", e))?;
+            }
+        }
+        Ok(self.host_buffers.force.clone())
+    }
+
+    /// Stable boid ids in the same order as `get_boids`.
+    /// Reflects the host copy refreshed by the last `get_boids` call.
+    pub fn ids(&self) -> Vec<u32> {
+        self.host_buffers.boids.iter().map(|b| b.id).collect()
+    }
+
+    /// Per-boid speed caps in the same order as `get_boids`, from the same host copy as `ids`
+    pub fn max_speeds(&self) -> Vec<f32> {
+        self.host_buffers.boids.iter().map(|b| b.max_speed).collect()
+    }
+
+    /// Speed, energy, spacing and per-species centroids of the current flock,
+    /// plus the gene distribution while genetics is enabled
+    pub fn statistics(&mut self) -> Result<BoidStats> {
+        self.get_boids()?;
+        let host = &mut self.host_buffers;
+        let mut stats = stats::compute(&host.boids, &mut host.grid);
+        if self.genetics {
+            stats.genes = Some(genetics::summarize(&host.boids, self.births));
+        }
+        Ok(stats)
+    }
+
+    /// The flock and its parameters as a `checkpoint` blob, for `restore_state`
+    pub fn serialize_state(&mut self) -> Result<Vec<u8>> {
+        self.get_boids()?;
+        let state = FlockState {
+            separation_radius: self.separation_radius,
+            alignment_radius: self.alignment_radius,
+            cohesion_radius: self.cohesion_radius,
+            max_speed_range: self.max_speed_range,
+            max_force: self.max_force,
+            jitter: self.jitter,
+            world_size: self.world_size,
+            jitter_seed: self.jitter_seed,
+            step_index: self.step_index,
+            next_id: self.next_id,
+            topological_k: self.topological_k as u32,
+            num_predators: self.num_predators as u32,
+            seed: self.seed(),
+            neighbor_mode: self.neighbor_mode,
+            boundary: self.boundary,
+            radius_check: self.radius_check,
+            continuous_collision: self.continuous_collision,
+            genetics: self.genetics,
+            species_masses: self.species_masses.clone(),
+            obstacles: self.obstacles.clone(),
+            attractors: self.attractors.clone(),
+            boids: self.host_buffers.boids.clone(),
+        };
+        Ok(state.encode())
+    }
+
+    /// Replace the flock and its parameters with a `serialize_state` blob. The whole
+    /// blob is checked first, so a rejected one leaves the simulation untouched.
+    pub fn restore_state(&mut self, bytes: &[u8]) -> Result<()> {
+        self.restore_checkpoint(decode_checkpoint(bytes)?)
+    }
+
+    /// Apply a checkpoint already checked by `decode_checkpoint`
+    pub fn restore_checkpoint(&mut self, mut state: FlockState) -> Result<()> {
+        let boids = std::mem::take(&mut state.boids);
+        self.resize_host_boids(|current| *current = boids)?;
+        self.separation_radius = state.separation_radius;
+        self.alignment_radius = state.alignment_radius;
+        self.cohesion_radius = state.cohesion_radius;
+        self.max_speed_range = state.max_speed_range;
+        self.max_speed = state.max_speed_range.1;
+        self.max_force = state.max_force;
+        self.jitter = state.jitter;
+        self.world_size = state.world_size;
+        self.jitter_seed = state.jitter_seed;
+        self.step_index = state.step_index;
+        self.next_id = state.next_id;
+        self.topological_k = state.topological_k as usize;
+        self.num_predators = state.num_predators as usize;
+        self.neighbor_mode = state.neighbor_mode;
+        self.boundary = state.boundary;
+        self.radius_check = state.radius_check;
+        self.continuous_collision = state.continuous_collision;
+        self.genetics = state.genetics;
+        self.births = 0;
+        self.genetics_elapsed = 0.0;
+        self.species_masses = state.species_masses;
+        self.obstacles = state.obstacles;
+        self.obstacles_dirty = true;
+        self.attractors = state.attractors;
+        self.attractors_dirty = true;
+        self.rng = SimRng::new(state.seed);
+        self.tune_elapsed = 0.0;
+        self.stasis = StasisDetector::new(self.stasis.threshold, self.stasis.window);
+        self.stasis_elapsed = 0.0;
+        Ok(())
+    }
+
+    /// Boid counts on a `resolution`² grid over the world (see `stats::density_grid`).
+    /// Bins the host copy refreshed by the last `get_boids` call.
+    pub fn density_grid(&self, resolution: usize) -> Vec<u32> {
+        stats::density_grid(&self.host_buffers.boids, resolution, self.world_size)
+    }
+
+    /// Population and colour of each species. Reflects the host copy refreshed by
+    /// the last `get_boids` call.
+    pub fn species_summary(&self) -> stats::SpeciesSummary {
+        stats::species_summary(&self.host_buffers.boids, self.num_species())
+    }
+
+    /// RGBA preview of the flock, one dot per boid coloured by species
+    pub fn render_thumbnail(&mut self, width: usize, height: usize) -> Result<Vec<u8>> {
+        self.get_boids()?;
+        Ok(thumbnail::splat(&self.host_buffers.boids, width, height, self.world_size))
+    }
+
+    /// Current interaction network: same-species boids within the cohesion radius,
+    /// looked up through the spatial grid
+    pub fn neighbor_graph(&mut self) -> Result<NeighborGraph> {
+        self.get_boids()?;
+        let radius = self.cohesion_radius;
+        let host = &mut self.host_buffers;
+        host.grid.rebuild(&host.boids, radius);
+        let (boids, grid) = (&host.boids, &host.grid);
+        let mut scratch = Vec::new();
+        let neighbors = (0..boids.len())
+            .map(|i| {
+                let bi = boids[i];
+                grid.near(i, &mut scratch);
+                scratch.retain(|&j| {
+                    let (dx, dy) = (bi.x - boids[j].x, bi.y - boids[j].y);
+                    boids[j].species == bi.species && dx * dx + dy * dy < radius * radius
+                });
+                scratch.sort_unstable();
+                scratch.iter().map(|&j| j as u32).collect()
+            })
+            .collect();
+        Ok(NeighborGraph {
+            ids: self.ids(),
+            neighbors,
+        })
+    }
+
+    pub fn used_cuda(&self) -> bool {
+        self.last_used_cuda
+    }
+
+    /// Where the last `step` actually ran
+    pub fn accelerator(&self) -> Accelerator {
+        Accelerator::from_used_cuda(self.last_used_cuda)
     }
 
-    pub fn get_boids(&mut self) -> Result<Vec<f32>> {
-        // Ensure CUDA context is set up in current thread before accessing device memory
-        self.context.ensure_context()?;
-        
-        self.ensure_aos_current()?;
-        let host_boids = &mut self.host_buffers.boids;
-        self.boids
-            .copy_to(&mut host_boids[..])
-            .map_err(|e| anyhow::anyhow!("Failed to copy boids: {:?}", e))?;
-        let mut result = Vec::with_capacity(self.num_boids * 4);
-        for b in host_boids.iter() {
-            result.push(b.x);
-            result.push(b.y);
-            result.push(b.vx);
-            result.push(b.vy);
-        }
+    /// PTX `step` launches from; `None` once the simulation has fallen back to the CPU
+    pub fn ptx(&self) -> Option<&str> {
+        self.ptx.as_deref()
+    }
 
-        Ok(result)
+    /// Whether a boids kernel was loaded and can be used by `step`
+    pub fn cuda_available(&self) -> bool {
+        self.ptx.is_some() && self.has_soa()
     }
 
-    pub fn used_cuda(&self) -> bool {
-        self.last_used_cuda
+    /// Force the CPU fallback even when the CUDA kernel is available
+    pub fn set_force_cpu(&mut self, force_cpu: bool) {
+        self.force_cpu = force_cpu;
     }
 }
 
+// The device buffers are raw pointers, so not `Send` by themselves; the simulation is only
+// ever used behind a `Mutex`, one thread at a time
 unsafe impl Send for BoidsSimulation {}
 
+/// Write `src` to the front of a device buffer, first allocating one with room for
+/// `capacity` elements if there is none or it's too small
+fn fill_device<T: Element + Default>(slot: &mut Option<SimBuffer<T>>, src: &[T], capacity: usize) -> Result<()> {
+    match slot {
+        Some(buffer) if buffer.len() >= src.len() => buffer.copy_prefix_from(src),
+        _ => {
+            let mut padded = src.to_vec();
+            padded.resize(capacity.max(src.len()), T::default());
+            *slot = Some(SimBuffer::from_slice(&padded)?);
+            Ok(())
+        }
+    }
+}
+
+fn hash_u32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Deterministic noise in [-1, 1)² for boid `i` at `step`; mirrors the jitter block in boids.cu
+pub fn jitter_noise(seed: u32, step: u32, i: u32) -> (f32, f32) {
+    let h1 = hash_u32(seed ^ hash_u32(step ^ hash_u32(i)));
+    let h2 = hash_u32(h1);
+    let unit = |h: u32| (h >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+    (unit(h1), unit(h2))
+}
+
+/// All other boids of the same species as boid `i`
+fn same_species_neighbors(boids: &[Boid], i: usize, out: &mut Vec<usize>) {
+    let species = boids[i].species;
+    out.clear();
+    out.extend(
+        boids
+            .iter()
+            .enumerate()
+            .filter(|(j, b)| *j != i && b.species == species)
+            .map(|(j, _)| j),
+    );
+}
+
+/// Same-species boids within `radius` of boid `i`
+pub fn metric_neighbors(boids: &[Boid], i: usize, radius: f32, out: &mut Vec<usize>) {
+    same_species_neighbors(boids, i, out);
+    let bi = boids[i];
+    out.retain(|&j| {
+        let dx = bi.x - boids[j].x;
+        let dy = bi.y - boids[j].y;
+        dx * dx + dy * dy < radius * radius
+    });
+}
+
+/// The `k` nearest same-species boids to boid `i`, nearest first. Searches `grid` ring by
+/// ring outward from `i`'s cell; `slack` is how far boids may have moved since its rebuild.
+pub fn topological_neighbors(
+    boids: &[Boid],
+    grid: &SpatialGrid,
+    i: usize,
+    k: usize,
+    slack: f32,
+    candidates: &mut Vec<(f32, usize)>,
+    out: &mut Vec<usize>,
+) {
+    let bi = boids[i];
+    candidates.clear();
+    for r in 0..grid.rings() {
+        if k == 0 {
+            break;
+        }
+        grid.ring(i, r, out);
+        candidates.extend(out.iter().filter(|&&j| boids[j].species == bi.species).map(|&j| {
+            let dx = bi.x - boids[j].x;
+            let dy = bi.y - boids[j].y;
+            (dx * dx + dy * dy, j)
+        }));
+        // Only the k nearest so far can still make the cut
+        if candidates.len() > k {
+            candidates.select_nth_unstable_by(k - 1, |a, b| a.0.total_cmp(&b.0));
+            candidates.truncate(k);
+        }
+        // Boids in outer rings are at least `bound` away, so a full set inside it is final
+        let bound = r as f32 * grid.cell_size() - slack;
+        let kth = candidates.iter().map(|c| c.0).fold(0.0, f32::max);
+        if candidates.len() == k && bound > 0.0 && kth <= bound * bound {
+            break;
+        }
+    }
+    candidates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    out.clear();
+    out.extend(candidates.iter().map(|&(_, j)| j));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::init_cuda_in_thread;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -506,6 +2180,7 @@ mod tests {
         )
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_boids_initialization() {
         let (context, _context_guard) = setup_test_context();
@@ -515,17 +2190,880 @@ mod tests {
 
     #[test]
     fn test_boids_step() {
-        let (context, _context_guard) = setup_test_context();
-        let mut sim = BoidsSimulation::new(&context, 1000).unwrap();
+        let mut sim = BoidsSimulation::new_host(1000).unwrap();
         let result = sim.step(0.016);
         assert!(result.is_ok(), "Boids step should succeed");
+        assert!(!sim.used_cuda() && !sim.cuda_available());
     }
 
     #[test]
     fn test_boids_count() {
-        let (context, _context_guard) = setup_test_context();
-        let mut sim = BoidsSimulation::new(&context, 1000).unwrap();
+        let mut sim = BoidsSimulation::new_host(1000).unwrap();
         let boids = sim.get_boids().unwrap();
         assert_eq!(boids.len(), 1000 * 4, "Should return boid data");
     }
+
+    #[test]
+    fn test_boids_stay_in_bounds_and_finite() {
+        let mut sim = BoidsSimulation::new_host(500).unwrap();
+        for _ in 0..50 {
+            sim.step(0.5).unwrap();
+        }
+        let boids = sim.get_boids().unwrap();
+        for b in boids.chunks(4) {
+            assert!(b.iter().all(|v| v.is_finite()), "Boid state should stay finite");
+            assert!((0.0..=1.0).contains(&b[0]) && (0.0..=1.0).contains(&b[1]));
+        }
+    }
+
+    #[test]
+    fn test_boid_ids_stable_across_steps() {
+        let mut sim = BoidsSimulation::new_host(100).unwrap();
+        sim.get_boids().unwrap();
+        let before = sim.ids();
+        assert_eq!(before, (0..100).collect::<Vec<u32>>());
+        for _ in 0..5 {
+            sim.step(0.016).unwrap();
+        }
+        sim.get_boids().unwrap();
+        assert_eq!(sim.ids(), before, "Ids should survive steps");
+    }
+
+    fn grid_flock(side: usize, spacing: f32) -> Vec<Boid> {
+        (0..side * side)
+            .map(|i| Boid {
+                x: 0.5 + (i % side) as f32 * spacing,
+                y: 0.5 + (i / side) as f32 * spacing,
+                ..Boid::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_topological_neighbor_count_independent_of_density() {
+        let dense = grid_flock(20, 0.002);
+        let sparse = grid_flock(20, 0.02);
+        let (mut candidates, mut out) = (Vec::new(), Vec::new());
+
+        metric_neighbors(&dense, 0, 0.1, &mut out);
+        let metric_dense = out.len();
+        metric_neighbors(&sparse, 0, 0.1, &mut out);
+        let metric_sparse = out.len();
+        assert!(metric_dense > metric_sparse * 4, "Metric count should track density");
+
+        let mut grid = SpatialGrid::default();
+        let k = DEFAULT_TOPOLOGICAL_K;
+        grid.rebuild(&dense, 0.05);
+        topological_neighbors(&dense, &grid, 0, k, 0.0, &mut candidates, &mut out);
+        assert_eq!(out.len(), DEFAULT_TOPOLOGICAL_K);
+        grid.rebuild(&sparse, 0.05);
+        topological_neighbors(&sparse, &grid, 0, k, 0.0, &mut candidates, &mut out);
+        assert_eq!(out.len(), DEFAULT_TOPOLOGICAL_K);
+        // Nearest first: the two lattice neighbours of the corner boid
+        assert!(out[..2].contains(&1) && out[..2].contains(&20));
+    }
+
+    #[test]
+    fn test_topological_neighbors_match_brute_force() {
+        let sim = BoidsSimulation::new_host_seeded(400, 11).unwrap();
+        let boids = &sim.host_buffers.boids;
+        let mut grid = SpatialGrid::default();
+        let (mut candidates, mut out) = (Vec::new(), Vec::new());
+        for cell_size in [0.01, 0.1, 1.0] {
+            grid.rebuild(boids, cell_size);
+            for (i, bi) in boids.iter().enumerate() {
+                topological_neighbors(boids, &grid, i, 7, 0.0, &mut candidates, &mut out);
+                let dist = |j: usize| (bi.x - boids[j].x).powi(2) + (bi.y - boids[j].y).powi(2);
+                let mut all: Vec<usize> = (0..boids.len())
+                    .filter(|&j| j != i && boids[j].species == bi.species)
+                    .collect();
+                all.sort_by(|&a, &b| dist(a).total_cmp(&dist(b)));
+                let found: Vec<f32> = out.iter().map(|&j| dist(j)).collect();
+                let expected: Vec<f32> = all.iter().take(7).map(|&j| dist(j)).collect();
+                assert_eq!(found, expected, "Boid {} with cells of {}", i, cell_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_heavier_boid_responds_less() {
+        let mut light = Boid { mass: 1.0, ..Boid::default() };
+        let mut heavy = Boid { mass: 2.0, ..Boid::default() };
+        light.apply_force(0.01, -0.02, 0.016);
+        heavy.apply_force(0.01, -0.02, 0.016);
+        assert!((heavy.vx - light.vx * 0.5).abs() < 1e-9);
+        assert!((heavy.vy - light.vy * 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_species_masses_validation() {
+        let mut sim = BoidsSimulation::new_host(100).unwrap();
+        assert!(sim.set_species_masses(&[1.0, 2.0]).is_err());
+        assert!(sim.set_species_masses(&[1.0, 0.0, 1.0, 1.0]).is_err());
+        assert!(sim.set_species_masses(&[1.0, 2.0, 0.5, 4.0]).is_ok());
+        assert_eq!(sim.species_masses(), &[1.0, 2.0, 0.5, 4.0]);
+    }
+
+    #[test]
+    fn test_cpu_step_scales_to_large_flocks() {
+        let mut sim = BoidsSimulation::new_host_seeded(5000, 11).unwrap();
+        // Interaction radii typical of large flocks; the grid bins by the largest
+        sim.set_params(&BoidsParams {
+            separation_radius: Some(0.01),
+            alignment_radius: Some(0.02),
+            cohesion_radius: Some(0.03),
+            ..Default::default()
+        })
+        .unwrap();
+        sim.step(0.016).unwrap();
+        let start = std::time::Instant::now();
+        sim.step(0.016).unwrap();
+        let elapsed = start.elapsed();
+        assert!(!sim.used_cuda());
+        // Generous for debug builds; the all-pairs scan was well over this
+        assert!(
+            elapsed < std::time::Duration::from_millis(500),
+            "5000-boid step took {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_halve_until_ok() {
+        // Pretend the GPU only has room for `capacity` boids
+        let attempts = |desired, min, capacity| {
+            let mut tried = Vec::new();
+            let out_of_memory = |e: &anyhow::Error| e.to_string() == "out of memory";
+            let result = halve_until_ok(desired, min, out_of_memory, |n| {
+                tried.push(n);
+                if n <= capacity { Ok(n) } else { anyhow::bail!("out of memory") }
+            });
+            (result.ok(), tried)
+        };
+        assert_eq!(attempts(100_000, 1_000, 200_000), (Some(100_000), vec![100_000]));
+        assert_eq!(attempts(100_000, 1_000, 30_000), (Some(25_000), vec![100_000, 50_000, 25_000]));
+        assert_eq!(attempts(10_000, 3_000, 1_000), (None, vec![10_000, 5_000, 3_000]), "stops after trying min");
+
+        // Errors a smaller flock wouldn't fix are returned right away
+        let mut tried = Vec::new();
+        let result: Result<usize> = halve_until_ok(100_000, 1_000, |_| false, |n| {
+            tried.push(n);
+            anyhow::bail!("no device")
+        });
+        assert!(result.is_err());
+        assert_eq!(tried, vec![100_000]);
+    }
+
+    fn host_with_species(num_boids: usize, num_species: u8) -> Result<BoidsSimulation> {
+        BoidsSimulation::with_backend(None, num_boids, num_species, &HostBackend, SimRng::new(3))
+    }
+
+    #[test]
+    fn test_num_species_range() {
+        assert!(host_with_species(10, 0).is_err());
+        assert!(host_with_species(10, MAX_SPECIES + 1).is_err());
+
+        for num_species in [1, MAX_SPECIES] {
+            let mut sim = host_with_species(2000, num_species).unwrap();
+            assert_eq!(sim.num_species(), num_species);
+            assert_eq!(sim.species_masses().len(), num_species as usize);
+            sim.step(0.016).unwrap();
+            let mut seen: Vec<u8> = sim.host_buffers.boids.iter().map(|b| b.species).collect();
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen, (0..num_species).collect::<Vec<_>>(), "Every species spawned, none beyond");
+        }
+    }
+
+    #[test]
+    fn test_radius_ordering_warns_or_rejects() {
+        let mut sim = BoidsSimulation::new_host(10).unwrap();
+        let inverted = BoidsParams {
+            separation_radius: Some(0.2),
+            ..Default::default()
+        };
+        let warnings = sim.set_params(&inverted).unwrap();
+        assert_eq!(warnings.len(), 1, "Out-of-order radii warn by default");
+        assert_eq!(sim.separation_radius(), 0.2, "and are still applied");
+
+        let strict = BoidsParams {
+            separation_radius: Some(0.3),
+            radius_check: Some(RadiusCheck::Error),
+            ..Default::default()
+        };
+        assert!(sim.set_params(&strict).is_err());
+        assert_eq!(sim.separation_radius(), 0.2);
+
+        let ordered = BoidsParams {
+            separation_radius: Some(0.05),
+            ..Default::default()
+        };
+        assert!(sim.set_params(&ordered).unwrap().is_empty());
+        assert!(sim
+            .set_params(&BoidsParams { cohesion_radius: Some(0.0), ..Default::default() })
+            .is_err());
+    }
+
+    #[test]
+    fn test_rejected_params_change_nothing() {
+        let mut sim = BoidsSimulation::new_host(10).unwrap();
+        let too_strong = BoidsParams {
+            separation_radius: Some(0.08),
+            max_force: Some(0.1),
+            ..Default::default()
+        };
+        assert!(sim.set_params(&too_strong).is_err(), "max_force above max_speed");
+        assert_eq!(sim.separation_radius(), 0.05, "Nothing applied on rejection");
+
+        let tuned = BoidsParams {
+            separation_radius: Some(0.08),
+            alignment_radius: Some(0.12),
+            cohesion_radius: Some(0.2),
+            max_speed: Some(0.06),
+            ..Default::default()
+        };
+        assert!(sim.set_params(&tuned).unwrap().is_empty());
+        assert_eq!(sim.separation_radius(), 0.08);
+        assert_eq!(sim.max_speed, 0.06);
+        sim.step(0.016).unwrap();
+    }
+
+    #[test]
+    fn test_per_boid_max_speed() {
+        let mut sim = BoidsSimulation::new_host(200).unwrap();
+        let range = BoidsParams { max_speed_range: Some((0.02, 0.08)), ..Default::default() };
+        sim.set_params(&range).unwrap();
+        for _ in 0..20 {
+            sim.step(0.016).unwrap();
+        }
+        sim.get_boids().unwrap();
+        let boids = sim.host_buffers.boids.clone();
+        for b in &boids {
+            assert!((0.02..=0.08).contains(&b.max_speed));
+            assert!((b.vx * b.vx + b.vy * b.vy).sqrt() <= b.max_speed + 1e-6, "Boid over its own cap");
+        }
+        let slowest = boids.iter().map(|b| b.max_speed).fold(f32::MAX, f32::min);
+        let fastest = boids.iter().map(|b| b.max_speed).fold(0.0, f32::max);
+        assert!(slowest < 0.03 && fastest > 0.07, "Caps spread over the range");
+
+        let both = BoidsParams { max_speed: Some(0.05), ..range };
+        assert!(sim.set_params(&both).is_err());
+        let inverted = BoidsParams { max_speed_range: Some((0.08, 0.02)), ..Default::default() };
+        assert!(sim.set_params(&inverted).is_err());
+        let below_force = BoidsParams { max_speed_range: Some((0.005, 0.08)), ..Default::default() };
+        assert!(sim.set_params(&below_force).is_err(), "max_force above the slowest cap");
+    }
+
+    fn frozen_pair() -> BoidsSimulation {
+        // Two resting boids too far apart to interact: every force is zero
+        let mut sim = BoidsSimulation::new_host(2).unwrap();
+        sim.update_host_boids(|boids| {
+            for (b, x) in boids.iter_mut().zip([0.25, 0.75]) {
+                *b = Boid { x, y: 0.5, vx: 0.0, vy: 0.0, species: 0, ..*b };
+            }
+        })
+        .unwrap();
+        sim
+    }
+
+    #[test]
+    fn test_jitter_unsticks_symmetric_pair() {
+        let mut still = frozen_pair();
+        let mut jittered = frozen_pair();
+        let params = BoidsParams { jitter: Some(1e-3), jitter_seed: Some(42), ..Default::default() };
+        jittered.set_params(&params).unwrap();
+        for _ in 0..10 {
+            still.step(0.016).unwrap();
+            jittered.step(0.016).unwrap();
+        }
+        let still = still.get_boids().unwrap();
+        assert_eq!(&still[..4], &[0.25, 0.5, 0.0, 0.0], "Without jitter the pair stays frozen");
+        let moved = jittered.get_boids().unwrap();
+        assert!(moved[2] != 0.0 && moved[3] != 0.0, "Jitter should set the pair moving");
+
+        // Same seed, same trajectory
+        let mut replay = frozen_pair();
+        replay.set_params(&params).unwrap();
+        for _ in 0..10 {
+            replay.step(0.016).unwrap();
+        }
+        assert_eq!(replay.get_boids().unwrap(), moved);
+    }
+
+    #[test]
+    fn test_frozen_flock_reports_stasis() {
+        let mut sim = frozen_pair();
+        let steps_per_window = (stasis::DEFAULT_WINDOW / 0.1) as usize;
+        for _ in 0..steps_per_window / 2 {
+            sim.step(0.1).unwrap();
+        }
+        assert!(!sim.in_stasis(), "Not flagged before the window has passed");
+        for _ in 0..steps_per_window {
+            sim.step(0.1).unwrap();
+        }
+        assert!(sim.in_stasis());
+
+        // Setting the flock moving clears the flag at the next check
+        sim.set_params(&BoidsParams { jitter: Some(0.05), ..Default::default() }).unwrap();
+        for _ in 0..10 {
+            sim.step(0.1).unwrap();
+        }
+        assert!(!sim.in_stasis());
+    }
+
+    #[test]
+    fn test_fast_boid_never_passes_thin_wall() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.45, y: 0.5, vx: 0.05, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        let wall = Obstacle::Wall { x0: 0.5, y0: 0.0, x1: 0.5, y1: 1.0, thickness: 0.001 };
+        let params = BoidsParams {
+            obstacles: Some(vec![wall]),
+            continuous_collision: Some(true),
+            ..Default::default()
+        };
+        sim.set_params(&params).unwrap();
+        // One step would carry the boid 0.2 units, far past the wall
+        sim.step(4.0).unwrap();
+        let state = sim.get_boids().unwrap();
+        assert!(state[0] < 0.5, "Boid tunnelled through the wall: x = {}", state[0]);
+        assert!(state[2] < 0.0, "Boid should bounce back off the wall");
+    }
+
+    #[test]
+    fn test_boid_spawned_inside_obstacle_eases_out() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.52, y: 0.5, vx: 0.0, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        let circle = Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 };
+        sim.set_obstacles(vec![circle]).unwrap();
+
+        let dt = 0.1;
+        let mut previous = sim.get_boids().unwrap();
+        for _ in 0..300 {
+            sim.step(dt).unwrap();
+            let state = sim.get_boids().unwrap();
+            // Shortest displacement, since the boid may wrap around the edge
+            let wrapped = |d: f32| d.abs().min(1.0 - d.abs());
+            let moved = wrapped(state[0] - previous[0]).hypot(wrapped(state[1] - previous[1]));
+            assert!(moved <= 0.05 * dt + 1e-6, "Boid jumped {} in one step", moved);
+            previous = state;
+        }
+        assert!(
+            circle.distance(previous[0], previous[1]).0 >= 0.0,
+            "Boid still inside at ({}, {})",
+            previous[0],
+            previous[1]
+        );
+        assert!(sim.set_obstacles(vec![circle; obstacles::MAX_OBSTACLES + 1]).is_err());
+    }
+
+    #[test]
+    fn test_attractor_pulls_boid_in() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.4, y: 0.5, vx: 0.0, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        let attractor = Attractor { x: 0.5, y: 0.5, strength: 2.0, radius: 0.25 };
+        sim.add_attractor(attractor).unwrap();
+        for _ in 0..10 {
+            sim.step(0.1).unwrap();
+        }
+        let state = sim.get_boids().unwrap();
+        assert!(state[0] > 0.4 && state[2] > 0.0, "Boid heads toward the attractor");
+
+        for i in 0..attractors::MAX_ATTRACTORS {
+            sim.add_attractor(Attractor { x: i as f32 * 0.01, ..attractor }).unwrap();
+        }
+        assert_eq!(sim.attractors().len(), attractors::MAX_ATTRACTORS);
+        assert_eq!(sim.attractors()[0].x, 0.0, "Oldest attractor dropped");
+        assert!(sim.add_attractor(Attractor { strength: 0.0, ..attractor }).is_err());
+        assert!(sim.set_attractors(vec![attractor; attractors::MAX_ATTRACTORS + 1]).is_err());
+        sim.clear_attractors();
+        assert!(sim.attractors().is_empty());
+    }
+
+    #[test]
+    fn test_repeller_pushes_boid_away() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.45, y: 0.5, vx: 0.0, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        let repeller = Attractor { x: 0.5, y: 0.5, strength: -2.0, radius: 0.2 };
+        sim.set_attractors(vec![repeller]).unwrap();
+        sim.step(0.1).unwrap();
+        assert!(sim.get_boids().unwrap()[2] < 0.0, "Boid pushed away from the repeller");
+    }
+
+    #[test]
+    fn test_cleared_attractors_leave_pure_flocking() {
+        let mut plain = BoidsSimulation::new_host_seeded(50, 7).unwrap();
+        let mut cleared = BoidsSimulation::new_host_seeded(50, 7).unwrap();
+        cleared
+            .set_attractors(vec![Attractor { x: 0.5, y: 0.5, strength: 5.0, radius: 0.5 }])
+            .unwrap();
+        cleared.clear_attractors();
+        for _ in 0..10 {
+            plain.step(0.016).unwrap();
+            cleared.step(0.016).unwrap();
+        }
+        assert_eq!(plain.get_boids().unwrap(), cleared.get_boids().unwrap());
+    }
+
+    #[test]
+    fn test_emitter_grows_population_up_to_cap() {
+        let mut sim = BoidsSimulation::new_host(0).unwrap();
+        let config = EmitterConfig {
+            x: 0.5,
+            y: 0.5,
+            rate: 100.0,
+            vx: 0.0,
+            vy: 0.01,
+            spread: 0.0,
+            max_population: 50,
+            species: None,
+        };
+        sim.set_emitter(Some(config)).unwrap();
+
+        // 100 boids/s for 0.1 s per step
+        for expected in [10, 20, 30, 40, 50, 50, 50] {
+            sim.step(0.1).unwrap();
+            sim.run_emitter(0.1).unwrap();
+            assert_eq!(sim.num_boids(), expected);
+        }
+        assert_eq!(sim.get_boids().unwrap().len(), 50 * 4);
+
+        // Once full, the oldest boids make room for new ones
+        let ids = sim.ids();
+        assert_eq!(ids.iter().min(), Some(&20));
+        assert_eq!(ids.iter().max(), Some(&69));
+        assert_eq!(sim.boids.capacity(), 60, "replacing boids doesn't reallocate");
+
+        let huge = EmitterConfig { rate: 1e12, ..sim.emitter_config().unwrap().clone() };
+        assert!(sim.set_emitter(Some(huge)).is_err());
+        let crowded = EmitterConfig { max_population: MAX_NUM_BOIDS + 1, ..sim.emitter_config().unwrap().clone() };
+        assert!(sim.set_emitter(Some(crowded)).is_err());
+    }
+
+    #[test]
+    fn test_oldest_boids_go_first_across_id_wraparound() {
+        let mut sim = BoidsSimulation::new_host_seeded(0, 3).unwrap();
+        sim.next_id = u32::MAX - 5;
+        let ids = sim.add_boids(8).unwrap();
+        assert_eq!(ids, [u32::MAX - 5, u32::MAX - 4, u32::MAX - 3, u32::MAX - 2, u32::MAX - 1, u32::MAX, 0, 1]);
+
+        sim.remove_boids(3).unwrap();
+        sim.get_boids().unwrap();
+        assert_eq!(sim.ids(), [u32::MAX - 2, u32::MAX - 1, u32::MAX, 0, 1]);
+    }
+
+    #[test]
+    fn test_resize_keeps_existing_boids() {
+        let mut sim = BoidsSimulation::new_host_seeded(100, 5).unwrap();
+        sim.set_num_predators(2).unwrap();
+        let before = sim.get_boids().unwrap();
+
+        let ids = sim.add_boids(50).unwrap();
+        assert_eq!(ids, (100..150).collect::<Vec<u32>>());
+        assert_eq!(sim.num_boids(), 150);
+        let grown = sim.get_boids().unwrap();
+        assert_eq!(grown.len(), 150 * 4);
+        assert_eq!(&grown[..before.len()], &before[..], "existing boids are untouched");
+        sim.step(0.016).unwrap();
+
+        sim.resize(30).unwrap();
+        assert_eq!(sim.num_boids(), 30);
+        assert_eq!(sim.ids().iter().min(), Some(&120), "the oldest boids go first");
+        assert_eq!(sim.get_boids().unwrap().len(), 30 * 4);
+        assert_eq!(sim.max_speeds().len(), 30);
+        sim.step(0.016).unwrap();
+
+        assert!(sim.remove_boids(30).is_err(), "the flock can't be emptied");
+        assert!(sim.resize(0).is_err());
+        assert!(sim.add_boids(MAX_NUM_BOIDS).is_err());
+        assert_eq!(sim.num_boids(), 30);
+    }
+
+    #[test]
+    fn test_genetics_keeps_population_constant() {
+        let mut sim = host_with_species(500, 1).unwrap();
+        sim.set_params(&BoidsParams {
+            max_speed_range: Some((0.02, 0.08)),
+            genetics: Some(true),
+            ..Default::default()
+        })
+        .unwrap();
+        for _ in 0..40 {
+            sim.step(0.1).unwrap();
+        }
+        assert_eq!(sim.num_boids(), 500);
+        assert_eq!(sim.get_boids().unwrap().len(), 500 * 4);
+        let stats = sim.statistics().unwrap();
+        let genes = stats.genes.expect("gene stats while genetics is on");
+        assert!(genes.births > 0, "500 boids in the unit square should have dense clusters");
+        assert_eq!(sim.ids().iter().collect::<std::collections::HashSet<_>>().len(), 500);
+
+        sim.set_params(&BoidsParams { genetics: Some(false), ..Default::default() }).unwrap();
+        assert!(sim.statistics().unwrap().genes.is_none());
+    }
+
+    #[test]
+    fn test_jitter_noise_range() {
+        for i in 0..1000 {
+            let (jx, jy) = jitter_noise(7, 3, i);
+            assert!((-1.0..1.0).contains(&jx) && (-1.0..1.0).contains(&jy));
+        }
+        assert_ne!(jitter_noise(7, 3, 0), jitter_noise(7, 4, 0));
+    }
+
+    #[test]
+    fn test_reset_positions_replaces_flock() {
+        let mut sim = BoidsSimulation::new_host(20).unwrap();
+        sim.reset_positions(&[(0.25, 0.5), (0.75, 0.5), (0.5, 0.1)]).unwrap();
+        assert_eq!(sim.num_boids(), 3);
+        assert_eq!(sim.ids(), vec![20, 21, 22]);
+        let state = sim.get_boids().unwrap();
+        assert_eq!((state[0], state[1]), (0.25, 0.5));
+        assert_eq!((state[8], state[9]), (0.5, 0.1));
+    }
+
+    fn seeded_run(seed: u64) -> (Vec<f32>, Vec<u32>) {
+        let mut sim = BoidsSimulation::new_host_seeded(200, seed).unwrap();
+        sim.set_params(&BoidsParams { jitter: Some(1e-3), ..Default::default() }).unwrap();
+        sim.set_emitter(Some(EmitterConfig {
+            x: 0.5,
+            y: 0.5,
+            rate: 100.0,
+            vx: 0.0,
+            vy: 0.0,
+            spread: 0.02,
+            max_population: 250,
+            species: None,
+        }))
+        .unwrap();
+        for _ in 0..20 {
+            sim.step(0.05).unwrap();
+            sim.run_emitter(0.05).unwrap();
+        }
+        (sim.get_boids().unwrap(), sim.ids())
+    }
+
+    #[test]
+    fn test_checkpoint_restore_reproduces_flock() {
+        let mut original = BoidsSimulation::new_host_seeded(150, 3).unwrap();
+        original
+            .set_params(&BoidsParams {
+                jitter: Some(1e-3),
+                world_size: Some(2.0),
+                num_predators: Some(2),
+                boundary: Some(BoundaryMode::Bounce),
+                obstacles: Some(vec![Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 }]),
+                genetics: Some(true),
+                ..Default::default()
+            })
+            .unwrap();
+        for _ in 0..10 {
+            original.step(0.05).unwrap();
+        }
+        let blob = original.serialize_state().unwrap();
+        assert!(
+            original.host_buffers.boids.iter().any(|b| b.genes != Genes::default()),
+            "some boids should have bred by now"
+        );
+
+        // Different size, seed and parameters: restore replaces all of them
+        let mut restored = BoidsSimulation::new_host_seeded(40, 99).unwrap();
+        restored.restore_state(&blob).unwrap();
+        assert_eq!(restored.get_boids().unwrap(), original.get_boids().unwrap());
+        assert_eq!(restored.ids(), original.ids());
+        assert_eq!(restored.boundary_mode(), BoundaryMode::Bounce);
+        assert_eq!(restored.obstacles(), original.obstacles());
+        assert_eq!(restored.num_predators(), 2);
+        assert_eq!(restored.world_size(), 2.0);
+        assert!(restored.genetics_enabled());
+        let genes = |sim: &BoidsSimulation| sim.host_buffers.boids.iter().map(|b| b.genes).collect::<Vec<_>>();
+        assert_eq!(genes(&restored), genes(&original));
+
+        // Both continue identically from the checkpoint
+        original.step(0.05).unwrap();
+        restored.step(0.05).unwrap();
+        assert_eq!(restored.get_boids().unwrap(), original.get_boids().unwrap());
+
+        let before = restored.get_boids().unwrap();
+        assert!(restored.restore_state(&blob[..blob.len() - 3]).is_err());
+        assert_eq!(restored.get_boids().unwrap(), before, "A rejected blob changes nothing");
+    }
+
+    #[test]
+    fn test_seed_replays_identically() {
+        let first = seeded_run(1234);
+        assert_eq!(first, seeded_run(1234), "Same seed must give bit-identical state");
+        assert_ne!(first.0, seeded_run(4321).0);
+    }
+
+    #[test]
+    fn test_reset_with_seed_matches_fresh_flock() {
+        let mut sim = BoidsSimulation::new_host_seeded(50, 1).unwrap();
+        for _ in 0..10 {
+            sim.step(0.05).unwrap();
+        }
+        sim.reset(Some(99)).unwrap();
+
+        let mut fresh = BoidsSimulation::new_host_seeded(50, 99).unwrap();
+        assert_eq!(sim.get_boids().unwrap(), fresh.get_boids().unwrap());
+        assert_eq!(sim.ids(), fresh.ids());
+        for _ in 0..5 {
+            sim.step(0.05).unwrap();
+            fresh.step(0.05).unwrap();
+        }
+        assert_eq!(sim.get_boids().unwrap(), fresh.get_boids().unwrap(), "Reset runs replay too");
+    }
+
+    #[test]
+    fn test_neighbor_graph_within_cohesion_radius() {
+        let mut sim = BoidsSimulation::new_host(4).unwrap();
+        sim.update_host_boids(|boids| {
+            let layout = [(0.1, 0), (0.2, 0), (0.9, 0), (0.15, 1)];
+            for (b, (x, species)) in boids.iter_mut().zip(layout) {
+                *b = Boid { x, y: 0.5, vx: 0.0, vy: 0.0, species, ..*b };
+            }
+        })
+        .unwrap();
+        let graph = sim.neighbor_graph().unwrap();
+        assert_eq!(graph.ids, vec![0, 1, 2, 3]);
+        // Far boid and other-species boid stay unconnected
+        assert_eq!(graph.neighbors, vec![vec![1], vec![0], vec![], vec![]]);
+    }
+
+    #[test]
+    fn test_neighbor_graph_matches_brute_force() {
+        let mut sim = BoidsSimulation::new_host_seeded(300, 9).unwrap();
+        let graph = sim.neighbor_graph().unwrap();
+        let boids = &sim.host_buffers.boids;
+        let mut expected = Vec::new();
+        for (i, neighbors) in graph.neighbors.iter().enumerate() {
+            metric_neighbors(boids, i, sim.cohesion_radius, &mut expected);
+            assert_eq!(*neighbors, expected.iter().map(|&j| j as u32).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_auto_tune_is_independent_of_world_size() {
+        let tuned = BoidsParams { auto_tune: Some(true), ..Default::default() };
+        let mut unit = BoidsSimulation::new_host_seeded(400, 12).unwrap();
+        unit.set_params(&tuned).unwrap();
+        let mut large = BoidsSimulation::new_host_seeded(400, 12).unwrap();
+        large.set_params(&BoidsParams { world_size: Some(10.0), ..tuned }).unwrap();
+
+        for _ in 0..3 {
+            unit.maybe_auto_tune(auto_tune::TUNE_INTERVAL).unwrap();
+            large.maybe_auto_tune(auto_tune::TUNE_INTERVAL).unwrap();
+        }
+        let relative = large.separation_radius / large.world_size();
+        assert_ne!(unit.separation_radius, 0.05, "the tuner should have moved");
+        assert!(
+            (relative - unit.separation_radius).abs() < 1e-4 * unit.separation_radius,
+            "unit world {} vs 10x world {} (relative)",
+            unit.separation_radius,
+            relative
+        );
+    }
+
+    #[test]
+    fn test_world_size_scales_the_flock() {
+        let mut sim = BoidsSimulation::new_host_seeded(200, 4).unwrap();
+        let before = sim.get_boids().unwrap();
+        let params = BoidsParams { world_size: Some(10.0), ..Default::default() };
+        assert!(sim.validate_params(&BoidsParams { world_size: Some(0.0), ..params.clone() }).is_err());
+        // Radii given alongside are in the new units; the scaled current radii keep their order
+        let with_radius = BoidsParams { separation_radius: Some(0.3), ..params.clone() };
+        assert!(sim.validate_params(&with_radius).unwrap().is_empty());
+
+        sim.set_params(&params).unwrap();
+        assert_eq!(sim.world_size(), 10.0);
+        assert_eq!(sim.max_speed_range(), (DEFAULT_MAX_SPEED * 10.0, DEFAULT_MAX_SPEED * 10.0));
+        let after = sim.get_boids().unwrap();
+        for (a, b) in after.iter().zip(&before) {
+            assert!((a - b * 10.0).abs() < 1e-4);
+        }
+
+        for mode in [BoundaryMode::Wrap, BoundaryMode::Bounce, BoundaryMode::Open] {
+            sim.set_boundary_mode(mode).unwrap();
+            for _ in 0..20 {
+                sim.step(0.5).unwrap();
+            }
+            for b in sim.get_boids().unwrap().chunks_exact(4) {
+                assert!((0.0..=10.0).contains(&b[0]) && (0.0..=10.0).contains(&b[1]), "{:?} left the world", mode);
+            }
+        }
+        sim.reset(Some(1)).unwrap();
+        let spread = sim.get_boids().unwrap().chunks_exact(4).fold(0.0f32, |m, b| m.max(b[0]));
+        assert!(spread > 5.0, "a reset fills the whole world");
+    }
+
+    #[test]
+    fn test_boundary_switch_applies_next_step() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        let place = |sim: &mut BoidsSimulation, x: f32| {
+            sim.update_host_boids(|boids| {
+                boids[0] = Boid { x, y: 0.5, vx: 0.05, vy: 0.0, ..boids[0] };
+            })
+            .unwrap();
+        };
+        place(&mut sim, 0.99);
+        sim.step(1.0).unwrap();
+        assert!(sim.get_boids().unwrap()[0] < 0.1, "Wrap re-enters from the left edge");
+
+        place(&mut sim, 0.99);
+        sim.set_boundary_mode(BoundaryMode::Bounce).unwrap();
+        sim.step(1.0).unwrap();
+        let state = sim.get_boids().unwrap();
+        assert!(state[0] > 0.9 && state[0] <= 1.0, "Bounce reflects off the right edge");
+        assert!(state[2] < 0.0);
+
+        sim.set_boundary_mode(BoundaryMode::Open).unwrap();
+        place(&mut sim, 1.5);
+        sim.set_boundary_mode(BoundaryMode::Bounce).unwrap();
+        assert_eq!(sim.get_boids().unwrap()[0], 1.0, "Switching to bounce clamps strays");
+    }
+
+    #[test]
+    fn test_open_respawns_and_bounce_leaves_edge() {
+        let mut sim = BoidsSimulation::new_host(1).unwrap();
+        sim.set_boundary_mode(BoundaryMode::Open).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.99, y: 0.5, vx: 0.05, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        sim.step(1.0).unwrap();
+        let state = sim.get_boids().unwrap();
+        assert_eq!(state[0], 0.0, "Open respawns on the opposite edge");
+        assert!(state[2] >= 0.0, "Respawned boid heads back into the square");
+        assert!(state[2].abs() <= 0.05 && state[3].abs() <= 0.05);
+
+        // A boid resting exactly on the edge is sent inward rather than left there
+        let mut b = Boid { x: 0.0, y: 1.0, vx: 0.0, vy: 0.0, ..Boid::default() };
+        BoundaryMode::Bounce.apply(&mut b, (0.0, 0.0), 1.0);
+        assert!(b.vx >= BOUNCE_MIN_SPEED && b.vy <= -BOUNCE_MIN_SPEED);
+        b.x += b.vx;
+        b.y += b.vy;
+        BoundaryMode::Bounce.apply(&mut b, (0.0, 0.0), 1.0);
+        assert!(b.x > 0.0 && b.y < 1.0);
+    }
+
+    /// One boid just inside the right edge heading out, stepped on the GPU
+    #[cfg(not(feature = "no-cuda"))]
+    fn gpu_boid_leaving_right_edge(boundary: BoundaryMode) -> Option<Vec<f32>> {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = BoidsSimulation::new_seeded(&context, 1, 7).unwrap();
+        if !sim.cuda_available() {
+            // Built without nvcc, so there is no kernel to test
+            return None;
+        }
+        sim.set_boundary_mode(boundary).unwrap();
+        sim.update_host_boids(|boids| {
+            boids[0] = Boid { x: 0.99, y: 0.5, vx: 0.05, vy: 0.0, ..boids[0] };
+        })
+        .unwrap();
+        assert_eq!(sim.step(1.0).unwrap(), Accelerator::Cuda);
+        Some(sim.get_boids().unwrap())
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_gpu_bounce_reflects_at_the_edge() {
+        let Some(state) = gpu_boid_leaving_right_edge(BoundaryMode::Bounce) else {
+            return;
+        };
+        assert!(state[0] > 0.9 && state[0] < 1.0, "bounced back inside, got x = {}", state[0]);
+        assert!(state[2] < 0.0, "heading back in, got vx = {}", state[2]);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_gpu_open_boundary_respawns() {
+        let Some(state) = gpu_boid_leaving_right_edge(BoundaryMode::Open) else {
+            return;
+        };
+        assert_eq!(state[0], 0.0, "Open respawns on the opposite edge");
+        assert!(state[2] >= 0.0, "Respawned boid heads back into the square");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_gpu_obstacle_push_matches_cpu() {
+        let (context, _context_guard) = setup_test_context();
+        let stepped = |force_cpu: bool| {
+            let mut sim = BoidsSimulation::new_seeded(&context, 1, 7).unwrap();
+            sim.set_force_cpu(force_cpu);
+            sim.update_host_boids(|boids| {
+                boids[0] = Boid { x: 0.62, y: 0.5, vx: 0.0, vy: 0.0, ..boids[0] };
+            })
+            .unwrap();
+            sim.set_obstacles(vec![Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 }]).unwrap();
+            let accelerator = sim.step(0.1).unwrap();
+            (accelerator, sim.get_boids().unwrap())
+        };
+        let (accelerator, gpu) = stepped(false);
+        if accelerator != Accelerator::Cuda {
+            // Built without nvcc, so there is no kernel to compare
+            return;
+        }
+        let (_, cpu) = stepped(true);
+        for (g, c) in gpu.iter().zip(&cpu) {
+            assert!((g - c).abs() < 1e-5, "GPU {:?} vs CPU {:?}", gpu, cpu);
+        }
+    }
+
+    #[test]
+    fn test_force_magnitudes_track_steering() {
+        let mut sim = frozen_pair();
+        sim.step(0.016).unwrap();
+        assert_eq!(sim.force_magnitudes().unwrap(), vec![0.0, 0.0], "Isolated boids feel no force");
+
+        // Two boids inside the separation radius push each other apart
+        sim.update_host_boids(|boids| {
+            boids[1].x = boids[0].x + 0.01;
+        })
+        .unwrap();
+        sim.step(0.016).unwrap();
+        let forces = sim.force_magnitudes().unwrap();
+        assert_eq!(forces.len(), 2);
+        assert!(forces.iter().all(|&f| f > 0.0 && f.is_finite()));
+    }
+
+    #[test]
+    fn test_predator_count_and_flee() {
+        let mut sim = BoidsSimulation::new_host_seeded(100, 5).unwrap();
+        let count = |sim: &BoidsSimulation| {
+            sim.host_buffers.boids.iter().filter(|b| predators::is_predator(b)).count()
+        };
+        sim.set_num_predators(3).unwrap();
+        assert_eq!(count(&sim), 3);
+        sim.set_num_predators(1).unwrap();
+        assert_eq!(count(&sim), 1);
+        assert!(sim.set_num_predators(predators::MAX_PREDATORS + 1).is_err());
+        sim.reset(Some(5)).unwrap();
+        assert_eq!(count(&sim), 1, "Predators survive a reset");
+
+        // A lone prey just right of a predator runs away even with its flockmates to the left
+        let mut sim = BoidsSimulation::new_host(3).unwrap();
+        sim.update_host_boids(|boids| {
+            let layout = [(0.5, PREDATOR_SPECIES), (0.55, 0), (0.45, 0)];
+            for (b, (x, species)) in boids.iter_mut().zip(layout) {
+                *b = Boid { x, y: 0.5, vx: 0.0, vy: 0.0, species, ..*b };
+            }
+        })
+        .unwrap();
+        sim.step(0.1).unwrap();
+        let state = sim.get_boids().unwrap();
+        assert!(state[4 + 2] > 0.0, "Prey flees away from the predator");
+        assert!(state[2] != 0.0, "Predator gives chase");
+    }
 }
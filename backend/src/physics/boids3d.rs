@@ -0,0 +1,507 @@
+// Opt-in 3D boids: separation, alignment and cohesion in a wrapping unit cube
+// Kept apart from the 2D flock so its kernel and wire format stay unchanged
+use super::accelerator::{Accelerator, CudaFailures};
+use super::boids::{
+    validate_steering, BoidsParams, BoundaryMode, NeighborMode, RadiusCheck, MIN_MASS, NUM_SPECIES,
+};
+use super::rng::SimRng;
+use super::storage::{SimBuffer, Storage};
+use crate::cuda::CudaContext;
+use anyhow::Result;
+use anyhow::Context as AnyhowContext;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::launch;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::memory::DeviceCopy;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::prelude::*;
+#[cfg(not(feature = "no-cuda"))]
+use std::ffi::CString;
+use std::sync::Arc;
+
+/// Floats per boid returned by `get_boids`: x, y, z, vx, vy, vz
+pub const FLOATS_PER_BOID: usize = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Boid3D {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub vz: f32,
+    pub mass: f32,
+    pub id: u32,
+    pub species: u8,
+}
+
+#[cfg(not(feature = "no-cuda"))]
+unsafe impl DeviceCopy for Boid3D {}
+
+/// Radii and speed limits; the only `BoidsParams` the 3D flock understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules3D {
+    pub separation_radius: f32,
+    pub alignment_radius: f32,
+    pub cohesion_radius: f32,
+    pub max_speed: f32,
+    pub max_force: f32,
+    pub radius_check: RadiusCheck,
+}
+
+impl Default for Rules3D {
+    fn default() -> Self {
+        // Same defaults as the 2D flock
+        Self {
+            separation_radius: 0.05,
+            alignment_radius: 0.1,
+            cohesion_radius: 0.15,
+            max_speed: 0.05,
+            max_force: 0.01,
+            radius_check: RadiusCheck::Warn,
+        }
+    }
+}
+
+impl Rules3D {
+    /// `self` with `params` applied, plus warnings for suspicious but allowed values.
+    /// Params with no 3D implementation are rejected rather than silently ignored.
+    pub fn with_params(&self, params: &BoidsParams) -> Result<(Self, Vec<String>)> {
+        let unsupported = [
+            ("species_masses", params.species_masses.is_some()),
+            ("auto_tune", params.auto_tune == Some(true)),
+            ("target_density", params.target_density.is_some()),
+            (
+                "neighbor_mode",
+                params.neighbor_mode.is_some_and(|m| m != NeighborMode::Metric),
+            ),
+            ("topological_k", params.topological_k.is_some()),
+            ("jitter", params.jitter.is_some_and(|j| j != 0.0)),
+            ("obstacles", params.obstacles.as_ref().is_some_and(|o| !o.is_empty())),
+            ("continuous_collision", params.continuous_collision == Some(true)),
+            ("boundary", params.boundary.is_some_and(|b| b != BoundaryMode::Wrap)),
+            ("num_predators", params.num_predators.is_some_and(|n| n > 0)),
+        ];
+        if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+            anyhow::bail!("{} is not supported in 3D mode", name);
+        }
+
+        let rules = Self {
+            separation_radius: params.separation_radius.unwrap_or(self.separation_radius),
+            alignment_radius: params.alignment_radius.unwrap_or(self.alignment_radius),
+            cohesion_radius: params.cohesion_radius.unwrap_or(self.cohesion_radius),
+            max_speed: params.max_speed.unwrap_or(self.max_speed),
+            max_force: params.max_force.unwrap_or(self.max_force),
+            radius_check: params.radius_check.unwrap_or(self.radius_check),
+        };
+        let warnings = validate_steering(
+            rules.separation_radius,
+            rules.alignment_radius,
+            rules.cohesion_radius,
+            rules.max_speed,
+            rules.max_force,
+            rules.radius_check,
+        )?;
+        Ok((rules, warnings))
+    }
+}
+
+/// Wrap a coordinate into [0, 1)
+fn wrap_unit(v: f32) -> f32 {
+    let w = v - v.floor();
+    // Tiny negatives round up to exactly 1.0
+    if w >= 1.0 {
+        0.0
+    } else {
+        w
+    }
+}
+
+/// New velocity and steering force magnitude for boid `i`, reading only `boids`.
+/// Mirrors `boids_step_3d` in boids.cu.
+fn steer(boids: &[Boid3D], i: usize, rules: &Rules3D, dt: f32) -> ([f32; 3], f32) {
+    let bi = boids[i];
+    let mut sep = [0.0f32; 3];
+    let mut align = [0.0f32; 3];
+    let mut coh = [0.0f32; 3];
+    let (mut sep_count, mut align_count, mut coh_count) = (0, 0, 0);
+
+    for (j, bj) in boids.iter().enumerate() {
+        if j == i || bj.species != bi.species {
+            continue;
+        }
+        let d = [bi.x - bj.x, bi.y - bj.y, bi.z - bj.z];
+        let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        if dist < rules.separation_radius && dist > 0.0 {
+            for k in 0..3 {
+                sep[k] += d[k] / dist;
+            }
+            sep_count += 1;
+        }
+        if dist < rules.alignment_radius {
+            align[0] += bj.vx;
+            align[1] += bj.vy;
+            align[2] += bj.vz;
+            align_count += 1;
+        }
+        if dist < rules.cohesion_radius {
+            coh[0] += bj.x;
+            coh[1] += bj.y;
+            coh[2] += bj.z;
+            coh_count += 1;
+        }
+    }
+
+    // Each rule steers toward a unit direction, weighted like the 2D CPU path
+    let mut force = [0.0f32; 3];
+    let mut add = |dir: [f32; 3], weight: f32| {
+        let mag = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+        if mag > 0.0 {
+            for k in 0..3 {
+                force[k] += dir[k] / mag * rules.max_force * weight;
+            }
+        }
+    };
+    if sep_count > 0 {
+        add(sep, 1.0);
+    }
+    if align_count > 0 {
+        let n = align_count as f32;
+        add([align[0] / n - bi.vx, align[1] / n - bi.vy, align[2] / n - bi.vz], 0.5);
+    }
+    if coh_count > 0 {
+        let n = coh_count as f32;
+        add([coh[0] / n - bi.x, coh[1] / n - bi.y, coh[2] / n - bi.z], 0.3);
+    }
+
+    let inv_mass = 1.0 / bi.mass.max(MIN_MASS);
+    let mut v = [
+        bi.vx + force[0] * inv_mass * dt,
+        bi.vy + force[1] * inv_mass * dt,
+        bi.vz + force[2] * inv_mass * dt,
+    ];
+    let speed = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if speed > rules.max_speed {
+        for c in &mut v {
+            *c = *c / speed * rules.max_speed;
+        }
+    }
+    let magnitude = (force[0] * force[0] + force[1] * force[1] + force[2] * force[2]).sqrt();
+    (v, magnitude)
+}
+
+pub struct Boids3DSimulation {
+    // Host copy; stale while `device_newer`
+    boids: Vec<Boid3D>,
+    // Scratch for the CPU step, which reads the old state while writing the new one
+    next: Vec<Boid3D>,
+    forces: Vec<f32>,
+    rules: Rules3D,
+    // PTX provided by build.rs via BOIDS_PTX; `None` means CPU only
+    ptx: Option<String>,
+    cuda_failures: CudaFailures,
+    // Current and next state; the kernel reads one and writes the other
+    d_boids: Option<(SimBuffer<Boid3D>, SimBuffer<Boid3D>)>,
+    d_force: Option<SimBuffer<f32>>,
+    device_newer: bool,
+    last_used_cuda: bool,
+    force_cpu: bool,
+}
+
+// Same reasoning as `BoidsSimulation`: the device buffers are only touched from behind a
+// `Mutex`, one thread at a time
+unsafe impl Send for Boids3DSimulation {}
+
+impl Boids3DSimulation {
+    /// Context must already be current on this thread (see `init_cuda_in_thread`)
+    pub fn new(_context: &Arc<CudaContext>, num_boids: usize) -> Result<Self> {
+        let ptx = option_env!("BOIDS_PTX").and_then(|path| std::fs::read_to_string(path).ok());
+        Self::with_rng(num_boids, SimRng::from_entropy(), ptx)
+    }
+
+    /// CPU-only flock with every random choice drawn from `seed`
+    pub fn new_host_seeded(num_boids: usize, seed: u64) -> Result<Self> {
+        Self::with_rng(num_boids, SimRng::new(seed), None)
+    }
+
+    fn with_rng(num_boids: usize, mut rng: SimRng, ptx: Option<String>) -> Result<Self> {
+        let boids: Vec<Boid3D> = (0..num_boids)
+            .map(|id| Boid3D {
+                x: rng.next_f32(),
+                y: rng.next_f32(),
+                z: rng.next_f32(),
+                vx: rng.range_f32(-0.03, 0.03),
+                vy: rng.range_f32(-0.03, 0.03),
+                vz: rng.range_f32(-0.03, 0.03),
+                mass: 1.0,
+                id: id as u32,
+                species: rng.below(NUM_SPECIES as u32) as u8,
+            })
+            .collect();
+        let (d_boids, d_force) = if ptx.is_some() {
+            let current = SimBuffer::from_slice(&boids)
+                .context("Failed to allocate 3D boids")?;
+            let next = SimBuffer::from_slice(&boids)
+                .context("Failed to allocate 3D boids")?;
+            let force = SimBuffer::from_slice(&vec![0.0f32; num_boids])
+                .context("Failed to allocate 3D forces")?;
+            (Some((current, next)), Some(force))
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            next: boids.clone(),
+            forces: vec![0.0; num_boids],
+            boids,
+            rules: Rules3D::default(),
+            ptx,
+            cuda_failures: CudaFailures::default(),
+            d_boids,
+            d_force,
+            device_newer: false,
+            last_used_cuda: false,
+            force_cpu: false,
+        })
+    }
+
+    pub fn num_boids(&self) -> usize {
+        self.boids.len()
+    }
+
+    pub fn rules(&self) -> Rules3D {
+        self.rules
+    }
+
+    /// Apply `params`, returning warnings about settings that were accepted but look wrong
+    pub fn set_params(&mut self, params: &BoidsParams) -> Result<Vec<String>> {
+        let (rules, warnings) = self.rules.with_params(params)?;
+        for warning in &warnings {
+            tracing::warn!("Boids 3D params: {}", warning);
+        }
+        self.rules = rules;
+        Ok(warnings)
+    }
+
+    #[cfg(feature = "no-cuda")]
+    fn step_cuda(&mut self, _dt: f32) -> Result<()> {
+        anyhow::bail!("built with the no-cuda feature");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    fn step_cuda(&mut self, dt: f32) -> Result<()> {
+        let (Some(ptx), Some((current, next)), Some(force)) =
+            (&self.ptx, &mut self.d_boids, &mut self.d_force)
+        else {
+            anyhow::bail!("3D boids kernel not loaded");
+        };
+        if !self.device_newer {
+            current
+                .copy_from(&self.boids[..])
+                .context("Failed to upload 3D boids")?;
+        }
+        let ptx_c = CString::new(ptx.as_str()).unwrap();
+        let module = Module::load_from_string(&ptx_c)
+            .context("Failed to load boids PTX")?;
+        let func = module
+            .get_function(&CString::new("boids_step_3d").unwrap())
+            .context("Failed to get boids_step_3d")?;
+        let stream = Stream::new(StreamFlags::DEFAULT, None)
+            .context("Failed to create stream")?;
+
+        let n = self.boids.len();
+        let block = (128u32, 1u32, 1u32);
+        let grid = ((n as u32).div_ceil(block.0), 1u32, 1u32);
+        let rules = &self.rules;
+        unsafe {
+            launch!(
+                func<<<grid, block, 0, stream>>>(
+                    n as i32,
+                    dt,
+                    rules.separation_radius,
+                    rules.alignment_radius,
+                    rules.cohesion_radius,
+                    rules.max_speed,
+                    rules.max_force,
+                    current.as_device_ptr(),
+                    next.as_device_ptr(),
+                    force.as_device_ptr()
+                )
+            )
+            .context("boids_step_3d launch failed")?;
+        }
+        stream
+            .synchronize()
+            .context("boids_step_3d sync failed")?;
+        std::mem::swap(current, next);
+        self.device_newer = true;
+        self.last_used_cuda = true;
+        Ok(())
+    }
+
+    pub fn step(&mut self, dt: f32) -> Result<Accelerator> {
+        if !self.force_cpu && !self.boids.is_empty() && self.ptx.is_some() && self.d_boids.is_some() {
+            match self.step_cuda(dt) {
+                Ok(()) => {
+                    self.cuda_failures.succeeded();
+                    return Ok(Accelerator::Cuda);
+                }
+                Err(e) => {
+                    tracing::warn!("3D boids CUDA step failed, running this step on the CPU: {:#}", e);
+                    if self.cuda_failures.give_up(&e) {
+                        // Drop the kernel so later steps stay on the CPU path
+                        tracing::warn!("Giving up on the 3D boids kernel");
+                        self.ptx = None;
+                    }
+                }
+            }
+        }
+
+        // CPU fallback
+        self.sync_from_device()?;
+        for i in 0..self.boids.len() {
+            let (v, force) = steer(&self.boids, i, &self.rules, dt);
+            let b = &mut self.next[i];
+            *b = self.boids[i];
+            b.vx = v[0];
+            b.vy = v[1];
+            b.vz = v[2];
+            b.x = wrap_unit(b.x + b.vx * dt);
+            b.y = wrap_unit(b.y + b.vy * dt);
+            b.z = wrap_unit(b.z + b.vz * dt);
+            self.forces[i] = force;
+        }
+        std::mem::swap(&mut self.boids, &mut self.next);
+        self.last_used_cuda = false;
+        Ok(Accelerator::Cpu)
+    }
+
+    fn sync_from_device(&mut self) -> Result<()> {
+        if !self.device_newer {
+            return Ok(());
+        }
+        if let (Some((current, _)), Some(force)) = (&self.d_boids, &self.d_force) {
+            current
+                .copy_to(&mut self.boids[..])
+                .map_err(|e| anyhow::anyhow!("Failed to copy 3D boids: {:?}", e))?;
+            force
+                .copy_to(&mut self.forces[..])
+                .map_err(|e| anyhow::anyhow!("Failed to copy 3D forces: {:?}", e))?;
+        }
+        self.device_newer = false;
+        Ok(())
+    }
+
+    /// `FLOATS_PER_BOID` floats per boid: x, y, z, vx, vy, vz
+    pub fn get_boids(&mut self) -> Result<Vec<f32>> {
+        self.sync_from_device()?;
+        Ok(self
+            .boids
+            .iter()
+            .flat_map(|b| [b.x, b.y, b.z, b.vx, b.vy, b.vz])
+            .collect())
+    }
+
+    /// Steering force magnitude per boid from the last step
+    pub fn force_magnitudes(&mut self) -> Result<Vec<f32>> {
+        self.sync_from_device()?;
+        Ok(self.forces.clone())
+    }
+
+    pub fn used_cuda(&self) -> bool {
+        self.last_used_cuda
+    }
+
+    /// PTX `step` launches from; `None` once the simulation has fallen back to the CPU
+    pub fn ptx(&self) -> Option<&str> {
+        self.ptx.as_deref()
+    }
+
+    /// Where the last `step` actually ran
+    pub fn accelerator(&self) -> Accelerator {
+        Accelerator::from_used_cuda(self.last_used_cuda)
+    }
+
+    /// Force the CPU path even when the kernel is available
+    pub fn set_force_cpu(&mut self, force_cpu: bool) {
+        self.force_cpu = force_cpu;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-cuda"))]
+    use crate::cuda::init_cuda_in_thread;
+
+    #[test]
+    fn test_boids_stay_in_unit_cube() {
+        let mut sim = Boids3DSimulation::new_host_seeded(200, 3).unwrap();
+        for _ in 0..50 {
+            sim.step(0.5).unwrap();
+        }
+        let boids = sim.get_boids().unwrap();
+        assert_eq!(boids.len(), 200 * FLOATS_PER_BOID);
+        for b in boids.chunks_exact(FLOATS_PER_BOID) {
+            assert!(b[..3].iter().all(|&p| (0.0..1.0).contains(&p)), "Escaped the cube: {:?}", b);
+            let speed = (b[3] * b[3] + b[4] * b[4] + b[5] * b[5]).sqrt();
+            assert!(speed <= 0.05 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_pair_separates_along_z() {
+        let mut sim = Boids3DSimulation::new_host_seeded(0, 1).unwrap();
+        let boid = |z| Boid3D { x: 0.5, y: 0.5, z, mass: 1.0, ..Default::default() };
+        sim.boids = vec![boid(0.49), boid(0.51)];
+        sim.next = sim.boids.clone();
+        sim.forces = vec![0.0; 2];
+        sim.step(0.016).unwrap();
+        let boids = sim.get_boids().unwrap();
+        assert!(boids[5] < 0.0 && boids[11] > 0.0, "Separation should push apart in z");
+        assert_eq!((boids[3], boids[4]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_rejects_2d_only_params() {
+        let rules = Rules3D::default();
+        let jitter = BoidsParams { jitter: Some(0.1), ..Default::default() };
+        assert!(rules.with_params(&jitter).is_err());
+        let radii = BoidsParams { cohesion_radius: Some(0.2), ..Default::default() };
+        let (rules, warnings) = rules.with_params(&radii).unwrap();
+        assert_eq!(rules.cohesion_radius, 0.2);
+        assert!(warnings.is_empty());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_cuda_matches_cpu() {
+        init_cuda_in_thread().expect("Failed to init CUDA in test thread");
+        let _ctx = rustacuda::prelude::Context::create_and_push(
+            rustacuda::prelude::ContextFlags::MAP_HOST
+                | rustacuda::prelude::ContextFlags::SCHED_AUTO,
+            rustacuda::prelude::Device::get_device(0).expect("Failed to get device"),
+        )
+        .expect("Failed to create context");
+        let context = Arc::new(CudaContext::new().expect("Failed to create CUDA context"));
+
+        let mut gpu = Boids3DSimulation::new(&context, 300).unwrap();
+        let mut cpu = Boids3DSimulation::new_host_seeded(0, 0).unwrap();
+        cpu.boids = gpu.boids.clone();
+        cpu.next = gpu.boids.clone();
+        cpu.forces = vec![0.0; 300];
+        for _ in 0..5 {
+            gpu.step(0.016).unwrap();
+            cpu.step(0.016).unwrap();
+        }
+        if !gpu.used_cuda() {
+            // Built without nvcc; nothing to compare
+            return;
+        }
+        let (g, c) = (gpu.get_boids().unwrap(), cpu.get_boids().unwrap());
+        for (a, b) in g.iter().zip(&c) {
+            assert!((a - b).abs() < 1e-4, "GPU {} vs CPU {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+// Versioned binary snapshot of a boids flock and its parameters, for
+// `BoidsSimulation::serialize_state` / `restore_state`. All values are little-endian:
+//
+//   header     MAGIC | version u16 | num_species u8 | reserved u8 | num_boids u32
+//              | num_obstacles u16 | num_attractors u16                      (HEADER_BYTES)
+//   params     separation, alignment, cohesion radius f32 | max_speed_range f32 x2
+//              | max_force f32 | jitter f32 | world_size f32 | jitter_seed u32 | step_index u32
+//              | next_id u32 | topological_k u32 | num_predators u32 | seed u64
+//              | neighbor_mode u8 | boundary u8 | radius_check u8
+//              | continuous_collision u8 | genetics u8                      (PARAMS_BYTES)
+//   masses     f32 per species
+//   obstacles  kind u8 (OBSTACLE_CIRCLE / OBSTACLE_WALL) + 5 f32 each       (OBSTACLE_BYTES)
+//   attractors x, y, strength, radius f32 each                             (ATTRACTOR_BYTES)
+//   boids      x, y, vx, vy, mass, max_speed, cohesion gene, hue gene f32
+//              | id u32 | species u8                                       (BOID_BYTES)
+//
+// Not saved: the emitter, the auto-tuner and stasis detector, progress towards the next
+// breeding round, and the RNG's position in its stream (a restored flock draws from a
+// fresh generator on `seed`).
+use super::attractors::Attractor;
+use super::boids::{Boid, BoundaryMode, NeighborMode, RadiusCheck};
+use super::genetics::Genes;
+use super::obstacles::Obstacle;
+use anyhow::Result;
+
+pub const MAGIC: &[u8; 8] = b"BOIDCKPT";
+/// Bumped whenever the layout changes; older versions are rejected, not migrated
+pub const VERSION: u16 = 3;
+pub const HEADER_BYTES: usize = 20;
+pub const PARAMS_BYTES: usize = 65;
+pub const OBSTACLE_BYTES: usize = 21;
+pub const ATTRACTOR_BYTES: usize = 16;
+pub const BOID_BYTES: usize = 37;
+pub const OBSTACLE_CIRCLE: u8 = 0;
+pub const OBSTACLE_WALL: u8 = 1;
+
+/// Everything a checkpoint carries, as plain data
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlockState {
+    pub separation_radius: f32,
+    pub alignment_radius: f32,
+    pub cohesion_radius: f32,
+    pub max_speed_range: (f32, f32),
+    pub max_force: f32,
+    pub jitter: f32,
+    pub world_size: f32,
+    pub jitter_seed: u32,
+    pub step_index: u32,
+    pub next_id: u32,
+    pub topological_k: u32,
+    pub num_predators: u32,
+    pub seed: u64,
+    pub neighbor_mode: NeighborMode,
+    pub boundary: BoundaryMode,
+    pub radius_check: RadiusCheck,
+    pub continuous_collision: bool,
+    pub genetics: bool,
+    pub species_masses: Vec<f32>,
+    pub obstacles: Vec<Obstacle>,
+    pub attractors: Vec<Attractor>,
+    pub boids: Vec<Boid>,
+}
+
+fn neighbor_mode_code(mode: NeighborMode) -> u8 {
+    match mode {
+        NeighborMode::Metric => 0,
+        NeighborMode::Topological => 1,
+    }
+}
+
+fn boundary_code(mode: BoundaryMode) -> u8 {
+    match mode {
+        BoundaryMode::Wrap => 0,
+        BoundaryMode::Bounce => 1,
+        BoundaryMode::Open => 2,
+    }
+}
+
+fn radius_check_code(check: RadiusCheck) -> u8 {
+    match check {
+        RadiusCheck::Warn => 0,
+        RadiusCheck::Error => 1,
+    }
+}
+
+impl FlockState {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            HEADER_BYTES
+                + PARAMS_BYTES
+                + 4 * self.species_masses.len()
+                + OBSTACLE_BYTES * self.obstacles.len()
+                + ATTRACTOR_BYTES * self.attractors.len()
+                + BOID_BYTES * self.boids.len(),
+        );
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.push(self.species_masses.len() as u8);
+        out.push(0);
+        out.extend_from_slice(&(self.boids.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(self.obstacles.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.attractors.len() as u16).to_le_bytes());
+
+        let floats = [
+            self.separation_radius,
+            self.alignment_radius,
+            self.cohesion_radius,
+            self.max_speed_range.0,
+            self.max_speed_range.1,
+            self.max_force,
+            self.jitter,
+            self.world_size,
+        ];
+        floats.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        let ints = [self.jitter_seed, self.step_index, self.next_id, self.topological_k, self.num_predators];
+        ints.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.push(neighbor_mode_code(self.neighbor_mode));
+        out.push(boundary_code(self.boundary));
+        out.push(radius_check_code(self.radius_check));
+        out.push(self.continuous_collision as u8);
+        out.push(self.genetics as u8);
+
+        self.species_masses.iter().for_each(|m| out.extend_from_slice(&m.to_le_bytes()));
+        for obstacle in &self.obstacles {
+            let (kind, values) = match *obstacle {
+                Obstacle::Circle { x, y, radius } => (OBSTACLE_CIRCLE, [x, y, radius, 0.0, 0.0]),
+                Obstacle::Wall { x0, y0, x1, y1, thickness } => (OBSTACLE_WALL, [x0, y0, x1, y1, thickness]),
+            };
+            out.push(kind);
+            values.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        }
+        for a in &self.attractors {
+            [a.x, a.y, a.strength, a.radius].iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        }
+        for b in &self.boids {
+            [b.x, b.y, b.vx, b.vy, b.mass, b.max_speed, b.genes.cohesion, b.genes.hue]
+                .iter()
+                .for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+            out.extend_from_slice(&b.id.to_le_bytes());
+            out.push(b.species);
+        }
+        out
+    }
+
+    /// Parse a checkpoint; only the layout is checked here, not whether the values are sensible
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_BYTES || &bytes[..MAGIC.len()] != MAGIC {
+            anyhow::bail!("not a boids checkpoint");
+        }
+        let mut r = Reader { bytes, pos: MAGIC.len() };
+        let version = r.u16();
+        if version != VERSION {
+            anyhow::bail!("unsupported checkpoint version {} (expected {})", version, VERSION);
+        }
+        let num_species = r.u8() as usize;
+        r.u8();
+        let num_boids = r.u32() as usize;
+        let num_obstacles = r.u16() as usize;
+        let num_attractors = r.u16() as usize;
+        let expected = HEADER_BYTES
+            + PARAMS_BYTES
+            + 4 * num_species
+            + OBSTACLE_BYTES * num_obstacles
+            + ATTRACTOR_BYTES * num_attractors
+            + BOID_BYTES * num_boids;
+        if bytes.len() != expected {
+            anyhow::bail!("checkpoint is {} bytes, its header implies {}", bytes.len(), expected);
+        }
+
+        let separation_radius = r.f32();
+        let alignment_radius = r.f32();
+        let cohesion_radius = r.f32();
+        let max_speed_range = (r.f32(), r.f32());
+        let max_force = r.f32();
+        let jitter = r.f32();
+        let world_size = r.f32();
+        let (jitter_seed, step_index, next_id, topological_k, num_predators) =
+            (r.u32(), r.u32(), r.u32(), r.u32(), r.u32());
+        let seed = r.u64();
+        let neighbor_mode = match r.u8() {
+            0 => NeighborMode::Metric,
+            1 => NeighborMode::Topological,
+            code => anyhow::bail!("unknown neighbor mode {}", code),
+        };
+        let boundary = match r.u8() {
+            0 => BoundaryMode::Wrap,
+            1 => BoundaryMode::Bounce,
+            2 => BoundaryMode::Open,
+            code => anyhow::bail!("unknown boundary mode {}", code),
+        };
+        let radius_check = match r.u8() {
+            0 => RadiusCheck::Warn,
+            1 => RadiusCheck::Error,
+            code => anyhow::bail!("unknown radius check {}", code),
+        };
+        let continuous_collision = r.u8() != 0;
+        let genetics = r.u8() != 0;
+
+        let species_masses = (0..num_species).map(|_| r.f32()).collect();
+        let obstacles = (0..num_obstacles)
+            .map(|_| {
+                let kind = r.u8();
+                let v = [r.f32(), r.f32(), r.f32(), r.f32(), r.f32()];
+                match kind {
+                    OBSTACLE_CIRCLE => Ok(Obstacle::Circle { x: v[0], y: v[1], radius: v[2] }),
+                    OBSTACLE_WALL => Ok(Obstacle::Wall { x0: v[0], y0: v[1], x1: v[2], y1: v[3], thickness: v[4] }),
+                    code => Err(anyhow::anyhow!("unknown obstacle kind {}", code)),
+                }
+            })
+            .collect::<Result<_>>()?;
+        let attractors = (0..num_attractors)
+            .map(|_| Attractor { x: r.f32(), y: r.f32(), strength: r.f32(), radius: r.f32() })
+            .collect();
+        let boids = (0..num_boids)
+            .map(|_| Boid {
+                x: r.f32(),
+                y: r.f32(),
+                vx: r.f32(),
+                vy: r.f32(),
+                mass: r.f32(),
+                max_speed: r.f32(),
+                genes: Genes { cohesion: r.f32(), hue: r.f32() },
+                id: r.u32(),
+                species: r.u8(),
+            })
+            .collect();
+
+        Ok(Self {
+            separation_radius,
+            alignment_radius,
+            cohesion_radius,
+            max_speed_range,
+            max_force,
+            jitter,
+            world_size,
+            jitter_seed,
+            step_index,
+            next_id,
+            topological_k,
+            num_predators,
+            seed,
+            neighbor_mode,
+            boundary,
+            radius_check,
+            continuous_collision,
+            genetics,
+            species_masses,
+            obstacles,
+            attractors,
+            boids,
+        })
+    }
+}
+
+// Sequential little-endian reads; `decode` checks the length up front
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let out = self.bytes[self.pos..self.pos + N].try_into().unwrap();
+        self.pos += N;
+        out
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> FlockState {
+        FlockState {
+            separation_radius: 0.05,
+            alignment_radius: 0.1,
+            cohesion_radius: 0.15,
+            max_speed_range: (0.03, 0.05),
+            max_force: 0.01,
+            jitter: 0.1,
+            world_size: 2.0,
+            jitter_seed: 7,
+            step_index: 42,
+            next_id: 3,
+            topological_k: 7,
+            num_predators: 1,
+            seed: u64::MAX - 1,
+            neighbor_mode: NeighborMode::Topological,
+            boundary: BoundaryMode::Bounce,
+            radius_check: RadiusCheck::Error,
+            continuous_collision: true,
+            genetics: true,
+            species_masses: vec![1.0, 2.0],
+            obstacles: vec![
+                Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 },
+                Obstacle::Wall { x0: 0.1, y0: 0.2, x1: 0.3, y1: 0.4, thickness: 0.01 },
+            ],
+            attractors: vec![Attractor { x: 0.2, y: 0.8, strength: -1.0, radius: 0.3 }],
+            boids: vec![
+                Boid { x: 0.1, y: 0.2, vx: 0.01, vy: -0.02, id: 0, species: 1, ..Boid::default() },
+                Boid {
+                    x: 0.9,
+                    y: 0.4,
+                    mass: 2.0,
+                    max_speed: 0.04,
+                    genes: Genes { cohesion: 2.5, hue: 0.1 },
+                    id: 2,
+                    species: 255,
+                    ..Boid::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let state = sample();
+        let bytes = state.encode();
+        assert_eq!(
+            bytes.len(),
+            HEADER_BYTES + PARAMS_BYTES + 2 * 4 + 2 * OBSTACLE_BYTES + ATTRACTOR_BYTES + 2 * BOID_BYTES
+        );
+        assert_eq!(FlockState::decode(&bytes).unwrap(), state);
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_input() {
+        let bytes = sample().encode();
+        assert!(FlockState::decode(&bytes[..bytes.len() - 1]).is_err(), "Truncated");
+        assert!(FlockState::decode(b"BOIDREC1").is_err(), "Wrong magic");
+        let mut newer = bytes.clone();
+        newer[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(FlockState::decode(&newer).is_err(), "Unknown version");
+    }
+}
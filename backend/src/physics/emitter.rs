@@ -0,0 +1,101 @@
+// Continuous boid emitter
+// Spawns boids at a point on a fixed schedule for fountain-like flocks
+use super::boids::{Boid, MAX_NUM_BOIDS};
+use super::rng::SimRng;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Highest spawn rate, in boids per second of simulated time
+pub const MAX_RATE: f32 = MAX_NUM_BOIDS as f32;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct EmitterConfig {
+    pub x: f32,
+    pub y: f32,
+    /// Boids spawned per second of simulated time
+    pub rate: f32,
+    /// Initial velocity of each spawned boid
+    #[serde(default)]
+    pub vx: f32,
+    #[serde(default)]
+    pub vy: f32,
+    /// Random velocity spread added to each spawned boid
+    #[serde(default)]
+    pub spread: f32,
+    /// Population cap; the oldest boids are removed to make room
+    pub max_population: usize,
+    /// Species of spawned boids (random when omitted)
+    #[serde(default)]
+    pub species: Option<u8>,
+}
+
+impl EmitterConfig {
+    /// Check the config for a simulation with `num_species` species
+    pub fn validate(&self, num_species: u8) -> Result<()> {
+        if !(self.x.is_finite() && self.y.is_finite() && self.vx.is_finite() && self.vy.is_finite()) {
+            anyhow::bail!("emitter position and velocity must be finite");
+        }
+        if !(self.rate > 0.0 && self.rate <= MAX_RATE) {
+            anyhow::bail!("emitter rate must be in (0, {}], got {}", MAX_RATE, self.rate);
+        }
+        if !self.spread.is_finite() || self.spread < 0.0 {
+            anyhow::bail!("emitter spread must be non-negative, got {}", self.spread);
+        }
+        if self.max_population == 0 || self.max_population > MAX_NUM_BOIDS {
+            anyhow::bail!(
+                "emitter max_population must be 1..={}, got {}",
+                MAX_NUM_BOIDS,
+                self.max_population
+            );
+        }
+        if let Some(species) = self.species {
+            if species >= num_species {
+                anyhow::bail!("emitter species must be below {}, got {}", num_species, species);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Emitter {
+    pub config: EmitterConfig,
+    // Fractional boids carried over between steps
+    pending: f32,
+}
+
+impl Emitter {
+    pub fn new(config: EmitterConfig) -> Self {
+        Self { config, pending: 0.0 }
+    }
+
+    /// Boids due after `dt` seconds, one species per entry of `species_masses`, never more
+    /// than `max_population` at once. Ids are assigned by the simulation.
+    pub fn emit(&mut self, dt: f32, species_masses: &[f32], rng: &mut SimRng) -> Vec<Boid> {
+        self.pending += self.config.rate.min(MAX_RATE) * dt;
+        let due = self.pending.floor();
+        self.pending -= due;
+        // Anything past the cap would be evicted straight away, so don't build it
+        let count = due.min(self.config.max_population.min(MAX_NUM_BOIDS) as f32);
+
+        let c = &self.config;
+        (0..count as usize)
+            .map(|_| {
+                let species = c.species.unwrap_or_else(|| rng.below(species_masses.len() as u32) as u8);
+                let (jx, jy) = if c.spread > 0.0 {
+                    (rng.range_f32(-c.spread, c.spread), rng.range_f32(-c.spread, c.spread))
+                } else {
+                    (0.0, 0.0)
+                };
+                Boid {
+                    x: c.x,
+                    y: c.y,
+                    vx: c.vx + jx,
+                    vy: c.vy + jy,
+                    mass: species_masses[species as usize],
+                    species,
+                    ..Boid::default()
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,210 @@
+// Boid genetics: boids in dense clusters occasionally reproduce, replacing a
+// distant boid with a mutated copy of themselves, so the flock size never changes.
+// Each boid's speed cap is heritable alongside the genes below.
+use super::boids::Boid;
+use super::predators;
+use super::rng::SimRng;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::memory::DeviceCopy;
+use serde::Serialize;
+
+/// Simulated seconds between breeding rounds
+pub const BREED_INTERVAL: f32 = 0.5;
+/// Would-be parents drawn per round
+const PARENTS_PER_ROUND: usize = 8;
+/// Same-species neighbours within the cohesion radius that make a cluster dense enough to breed
+pub const DENSE_NEIGHBORS: usize = 6;
+/// Boids sampled when looking for a distant one to replace; the farthest is chosen
+const VICTIM_SAMPLES: usize = 8;
+/// Largest relative change a mutation makes to a gene or speed cap
+pub const MUTATION_RATE: f32 = 0.1;
+pub const COHESION_GENE_RANGE: (f32, f32) = (0.0, 3.0);
+/// How far from its parent a child is placed on each axis, as a fraction of the world size
+const CHILD_OFFSET: f32 = 0.005;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Genes {
+    /// Multiplier on the cohesion force
+    pub cohesion: f32,
+    /// Display colour in `0..=1`; no effect on steering
+    pub hue: f32,
+}
+
+impl Default for Genes {
+    fn default() -> Self {
+        Self { cohesion: 1.0, hue: 0.5 }
+    }
+}
+
+#[cfg(not(feature = "no-cuda"))]
+unsafe impl DeviceCopy for Genes {}
+
+impl Genes {
+    fn mutated(self, rng: &mut SimRng) -> Self {
+        let (lo, hi) = COHESION_GENE_RANGE;
+        Self {
+            cohesion: mutate(rng, self.cohesion).clamp(lo, hi),
+            hue: (self.hue + rng.range_f32(-MUTATION_RATE, MUTATION_RATE)).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Whether `genes` are within the ranges breeding keeps them in
+pub fn valid_genes(genes: &Genes) -> bool {
+    let (lo, hi) = COHESION_GENE_RANGE;
+    (lo..=hi).contains(&genes.cohesion) && (0.0..=1.0).contains(&genes.hue)
+}
+
+fn mutate(rng: &mut SimRng, value: f32) -> f32 {
+    value * (1.0 + rng.range_f32(-MUTATION_RATE, MUTATION_RATE))
+}
+
+/// Same-species boids within `radius` of `boids[i]`, not counting itself
+fn neighbor_count(boids: &[Boid], i: usize, radius: f32) -> usize {
+    let bi = &boids[i];
+    let r2 = radius * radius;
+    boids
+        .iter()
+        .enumerate()
+        .filter(|&(j, bj)| j != i && bj.species == bi.species)
+        .filter(|(_, bj)| (bi.x - bj.x).powi(2) + (bi.y - bj.y).powi(2) < r2)
+        .count()
+}
+
+/// One breeding round: each sampled boid with at least `DENSE_NEIGHBORS` neighbours
+/// within `radius` replaces the farthest of a few random boids with its mutated child.
+/// Children take fresh ids from `next_id`, speed caps within `max_speed_range` and stay
+/// inside the `world_size` square. Predators neither breed nor get replaced. Returns the
+/// number of births.
+pub fn breed(
+    boids: &mut [Boid],
+    radius: f32,
+    max_speed_range: (f32, f32),
+    world_size: f32,
+    rng: &mut SimRng,
+    next_id: &mut u32,
+) -> usize {
+    let n = boids.len();
+    if n < 2 {
+        return 0;
+    }
+    let offset = CHILD_OFFSET * world_size;
+    let mut births = 0;
+    for _ in 0..PARENTS_PER_ROUND {
+        let parent_index = rng.below(n as u32) as usize;
+        let parent = boids[parent_index];
+        if predators::is_predator(&parent) || neighbor_count(boids, parent_index, radius) < DENSE_NEIGHBORS {
+            continue;
+        }
+        let victim = (0..VICTIM_SAMPLES)
+            .map(|_| rng.below(n as u32) as usize)
+            .filter(|&j| j != parent_index && !predators::is_predator(&boids[j]))
+            .max_by(|&a, &b| {
+                let dist = |j: usize| (boids[j].x - parent.x).powi(2) + (boids[j].y - parent.y).powi(2);
+                dist(a).total_cmp(&dist(b))
+            });
+        let Some(victim) = victim else {
+            continue;
+        };
+        let (min_speed, max_speed) = max_speed_range;
+        boids[victim] = Boid {
+            x: (parent.x + rng.range_f32(-offset, offset)).clamp(0.0, world_size),
+            y: (parent.y + rng.range_f32(-offset, offset)).clamp(0.0, world_size),
+            max_speed: mutate(rng, parent.max_speed).clamp(min_speed, max_speed),
+            id: *next_id,
+            genes: parent.genes.mutated(rng),
+            ..parent
+        };
+        *next_id = next_id.wrapping_add(1);
+        births += 1;
+    }
+    births
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GeneSummary {
+    pub mean: f32,
+    pub std_dev: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl GeneSummary {
+    fn of(values: impl Iterator<Item = f32> + Clone) -> Self {
+        let n = values.clone().count().max(1) as f64;
+        let mean = values.clone().map(f64::from).sum::<f64>() / n;
+        let variance = values.clone().map(|v| (v as f64 - mean).powi(2)).sum::<f64>() / n;
+        Self {
+            mean: mean as f32,
+            std_dev: variance.sqrt() as f32,
+            min: values.clone().fold(f32::INFINITY, f32::min),
+            max: values.fold(f32::NEG_INFINITY, f32::max),
+        }
+    }
+}
+
+/// Population-wide gene distribution, excluding predators
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GeneStats {
+    /// Children born since genetics was enabled
+    pub births: u64,
+    pub cohesion: GeneSummary,
+    pub max_speed: GeneSummary,
+    pub hue: GeneSummary,
+}
+
+pub fn summarize(boids: &[Boid], births: u64) -> GeneStats {
+    let prey = || boids.iter().filter(|b| !predators::is_predator(b));
+    GeneStats {
+        births,
+        cohesion: GeneSummary::of(prey().map(|b| b.genes.cohesion)),
+        max_speed: GeneSummary::of(prey().map(|b| b.max_speed)),
+        hue: GeneSummary::of(prey().map(|b| b.genes.hue)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster_and_stragglers() -> Vec<Boid> {
+        let mut boids: Vec<Boid> = (0..20)
+            .map(|i| Boid { x: 0.5 + i as f32 * 0.001, y: 0.5, id: i, ..Boid::default() })
+            .collect();
+        boids.extend((20..40).map(|i| Boid { x: 0.05, y: 0.05 + (i - 20) as f32 * 0.04, id: i, ..Boid::default() }));
+        boids
+    }
+
+    #[test]
+    fn test_dense_clusters_breed_into_distant_slots() {
+        let mut boids = cluster_and_stragglers();
+        let mut rng = SimRng::new(3);
+        let mut next_id = 40;
+        let mut births = 0;
+        for _ in 0..20 {
+            births += breed(&mut boids, 0.05, (0.02, 0.08), 1.0, &mut rng, &mut next_id);
+        }
+        assert!(births > 0, "a tight cluster should breed");
+        assert_eq!(boids.len(), 40);
+        assert_eq!(next_id, 40 + births as u32);
+        let mut ids: Vec<u32> = boids.iter().map(|b| b.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 40, "children get fresh ids");
+        let stats = summarize(&boids, births as u64);
+        assert!(stats.cohesion.std_dev > 0.0, "children should mutate");
+        assert!(stats.cohesion.min >= COHESION_GENE_RANGE.0 && stats.cohesion.max <= COHESION_GENE_RANGE.1);
+        assert!(stats.max_speed.min >= 0.02 && stats.max_speed.max <= 0.08);
+    }
+
+    #[test]
+    fn test_sparse_flock_does_not_breed() {
+        let mut boids: Vec<Boid> = (0..40)
+            .map(|i| Boid { x: (i % 8) as f32 * 0.12, y: (i / 8) as f32 * 0.2, ..Boid::default() })
+            .collect();
+        let mut next_id = 40;
+        assert_eq!(breed(&mut boids, 0.05, (0.05, 0.05), 1.0, &mut SimRng::new(1), &mut next_id), 0);
+        assert_eq!(next_id, 40);
+    }
+}
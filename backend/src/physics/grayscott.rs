@@ -1,84 +1,29 @@
 // Gray-Scott reaction-diffusion simulation
 // Based on Turing pattern equations
+use super::accelerator::Accelerator;
+#[cfg(feature = "cuda-kernel")]
+use super::accelerator::CudaFailures;
+use super::rng::SimRng;
 use crate::cuda::CudaContext;
 use anyhow::Result;
+use anyhow::Context as AnyhowContext;
+use serde::Deserialize;
+#[cfg(feature = "cuda-kernel")]
 use rustacuda::prelude::*;
-use rustacuda::memory::DeviceBuffer;
+use super::storage::{SimBuffer, Storage};
+use super::substeps;
+#[cfg(feature = "cuda-kernel")]
+use super::kernel_cache::{self, NvrtcKernel};
+#[cfg(feature = "cuda-kernel")]
+use rustacuda::launch;
 #[cfg(feature = "cuda-kernel")]
-use nvrtc::NvrtcProgram;
+use std::ffi::CString;
 use std::sync::Arc;
 
-pub struct GrayScottSimulation {
-    #[allow(dead_code)]
-    context: Arc<CudaContext>,
-    width: usize,
-    height: usize,
-    u_field: DeviceBuffer<f32>,  // Concentration field u
-    v_field: DeviceBuffer<f32>,  // Catalyst field v
-    #[allow(dead_code)]
-    u_temp: DeviceBuffer<f32>,    // Temporary buffer for u
-    #[allow(dead_code)]
-    v_temp: DeviceBuffer<f32>,   // Temporary buffer for v
-    // Gray-Scott parameters
-    du: f32,  // Diffusion rate for u
-    dv: f32,  // Diffusion rate for v
-    f: f32,   // Feed rate
-    k: f32,   // Kill rate
-    // CUDA kernel PTX code
-    #[cfg(feature = "cuda-kernel")]
-    ptx: String,
-}
-
-impl GrayScottSimulation {
-    pub fn new(context: &Arc<CudaContext>, width: usize, height: usize) -> Result<Self> {
-        // Context should already be initialized by caller
-        
-        let size = width * height;
-        
-        // Initialize u field (mostly 1.0)
-        let mut u_host = vec![1.0f32; size];
-        // Add some initial pattern in center
-        let center_x = width / 2;
-        let center_y = height / 2;
-        for y in 0..height {
-            for x in 0..width {
-                let dx = x as i32 - center_x as i32;
-                let dy = y as i32 - center_y as i32;
-                let dist_sq = (dx * dx + dy * dy) as f32;
-                if dist_sq < 100.0 {
-                    let idx = y * width + x;
-                    u_host[idx] = 0.5;
-                }
-            }
-        }
-        
-        // Initialize v field (mostly 0.0)
-        let mut v_host = vec![0.0f32; size];
-        // Add catalyst in center
-        for y in 0..height {
-            for x in 0..width {
-                let dx = x as i32 - center_x as i32;
-                let dy = y as i32 - center_y as i32;
-                let dist_sq = (dx * dx + dy * dy) as f32;
-                if dist_sq < 25.0 {
-                    let idx = y * width + x;
-                    v_host[idx] = 0.25;
-                }
-            }
-        }
-        
-        let u_field = DeviceBuffer::from_slice(&u_host)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate u field: {:?}", e))?;
-        let v_field = DeviceBuffer::from_slice(&v_host)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate v field: {:?}", e))?;
-        let u_temp = DeviceBuffer::from_slice(&u_host)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate u_temp: {:?}", e))?;
-        let v_temp = DeviceBuffer::from_slice(&v_host)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate v_temp: {:?}", e))?;
-        
-        // Compile CUDA kernel at runtime using NVRTC (when enabled)
-        #[cfg(feature = "cuda-kernel")]
-        let src = r#"
+#[cfg(feature = "cuda-kernel")]
+pub const GRAY_SCOTT_KERNEL: NvrtcKernel = NvrtcKernel {
+    name: "gray_scott_step",
+    source: r#"
         extern "C" __global__ void gray_scott_step(
             const int width, const int height, const float du, const float dv,
             const float f, const float k, const float dt,
@@ -119,17 +64,244 @@ impl GrayScottSimulation {
             u_out[idx] = un;
             v_out[idx] = vn;
         }
-        "#;
+        "#,
+};
+
+/// Grid side used when the request doesn't give one
+pub const DEFAULT_GRID_SIZE: usize = 512;
+/// Largest accepted grid width or height
+pub const MAX_GRID_SIZE: usize = 4096;
+/// Largest accepted width × height; the four f32 fields then take 64 MiB
+pub const MAX_GRID_CELLS: usize = 2048 * 2048;
+/// Feed and kill rates outside this range just decay to a uniform field
+pub const MAX_RATE: f32 = 0.1;
+/// Diffusion rates above this make the explicit step unstable at dt = 0.016
+pub const MAX_DIFFUSION: f32 = 1.0;
 
+/// Reaction, grid and output options accepted by the Gray-Scott endpoint
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct GrayScottParams {
+    /// Feed rate (default 0.055)
+    pub f: Option<f32>,
+    /// Kill rate (default 0.062)
+    pub k: Option<f32>,
+    /// Diffusion rate of u (default 0.16)
+    pub du: Option<f32>,
+    /// Diffusion rate of v (default 0.08)
+    pub dv: Option<f32>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    /// Rescale the returned field from its actual min/max to [0, 1]
+    pub normalize: Option<bool>,
+    /// Rescale from this explicit (min, max) instead, clamping values outside it
+    pub range: Option<(f32, f32)>,
+}
+
+impl GrayScottParams {
+    pub fn validate(&self) -> Result<()> {
+        for (name, rate) in [("f", self.f), ("k", self.k)] {
+            if let Some(rate) = rate {
+                if !(0.0..=MAX_RATE).contains(&rate) {
+                    anyhow::bail!("{} must be in 0..={}, got {}", name, MAX_RATE, rate);
+                }
+            }
+        }
+        for (name, rate) in [("du", self.du), ("dv", self.dv)] {
+            if let Some(rate) = rate {
+                if !(rate > 0.0 && rate <= MAX_DIFFUSION) {
+                    anyhow::bail!("{} must be in (0, {}], got {}", name, MAX_DIFFUSION, rate);
+                }
+            }
+        }
+        let (width, height) = self.size();
+        check_grid_size(width, height)?;
+        if let Some((min, max)) = self.range {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                anyhow::bail!("range must be finite with min < max, got ({}, {})", min, max);
+            }
+        }
+        Ok(())
+    }
+
+    /// Grid (width, height) to simulate
+    pub fn size(&self) -> (usize, usize) {
+        (
+            self.width.unwrap_or(DEFAULT_GRID_SIZE),
+            self.height.unwrap_or(DEFAULT_GRID_SIZE),
+        )
+    }
+
+    /// Range the field should be rescaled from, if any
+    pub fn output_range(&self, field: &[f32]) -> Option<(f32, f32)> {
+        if self.range.is_some() {
+            return self.range;
+        }
+        if self.normalize != Some(true) {
+            return None;
+        }
+        let (min, max) = field
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        Some((min, max))
+    }
+}
+
+/// Each side within `MAX_GRID_SIZE` and the whole grid within `MAX_GRID_CELLS`
+pub fn check_grid_size(width: usize, height: usize) -> Result<()> {
+    for (name, size) in [("width", width), ("height", height)] {
+        if !(1..=MAX_GRID_SIZE).contains(&size) {
+            anyhow::bail!("{} must be 1..={}, got {}", name, MAX_GRID_SIZE, size);
+        }
+    }
+    if width * height > MAX_GRID_CELLS {
+        anyhow::bail!(
+            "width * height must be at most {}, got {}x{} = {}",
+            MAX_GRID_CELLS,
+            width,
+            height,
+            width * height
+        );
+    }
+    Ok(())
+}
+
+/// Map `[min, max]` onto `[0, 1]` in place; a flat field maps to 0
+pub fn rescale_field(field: &mut [f32], (min, max): (f32, f32)) {
+    let span = max - min;
+    for v in field.iter_mut() {
+        *v = if span > 0.0 { ((*v - min) / span).clamp(0.0, 1.0) } else { 0.0 };
+    }
+}
+
+/// u = 1, v = 0 everywhere except a seeded patch in the centre: u ~0.5 within
+/// radius 10 and v ~0.25 within radius 5, each with a little noise from `rng`
+/// to break the symmetry
+fn initial_fields(width: usize, height: usize, seed: Option<u64>) -> (Vec<f32>, Vec<f32>) {
+    const NOISE: f32 = 0.02;
+    let mut rng = seed.map(SimRng::new);
+    let mut noise = || rng.as_mut().map_or(0.0, |rng| rng.range_f32(-NOISE, NOISE));
+    let mut u = vec![1.0f32; width * height];
+    let mut v = vec![0.0f32; width * height];
+    let (center_x, center_y) = ((width / 2) as i32, (height / 2) as i32);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as i32 - center_x;
+            let dy = y as i32 - center_y;
+            let dist_sq = dx * dx + dy * dy;
+            let idx = y * width + x;
+            if dist_sq < 100 {
+                u[idx] = 0.5 + noise();
+            }
+            if dist_sq < 25 {
+                v[idx] = 0.25 + noise();
+            }
+        }
+    }
+    (u, v)
+}
+
+struct Rates {
+    du: f32,
+    dv: f32,
+    f: f32,
+    k: f32,
+}
+
+/// One step of the row-major `width`×`height` fields, matching the kernel: every cell
+/// reads the previous step's values, and edges mirror themselves (zero-flux)
+fn cpu_step(u: &[f32], v: &[f32], width: usize, height: usize, rates: &Rates, dt: f32) -> (Vec<f32>, Vec<f32>) {
+    let mut u_next = vec![0.0f32; width * height];
+    let mut v_next = vec![0.0f32; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            let (left, right) = (idx - (x > 0) as usize, idx + (x + 1 < width) as usize);
+            let (up, down) = (
+                idx - if y > 0 { width } else { 0 },
+                idx + if y + 1 < height { width } else { 0 },
+            );
+            let lap_u = u[left] + u[right] + u[up] + u[down] - 4.0 * u[idx];
+            let lap_v = v[left] + v[right] + v[up] + v[down] - 4.0 * v[idx];
+            let uv2 = u[idx] * v[idx] * v[idx];
+            let du_dt = rates.du * lap_u - uv2 + rates.f * (1.0 - u[idx]);
+            let dv_dt = rates.dv * lap_v + uv2 - (rates.f + rates.k) * v[idx];
+            u_next[idx] = (u[idx] + du_dt * dt).clamp(0.0, 1.0);
+            v_next[idx] = (v[idx] + dv_dt * dt).clamp(0.0, 1.0);
+        }
+    }
+    (u_next, v_next)
+}
+
+/// PTX of the Gray-Scott kernel if NVRTC has built it, which new runs then step with
+pub fn kernel_ptx() -> Option<Arc<String>> {
+    #[cfg(feature = "cuda-kernel")]
+    {
+        kernel_cache::cached(&GRAY_SCOTT_KERNEL)
+    }
+    #[cfg(not(feature = "cuda-kernel"))]
+    {
+        None
+    }
+}
+
+pub struct GrayScottSimulation {
+    context: Arc<CudaContext>,
+    width: usize,
+    height: usize,
+    u_field: SimBuffer<f32>,  // Concentration field u
+    v_field: SimBuffer<f32>,  // Catalyst field v
+    #[allow(dead_code)]
+    u_temp: SimBuffer<f32>,    // Temporary buffer for u
+    #[allow(dead_code)]
+    v_temp: SimBuffer<f32>,   // Temporary buffer for v
+    // Gray-Scott parameters
+    du: f32,  // Diffusion rate for u
+    dv: f32,  // Diffusion rate for v
+    f: f32,   // Feed rate
+    k: f32,   // Kill rate
+    seed: Option<u64>,
+    // Integration steps per `step` call, each with `dt / substeps`
+    substeps: u32,
+    // CUDA kernel PTX code, shared through the NVRTC cache; `None` if it failed to build
+    #[cfg(feature = "cuda-kernel")]
+    ptx: Option<Arc<String>>,
+    #[cfg(feature = "cuda-kernel")]
+    cuda_failures: CudaFailures,
+    last_used_cuda: bool,
+}
+
+impl GrayScottSimulation {
+    /// Grid starting from the noise-free seeded patch
+    pub fn new(context: &Arc<CudaContext>, width: usize, height: usize) -> Result<Self> {
+        Self::with_seed(context, width, height, None)
+    }
+
+    /// Like `new`, but with a little noise on the initial pattern drawn from `seed`
+    pub fn new_seeded(context: &Arc<CudaContext>, width: usize, height: usize, seed: u64) -> Result<Self> {
+        Self::with_seed(context, width, height, Some(seed))
+    }
+
+    /// `new_seeded` with a seed, `new` without
+    pub fn with_seed(context: &Arc<CudaContext>, width: usize, height: usize, seed: Option<u64>) -> Result<Self> {
+        // Context should already be initialized by caller
+        check_grid_size(width, height)?;
+        
+        let (u_host, v_host) = initial_fields(width, height, seed);
+        
+        let u_field = SimBuffer::from_slice(&u_host)
+            .context("Failed to allocate u field")?;
+        let v_field = SimBuffer::from_slice(&v_host)
+            .context("Failed to allocate v field")?;
+        let u_temp = SimBuffer::from_slice(&u_host)
+            .context("Failed to allocate u_temp")?;
+        let v_temp = SimBuffer::from_slice(&v_host)
+            .context("Failed to allocate v_temp")?;
+        
+        // Compile CUDA kernel at runtime using NVRTC (cached after the first build)
         #[cfg(feature = "cuda-kernel")]
-        let ptx = {
-            let prog = NvrtcProgram::new(src, None, &[], &[])
-                .map_err(|e| anyhow::anyhow!("NVRTC program error: {:?}", e))?;
-            prog.compile(&[])
-                .map_err(|e| anyhow::anyhow!("NVRTC compile error: {:?}", e))?;
-            prog.get_ptx()
-                .map_err(|e| anyhow::anyhow!("NVRTC get_ptx error: {:?}", e))?
-        };
+        let ptx = kernel_cache::ptx(&GRAY_SCOTT_KERNEL)
+            .map_err(|e| tracing::warn!("Gray-Scott kernel unavailable, using the CPU: {:?}", e))
+            .ok();
 
         Ok(Self {
             context: Arc::clone(context),
@@ -143,125 +315,159 @@ impl GrayScottSimulation {
             dv: 0.08,
             f: 0.055,
             k: 0.062,
+            seed,
+            substeps: substeps::DEFAULT_SUBSTEPS,
             #[cfg(feature = "cuda-kernel")]
             ptx,
+            #[cfg(feature = "cuda-kernel")]
+            cuda_failures: CudaFailures::default(),
+            last_used_cuda: false,
         })
     }
 
-    pub fn step(&mut self, dt: f32) -> Result<()> {
-        // Launch CUDA kernel when enabled; otherwise fallback CPU
-        #[cfg(feature = "cuda-kernel")]
-        let width_i32 = self.width as i32;
-        #[cfg(feature = "cuda-kernel")]
-        let height_i32 = self.height as i32;
-        #[cfg(feature = "cuda-kernel")]
-        let du = self.du;
-        #[cfg(feature = "cuda-kernel")]
-        let dv = self.dv;
-        #[cfg(feature = "cuda-kernel")]
-        let f = self.f;
-        #[cfg(feature = "cuda-kernel")]
-        let k = self.k;
-        #[cfg(feature = "cuda-kernel")]
-        let dt = dt;
+    /// Seed the initial noise came from, if any; `with_seed` with it gives the same start
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
 
+    /// Apply the reaction and diffusion rates in `params`; omitted ones keep their value.
+    /// The grid size is fixed at construction.
+    pub fn set_params(&mut self, params: &GrayScottParams) -> Result<()> {
+        params.validate()?;
+        self.f = params.f.unwrap_or(self.f);
+        self.k = params.k.unwrap_or(self.k);
+        self.du = params.du.unwrap_or(self.du);
+        self.dv = params.dv.unwrap_or(self.dv);
+        Ok(())
+    }
+
+    /// Split each `step` into `substeps` steps of `dt / substeps`
+    pub fn set_substeps(&mut self, substeps: u32) -> Result<()> {
+        substeps::check_substeps(substeps)?;
+        self.substeps = substeps;
+        Ok(())
+    }
+
+    /// Advance `dt`, split into `substeps` smaller steps
+    pub fn step(&mut self, dt: f32) -> Result<Accelerator> {
+        substeps::run(self.substeps, dt, |sub_dt| self.step_once(sub_dt))
+    }
+
+    /// Advance one step on the GPU, or the CPU if the kernel is unavailable or fails
+    fn step_once(&mut self, dt: f32) -> Result<Accelerator> {
         #[cfg(feature = "cuda-kernel")]
+        if let Some(ptx) = self.ptx.clone() {
+            match self.step_cuda(&ptx, dt) {
+                Ok(()) => {
+                    self.cuda_failures.succeeded();
+                    self.last_used_cuda = true;
+                    return Ok(Accelerator::Cuda);
+                }
+                Err(e) => {
+                    tracing::warn!("Gray-Scott kernel failed, running this step on the CPU: {:?}", e);
+                    if self.cuda_failures.give_up(&e) {
+                        // Later steps go straight to the CPU
+                        tracing::warn!("Giving up on the Gray-Scott kernel");
+                        self.ptx = None;
+                    }
+                }
+            }
+        }
+
+        self.step_cpu(dt)?;
+        self.last_used_cuda = false;
+        Ok(Accelerator::Cpu)
+    }
+
+    /// Path the last step took
+    pub fn accelerator(&self) -> Accelerator {
+        Accelerator::from_used_cuda(self.last_used_cuda)
+    }
+
+    #[cfg(feature = "cuda-kernel")]
+    fn step_cuda(&mut self, ptx: &str, dt: f32) -> Result<()> {
         let block = (16, 16, 1);
-        #[cfg(feature = "cuda-kernel")]
         let grid = (
             ((self.width as u32) + block.0 - 1) / block.0,
             ((self.height as u32) + block.1 - 1) / block.1,
             1,
         );
 
-        #[cfg(feature = "cuda-kernel")]
-        {
-            // Load module and function fresh each time
-            let ptx_c = CString::new(self.ptx.as_str()).unwrap();
-            let module = Module::load_from_string(&ptx_c)
-                .map_err(|e| anyhow::anyhow!("Failed to load PTX module: {:?}", e))?;
-            let func = module.get_function(&CString::new("gray_scott_step").unwrap())
-                .map_err(|e| anyhow::anyhow!("Failed to get kernel function: {:?}", e))?;
-            let stream = Stream::new(StreamFlags::DEFAULT, None)
-                .map_err(|e| anyhow::anyhow!("Failed to create stream: {:?}", e))?;
-            
-            unsafe {
-                launch!(
-                    func<<<grid, block, 0, stream>>>(
-                        width_i32, height_i32, du, dv, f, k, dt,
-                        self.u_field.as_device_ptr(),
-                        self.v_field.as_device_ptr(),
-                        self.u_temp.as_device_ptr(),
-                        self.v_temp.as_device_ptr()
-                    )
+        // Load module and function fresh each time
+        let ptx_c = CString::new(ptx).unwrap();
+        let module = Module::load_from_string(&ptx_c)
+            .context("Failed to load PTX module")?;
+        let func = module.get_function(&CString::new("gray_scott_step").unwrap())
+            .context("Failed to get kernel function")?;
+        let stream = Stream::new(StreamFlags::DEFAULT, None)
+            .context("Failed to create stream")?;
+        
+        unsafe {
+            launch!(
+                func<<<grid, block, 0, stream>>>(
+                    self.width as i32, self.height as i32,
+                    self.du, self.dv, self.f, self.k, dt,
+                    self.u_field.as_device_ptr(),
+                    self.v_field.as_device_ptr(),
+                    self.u_temp.as_device_ptr(),
+                    self.v_temp.as_device_ptr()
                 )
-                .map_err(|e| anyhow::anyhow!("Kernel launch failed: {:?}", e))?;
-            }
-            stream.synchronize()
-                .map_err(|e| anyhow::anyhow!("Stream sync failed: {:?}", e))?;
-            std::mem::swap(&mut self.u_field, &mut self.u_temp);
-            std::mem::swap(&mut self.v_field, &mut self.v_temp);
-            return Ok(());
+            )
+            .context("Kernel launch failed")?;
         }
+        stream.synchronize()
+            .context("Stream sync failed")?;
+        std::mem::swap(&mut self.u_field, &mut self.u_temp);
+        std::mem::swap(&mut self.v_field, &mut self.v_temp);
+        Ok(())
+    }
 
-        #[cfg(not(feature = "cuda-kernel"))]
-        {
-            // CPU fallback (original implementation)
-            let mut u_host = vec![0.0f32; self.width * self.height];
-            let mut v_host = vec![0.0f32; self.width * self.height];
-            self.u_field.copy_to(&mut u_host[..])
-                .map_err(|e| anyhow::anyhow!("Failed to copy u field: {:?}", e))?;
-            self.v_field.copy_to(&mut v_host[..])
-                .map_err(|e| anyhow::anyhow!("Failed to copy v field: {:?}", e))?;
-            for y in 0..self.height {
-                for x in 0..self.width {
-                    let idx = y * self.width + x;
-                    let u = u_host[idx];
-                    let v = v_host[idx];
-                    let neighbors = [
-                        (x as i32, y as i32 - 1),
-                        (x as i32, y as i32 + 1),
-                        (x as i32 - 1, y as i32),
-                        (x as i32 + 1, y as i32),
-                    ];
-                    let mut lap_u = 0.0;
-                    let mut lap_v = 0.0;
-                    for (nx, ny) in neighbors.iter() {
-                        if *nx >= 0 && *nx < self.width as i32 && *ny >= 0 && *ny < self.height as i32 {
-                            let nidx = (*ny as usize) * self.width + (*nx as usize);
-                            lap_u += u_host[nidx] - u;
-                            lap_v += v_host[nidx] - v;
-                        }
-                    }
-                    let uv2 = u * v * v;
-                    let du_dt = self.du * lap_u - uv2 + self.f * (1.0 - u);
-                    let dv_dt = self.dv * lap_v + uv2 - (self.f + self.k) * v;
-                    u_host[idx] = (u + du_dt * dt).max(0.0).min(1.0);
-                    v_host[idx] = (v + dv_dt * dt).max(0.0).min(1.0);
-                }
-            }
-            self.u_field.copy_from(&u_host[..])
-                .map_err(|e| anyhow::anyhow!("Failed to copy u field back: {:?}", e))?;
-            self.v_field.copy_from(&v_host[..])
-                .map_err(|e| anyhow::anyhow!("Failed to copy v field back: {:?}", e))?;
-            Ok(())
-        }
+    fn step_cpu(&mut self, dt: f32) -> Result<()> {
+        let mut u_host = vec![0.0f32; self.width * self.height];
+        let mut v_host = vec![0.0f32; self.width * self.height];
+        self.u_field.copy_to(&mut u_host[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy u field: {:?}", e))?;
+        self.v_field.copy_to(&mut v_host[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy v field: {:?}", e))?;
+        let rates = Rates { du: self.du, dv: self.dv, f: self.f, k: self.k };
+        let (u_next, v_next) = cpu_step(&u_host, &v_host, self.width, self.height, &rates, dt);
+        self.u_field.copy_from(&u_next[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy u field back: {:?}", e))?;
+        self.v_field.copy_from(&v_next[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy v field back: {:?}", e))?;
+        Ok(())
     }
 
     pub fn get_field(&self) -> Result<Vec<f32>> {
+        self.context.ensure_context()?;
         let size = self.width * self.height;
         let mut u_host = vec![0.0f32; size];
         self.u_field.copy_to(&mut u_host[..])
             .map_err(|e| anyhow::anyhow!("Failed to copy u field: {:?}", e))?;
         Ok(u_host)
     }
+
+    /// Both the concentration `u` and catalyst `v` fields, row-major
+    pub fn get_fields(&self) -> Result<(Vec<f32>, Vec<f32>)> {
+        self.context.ensure_context()?;
+        let size = self.width * self.height;
+        let mut u_host = vec![0.0f32; size];
+        let mut v_host = vec![0.0f32; size];
+        self.u_field.copy_to(&mut u_host[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy u field: {:?}", e))?;
+        self.v_field.copy_to(&mut v_host[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy v field: {:?}", e))?;
+        Ok((u_host, v_host))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::init_cuda_in_thread;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -271,6 +477,82 @@ mod tests {
         (Arc::new(CudaContext::new().expect("Failed to create CUDA context")), context_obj)
     }
 
+    #[test]
+    fn test_output_range() {
+        let field = [0.2f32, 0.3, 0.25];
+        let none = GrayScottParams::default();
+        assert_eq!(none.output_range(&field), None);
+
+        let normalize = GrayScottParams { normalize: Some(true), ..Default::default() };
+        let range = normalize.output_range(&field).unwrap();
+        assert_eq!(range, (0.2, 0.3));
+        let mut out = field;
+        rescale_field(&mut out, range);
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[1], 1.0);
+        assert!((out[2] - 0.5).abs() < 1e-5);
+
+        // An explicit range wins over normalize and clamps outliers
+        let explicit = GrayScottParams {
+            normalize: Some(true),
+            range: Some((0.25, 0.3)),
+            ..Default::default()
+        };
+        let mut out = field;
+        rescale_field(&mut out, explicit.output_range(&field).unwrap());
+        assert_eq!(out[0], 0.0);
+
+        let bad = GrayScottParams { range: Some((0.5, 0.5)), ..Default::default() };
+        assert!(bad.validate().is_err());
+        assert!(explicit.validate().is_ok());
+
+        let mut flat = [0.7f32; 3];
+        rescale_field(&mut flat, (0.7, 0.7));
+        assert_eq!(flat, [0.0; 3]);
+    }
+
+    #[test]
+    fn test_initial_fields_are_seeded() {
+        let (plain_u, plain_v) = initial_fields(64, 48, None);
+        let centre = 24 * 64 + 32;
+        assert_eq!((plain_u[centre], plain_v[centre]), (0.5, 0.25), "No seed, no noise");
+
+        let (u, v) = initial_fields(64, 48, Some(7));
+        assert_eq!((u.len(), v.len()), (64 * 48, 64 * 48));
+        assert_eq!((u[0], v[0]), (1.0, 0.0), "Outside the patch is untouched");
+        assert!((u[centre] - 0.5).abs() <= 0.02 && (v[centre] - 0.25).abs() <= 0.02);
+
+        assert_eq!((u.clone(), v.clone()), initial_fields(64, 48, Some(7)));
+        assert_ne!(u, initial_fields(64, 48, Some(8)).0);
+    }
+
+    #[test]
+    fn test_rate_and_size_validation() {
+        let params = GrayScottParams {
+            f: Some(0.03),
+            k: Some(0.06),
+            du: Some(0.16),
+            dv: Some(0.08),
+            width: Some(256),
+            height: Some(128),
+            ..Default::default()
+        };
+        assert!(params.validate().is_ok());
+        assert_eq!(params.size(), (256, 128));
+        assert_eq!(GrayScottParams::default().size(), (DEFAULT_GRID_SIZE, DEFAULT_GRID_SIZE));
+
+        let err = GrayScottParams { f: Some(0.5), ..Default::default() }.validate().unwrap_err();
+        assert!(err.to_string().contains("f must be"), "{}", err);
+        assert!(GrayScottParams { k: Some(-0.01), ..Default::default() }.validate().is_err());
+        assert!(GrayScottParams { k: Some(f32::NAN), ..Default::default() }.validate().is_err());
+        assert!(GrayScottParams { dv: Some(0.0), ..Default::default() }.validate().is_err());
+        assert!(GrayScottParams { width: Some(0), ..Default::default() }.validate().is_err());
+        assert!(GrayScottParams { height: Some(MAX_GRID_SIZE + 1), ..Default::default() }
+            .validate()
+            .is_err());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_grayscott_initialization() {
         let (context, _context_guard) = setup_test_context();
@@ -278,6 +560,7 @@ mod tests {
         assert!(sim.is_ok(), "Gray-Scott simulation should initialize");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_grayscott_step() {
         let (context, _context_guard) = setup_test_context();
@@ -286,6 +569,7 @@ mod tests {
         assert!(result.is_ok(), "Gray-Scott step should succeed");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_grayscott_field_size() {
         let (context, _context_guard) = setup_test_context();
@@ -293,4 +577,62 @@ mod tests {
         let field = sim.get_field().unwrap();
         assert_eq!(field.len(), 512 * 512, "Field should match dimensions");
     }
+
+    #[test]
+    fn test_grid_cell_cap() {
+        assert!(check_grid_size(800, 450).is_ok());
+        assert!(check_grid_size(MAX_GRID_SIZE, MAX_GRID_CELLS / MAX_GRID_SIZE).is_ok());
+        let err = check_grid_size(MAX_GRID_SIZE, MAX_GRID_SIZE).unwrap_err();
+        assert!(err.to_string().contains("width * height"), "{}", err);
+        let wide = GrayScottParams { width: Some(4096), height: Some(1025), ..Default::default() };
+        assert!(wide.validate().is_err());
+    }
+
+    #[test]
+    fn test_cpu_step_on_rectangular_field() {
+        let (width, height) = (640, 360);
+        let mut u = vec![1.0f32; width * height];
+        let mut v = vec![0.0f32; width * height];
+        // A patch symmetric about the centre lines x = 319.5 and y = 179.5
+        for y in 170..190 {
+            for x in 310..330 {
+                u[y * width + x] = 0.5;
+                v[y * width + x] = 0.25;
+            }
+        }
+        let rates = Rates { du: 0.16, dv: 0.08, f: 0.055, k: 0.062 };
+        for _ in 0..50 {
+            (u, v) = cpu_step(&u, &v, width, height, &rates, 0.5);
+        }
+        assert_eq!((u.len(), v.len()), (width * height, width * height));
+        for y in 0..height {
+            for x in 0..width {
+                let (a, b, c) = (y * width + x, y * width + (width - 1 - x), (height - 1 - y) * width + x);
+                // Mirrored cells sum their neighbours in a different order, hence the tolerance
+                assert!((v[a] - v[b]).abs() < 1e-6, "not mirrored left-right at ({}, {})", x, y);
+                assert!((v[a] - v[c]).abs() < 1e-6, "not mirrored top-bottom at ({}, {})", x, y);
+            }
+        }
+        assert!(v[180 * width + 320] > 0.0 && v[0] == 0.0, "the patch spreads but not to the corners");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_grayscott_rectangular_field() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = GrayScottSimulation::new_seeded(&context, 640, 360, 1).unwrap();
+        sim.step(0.016).unwrap();
+        assert_eq!(sim.get_field().unwrap().len(), 640 * 360);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_grayscott_get_fields() {
+        let (context, _context_guard) = setup_test_context();
+        let sim = GrayScottSimulation::new_seeded(&context, 64, 32, 1).unwrap();
+        let (u, v) = sim.get_fields().unwrap();
+        assert_eq!(u, sim.get_field().unwrap());
+        assert_eq!(v.len(), 64 * 32);
+        assert!(v.iter().any(|&x| x > 0.0), "seeded patch should contain catalyst");
+    }
 }
@@ -0,0 +1,132 @@
+// Initial boid positions sampled from an image's luminance
+// Lets a flock start out drawing a logo or shape before it disperses
+use super::rng::SimRng;
+use anyhow::Result;
+use image::codecs::png::PngDecoder;
+use image::{DynamicImage, ImageDecoder};
+use std::io::Cursor;
+
+/// Largest accepted PNG upload
+pub const MAX_IMAGE_BYTES: usize = 1024 * 1024;
+/// Largest accepted width or height in pixels
+pub const MAX_IMAGE_DIM: u32 = 1024;
+/// Largest flock that can be initialized from an image
+pub const MAX_IMAGE_BOIDS: usize = 200_000;
+
+/// Per-pixel sampling weights in [0, 1], row-major from the top-left
+pub struct LuminanceMap {
+    pub width: u32,
+    pub height: u32,
+    weights: Vec<f32>,
+}
+
+impl LuminanceMap {
+    /// Decode a PNG and weight each pixel by its darkness (or brightness with `bright`)
+    pub fn from_png(png: &[u8], bright: bool) -> Result<Self> {
+        if png.len() > MAX_IMAGE_BYTES {
+            anyhow::bail!("image is {} bytes, limit is {}", png.len(), MAX_IMAGE_BYTES);
+        }
+        // The header alone gives the size, so oversized images are rejected before decoding
+        let decoder = PngDecoder::new(Cursor::new(png)).map_err(|e| anyhow::anyhow!("Failed to decode PNG: {:?}", e))?;
+        let (width, height) = decoder.dimensions();
+        if width == 0 || height == 0 || width > MAX_IMAGE_DIM || height > MAX_IMAGE_DIM {
+            anyhow::bail!(
+                "image is {}x{}, each side must be 1..={}",
+                width, height, MAX_IMAGE_DIM
+            );
+        }
+        let image = DynamicImage::from_decoder(decoder)
+            .map_err(|e| anyhow::anyhow!("Failed to decode PNG: {:?}", e))?
+            .to_luma8();
+        let weights = image
+            .pixels()
+            .map(|p| {
+                let l = p.0[0] as f32 / 255.0;
+                if bright { l } else { 1.0 - l }
+            })
+            .collect();
+        Ok(Self { width, height, weights })
+    }
+
+    /// Rejection-sample `count` positions in [0, 1)², denser where the weight is higher.
+    /// Image rows map to increasing y, matching screen coordinates.
+    pub fn sample(&self, count: usize, rng: &mut SimRng) -> Result<Vec<(f32, f32)>> {
+        if count == 0 || count > MAX_IMAGE_BOIDS {
+            anyhow::bail!("count must be 1..={}, got {}", MAX_IMAGE_BOIDS, count);
+        }
+        // Propose only pixels that can be accepted, so mostly-blank images stay fast
+        let candidates: Vec<usize> = (0..self.weights.len())
+            .filter(|&i| self.weights[i] > 0.0)
+            .collect();
+        if candidates.is_empty() {
+            anyhow::bail!("image has no pixels to sample from");
+        }
+        let max_weight = candidates.iter().map(|&i| self.weights[i]).fold(0.0, f32::max);
+
+        let mut positions = Vec::with_capacity(count);
+        while positions.len() < count {
+            let i = candidates[rng.below(candidates.len() as u32) as usize];
+            if rng.next_f32() * max_weight >= self.weights[i] {
+                continue;
+            }
+            let (px, py) = (i as u32 % self.width, i as u32 / self.width);
+            // Spread within the pixel so low-res images don't stack boids
+            positions.push((
+                (px as f32 + rng.next_f32()) / self.width as f32,
+                (py as f32 + rng.next_f32()) / self.height as f32,
+            ));
+        }
+        Ok(positions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Left half black, right half white
+    fn split_png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::GrayImage::from_fn(width, height, |x, _| {
+            image::Luma([if x < width / 2 { 0 } else { 255 }])
+        });
+        let mut png = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_samples_follow_luminance() {
+        let mut rng = SimRng::new(7);
+        let dark = LuminanceMap::from_png(&split_png(8, 4), false).unwrap();
+        let positions = dark.sample(500, &mut rng).unwrap();
+        assert_eq!(positions.len(), 500);
+        assert!(positions.iter().all(|&(x, y)| (0.0..=0.5).contains(&x) && (0.0..=1.0).contains(&y)));
+
+        let bright = LuminanceMap::from_png(&split_png(8, 4), true).unwrap();
+        let positions = bright.sample(500, &mut rng).unwrap();
+        assert!(positions.iter().all(|&(x, _)| (0.5..=1.0).contains(&x)));
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let mut rng = SimRng::new(7);
+        assert!(LuminanceMap::from_png(b"not a png", false).is_err());
+        let too_wide = LuminanceMap::from_png(&split_png(MAX_IMAGE_DIM + 1, 1), false).err().unwrap();
+        assert!(too_wide.to_string().contains("each side must be"), "{}", too_wide);
+        // Only the header is read before the size check, so a truncated body doesn't matter
+        let mut truncated = split_png(MAX_IMAGE_DIM + 1, 1);
+        truncated.truncate(64);
+        let error = LuminanceMap::from_png(&truncated, false).err().unwrap();
+        assert!(error.to_string().contains("each side must be"), "{}", error);
+
+        let map = LuminanceMap::from_png(&split_png(8, 4), false).unwrap();
+        assert!(map.sample(0, &mut rng).is_err());
+        assert!(map.sample(MAX_IMAGE_BOIDS + 1, &mut rng).is_err());
+
+        // A blank image has nothing to sample
+        let blank = LuminanceMap { width: 2, height: 2, weights: vec![0.0; 4] };
+        assert!(blank.sample(10, &mut rng).is_err());
+    }
+}
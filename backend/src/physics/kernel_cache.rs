@@ -0,0 +1,90 @@
+// Process-wide cache of NVRTC-compiled kernels
+// Compiling on first use costs hundreds of ms, so the server warms this at startup
+use anyhow::Result;
+use nvrtc::NvrtcProgram;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::info;
+
+/// CUDA C++ source compiled at runtime with NVRTC
+pub struct NvrtcKernel {
+    /// Entry point name, also the cache key
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+/// Every NVRTC kernel in the crate, compiled up front by `precompile_all`
+pub const NVRTC_KERNELS: &[NvrtcKernel] =
+    &[super::grayscott::GRAY_SCOTT_KERNEL, super::sdf::SDF_KERNEL];
+
+fn cache() -> &'static Mutex<HashMap<&'static str, Arc<String>>> {
+    static CACHE: OnceLock<Mutex<HashMap<&'static str, Arc<String>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// PTX for `kernel`, compiling it on the first call
+pub fn ptx(kernel: &NvrtcKernel) -> Result<Arc<String>> {
+    if let Some(ptx) = cache().lock().unwrap().get(kernel.name) {
+        return Ok(Arc::clone(ptx));
+    }
+
+    // Compile without holding the lock so other kernels can build in parallel
+    let start = Instant::now();
+    let prog = NvrtcProgram::new(kernel.source, None, &[], &[])
+        .map_err(|e| anyhow::anyhow!("NVRTC program error ({}): {:?}", kernel.name, e))?;
+    prog.compile(&[])
+        .map_err(|e| anyhow::anyhow!("NVRTC compile error ({}): {:?}", kernel.name, e))?;
+    let ptx = prog
+        .get_ptx()
+        .map_err(|e| anyhow::anyhow!("NVRTC get_ptx error ({}): {:?}", kernel.name, e))?;
+    info!(
+        "Compiled NVRTC kernel {} in {:.1} ms",
+        kernel.name,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+
+    let mut cache = cache().lock().unwrap();
+    Ok(Arc::clone(cache.entry(kernel.name).or_insert_with(|| Arc::new(ptx))))
+}
+
+/// PTX for `kernel` if it has already been compiled
+pub fn cached(kernel: &NvrtcKernel) -> Option<Arc<String>> {
+    cache().lock().unwrap().get(kernel.name).cloned()
+}
+
+/// Compile every NVRTC kernel using up to `threads` compile threads
+pub fn precompile_all(threads: usize) -> Result<()> {
+    let threads = threads.clamp(1, NVRTC_KERNELS.len().max(1));
+    let start = Instant::now();
+    let chunk = NVRTC_KERNELS.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = NVRTC_KERNELS
+            .chunks(chunk.max(1))
+            .map(|kernels| scope.spawn(move || kernels.iter().try_for_each(|k| ptx(k).map(|_| ()))))
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|w| w.join().map_err(|_| anyhow::anyhow!("NVRTC compile thread panicked"))?)
+    })?;
+    info!(
+        "Precompiled {} NVRTC kernel(s) on {} thread(s) in {:.1} ms",
+        NVRTC_KERNELS.len(),
+        threads,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ptx_is_cached() {
+        let first = ptx(&NVRTC_KERNELS[0]).unwrap();
+        let second = ptx(&NVRTC_KERNELS[0]).unwrap();
+        assert!(Arc::ptr_eq(&first, &second), "Second lookup should hit the cache");
+        assert!(precompile_all(4).is_ok());
+    }
+}
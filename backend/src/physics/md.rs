@@ -0,0 +1,315 @@
+// Lennard-Jones molecular dynamics in 2D
+// Reduced units (sigma = epsilon = mass = 1), velocity-Verlet integration and a
+// periodic square box. CPU only for now; the buffer is ready for a kernel.
+use super::accelerator::Accelerator;
+use super::rng::SimRng;
+use super::storage::{SimBuffer, Storage};
+use crate::cuda::CudaContext;
+use anyhow::{Context, Result};
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::memory::DeviceCopy;
+use std::sync::Arc;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Particle {
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    // Acceleration from the last force pass, reused by the next Verlet step
+    pub ax: f32,
+    pub ay: f32,
+}
+
+#[cfg(not(feature = "no-cuda"))]
+unsafe impl DeviceCopy for Particle {}
+
+/// Particles when a request doesn't say
+pub const DEFAULT_NUM_PARTICLES: usize = 400;
+/// Largest particle count accepted; forces are all-pairs, so cost grows as N²
+pub const MAX_NUM_PARTICLES: usize = 5_000;
+/// Step in reduced time units; stable for the default density and temperature
+pub const DEFAULT_DT: f32 = 0.005;
+/// Particles per unit area of the box
+pub const DENSITY: f32 = 0.5;
+/// Temperature the initial velocities are scaled to
+pub const TEMPERATURE: f32 = 1.0;
+/// Pairs further apart than this don't interact
+pub const CUTOFF: f32 = 2.5;
+
+pub fn check_num_particles(num_particles: usize) -> Result<()> {
+    if !(1..=MAX_NUM_PARTICLES).contains(&num_particles) {
+        anyhow::bail!(
+            "num_particles must be 1..={}, got {}",
+            MAX_NUM_PARTICLES,
+            num_particles
+        );
+    }
+    Ok(())
+}
+
+/// Side of the periodic box; at least two cutoffs, so each pair sees one image at most
+fn box_size(num_particles: usize) -> f32 {
+    (num_particles as f32 / DENSITY).sqrt().max(2.0 * CUTOFF)
+}
+
+/// Shortest separation along one axis under periodic wrapping
+fn minimum_image(d: f32, box_size: f32) -> f32 {
+    d - box_size * (d / box_size).round()
+}
+
+/// Force magnitude over distance and potential energy of a pair `r2` apart.
+/// The potential is shifted to zero at the cutoff so energy is conserved.
+fn pair_interaction(r2: f32) -> Option<(f32, f32)> {
+    if r2 >= CUTOFF * CUTOFF || r2 == 0.0 {
+        return None;
+    }
+    let inv_r6 = (1.0 / r2).powi(3);
+    let inv_rc6 = (1.0 / (CUTOFF * CUTOFF)).powi(3);
+    let force_over_r = 24.0 * inv_r6 * (2.0 * inv_r6 - 1.0) / r2;
+    let potential = 4.0 * inv_r6 * (inv_r6 - 1.0) - 4.0 * inv_rc6 * (inv_rc6 - 1.0);
+    Some((force_over_r, potential))
+}
+
+/// Overwrite every particle's acceleration; returns the total potential energy
+fn compute_accelerations(particles: &mut [Particle], box_size: f32) -> f32 {
+    for p in particles.iter_mut() {
+        p.ax = 0.0;
+        p.ay = 0.0;
+    }
+    let mut potential = 0.0;
+    for i in 0..particles.len() {
+        for j in (i + 1)..particles.len() {
+            let dx = minimum_image(particles[i].x - particles[j].x, box_size);
+            let dy = minimum_image(particles[i].y - particles[j].y, box_size);
+            if let Some((f, u)) = pair_interaction(dx * dx + dy * dy) {
+                particles[i].ax += f * dx;
+                particles[i].ay += f * dy;
+                particles[j].ax -= f * dx;
+                particles[j].ay -= f * dy;
+                potential += u;
+            }
+        }
+    }
+    potential
+}
+
+/// One velocity-Verlet step; accelerations must be current on entry and are on exit
+fn verlet_step(particles: &mut [Particle], box_size: f32, dt: f32) {
+    for p in particles.iter_mut() {
+        p.vx += 0.5 * p.ax * dt;
+        p.vy += 0.5 * p.ay * dt;
+        p.x = (p.x + p.vx * dt).rem_euclid(box_size);
+        p.y = (p.y + p.vy * dt).rem_euclid(box_size);
+    }
+    compute_accelerations(particles, box_size);
+    for p in particles.iter_mut() {
+        p.vx += 0.5 * p.ax * dt;
+        p.vy += 0.5 * p.ay * dt;
+    }
+}
+
+fn kinetic_energy(particles: &[Particle]) -> f32 {
+    particles.iter().map(|p| 0.5 * (p.vx * p.vx + p.vy * p.vy)).sum()
+}
+
+/// Square lattice filling the box, with random velocities at `TEMPERATURE` and no net drift
+fn initial_layout(num_particles: usize, rng: &mut SimRng) -> Vec<Particle> {
+    let side = box_size(num_particles);
+    let cols = (num_particles as f32).sqrt().ceil() as usize;
+    let spacing = side / cols as f32;
+    let speed = (3.0 * TEMPERATURE).sqrt();
+    let mut particles: Vec<Particle> = (0..num_particles)
+        .map(|i| Particle {
+            x: ((i % cols) as f32 + 0.5) * spacing,
+            y: ((i / cols) as f32 + 0.5) * spacing,
+            vx: rng.range_f32(-speed, speed),
+            vy: rng.range_f32(-speed, speed),
+            ax: 0.0,
+            ay: 0.0,
+        })
+        .collect();
+
+    let n = num_particles as f32;
+    let (mean_vx, mean_vy) = particles
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), p| (sx + p.vx / n, sy + p.vy / n));
+    for p in particles.iter_mut() {
+        p.vx -= mean_vx;
+        p.vy -= mean_vy;
+    }
+    // Two degrees of freedom per particle: T = KE / N
+    let kinetic = kinetic_energy(&particles);
+    if kinetic > 0.0 {
+        let scale = (TEMPERATURE * n / kinetic).sqrt();
+        for p in particles.iter_mut() {
+            p.vx *= scale;
+            p.vy *= scale;
+        }
+    }
+    compute_accelerations(&mut particles, side);
+    particles
+}
+
+pub struct MdSimulation {
+    #[allow(dead_code)]
+    context: Arc<CudaContext>,
+    num_particles: usize,
+    box_size: f32,
+    particles: SimBuffer<Particle>,
+    seed: u64,
+}
+
+impl MdSimulation {
+    pub fn new(context: &Arc<CudaContext>, num_particles: usize) -> Result<Self> {
+        Self::new_seeded(context, num_particles, rand::random())
+    }
+
+    /// Like `new`, but the initial velocities are drawn from `seed`
+    pub fn new_seeded(context: &Arc<CudaContext>, num_particles: usize, seed: u64) -> Result<Self> {
+        check_num_particles(num_particles)?;
+        let host_particles = initial_layout(num_particles, &mut SimRng::new(seed));
+        let particles = SimBuffer::from_slice(&host_particles)
+            .context("Failed to allocate particles")?;
+        Ok(Self {
+            context: Arc::clone(context),
+            num_particles,
+            box_size: box_size(num_particles),
+            particles,
+            seed,
+        })
+    }
+
+    pub fn step(&mut self, dt: f32) -> Result<Accelerator> {
+        let mut host_particles = self.host_particles()?;
+        verlet_step(&mut host_particles, self.box_size, dt);
+        self.particles.copy_from(&host_particles[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy particles back: {:?}", e))?;
+        Ok(Accelerator::Cpu)
+    }
+
+    /// Kinetic plus potential energy; constant up to integration error
+    pub fn total_energy(&self) -> Result<f32> {
+        let mut host_particles = self.host_particles()?;
+        let potential = compute_accelerations(&mut host_particles, self.box_size);
+        Ok(kinetic_energy(&host_particles) + potential)
+    }
+
+    /// Where `step` runs; always the CPU until the MD kernel lands
+    pub fn accelerator(&self) -> Accelerator {
+        Accelerator::Cpu
+    }
+
+    /// Side of the periodic box in reduced units
+    pub fn box_size(&self) -> f32 {
+        self.box_size
+    }
+
+    /// Seed the initial velocities came from; `new_seeded` with it gives the same start
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn host_particles(&self) -> Result<Vec<Particle>> {
+        let mut host_particles = vec![Particle::default(); self.num_particles];
+        self.particles.copy_to(&mut host_particles[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy particles: {:?}", e))?;
+        Ok(host_particles)
+    }
+
+    /// Flattened `[x, y, vx, vy, ...]` scaled by the box size, so positions fall in the unit square
+    pub fn get_particles(&self) -> Result<Vec<f32>> {
+        let scale = 1.0 / self.box_size;
+        Ok(self
+            .host_particles()?
+            .iter()
+            .flat_map(|p| [p.x * scale, p.y * scale, p.vx * scale, p.vy * scale])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-cuda"))]
+    use crate::cuda::init_cuda_in_thread;
+
+    #[cfg(not(feature = "no-cuda"))]
+    fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
+        // Initialize CUDA in this test thread and keep context alive
+        init_cuda_in_thread().expect("Failed to init CUDA in test thread");
+        let context_obj = rustacuda::prelude::Context::create_and_push(
+            rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
+            rustacuda::prelude::Device::get_device(0).expect("Failed to get device")
+        ).expect("Failed to create context");
+        (Arc::new(CudaContext::new().expect("Failed to create CUDA context")), context_obj)
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_md_initialization() {
+        let (context, _context_guard) = setup_test_context();
+        let sim = MdSimulation::new(&context, DEFAULT_NUM_PARTICLES);
+        assert!(sim.is_ok(), "MD simulation should initialize");
+        assert!(MdSimulation::new(&context, MAX_NUM_PARTICLES + 1).is_err());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_md_step() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = MdSimulation::new_seeded(&context, DEFAULT_NUM_PARTICLES, 1).unwrap();
+        assert_eq!(sim.step(DEFAULT_DT).unwrap(), Accelerator::Cpu);
+        let particles = sim.get_particles().unwrap();
+        assert!(particles.iter().all(|v| v.is_finite()));
+        // Positions stay inside the (unit-scaled) periodic box
+        assert!(particles.chunks(4).all(|p| (0.0..=1.0).contains(&p[0]) && (0.0..=1.0).contains(&p[1])));
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_md_particle_count() {
+        let (context, _context_guard) = setup_test_context();
+        let sim = MdSimulation::new(&context, DEFAULT_NUM_PARTICLES).unwrap();
+        let particles = sim.get_particles().unwrap();
+        // Should return 4 values per particle (x, y, vx, vy)
+        assert_eq!(particles.len(), DEFAULT_NUM_PARTICLES * 4);
+    }
+
+    #[test]
+    fn test_pair_force_vanishes_at_potential_minimum() {
+        let r_min = 2f32.powf(1.0 / 6.0);
+        let (force, potential) = pair_interaction(r_min * r_min).unwrap();
+        assert!(force.abs() < 1e-4, "force at the minimum was {}", force);
+        assert!(potential < 0.0);
+        // Repulsive inside the minimum, no interaction past the cutoff
+        assert!(pair_interaction(0.9 * 0.9).unwrap().0 > 0.0);
+        assert!(pair_interaction(CUTOFF * CUTOFF).is_none());
+    }
+
+    #[test]
+    fn test_periodic_wrap() {
+        let side = box_size(2);
+        assert!((minimum_image(side - 0.5, side) + 0.5).abs() < 1e-5);
+        let mut particles = vec![Particle { x: side - 0.01, y: 0.01, vx: 1.0, vy: -1.0, ..Default::default() }];
+        verlet_step(&mut particles, side, 0.1);
+        assert!(particles[0].x < 1.0 && particles[0].y > side - 1.0);
+    }
+
+    #[test]
+    fn test_verlet_conserves_energy() {
+        let mut particles = initial_layout(100, &mut SimRng::new(7));
+        let side = box_size(100);
+        let energy = |particles: &mut [Particle]| {
+            let potential = compute_accelerations(particles, side);
+            kinetic_energy(particles) + potential
+        };
+        let start = energy(&mut particles);
+        for _ in 0..500 {
+            verlet_step(&mut particles, side, DEFAULT_DT);
+        }
+        let end = energy(&mut particles);
+        assert!(((end - start) / start.abs()).abs() < 0.01, "energy drifted {} -> {}", start, end);
+    }
+}
@@ -1,13 +1,39 @@
 // Physics simulation modules
 
+pub mod accelerator;
+pub mod attractors;
+pub mod auto_tune;
 pub mod sph;
 pub mod boids;
+pub mod boids3d;
+pub mod checkpoint;
+pub mod emitter;
+pub mod genetics;
 pub mod grayscott;
+pub mod image_init;
+pub mod md;
+pub mod obstacles;
+pub mod predators;
+pub mod rng;
+pub mod stasis;
+pub mod stats;
+pub mod substeps;
+pub mod thumbnail;
+pub mod verlet;
+#[cfg(all(feature = "cuda-kernel", feature = "no-cuda"))]
+compile_error!("the cuda-kernel and no-cuda features are mutually exclusive");
+#[cfg(feature = "cuda-kernel")]
+pub mod kernel_cache;
 pub mod sdf;
+pub mod spatial_grid;
+pub mod storage;
 
 // Re-export for convenience
+pub use accelerator::Accelerator;
 pub use sph::SphSimulation;
-pub use boids::BoidsSimulation;
-pub use grayscott::GrayScottSimulation;
+pub use boids::{BoidsParams, BoidsSimulation};
+pub use boids3d::Boids3DSimulation;
+pub use grayscott::{GrayScottParams, GrayScottSimulation};
+pub use md::MdSimulation;
 // pub use sdf::SdfRenderer; // Not currently used
 
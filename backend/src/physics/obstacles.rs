@@ -0,0 +1,290 @@
+// Static obstacles for the boids simulation
+// Boids steer away from surfaces within `AVOID_MARGIN`. Discrete checks push boids
+// out of obstacles they enter during a step; the swept (continuous) check catches
+// fast boids that would tunnel through thin walls
+use serde::{Deserialize, Serialize};
+
+// Gap left between a boid and the surface it was stopped at
+const SKIN: f32 = 1e-4;
+
+/// Most obstacles a simulation accepts; bounds the per-boid loop and the kernel's obstacle buffer
+pub const MAX_OBSTACLES: usize = 32;
+/// Floats per obstacle in the kernel buffer: capsule spine (x0, y0, x1, y1) and radius
+pub const CAPSULE_FLOATS: usize = 5;
+/// Distance outside a surface at which boids start steering away, in a unit world;
+/// simulations scale it by their world size
+pub const AVOID_MARGIN: f32 = 0.05;
+// Cap on the repulsion strength (1 at the surface); keep in sync with boids.cu
+const MAX_PUSH: f32 = 3.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Obstacle {
+    Circle { x: f32, y: f32, radius: f32 },
+    /// Wall from (x0, y0) to (x1, y1) with half-width `thickness`
+    Wall { x0: f32, y0: f32, x1: f32, y1: f32, thickness: f32 },
+}
+
+/// First contact along a swept path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    /// Fraction of the path travelled before contact, in [0, 1]
+    pub t: f32,
+    /// Outward surface normal at the contact point
+    pub nx: f32,
+    pub ny: f32,
+}
+
+impl Obstacle {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let ok = match *self {
+            Obstacle::Circle { x, y, radius } => {
+                x.is_finite() && y.is_finite() && radius.is_finite() && radius > 0.0
+            }
+            Obstacle::Wall { x0, y0, x1, y1, thickness } => {
+                [x0, y0, x1, y1, thickness].iter().all(|v| v.is_finite())
+                    && thickness >= 0.0
+                    && (x0 != x1 || y0 != y1)
+            }
+        };
+        if !ok {
+            anyhow::bail!("invalid obstacle: {:?}", self);
+        }
+        Ok(())
+    }
+
+    /// Capsule the kernel sees: a circle is a capsule with a zero-length spine
+    pub fn capsule(&self) -> [f32; CAPSULE_FLOATS] {
+        match *self {
+            Obstacle::Circle { x, y, radius } => [x, y, x, y, radius],
+            Obstacle::Wall { x0, y0, x1, y1, thickness } => [x0, y0, x1, y1, thickness],
+        }
+    }
+
+    /// Signed distance from (px, py) to the surface (negative inside) and the outward normal
+    pub fn distance(&self, px: f32, py: f32) -> (f32, f32, f32) {
+        let (cx, cy, radius) = match *self {
+            Obstacle::Circle { x, y, radius } => (x, y, radius),
+            Obstacle::Wall { x0, y0, x1, y1, thickness } => {
+                // Distance to a capsule is distance to the closest point on its spine
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let s = ((px - x0) * dx + (py - y0) * dy) / (dx * dx + dy * dy);
+                let s = s.clamp(0.0, 1.0);
+                (x0 + s * dx, y0 + s * dy, thickness)
+            }
+        };
+        let (dx, dy) = (px - cx, py - cy);
+        let d = (dx * dx + dy * dy).sqrt();
+        if d > 0.0 {
+            (d - radius, dx / d, dy / d)
+        } else {
+            (-radius, 1.0, 0.0)
+        }
+    }
+
+    /// First contact of the segment (x0, y0) → (x1, y1) with the obstacle surface
+    pub fn sweep(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> Option<Hit> {
+        match *self {
+            Obstacle::Circle { x, y, radius } => sweep_circle(x0, y0, x1, y1, x, y, radius),
+            Obstacle::Wall { x0: ax, y0: ay, x1: bx, y1: by, thickness } => {
+                let len = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+                let (ux, uy) = ((bx - ax) / len, (by - ay) / len);
+                // Normal pointing to the side the path starts on
+                let (mut nx, mut ny) = (-uy, ux);
+                if (x0 - ax) * nx + (y0 - ay) * ny < 0.0 {
+                    nx = -nx;
+                    ny = -ny;
+                }
+                // Heights above the near face at both ends of the path
+                let h0 = (x0 - ax) * nx + (y0 - ay) * ny - thickness;
+                let h1 = (x1 - ax) * nx + (y1 - ay) * ny - thickness;
+                let face = if h0 >= 0.0 && h1 < 0.0 {
+                    let t = h0 / (h0 - h1);
+                    let along = (x0 + t * (x1 - x0) - ax) * ux + (y0 + t * (y1 - y0) - ay) * uy;
+                    (0.0..=len).contains(&along).then_some(Hit { t, nx, ny })
+                } else {
+                    None
+                };
+                // The rounded ends catch paths that clip a corner
+                let caps = [
+                    sweep_circle(x0, y0, x1, y1, ax, ay, thickness),
+                    sweep_circle(x0, y0, x1, y1, bx, by, thickness),
+                ];
+                face.into_iter()
+                    .chain(caps.into_iter().flatten())
+                    .min_by(|a, b| a.t.total_cmp(&b.t))
+            }
+        }
+    }
+}
+
+fn sweep_circle(x0: f32, y0: f32, x1: f32, y1: f32, cx: f32, cy: f32, r: f32) -> Option<Hit> {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let (fx, fy) = (x0 - cx, y0 - cy);
+    let a = dx * dx + dy * dy;
+    let c = fx * fx + fy * fy - r * r;
+    if a == 0.0 || c < 0.0 {
+        // Not moving, or already inside (left to the discrete check)
+        return None;
+    }
+    let b = 2.0 * (fx * dx + fy * dy);
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = (-b - disc.sqrt()) / (2.0 * a);
+    if !(0.0..=1.0).contains(&t) {
+        return None;
+    }
+    let (hx, hy) = (fx + t * dx, fy + t * dy);
+    let len = (hx * hx + hy * hy).sqrt().max(f32::EPSILON);
+    Some(Hit { t, nx: hx / len, ny: hy / len })
+}
+
+/// Reject obstacle sets the simulation (and kernel buffer) can't hold
+pub fn validate_all(obstacles: &[Obstacle]) -> anyhow::Result<()> {
+    if obstacles.len() > MAX_OBSTACLES {
+        anyhow::bail!("at most {} obstacles are supported, got {}", MAX_OBSTACLES, obstacles.len());
+    }
+    obstacles.iter().try_for_each(Obstacle::validate)
+}
+
+/// Obstacles packed for the kernel, padded to `MAX_OBSTACLES` entries
+pub fn pack_capsules(obstacles: &[Obstacle]) -> Vec<f32> {
+    let mut packed = vec![0.0; MAX_OBSTACLES * CAPSULE_FLOATS];
+    for (slot, obstacle) in packed.chunks_exact_mut(CAPSULE_FLOATS).zip(obstacles) {
+        slot.copy_from_slice(&obstacle.capsule());
+    }
+    packed
+}
+
+/// Summed outward steering from obstacles within `margin` of (x, y).
+/// Each push grows linearly from 0 at the margin to 1 at the surface and keeps
+/// growing inside (up to `MAX_PUSH`), so a boid spawned inside eases its way out.
+pub fn avoidance(obstacles: &[Obstacle], x: f32, y: f32, margin: f32) -> (f32, f32) {
+    let (mut fx, mut fy) = (0.0, 0.0);
+    for obstacle in obstacles {
+        let (d, nx, ny) = obstacle.distance(x, y);
+        if d < margin {
+            let push = ((margin - d) / margin).min(MAX_PUSH);
+            fx += nx * push;
+            fy += ny * push;
+        }
+    }
+    (fx, fy)
+}
+
+/// Earliest hit against any obstacle along the path
+pub fn first_hit(obstacles: &[Obstacle], x0: f32, y0: f32, x1: f32, y1: f32) -> Option<Hit> {
+    obstacles
+        .iter()
+        .filter_map(|o| o.sweep(x0, y0, x1, y1))
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+/// Reflect the velocity component heading into the surface
+fn reflect(vx: &mut f32, vy: &mut f32, nx: f32, ny: f32) {
+    let vn = *vx * nx + *vy * ny;
+    if vn < 0.0 {
+        *vx -= 2.0 * vn * nx;
+        *vy -= 2.0 * vn * ny;
+    }
+}
+
+/// Move from (x, y) by (vx, vy) * dt, colliding with obstacles.
+/// With `continuous` the whole path is swept and the boid stops at the first hit,
+/// otherwise only the end position is checked. Boids that start the step inside an
+/// obstacle are left to `avoidance` rather than snapped to its surface.
+pub fn advance(
+    obstacles: &[Obstacle],
+    continuous: bool,
+    x: &mut f32,
+    y: &mut f32,
+    vx: &mut f32,
+    vy: &mut f32,
+    dt: f32,
+) {
+    let (x0, y0) = (*x, *y);
+    let (x1, y1) = (x0 + *vx * dt, y0 + *vy * dt);
+    if continuous {
+        if let Some(hit) = first_hit(obstacles, *x, *y, x1, y1) {
+            *x += (x1 - *x) * hit.t + hit.nx * SKIN;
+            *y += (y1 - *y) * hit.t + hit.ny * SKIN;
+            reflect(vx, vy, hit.nx, hit.ny);
+            return;
+        }
+    }
+    *x = x1;
+    *y = y1;
+    for obstacle in obstacles {
+        let (d, nx, ny) = obstacle.distance(*x, *y);
+        if d < 0.0 && obstacle.distance(x0, y0).0 >= 0.0 {
+            *x += nx * (SKIN - d);
+            *y += ny * (SKIN - d);
+            reflect(vx, vy, nx, ny);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THIN_WALL: Obstacle =
+        Obstacle::Wall { x0: 0.5, y0: 0.0, x1: 0.5, y1: 1.0, thickness: 0.001 };
+
+    #[test]
+    fn test_fast_boid_tunnels_without_ccd() {
+        let (mut x, mut y, mut vx, mut vy) = (0.4, 0.5, 0.05, 0.0);
+        advance(&[THIN_WALL], false, &mut x, &mut y, &mut vx, &mut vy, 4.0);
+        assert!(x > 0.5, "Endpoint check alone misses the wall");
+    }
+
+    #[test]
+    fn test_fast_boid_stops_at_thin_wall() {
+        let (mut x, mut y, mut vx, mut vy) = (0.4, 0.5, 0.05, 0.01);
+        advance(&[THIN_WALL], true, &mut x, &mut y, &mut vx, &mut vy, 4.0);
+        assert!(x < 0.5 - 0.001, "Boid should stay on the near side, got x = {}", x);
+        assert!(vx < 0.0, "Velocity into the wall should be reflected");
+        assert!((vy - 0.01).abs() < 1e-6, "Tangential velocity is kept");
+    }
+
+    #[test]
+    fn test_circle_sweep_and_push_out() {
+        let circle = Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 };
+        let hit = circle.sweep(0.2, 0.5, 0.8, 0.5).unwrap();
+        assert!((hit.t - 0.2 / 0.6).abs() < 1e-5);
+        assert!((hit.nx + 1.0).abs() < 1e-5);
+        assert!(circle.sweep(0.2, 0.8, 0.8, 0.8).is_none());
+
+        // Discrete mode pushes a boid that ends inside back to the surface
+        let (mut x, mut y, mut vx, mut vy) = (0.38, 0.5, 0.05, 0.0);
+        advance(&[circle], false, &mut x, &mut y, &mut vx, &mut vy, 1.0);
+        assert!(circle.distance(x, y).0 >= 0.0);
+        assert!(vx < 0.0);
+    }
+
+    #[test]
+    fn test_avoidance_fades_with_distance() {
+        let circle = Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 };
+        let (far, _) = avoidance(&[circle], 0.5 + 0.1 + AVOID_MARGIN * 1.5, 0.5, AVOID_MARGIN);
+        assert_eq!(far, 0.0, "No push beyond the margin");
+        let (near, _) = avoidance(&[circle], 0.5 + 0.1 + AVOID_MARGIN * 0.5, 0.5, AVOID_MARGIN);
+        let (surface, _) = avoidance(&[circle], 0.5 + 0.1, 0.5, AVOID_MARGIN);
+        let (inside, _) = avoidance(&[circle], 0.55, 0.5, AVOID_MARGIN);
+        assert!(0.0 < near && near < surface && surface < inside);
+        assert!(inside <= MAX_PUSH);
+        let (left, _) = avoidance(&[circle], 0.42, 0.5, AVOID_MARGIN);
+        assert!(left < 0.0, "Push points away from the centre");
+    }
+
+    #[test]
+    fn test_too_many_obstacles_rejected() {
+        let circle = Obstacle::Circle { x: 0.5, y: 0.5, radius: 0.1 };
+        assert!(validate_all(&vec![circle; MAX_OBSTACLES]).is_ok());
+        assert!(validate_all(&vec![circle; MAX_OBSTACLES + 1]).is_err());
+        let packed = pack_capsules(&[circle]);
+        assert_eq!(packed.len(), MAX_OBSTACLES * CAPSULE_FLOATS);
+        assert_eq!(packed[..CAPSULE_FLOATS], [0.5, 0.5, 0.5, 0.5, 0.1]);
+    }
+}
@@ -0,0 +1,97 @@
+// Predator boids: a reserved species that chases the flock, which scatters in turn
+use super::boids::Boid;
+
+/// Species id reserved for predators; never handed out to ordinary boids
+pub const PREDATOR_SPECIES: u8 = u8::MAX;
+/// Most predators `set_num_predators` accepts
+pub const MAX_PREDATORS: usize = 64;
+/// Prey within this distance of a predator flee (matches FLEE_RADIUS in boids.cu)
+pub const FLEE_RADIUS: f32 = 0.15;
+/// Above separation + alignment + cohesion so fleeing wins over flocking
+pub const FLEE_WEIGHT: f32 = 3.0;
+/// Pursuit weight relative to `max_force`
+pub const PURSUIT_WEIGHT: f32 = 1.5;
+
+pub fn is_predator(b: &Boid) -> bool {
+    b.species == PREDATOR_SPECIES
+}
+
+/// Unit direction away from the predators within `FLEE_RADIUS` of (x, y), closer ones
+/// counting more. `None` when no predator is in range.
+pub fn flee(predators: &[(f32, f32)], x: f32, y: f32) -> Option<(f32, f32)> {
+    let mut fx = 0.0;
+    let mut fy = 0.0;
+    let mut in_range = false;
+    for &(px, py) in predators {
+        let dx = x - px;
+        let dy = y - py;
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist < FLEE_RADIUS && dist > 0.0 {
+            let closeness = 1.0 - dist / FLEE_RADIUS;
+            fx += dx / dist * closeness;
+            fy += dy / dist * closeness;
+            in_range = true;
+        }
+    }
+    let mag = (fx * fx + fy * fy).sqrt();
+    if !in_range || mag == 0.0 {
+        return None;
+    }
+    Some((fx / mag, fy / mag))
+}
+
+/// Index of the prey boid nearest to boid `i`
+pub fn nearest_prey(boids: &[Boid], i: usize) -> Option<usize> {
+    let bi = &boids[i];
+    boids
+        .iter()
+        .enumerate()
+        .filter(|(j, b)| *j != i && !is_predator(b))
+        .map(|(j, b)| (j, (b.x - bi.x).powi(2) + (b.y - bi.y).powi(2)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(j, _)| j)
+}
+
+/// Steering force (before `max_force` scaling) that turns boid `i` toward its target
+pub fn pursuit(boids: &[Boid], i: usize, max_speed: f32) -> (f32, f32) {
+    let Some(j) = nearest_prey(boids, i) else {
+        return (0.0, 0.0);
+    };
+    let (bi, bj) = (&boids[i], &boids[j]);
+    let dx = bj.x - bi.x;
+    let dy = bj.y - bi.y;
+    let dist = (dx * dx + dy * dy).sqrt();
+    if dist == 0.0 {
+        return (0.0, 0.0);
+    }
+    // Reynolds seek: desired velocity minus current velocity
+    let sx = dx / dist * max_speed - bi.vx;
+    let sy = dy / dist * max_speed - bi.vy;
+    let mag = (sx * sx + sy * sy).sqrt();
+    if mag == 0.0 {
+        return (0.0, 0.0);
+    }
+    (sx / mag * PURSUIT_WEIGHT, sy / mag * PURSUIT_WEIGHT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flee_points_away_and_ignores_distant_predators() {
+        let (fx, fy) = flee(&[(0.5, 0.5)], 0.55, 0.5).unwrap();
+        assert!(fx > 0.99 && fy.abs() < 1e-6);
+        assert!(flee(&[(0.5, 0.5)], 0.5 + FLEE_RADIUS * 1.1, 0.5).is_none());
+        assert!(flee(&[], 0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn test_pursuit_targets_nearest_prey() {
+        let boid = |x: f32, species: u8| Boid { x, y: 0.5, species, ..Boid::default() };
+        let boids = [boid(0.5, PREDATOR_SPECIES), boid(0.2, 0), boid(0.6, 1), boid(0.55, PREDATOR_SPECIES)];
+        assert_eq!(nearest_prey(&boids, 0), Some(2));
+        let (sx, sy) = pursuit(&boids, 0, 0.1);
+        assert!(sx > 0.0 && sy.abs() < 1e-6);
+    }
+}
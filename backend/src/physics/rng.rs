@@ -0,0 +1,74 @@
+// Seeded PRNG shared by all simulation randomness
+// Pcg64 plus our own float/range conversions, so a seed yields the same stream on
+// every platform and across `rand` upgrades (whose `gen_range` algorithms may change)
+use rand::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+
+pub struct SimRng {
+    seed: u64,
+    inner: Pcg64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            inner: Pcg64::seed_from_u64(seed),
+        }
+    }
+
+    /// Generator with a fresh random seed, for runs that didn't ask for one
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    /// Seed this generator was created from; reusing it replays the run
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.inner.next_u32()
+    }
+
+    /// Uniform in [0, 1), built from the top 24 bits
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    /// Uniform in [lo, hi)
+    pub fn range_f32(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + (hi - lo) * self.next_f32()
+    }
+
+    /// Uniform integer in [0, n); `n` must be non-zero
+    pub fn below(&mut self, n: u32) -> u32 {
+        // Multiply-shift: branch-free and identical everywhere (bias is < n / 2^32)
+        ((self.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_is_pinned() {
+        // Golden values: if these change, every shared seed replays differently
+        let mut rng = SimRng::new(42);
+        let values: Vec<u32> = (0..4).map(|_| rng.next_u32()).collect();
+        assert_eq!(values, [2734692361, 743823633, 2592827949, 3801506028]);
+        assert_eq!(rng.seed(), 42);
+    }
+
+    #[test]
+    fn test_conversions_stay_in_range() {
+        let mut rng = SimRng::new(7);
+        for _ in 0..10_000 {
+            let f = rng.next_f32();
+            assert!((0.0..1.0).contains(&f));
+            assert!((-0.03..=0.03).contains(&rng.range_f32(-0.03, 0.03)));
+            assert!(rng.below(4) < 4);
+        }
+    }
+}
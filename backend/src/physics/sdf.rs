@@ -1,85 +1,419 @@
 // Signed Distance Field (SDF) rendering
-// Perfect circle rendering using SDF
+// Scene distances for /api/sdf/sample and antialiased primitives for /api/render/sdf
 use crate::cuda::CudaContext;
 use anyhow::Result;
-use rustacuda::memory::DeviceBuffer;
+use anyhow::Context as AnyhowContext;
+use super::storage::SimBuffer;
+#[cfg(feature = "cuda-kernel")]
+use super::kernel_cache::{self, NvrtcKernel};
+#[cfg(feature = "cuda-kernel")]
+use rustacuda::launch;
+#[cfg(feature = "cuda-kernel")]
+use rustacuda::prelude::*;
+#[cfg(feature = "cuda-kernel")]
+use std::ffi::CString;
 use std::sync::Arc;
 
-#[allow(dead_code)]
+/// A 2D scene parsed from the shape grammar:
+/// `circle(x, y, r)`, `box(x, y, half_w, half_h)`,
+/// `union(a, b, ...)`, `intersect(a, b, ...)`, `subtract(a, b)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle { x: f32, y: f32, r: f32 },
+    Box { x: f32, y: f32, half_w: f32, half_h: f32 },
+    Union(Vec<Shape>),
+    Intersect(Vec<Shape>),
+    Subtract(Box<Shape>, Box<Shape>),
+}
+
+impl Shape {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser { src, pos: 0 };
+        let shape = parser.shape()?;
+        parser.skip_ws();
+        if parser.pos != src.len() {
+            anyhow::bail!("unexpected trailing input at {}", parser.pos);
+        }
+        Ok(shape)
+    }
+
+    /// Signed distance from (x, y) to the shape surface; negative inside
+    pub fn distance(&self, x: f32, y: f32) -> f32 {
+        match self {
+            Shape::Circle { x: cx, y: cy, r } => {
+                ((x - cx).powi(2) + (y - cy).powi(2)).sqrt() - r
+            }
+            Shape::Box { x: cx, y: cy, half_w, half_h } => {
+                let qx = (x - cx).abs() - half_w;
+                let qy = (y - cy).abs() - half_h;
+                let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+                outside + qx.max(qy).min(0.0)
+            }
+            Shape::Union(shapes) => shapes
+                .iter()
+                .map(|s| s.distance(x, y))
+                .fold(f32::INFINITY, f32::min),
+            Shape::Intersect(shapes) => shapes
+                .iter()
+                .map(|s| s.distance(x, y))
+                .fold(f32::NEG_INFINITY, f32::max),
+            Shape::Subtract(a, b) => a.distance(x, y).max(-b.distance(x, y)),
+        }
+    }
+}
+
+// Recursive-descent parser over the scene string
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.src.len() - trimmed.len();
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_ws();
+        if !self.rest().starts_with(c) {
+            anyhow::bail!("expected '{}' at {}", c, self.pos);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        self.skip_ws();
+        let start = self.pos;
+        let len = self.rest().find(|c: char| !f(c)).unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.src[start..self.pos]
+    }
+
+    fn number(&mut self) -> Result<f32> {
+        let at = self.pos;
+        let token = self
+            .take_while(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+        match token.parse::<f32>() {
+            Ok(v) if v.is_finite() => Ok(v),
+            _ => anyhow::bail!("expected a number at {}", at),
+        }
+    }
+
+    fn numbers<const N: usize>(&mut self) -> Result<[f32; N]> {
+        let mut out = [0.0; N];
+        for (i, v) in out.iter_mut().enumerate() {
+            if i > 0 {
+                self.expect(',')?;
+            }
+            *v = self.number()?;
+        }
+        Ok(out)
+    }
+
+    fn shapes(&mut self) -> Result<Vec<Shape>> {
+        let mut shapes = vec![self.shape()?];
+        loop {
+            self.skip_ws();
+            if !self.rest().starts_with(',') {
+                return Ok(shapes);
+            }
+            self.pos += 1;
+            shapes.push(self.shape()?);
+        }
+    }
+
+    fn shape(&mut self) -> Result<Shape> {
+        let at = self.pos;
+        let name = self.take_while(|c| c.is_ascii_alphabetic());
+        self.expect('(')?;
+        let shape = match name {
+            "circle" => {
+                let [x, y, r] = self.numbers()?;
+                if r < 0.0 {
+                    anyhow::bail!("circle radius must be non-negative");
+                }
+                Shape::Circle { x, y, r }
+            }
+            "box" => {
+                let [x, y, half_w, half_h] = self.numbers()?;
+                if half_w < 0.0 || half_h < 0.0 {
+                    anyhow::bail!("box extents must be non-negative");
+                }
+                Shape::Box { x, y, half_w, half_h }
+            }
+            "union" | "intersect" => {
+                let shapes = self.shapes()?;
+                if shapes.len() < 2 {
+                    anyhow::bail!("{} needs at least two shapes", name);
+                }
+                if name == "union" {
+                    Shape::Union(shapes)
+                } else {
+                    Shape::Intersect(shapes)
+                }
+            }
+            "subtract" => {
+                let a = self.shape()?;
+                self.expect(',')?;
+                let b = self.shape()?;
+                Shape::Subtract(Box::new(a), Box::new(b))
+            }
+            "" => anyhow::bail!("expected a shape at {}", at),
+            other => anyhow::bail!("unknown shape '{}' at {}", other, at),
+        };
+        self.expect(')')?;
+        Ok(shape)
+    }
+}
+
+#[cfg(feature = "cuda-kernel")]
+pub const SDF_KERNEL: NvrtcKernel = NvrtcKernel {
+    name: "sdf_render",
+    source: r#"
+        // Must match `primitive_distance` in sdf.rs
+        __device__ float sdf(int primitive, float half, float px, float py) {
+            float ax = fabsf(px);
+            float ay = fabsf(py);
+            if (primitive == 0) {
+                return sqrtf(px * px + py * py) - half;
+            }
+            // Box (corner radius 0) or rounded box (a quarter of the half-extent)
+            float r = primitive == 2 ? half * 0.25f : 0.0f;
+            float qx = ax - (half - r);
+            float qy = ay - (half - r);
+            float ox = fmaxf(qx, 0.0f);
+            float oy = fmaxf(qy, 0.0f);
+            return sqrtf(ox * ox + oy * oy) + fminf(fmaxf(qx, qy), 0.0f) - r;
+        }
+
+        extern "C" __global__ void sdf_render(
+            const int width, const int height, const int primitive, const float half,
+            unsigned char* out
+        ) {
+            int x = blockIdx.x * blockDim.x + threadIdx.x;
+            int y = blockIdx.y * blockDim.y + threadIdx.y;
+            if (x >= width || y >= height) return;
+
+            float px = x + 0.5f - width * 0.5f;
+            float py = y + 0.5f - height * 0.5f;
+            float d = sdf(primitive, half, px, py);
+            // Scale the distance by its gradient so edges are one pixel wide
+            float gx = 0.5f * (sdf(primitive, half, px + 1.0f, py) - sdf(primitive, half, px - 1.0f, py));
+            float gy = 0.5f * (sdf(primitive, half, px, py + 1.0f) - sdf(primitive, half, px, py - 1.0f));
+            float g = sqrtf(gx * gx + gy * gy);
+            if (g < 1e-6f) g = 1.0f;
+            float coverage = fminf(fmaxf(0.5f - d / g, 0.0f), 1.0f);
+            unsigned char v = (unsigned char)(coverage * 255.0f + 0.5f);
+
+            int idx = (y * width + x) * 4;
+            out[idx] = v;
+            out[idx + 1] = v;
+            out[idx + 2] = v;
+            out[idx + 3] = 255;
+        }
+        "#,
+};
+
+/// Shape drawn by `SdfRenderer`, centered in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    Circle,
+    Box,
+    RoundedBox,
+}
+
+impl Primitive {
+    #[cfg(feature = "cuda-kernel")]
+    fn kernel_code(self) -> i32 {
+        match self {
+            Primitive::Circle => 0,
+            Primitive::Box => 1,
+            Primitive::RoundedBox => 2,
+        }
+    }
+
+    /// Signed distance in pixels from a point relative to the center; `half` is the
+    /// radius or half-extent
+    pub fn distance(self, half: f32, px: f32, py: f32) -> f32 {
+        if self == Primitive::Circle {
+            return (px * px + py * py).sqrt() - half;
+        }
+        let r = if self == Primitive::RoundedBox { half * 0.25 } else { 0.0 };
+        let qx = px.abs() - (half - r);
+        let qy = py.abs() - (half - r);
+        let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+        outside + qx.max(qy).min(0.0) - r
+    }
+}
+
+impl std::str::FromStr for Primitive {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "circle" => Ok(Primitive::Circle),
+            "box" => Ok(Primitive::Box),
+            "rounded_box" => Ok(Primitive::RoundedBox),
+            other => anyhow::bail!(
+                "unknown sdf_function '{}', expected circle, box or rounded_box",
+                other
+            ),
+        }
+    }
+}
+
+/// Largest accepted image width or height
+pub const MAX_RENDER_SIZE: usize = 2048;
+
+/// Reject image sizes and shape sizes `SdfRenderer` can't draw
+pub fn check_render_size(width: usize, height: usize, size: f32) -> Result<()> {
+    if width == 0 || height == 0 || width > MAX_RENDER_SIZE || height > MAX_RENDER_SIZE {
+        anyhow::bail!("image size must be 1..={} per side, got {}x{}", MAX_RENDER_SIZE, width, height);
+    }
+    if !(size > 0.0 && size <= 1.0) {
+        anyhow::bail!("size must be in (0, 1], got {}", size);
+    }
+    Ok(())
+}
+
+/// Half-extent in pixels for a `size` in (0, 1] of half the shorter side
+fn half_extent(width: usize, height: usize, size: f32) -> f32 {
+    width.min(height) as f32 / 2.0 * size
+}
+
+/// CPU reference for the `sdf_render` kernel: white shape on black, with
+/// edges antialiased over one pixel using the distance gradient
+pub fn render_rgba(primitive: Primitive, size: f32, width: usize, height: usize) -> Vec<u8> {
+    let half = half_extent(width, height, size);
+    let d = |px: f32, py: f32| primitive.distance(half, px, py);
+    let mut out = vec![0u8; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let px = x as f32 + 0.5 - width as f32 * 0.5;
+            let py = y as f32 + 0.5 - height as f32 * 0.5;
+            let gx = 0.5 * (d(px + 1.0, py) - d(px - 1.0, py));
+            let gy = 0.5 * (d(px, py + 1.0) - d(px, py - 1.0));
+            let g = (gx * gx + gy * gy).sqrt();
+            let g = if g < 1e-6 { 1.0 } else { g };
+            let coverage = (0.5 - d(px, py) / g).clamp(0.0, 1.0);
+            let v = (coverage * 255.0 + 0.5) as u8;
+            let idx = (y * width + x) * 4;
+            out[idx..idx + 4].copy_from_slice(&[v, v, v, 255]);
+        }
+    }
+    out
+}
+
+/// Encode RGBA pixels as a PNG
+pub fn encode_png(rgba: Vec<u8>, width: usize, height: usize) -> Result<Vec<u8>> {
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, rgba)
+        .ok_or_else(|| anyhow::anyhow!("RGBA buffer doesn't match {}x{}", width, height))?;
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| anyhow::anyhow!("Failed to encode PNG: {:?}", e))?;
+    Ok(png)
+}
+
 pub struct SdfRenderer {
     #[allow(dead_code)]
     context: Arc<CudaContext>,
     width: usize,
     height: usize,
-    output: DeviceBuffer<u8>,
+    #[cfg_attr(not(feature = "cuda-kernel"), allow(dead_code))]
+    output: SimBuffer<u8>,
+    #[cfg(feature = "cuda-kernel")]
+    ptx: Arc<String>,
 }
 
-#[allow(dead_code)]
 impl SdfRenderer {
     pub fn new(context: &Arc<CudaContext>, width: usize, height: usize) -> Result<Self> {
         // Context should already be initialized by caller
+        check_render_size(width, height, 1.0)?;
         
         let size = width * height * 4; // RGBA
         
         // Initialize output buffer
         let output_host = vec![0u8; size];
-        let output = DeviceBuffer::from_slice(&output_host)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate output buffer: {:?}", e))?;
+        let output = SimBuffer::from_slice(&output_host)
+            .context("Failed to allocate output buffer")?;
+
+        #[cfg(feature = "cuda-kernel")]
+        let ptx = kernel_cache::ptx(&SDF_KERNEL)?;
         
         Ok(Self {
             context: Arc::clone(context),
             width,
             height,
             output,
+            #[cfg(feature = "cuda-kernel")]
+            ptx,
         })
     }
 
-    pub fn render(&self, _sdf_function: &str) -> Result<Vec<u8>> {
-        let size = self.width * self.height * 4;
-        let mut output_host = vec![0u8; size];
-        
-        // Simple CPU-based SDF rendering for now
-        // TODO: Implement CUDA kernel
-        let center_x = self.width as f32 / 2.0;
-        let center_y = self.height as f32 / 2.0;
-        let radius = (self.width.min(self.height) as f32 / 2.0) * 0.8;
-        
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let dx = x as f32 - center_x;
-                let dy = y as f32 - center_y;
-                let dist = (dx * dx + dy * dy).sqrt();
-                
-                // SDF for circle
-                let sdf = dist - radius;
-                
-                // Convert SDF to color (simple threshold for now)
-                let idx = (y * self.width + x) * 4;
-                if sdf < 0.0 {
-                    // Inside circle
-                    output_host[idx] = 255;     // R
-                    output_host[idx + 1] = 255; // G
-                    output_host[idx + 2] = 255; // B
-                    output_host[idx + 3] = 255; // A
-                } else {
-                    // Outside circle
-                    output_host[idx] = 0;       // R
-                    output_host[idx + 1] = 0;   // G
-                    output_host[idx + 2] = 0;   // B
-                    output_host[idx + 3] = 255; // A
-                }
+    /// Render the primitive named by `sdf_function` (`circle`, `box` or `rounded_box`)
+    /// as RGBA, sized to `size` in (0, 1] of half the shorter image side
+    pub fn render(&mut self, sdf_function: &str, size: f32) -> Result<Vec<u8>> {
+        let primitive: Primitive = sdf_function.parse()?;
+        check_render_size(self.width, self.height, size)?;
+
+        #[cfg(feature = "cuda-kernel")]
+        {
+            let ptx_c = CString::new(self.ptx.as_str()).unwrap();
+            let module = Module::load_from_string(&ptx_c)
+                .map_err(|e| anyhow::anyhow!("Failed to load SDF PTX: {:?}", e))?;
+            let func = module
+                .get_function(&CString::new("sdf_render").unwrap())
+                .map_err(|e| anyhow::anyhow!("Failed to get sdf_render: {:?}", e))?;
+            let stream = Stream::new(StreamFlags::DEFAULT, None)
+                .map_err(|e| anyhow::anyhow!("Failed to create stream: {:?}", e))?;
+
+            let block = (16u32, 16u32, 1u32);
+            let grid = (
+                (self.width as u32).div_ceil(block.0),
+                (self.height as u32).div_ceil(block.1),
+                1u32,
+            );
+            unsafe {
+                launch!(
+                    func<<<grid, block, 0, stream>>>(
+                        self.width as i32,
+                        self.height as i32,
+                        primitive.kernel_code(),
+                        half_extent(self.width, self.height, size),
+                        self.output.as_device_ptr()
+                    )
+                )
+                .map_err(|e| anyhow::anyhow!("sdf_render launch failed: {:?}", e))?;
             }
+            stream
+                .synchronize()
+                .map_err(|e| anyhow::anyhow!("sdf_render sync failed: {:?}", e))?;
+            let mut output_host = vec![0u8; self.width * self.height * 4];
+            self.output
+                .copy_to(&mut output_host[..])
+                .map_err(|e| anyhow::anyhow!("Failed to copy SDF output: {:?}", e))?;
+            Ok(output_host)
         }
-        
-        Ok(output_host)
+
+        #[cfg(not(feature = "cuda-kernel"))]
+        Ok(render_rgba(primitive, size, self.width, self.height))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::init_cuda_in_thread;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -89,6 +423,73 @@ mod tests {
         (Arc::new(CudaContext::new().expect("Failed to create CUDA context")), context_obj)
     }
 
+    #[test]
+    fn test_scene_distance() {
+        let circle = Shape::parse("circle(0.5, 0.5, 0.25)").unwrap();
+        assert!((circle.distance(0.5, 0.5) + 0.25).abs() < 1e-6);
+        assert!((circle.distance(1.0, 0.5) - 0.25).abs() < 1e-6);
+
+        let ring = Shape::parse("subtract(circle(0,0,1), circle(0,0,0.5))").unwrap();
+        assert!(ring.distance(0.0, 0.0) > 0.0, "Hole is outside");
+        assert!(ring.distance(0.75, 0.0) < 0.0);
+
+        let scene = Shape::parse(" union( box(0,0,1,1) , circle(3,0,1), circle(-3,0,1) ) ").unwrap();
+        assert!((scene.distance(0.0, 2.0) - 1.0).abs() < 1e-6);
+        assert!(scene.distance(3.0, 0.0) < 0.0);
+
+        let lens = Shape::parse("intersect(circle(-0.5,0,1), circle(0.5,0,1))").unwrap();
+        assert!(lens.distance(0.0, 0.0) < 0.0 && lens.distance(1.2, 0.0) > 0.0);
+    }
+
+    #[test]
+    fn test_scene_parse_errors() {
+        for bad in [
+            "",
+            "circle(0, 0)",
+            "circle(0, 0, -1)",
+            "triangle(0, 0, 1)",
+            "union(circle(0,0,1))",
+            "circle(0, 0, 1) extra",
+            "circle(0, 0, nan)",
+            "subtract(circle(0,0,1)",
+        ] {
+            assert!(Shape::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_render_rgba_size_and_antialiasing() {
+        for primitive in [Primitive::Circle, Primitive::Box, Primitive::RoundedBox] {
+            let (width, height) = (64, 48);
+            let out = render_rgba(primitive, 0.45, width, height);
+            assert_eq!(out.len(), width * height * 4);
+            let pixel = |x: usize, y: usize| out[(y * width + x) * 4];
+            assert_eq!(pixel(32, 24), 255, "{:?} center is inside", primitive);
+            assert_eq!(pixel(0, 0), 0, "{:?} corner is outside", primitive);
+            assert!(out.chunks_exact(4).all(|p| p[3] == 255));
+            assert!(
+                out.chunks_exact(4).any(|p| p[0] > 0 && p[0] < 255),
+                "{:?} edges should be antialiased",
+                primitive
+            );
+        }
+        // The rounded box's corners are cut away where the plain box is solid
+        let half = 12.0;
+        assert!(Primitive::Box.distance(half, 11.5, 11.5) < 0.0);
+        assert!(Primitive::RoundedBox.distance(half, 11.5, 11.5) > 0.0);
+        assert!("triangle".parse::<Primitive>().is_err());
+    }
+
+    #[test]
+    fn test_encode_png_roundtrip() {
+        let rgba = render_rgba(Primitive::Circle, 0.8, 16, 8);
+        let png = encode_png(rgba.clone(), 16, 8).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (16, 8));
+        assert_eq!(decoded.into_raw(), rgba);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sdf_initialization() {
         let (context, _context_guard) = setup_test_context();
@@ -96,19 +497,21 @@ mod tests {
         assert!(renderer.is_ok(), "SDF renderer should initialize");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sdf_render() {
         let (context, _context_guard) = setup_test_context();
-        let renderer = SdfRenderer::new(&context, 512, 512).unwrap();
-        let result = renderer.render("circle");
+        let mut renderer = SdfRenderer::new(&context, 512, 512).unwrap();
+        let result = renderer.render("circle", 0.8);
         assert!(result.is_ok(), "SDF render should succeed");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sdf_output_size() {
         let (context, _context_guard) = setup_test_context();
-        let renderer = SdfRenderer::new(&context, 512, 512).unwrap();
-        let output = renderer.render("circle").unwrap();
+        let mut renderer = SdfRenderer::new(&context, 512, 512).unwrap();
+        let output = renderer.render("circle", 0.8).unwrap();
         assert_eq!(output.len(), 512 * 512 * 4, "Should return RGBA image");
     }
 }
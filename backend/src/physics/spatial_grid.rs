@@ -0,0 +1,215 @@
+// Uniform binning over the world square for the boids CPU fallback
+// Neighbour queries only visit the 3x3 block of cells around a boid, so a step
+// scales roughly linearly for evenly spread flocks instead of O(n²)
+use super::boids::Boid;
+
+/// Cap on cells per side so tiny radii don't allocate huge grids
+const MAX_CELLS_PER_SIDE: usize = 256;
+
+/// Boid indices bucketed by cell; buffers are reused across rebuilds
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells_per_side: usize,
+    // Side length of the binned square: the unit square, grown to hold every boid
+    extent: f32,
+    // Cell c holds items[start[c]..start[c + 1]]
+    start: Vec<u32>,
+    items: Vec<u32>,
+    cell_of: Vec<u32>,
+}
+
+impl SpatialGrid {
+    /// Bin `boids` into square cells at least `min_cell_size` wide, covering the unit
+    /// square or a larger world. Boids below zero (open boundary) land in the edge cell.
+    pub fn rebuild(&mut self, boids: &[Boid], min_cell_size: f32) {
+        self.extent = boids
+            .iter()
+            .flat_map(|b| [b.x, b.y])
+            .filter(|v| v.is_finite())
+            .fold(1.0, f32::max);
+        self.cells_per_side = if min_cell_size.is_finite() && min_cell_size > 0.0 {
+            ((self.extent / min_cell_size).floor() as usize).clamp(1, MAX_CELLS_PER_SIDE)
+        } else {
+            1
+        };
+        let cells = self.cells_per_side * self.cells_per_side;
+
+        // Counting sort: per-cell counts, running totals, then place back-to-front
+        self.start.clear();
+        self.start.resize(cells + 1, 0);
+        self.cell_of.clear();
+        for b in boids {
+            let cell = self.cell_index(b.x, b.y);
+            self.cell_of.push(cell as u32);
+            self.start[cell] += 1;
+        }
+        for c in 1..cells {
+            self.start[c] += self.start[c - 1];
+        }
+        self.start[cells] = boids.len() as u32;
+        self.items.clear();
+        self.items.resize(boids.len(), 0);
+        for (i, &cell) in self.cell_of.iter().enumerate().rev() {
+            let slot = &mut self.start[cell as usize];
+            *slot -= 1;
+            self.items[*slot as usize] = i as u32;
+        }
+    }
+
+    /// Every boid binned in the 3x3 block of cells around boid `i`'s cell, excluding `i`.
+    /// Includes everyone within `min_cell_size` of `i` as of the last rebuild.
+    pub fn near(&self, i: usize, out: &mut Vec<usize>) {
+        out.clear();
+        let side = self.cells_per_side;
+        let cell = self.cell_of[i] as usize;
+        let (cx, cy) = (cell % side, cell / side);
+        for y in cy.saturating_sub(1)..=(cy + 1).min(side - 1) {
+            for x in cx.saturating_sub(1)..=(cx + 1).min(side - 1) {
+                let c = y * side + x;
+                let range = self.start[c] as usize..self.start[c + 1] as usize;
+                out.extend(
+                    self.items[range]
+                        .iter()
+                        .map(|&j| j as usize)
+                        .filter(|&j| j != i),
+                );
+            }
+        }
+    }
+
+    /// Side length of a cell as of the last rebuild
+    pub fn cell_size(&self) -> f32 {
+        self.extent / self.cells_per_side as f32
+    }
+
+    /// Number of rings around any cell; rings `0..rings()` together cover the whole grid
+    pub fn rings(&self) -> usize {
+        self.cells_per_side
+    }
+
+    /// Every boid binned in the cells exactly `r` cells away from boid `i`'s cell
+    /// (Chebyshev distance), excluding `i`. Boids outside rings `0..=r` are more than
+    /// `r * cell_size()` from `i` as of the last rebuild.
+    pub fn ring(&self, i: usize, r: usize, out: &mut Vec<usize>) {
+        out.clear();
+        let side = self.cells_per_side;
+        let cell = self.cell_of[i] as usize;
+        let (cx, cy) = (cell % side, cell / side);
+        let (x0, x1) = (cx.saturating_sub(r), (cx + r).min(side - 1));
+        for y in cy.saturating_sub(r)..=(cy + r).min(side - 1) {
+            let edge_row = y.abs_diff(cy) == r;
+            for x in x0..=x1 {
+                if !edge_row && x.abs_diff(cx) != r {
+                    continue;
+                }
+                let c = y * side + x;
+                let range = self.start[c] as usize..self.start[c + 1] as usize;
+                out.extend(
+                    self.items[range]
+                        .iter()
+                        .map(|&j| j as usize)
+                        .filter(|&j| j != i),
+                );
+            }
+        }
+    }
+
+    fn cell_index(&self, x: f32, y: f32) -> usize {
+        let side = self.cells_per_side;
+        // Clamping keeps boids that are within one cell of each other in adjacent cells
+        let scale = side as f32 / self.extent;
+        let axis = |v: f32| ((v * scale).floor().max(0.0) as usize).min(side - 1);
+        axis(y) * side + axis(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::rng::SimRng;
+
+    #[test]
+    fn test_near_finds_everyone_within_cell_size() {
+        let mut rng = SimRng::new(5);
+        // Include strays outside the unit square, as in open boundary mode
+        let boids: Vec<Boid> = (0..500)
+            .map(|_| Boid {
+                x: rng.range_f32(-0.2, 1.2),
+                y: rng.range_f32(-0.2, 1.2),
+                ..Boid::default()
+            })
+            .collect();
+        let radius = 0.07;
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(&boids, radius);
+
+        let mut near = Vec::new();
+        for (i, bi) in boids.iter().enumerate() {
+            grid.near(i, &mut near);
+            assert!(!near.contains(&i));
+            for (j, bj) in boids.iter().enumerate() {
+                let (dx, dy) = (bi.x - bj.x, bi.y - bj.y);
+                if i != j && dx * dx + dy * dy < radius * radius {
+                    assert!(near.contains(&j), "Boid {} missed neighbour {}", i, j);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_grows_to_cover_a_larger_world() {
+        let mut rng = SimRng::new(6);
+        let boids: Vec<Boid> = (0..500)
+            .map(|_| Boid { x: rng.range_f32(0.0, 10.0), y: rng.range_f32(0.0, 10.0), ..Boid::default() })
+            .collect();
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(&boids, 0.7);
+        assert_eq!(grid.cells_per_side, 14);
+
+        let mut near = Vec::new();
+        grid.near(0, &mut near);
+        assert!(near.len() < boids.len() / 4, "boids far across the world shouldn't share cells");
+        for (j, bj) in boids.iter().enumerate().skip(1) {
+            if (boids[0].x - bj.x).powi(2) + (boids[0].y - bj.y).powi(2) < 0.49 {
+                assert!(near.contains(&j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rings_partition_the_grid_by_distance() {
+        let mut rng = SimRng::new(7);
+        let boids: Vec<Boid> = (0..300)
+            .map(|_| Boid { x: rng.range_f32(-0.1, 1.1), y: rng.range_f32(-0.1, 1.1), ..Boid::default() })
+            .collect();
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(&boids, 0.1);
+
+        let (mut ring, mut seen) = (Vec::new(), Vec::new());
+        for r in 0..grid.rings() {
+            grid.ring(0, r, &mut ring);
+            seen.extend_from_slice(&ring);
+            // Anyone closer than r cells must already have turned up
+            let bound = r as f32 * grid.cell_size();
+            for (j, bj) in boids.iter().enumerate().skip(1) {
+                if (boids[0].x - bj.x).powi(2) + (boids[0].y - bj.y).powi(2) < bound * bound {
+                    assert!(seen.contains(&j), "Ring {} missed boid {}", r, j);
+                }
+            }
+        }
+        seen.sort_unstable();
+        assert_eq!(seen, (1..boids.len()).collect::<Vec<_>>(), "Each boid appears in exactly one ring");
+    }
+
+    #[test]
+    fn test_degenerate_cell_sizes_use_one_cell() {
+        let boids = vec![Boid::default(); 3];
+        let mut grid = SpatialGrid::default();
+        for size in [0.0, f32::INFINITY, 2.0] {
+            grid.rebuild(&boids, size);
+            let mut near = Vec::new();
+            grid.near(0, &mut near);
+            assert_eq!(near, [1, 2]);
+        }
+    }
+}
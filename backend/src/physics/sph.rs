@@ -1,10 +1,22 @@
 // SPH (Smoothed Particle Hydrodynamics) simulation
 // Based on Navier-Stokes equations discretized using SPH
+use super::accelerator::{Accelerator, CudaFailures};
+use super::rng::SimRng;
+use super::storage::{SimBuffer, Storage};
+use super::substeps;
+use super::verlet::VerletList;
 use crate::cuda::CudaContext;
 use anyhow::Result;
+use anyhow::Context as AnyhowContext;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::launch;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::prelude::*;
-use rustacuda::memory::DeviceBuffer;
+#[cfg(not(feature = "no-cuda"))]
 use rustacuda::memory::DeviceCopy;
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "no-cuda"))]
+use std::ffi::CString;
 use std::sync::Arc;
 
 #[repr(C)]
@@ -18,174 +30,712 @@ pub struct Particle {
     pub pressure: f32,
 }
 
+#[cfg(not(feature = "no-cuda"))]
 unsafe impl DeviceCopy for Particle {}
 
+/// Particles when a request doesn't say
+pub const DEFAULT_NUM_PARTICLES: usize = 1000;
+/// Largest particle count accepted, so a typo can't exhaust device memory
+pub const MAX_NUM_PARTICLES: usize = 50_000;
+// Particles per ring of the initial layout; more particles add inner rings
+const PARTICLES_PER_RING: usize = 1000;
+// Largest random velocity added to each particle of the initial layout
+const INITIAL_VELOCITY_NOISE: f32 = 0.005;
+// GPU steps between the device->host copies that check whether the fluid blew up
+const STABILITY_CHECK_INTERVAL: u32 = 16;
+/// Speed cap; at dt = 0.016 a particle moves less than one smoothing radius per step
+pub const MAX_SPEED: f32 = 5.0;
+/// Largest gravity component accepted
+pub const MAX_GRAVITY: f32 = 100.0;
+// Accepted fluid properties. Each is positive (surface tension may be zero) and
+// capped where the explicit integrator stays stable at dt = 0.016.
+pub const MAX_REST_DENSITY: f32 = 10_000.0;
+pub const MAX_GAS_CONSTANT: f32 = 10_000.0;
+pub const MAX_VISCOSITY: f32 = 1.0;
+pub const MAX_SURFACE_TENSION: f32 = 10.0;
+/// Smaller radii than this let a particle at `MAX_SPEED` skip past its neighbors in one step
+pub const MIN_SMOOTHING_RADIUS: f32 = 0.02;
+pub const MAX_SMOOTHING_RADIUS: f32 = 0.25;
+pub const MAX_PARTICLE_MASS: f32 = 1.0;
+pub const MAX_VORTICITY_CONFINEMENT: f32 = 1.0;
+/// Radius the smoothing kernel is normalized against; other radii are rescaled to match it
+pub const DEFAULT_SMOOTHING_RADIUS: f32 = 0.1;
+
+/// Options accepted by the SPH endpoint; omitted ones keep their `FluidParams` default
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SphParams {
+    /// Constant acceleration on every particle, e.g. `[0.0, -9.8]` (default none)
+    pub gravity: Option<(f32, f32)>,
+    pub rest_density: Option<f32>,
+    pub gas_constant: Option<f32>,
+    /// Higher is thicker: water is near the default, honey towards `MAX_VISCOSITY`
+    pub viscosity: Option<f32>,
+    /// Cohesion between neighboring particles, so droplets hold together (default 0)
+    pub surface_tension: Option<f32>,
+    pub smoothing_radius: Option<f32>,
+    pub mass: Option<f32>,
+    /// Strength of the force that keeps swirls from smearing out (default 0, off).
+    /// Only the CPU path has it, so a non-zero value runs on the CPU.
+    pub vorticity_confinement: Option<f32>,
+}
+
+fn check_range(name: &str, value: Option<f32>, min: f32, max: f32, min_inclusive: bool) -> Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    let above_min = if min_inclusive { value >= min } else { value > min };
+    if !(above_min && value <= max) {
+        let open = if min_inclusive { '[' } else { '(' };
+        anyhow::bail!("{} must be in {}{}, {}], got {}", name, open, min, max, value);
+    }
+    Ok(())
+}
+
+impl SphParams {
+    pub fn validate(&self) -> Result<()> {
+        if let Some((gx, gy)) = self.gravity {
+            if !(gx.is_finite() && gy.is_finite() && gx.abs() <= MAX_GRAVITY && gy.abs() <= MAX_GRAVITY) {
+                anyhow::bail!(
+                    "gravity components must be finite and within ±{}, got ({}, {})",
+                    MAX_GRAVITY,
+                    gx,
+                    gy
+                );
+            }
+        }
+        check_range("rest_density", self.rest_density, 0.0, MAX_REST_DENSITY, false)?;
+        check_range("gas_constant", self.gas_constant, 0.0, MAX_GAS_CONSTANT, false)?;
+        check_range("viscosity", self.viscosity, 0.0, MAX_VISCOSITY, false)?;
+        check_range("surface_tension", self.surface_tension, 0.0, MAX_SURFACE_TENSION, true)?;
+        check_range(
+            "smoothing_radius",
+            self.smoothing_radius,
+            MIN_SMOOTHING_RADIUS,
+            MAX_SMOOTHING_RADIUS,
+            true,
+        )?;
+        check_range("mass", self.mass, 0.0, MAX_PARTICLE_MASS, false)?;
+        check_range(
+            "vorticity_confinement",
+            self.vorticity_confinement,
+            0.0,
+            MAX_VORTICITY_CONFINEMENT,
+            true,
+        )
+    }
+}
+
+/// The fluid properties a simulation is running with, as reported in response metadata
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct FluidParams {
+    pub rest_density: f32,
+    pub gas_constant: f32,
+    pub viscosity: f32,
+    pub surface_tension: f32,
+    pub smoothing_radius: f32,
+    pub mass: f32,
+    pub gravity: (f32, f32),
+    pub vorticity_confinement: f32,
+}
+
+impl Default for FluidParams {
+    fn default() -> Self {
+        Self {
+            rest_density: 1000.0,
+            gas_constant: 2000.0,
+            viscosity: 0.018,
+            surface_tension: 0.0,
+            smoothing_radius: DEFAULT_SMOOTHING_RADIUS,
+            mass: 0.02,
+            gravity: (0.0, 0.0),
+            vorticity_confinement: 0.0,
+        }
+    }
+}
+
+impl FluidParams {
+    /// Pressure floor and speed cap: with gravity on, pressure can't go negative and
+    /// speeds are capped at `MAX_SPEED` to keep the pooling fluid stable. Without it
+    /// neither applies, as before gravity existed.
+    fn limits(&self) -> (f32, f32) {
+        if self.gravity == (0.0, 0.0) {
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else {
+            (0.0, MAX_SPEED)
+        }
+    }
+}
+
+/// Factors for W, dW/dr and the Laplacian that keep the spline normalized as the
+/// radius changes. In 2D the kernel's integral grows with h², so W is scaled by
+/// (h0 / h)² and each derivative adds another h0 / h; all are 1 at the default radius.
+fn kernel_scales(h: f32) -> (f32, f32, f32) {
+    let r = DEFAULT_SMOOTHING_RADIUS / h;
+    (r * r, r * r * r, r * r * r * r)
+}
+
+// Cubic spline kernel W(q) and its derivatives, q = r / h; same as sph.cu
+fn spline_w(q: f32) -> f32 {
+    if q < 1.0 {
+        let q2 = q * q;
+        1.0 - 1.5 * q2 + 0.75 * q2 * q
+    } else if q < 2.0 {
+        0.25 * (2.0 - q) * (2.0 - q) * (2.0 - q)
+    } else {
+        0.0
+    }
+}
+
+fn spline_dw(q: f32) -> f32 {
+    if q < 1.0 {
+        -3.0 * q + 2.25 * q * q
+    } else if q < 2.0 {
+        -0.75 * (2.0 - q) * (2.0 - q)
+    } else {
+        0.0
+    }
+}
+
+fn spline_laplacian(q: f32) -> f32 {
+    if q < 1.0 {
+        3.0 - 4.5 * q
+    } else if q < 2.0 {
+        1.5 * (2.0 - q)
+    } else {
+        0.0
+    }
+}
+
+/// Density at (x, y) from every particle within the smoothing radius
+fn density_at<'a>(particles: impl IntoIterator<Item = &'a Particle>, x: f32, y: f32, fluid: &FluidParams) -> f32 {
+    let (w_scale, _, _) = kernel_scales(fluid.smoothing_radius);
+    particles
+        .into_iter()
+        .map(|p| {
+            let dist = ((x - p.x) * (x - p.x) + (y - p.y) * (y - p.y)).sqrt();
+            if dist < fluid.smoothing_radius {
+                fluid.mass * w_scale * spline_w(dist / fluid.smoothing_radius)
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+/// Candidates for particle `i`'s neighbours: its Verlet list, or every other particle
+fn neighbors_into(list: Option<&VerletList>, i: usize, n: usize, out: &mut Vec<usize>) {
+    out.clear();
+    match list {
+        Some(list) => out.extend(list.neighbors(i).iter().map(|&j| j as usize)),
+        None => out.extend((0..n).filter(|&j| j != i)),
+    }
+}
+
+/// One CPU step of `particles`: densities, then forces from a consistent snapshot like
+/// the kernels, then integration. Neighbours come from `list` (already updated for these
+/// positions) or, without one, an all-pairs scan.
+fn cpu_step(particles: &mut [Particle], fluid: &FluidParams, dt: f32, list: Option<&VerletList>) {
+    let n = particles.len();
+    let (w_scale, grad_scale, lap_scale) = kernel_scales(fluid.smoothing_radius);
+    let (min_pressure, max_speed) = fluid.limits();
+    let mut near = Vec::new();
+
+    // SPH density calculation, starting from each particle's own contribution
+    for i in 0..n {
+        neighbors_into(list, i, n, &mut near);
+        let pi = particles[i];
+        let density = fluid.mass * w_scale * spline_w(0.0)
+            + density_at(near.iter().map(|&j| &particles[j]), pi.x, pi.y, fluid);
+        particles[i].density = density;
+        // Pressure from equation of state
+        particles[i].pressure = (fluid.gas_constant * (density - fluid.rest_density)).max(min_pressure);
+    }
+
+    // SPH force calculation
+    let mut accel = vec![(0.0f32, 0.0f32); n];
+    for (i, a) in accel.iter_mut().enumerate() {
+        neighbors_into(list, i, n, &mut near);
+        let mut fx = 0.0;
+        let mut fy = 0.0;
+        let pi = &particles[i];
+
+        for &j in &near {
+            let pj = &particles[j];
+            let dx = pi.x - pj.x;
+            let dy = pi.y - pj.y;
+            let dist_sq = dx * dx + dy * dy;
+            let dist = dist_sq.sqrt().max(0.0001); // Avoid division by zero
+            
+            if dist < fluid.smoothing_radius {
+                // Pressure force
+                let pressure_force = -(pi.pressure + pj.pressure) / (2.0 * pj.density);
+                let q = dist / fluid.smoothing_radius;
+                let dw_dr = grad_scale * spline_dw(q);
+                
+                fx += pressure_force * fluid.mass * dw_dr * (dx / dist);
+                fy += pressure_force * fluid.mass * dw_dr * (dy / dist);
+                
+                // Viscosity force
+                let dvx = pi.vx - pj.vx;
+                let dvy = pi.vy - pj.vy;
+                let laplacian_w = lap_scale * spline_laplacian(q);
+                
+                fx += fluid.viscosity * fluid.mass * laplacian_w * dvx / pj.density;
+                fy += fluid.viscosity * fluid.mass * laplacian_w * dvy / pj.density;
+
+                // Surface tension: cohesion pulling neighbors together
+                let cohesion = fluid.surface_tension * fluid.mass * w_scale * spline_w(q);
+                fx -= cohesion * (dx / dist);
+                fy -= cohesion * (dy / dist);
+            }
+        }
+        *a = (fx, fy);
+    }
+    if fluid.vorticity_confinement > 0.0 {
+        for (a, (fx, fy)) in accel.iter_mut().zip(vorticity_confinement(particles, fluid, list)) {
+            a.0 += fx;
+            a.1 += fy;
+        }
+    }
+
+    for (p, (fx, fy)) in particles.iter_mut().zip(accel) {
+        // Update velocity
+        p.vx += (fx + fluid.gravity.0) * dt;
+        p.vy += (fy + fluid.gravity.1) * dt;
+        let speed = (p.vx * p.vx + p.vy * p.vy).sqrt();
+        if speed > max_speed {
+            p.vx *= max_speed / speed;
+            p.vy *= max_speed / speed;
+        }
+        
+        // Update position
+        p.x += p.vx * dt;
+        p.y += p.vy * dt;
+        
+        // Boundary conditions (bounce)
+        if p.x < 0.0 || p.x > 1.0 {
+            p.vx *= -0.5;
+            p.x = p.x.clamp(0.0, 1.0);
+        }
+        if p.y < 0.0 || p.y > 1.0 {
+            p.vy *= -0.5;
+            p.y = p.y.clamp(0.0, 1.0);
+        }
+    }
+}
+
+/// Vorticity confinement (Fedkiw et al.): the curl ω of the velocity field and the
+/// gradient of |ω| from SPH sums, then ε (N × ω) with N the unit gradient, which pushes
+/// each particle around the nearest swirl and so puts back rotation the smoothing loses
+fn vorticity_confinement(particles: &[Particle], fluid: &FluidParams, list: Option<&VerletList>) -> Vec<(f32, f32)> {
+    let n = particles.len();
+    let h = fluid.smoothing_radius;
+    let (_, grad_scale, _) = kernel_scales(h);
+    let mut near = Vec::new();
+    // ∇W_ij as seen from i, with the neighbour's volume m / ρ_j folded in
+    let weighted_grad = |pi: &Particle, pj: &Particle| {
+        let (dx, dy) = (pi.x - pj.x, pi.y - pj.y);
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist >= h || dist < 1e-6 || pj.density <= 0.0 {
+            return None;
+        }
+        let g = fluid.mass / pj.density * grad_scale * spline_dw(dist / h) / dist;
+        Some((g * dx, g * dy))
+    };
+
+    let mut curl = vec![0.0f32; n];
+    for (i, w) in curl.iter_mut().enumerate() {
+        neighbors_into(list, i, n, &mut near);
+        let pi = &particles[i];
+        for &j in &near {
+            let pj = &particles[j];
+            if let Some((gx, gy)) = weighted_grad(pi, pj) {
+                // (v_j - v_i) × ∇_j W_ij, and ∇_j W_ij = -∇_i W_ij
+                let (dvx, dvy) = (pj.vx - pi.vx, pj.vy - pi.vy);
+                *w += dvy * gx - dvx * gy;
+            }
+        }
+    }
+
+    (0..n)
+        .map(|i| {
+            neighbors_into(list, i, n, &mut near);
+            let pi = &particles[i];
+            let (mut ex, mut ey) = (0.0f32, 0.0f32);
+            for &j in &near {
+                if let Some((gx, gy)) = weighted_grad(pi, &particles[j]) {
+                    let d = curl[j].abs() - curl[i].abs();
+                    ex += d * gx;
+                    ey += d * gy;
+                }
+            }
+            let len = (ex * ex + ey * ey).sqrt();
+            if len < 1e-6 {
+                return (0.0, 0.0);
+            }
+            let (nx, ny) = (ex / len, ey / len);
+            let strength = fluid.vorticity_confinement * curl[i];
+            (strength * ny, -strength * nx)
+        })
+        .collect()
+}
+
+/// PTX build.rs compiled the SPH kernels into, if nvcc was available
+pub fn kernel_ptx() -> Option<String> {
+    option_env!("SPH_PTX").and_then(|path| std::fs::read_to_string(path).ok())
+}
+
+pub fn check_num_particles(num_particles: usize) -> Result<()> {
+    if !(1..=MAX_NUM_PARTICLES).contains(&num_particles) {
+        anyhow::bail!(
+            "num_particles must be 1..={}, got {}",
+            MAX_NUM_PARTICLES,
+            num_particles
+        );
+    }
+    Ok(())
+}
+
+/// Swirling concentric rings inside radius 0.3; up to `PARTICLES_PER_RING` is a single ring.
+/// With a seed, velocities get a little noise so the swirl isn't perfectly symmetric.
+fn initial_layout(num_particles: usize, seed: Option<u64>) -> Vec<Particle> {
+    let rings = num_particles.div_ceil(PARTICLES_PER_RING).max(1);
+    let per_ring = num_particles.div_ceil(rings);
+    let mut rng = seed.map(SimRng::new);
+    (0..num_particles)
+        .map(|i| {
+            let (ring, slot) = (i % rings, i / rings);
+            let angle = (slot as f32 / per_ring as f32) * 2.0 * std::f32::consts::PI;
+            let radius = 0.3 * (1.0 - ring as f32 / rings as f32);
+            let mut noise = || {
+                rng.as_mut()
+                    .map_or(0.0, |rng| rng.range_f32(-INITIAL_VELOCITY_NOISE, INITIAL_VELOCITY_NOISE))
+            };
+            Particle {
+                x: 0.5 + radius * angle.cos(),
+                y: 0.5 + radius * angle.sin(),
+                vx: -angle.sin() * 0.1 + noise(),
+                vy: angle.cos() * 0.1 + noise(),
+                density: 1000.0,
+                pressure: 0.0,
+            }
+        })
+        .collect()
+}
+
+fn all_finite(particles: &[Particle]) -> bool {
+    particles
+        .iter()
+        .all(|p| p.x.is_finite() && p.y.is_finite() && p.vx.is_finite() && p.vy.is_finite())
+}
+
 pub struct SphSimulation {
     #[allow(dead_code)]
     context: Arc<CudaContext>,
     num_particles: usize,
-    particles: DeviceBuffer<Particle>,
-    // SPH parameters
-    rest_density: f32,
-    gas_constant: f32,
-    viscosity: f32,
-    smoothing_radius: f32,
-    mass: f32,
+    particles: SimBuffer<Particle>,
+    fluid: FluidParams,
+    seed: Option<u64>,
+    // PTX provided by build.rs via SPH_PTX; `None` means CPU only
+    ptx: Option<String>,
+    cuda_failures: CudaFailures,
+    // Per-particle accelerations written by `sph_forces`
+    d_ax: Option<SimBuffer<f32>>,
+    d_ay: Option<SimBuffer<f32>>,
+    last_used_cuda: bool,
+    force_cpu: bool,
+    // Times the fluid blew up and was reset to its initial layout
+    resets: u32,
+    // GPU steps since the particles were last copied back and checked
+    steps_since_check: u32,
+    // Integration steps per `step` call, each with `dt / substeps`
+    substeps: u32,
+    // Neighbour lists for the CPU path; `None` scans all pairs
+    neighbor_list: Option<VerletList>,
 }
 
 impl SphSimulation {
-    pub fn new(context: &Arc<CudaContext>) -> Result<Self> {
+    /// Fluid starting from the noise-free layout
+    pub fn new(context: &Arc<CudaContext>, num_particles: usize) -> Result<Self> {
+        Self::with_seed(context, num_particles, None)
+    }
+
+    /// Like `new`, but with a little initial velocity noise drawn from `seed`
+    pub fn new_seeded(context: &Arc<CudaContext>, num_particles: usize, seed: u64) -> Result<Self> {
+        Self::with_seed(context, num_particles, Some(seed))
+    }
+
+    /// `new_seeded` with a seed, `new` without
+    pub fn with_seed(context: &Arc<CudaContext>, num_particles: usize, seed: Option<u64>) -> Result<Self> {
         // Context should already be initialized by caller (init_cuda_in_thread)
         // No need to call ensure_context() here
-        
-        let num_particles = 1000;
+        check_num_particles(num_particles)?;
         
         // Initialize particles in a circle
-        let mut host_particles = Vec::new();
-        for i in 0..num_particles {
-            let angle = (i as f32 / num_particles as f32) * 2.0 * std::f32::consts::PI;
-            let radius = 0.3;
-            host_particles.push(Particle {
-                x: 0.5 + radius * angle.cos(),
-                y: 0.5 + radius * angle.sin(),
-                vx: -angle.sin() * 0.1,
-                vy: angle.cos() * 0.1,
-                density: 1000.0,
-                pressure: 0.0,
-            });
-        }
+        let host_particles = initial_layout(num_particles, seed);
         
         // Copy to device
-        let particles = DeviceBuffer::from_slice(&host_particles)
-            .map_err(|e| anyhow::anyhow!("Failed to allocate particles: {:?}", e))?;
+        let particles = SimBuffer::from_slice(&host_particles)
+            .context("Failed to allocate particles")?;
+
+        let ptx = kernel_ptx();
+        let (d_ax, d_ay) = if ptx.is_some() {
+            let zeros = vec![0.0f32; num_particles];
+            (
+                Some(SimBuffer::from_slice(&zeros)
+                    .context("alloc d_ax")?),
+                Some(SimBuffer::from_slice(&zeros)
+                    .context("alloc d_ay")?),
+            )
+        } else {
+            (None, None)
+        };
         
         Ok(Self {
             context: Arc::clone(context),
             num_particles,
             particles,
-            rest_density: 1000.0,
-            gas_constant: 2000.0,
-            viscosity: 0.018,
-            smoothing_radius: 0.1,
-            mass: 0.02,
+            fluid: FluidParams::default(),
+            seed,
+            ptx,
+            cuda_failures: CudaFailures::default(),
+            d_ax,
+            d_ay,
+            last_used_cuda: false,
+            force_cpu: false,
+            resets: 0,
+            steps_since_check: 0,
+            substeps: substeps::DEFAULT_SUBSTEPS,
+            neighbor_list: Some(VerletList::default()),
         })
     }
 
-    pub fn step(&mut self, dt: f32) -> Result<()> {
-        // Copy particles to host for CPU computation
-        // TODO: Replace with CUDA kernel for GPU acceleration
-        let mut host_particles = vec![Particle::default(); self.num_particles];
-        self.particles.copy_to(&mut host_particles[..])
-            .map_err(|e| anyhow::anyhow!("Failed to copy particles: {:?}", e))?;
-        
-        // SPH density calculation
-        for i in 0..self.num_particles {
-            let mut density = 0.0;
-            let pi = &host_particles[i];
-            
-            for j in 0..self.num_particles {
-                let pj = &host_particles[j];
-                let dx = pi.x - pj.x;
-                let dy = pi.y - pj.y;
-                let dist_sq = dx * dx + dy * dy;
-                let dist = dist_sq.sqrt();
-                
-                if dist < self.smoothing_radius {
-                    // Cubic spline smoothing kernel
-                    let q = dist / self.smoothing_radius;
-                    let w = if q < 1.0 {
-                        let q2 = q * q;
-                        let q3 = q2 * q;
-                        1.0 - 1.5 * q2 + 0.75 * q3
-                    } else if q < 2.0 {
-                        let q2 = q * q;
-                        let _q3 = q2 * q;
-                        0.25 * (2.0 - q) * (2.0 - q) * (2.0 - q)
-                    } else {
-                        0.0
-                    };
-                    
-                    density += self.mass * w;
+    #[cfg(feature = "no-cuda")]
+    fn step_cuda(&mut self, _dt: f32) -> Result<()> {
+        anyhow::bail!("built with the no-cuda feature");
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    fn step_cuda(&mut self, dt: f32) -> Result<()> {
+        let (Some(ptx), Some(ax), Some(ay)) = (&self.ptx, &mut self.d_ax, &mut self.d_ay) else {
+            anyhow::bail!("SPH kernels not loaded");
+        };
+        let ptx_c = CString::new(ptx.as_str()).unwrap();
+        let module = Module::load_from_string(&ptx_c)
+            .context("Failed to load SPH PTX")?;
+        let get = |name: &str| {
+            module
+                .get_function(&CString::new(name).unwrap())
+                .with_context(|| format!("Failed to get {}", name))
+        };
+        let (density, forces, integrate) =
+            (get("sph_density")?, get("sph_forces")?, get("sph_integrate")?);
+        let stream = Stream::new(StreamFlags::DEFAULT, None)
+            .context("Failed to create stream")?;
+
+        let n = self.num_particles as i32;
+        let block = (128u32, 1u32, 1u32);
+        let grid = ((self.num_particles as u32).div_ceil(block.0), 1u32, 1u32);
+        let particles = &mut self.particles;
+        let fluid = self.fluid;
+        let (w_scale, grad_scale, lap_scale) = kernel_scales(fluid.smoothing_radius);
+        let (min_pressure, max_speed) = fluid.limits();
+        // Same stream, so each pass sees the previous one's output
+        unsafe {
+            launch!(
+                density<<<grid, block, 0, stream>>>(
+                    n,
+                    particles.as_device_ptr(),
+                    fluid.mass,
+                    fluid.smoothing_radius,
+                    fluid.gas_constant,
+                    fluid.rest_density,
+                    w_scale,
+                    min_pressure
+                )
+            )
+            .context("sph_density launch failed")?;
+            launch!(
+                forces<<<grid, block, 0, stream>>>(
+                    n,
+                    particles.as_device_ptr(),
+                    fluid.mass,
+                    fluid.smoothing_radius,
+                    fluid.viscosity,
+                    fluid.surface_tension,
+                    w_scale,
+                    grad_scale,
+                    lap_scale,
+                    ax.as_device_ptr(),
+                    ay.as_device_ptr()
+                )
+            )
+            .context("sph_forces launch failed")?;
+            launch!(
+                integrate<<<grid, block, 0, stream>>>(
+                    n,
+                    particles.as_device_ptr(),
+                    ax.as_device_ptr(),
+                    ay.as_device_ptr(),
+                    dt,
+                    fluid.gravity.0,
+                    fluid.gravity.1,
+                    max_speed
+                )
+            )
+            .context("sph_integrate launch failed")?;
+        }
+        stream
+            .synchronize()
+            .context("SPH kernels sync failed")?;
+        self.last_used_cuda = true;
+        Ok(())
+    }
+
+    /// Advance `dt`, split into `substeps` smaller steps
+    pub fn step(&mut self, dt: f32) -> Result<Accelerator> {
+        substeps::run(self.substeps, dt, |sub_dt| self.step_once(sub_dt))
+    }
+
+    fn step_once(&mut self, dt: f32) -> Result<Accelerator> {
+        // The kernels have no vorticity confinement yet
+        let cuda_supported = self.fluid.vorticity_confinement == 0.0;
+        if !self.force_cpu && cuda_supported && self.ptx.is_some() && self.d_ax.is_some() && self.d_ay.is_some() {
+            match self.step_cuda(dt) {
+                Ok(()) => {
+                    self.cuda_failures.succeeded();
+                    self.steps_since_check += 1;
+                    if self.steps_since_check >= STABILITY_CHECK_INTERVAL {
+                        self.steps_since_check = 0;
+                        let mut host_particles = vec![Particle::default(); self.num_particles];
+                        self.particles.copy_to(&mut host_particles[..])
+                            .map_err(|e| anyhow::anyhow!("Failed to copy particles: {:?}", e))?;
+                        if self.reset_if_unstable(&mut host_particles) {
+                            self.particles.copy_from(&host_particles[..])
+                                .map_err(|e| anyhow::anyhow!("Failed to copy particles back: {:?}", e))?;
+                        }
+                    }
+                    return Ok(Accelerator::Cuda);
+                }
+                Err(e) => {
+                    tracing::warn!("SPH CUDA step failed, running this step on the CPU: {:#}", e);
+                    if self.cuda_failures.give_up(&e) {
+                        // Drop the kernels so later steps stay on the CPU path
+                        tracing::warn!("Giving up on the SPH kernels");
+                        self.ptx = None;
+                    }
                 }
             }
-            
-            host_particles[i].density = density;
-            // Pressure from equation of state
-            host_particles[i].pressure = self.gas_constant * (density - self.rest_density);
         }
+
+        // CPU fallback
+        let mut host_particles = vec![Particle::default(); self.num_particles];
+        self.particles.copy_to(&mut host_particles[..])
+            .map_err(|e| anyhow::anyhow!("Failed to copy particles: {:?}", e))?;
         
-        // SPH force calculation and velocity update
-        for i in 0..self.num_particles {
-            let mut fx = 0.0;
-            let mut fy = 0.0;
-            let pi = &host_particles[i];
-            
-            for j in 0..self.num_particles {
-                if i == j { continue; }
-                
-                let pj = &host_particles[j];
-                let dx = pi.x - pj.x;
-                let dy = pi.y - pj.y;
-                let dist_sq = dx * dx + dy * dy;
-                let dist = dist_sq.sqrt().max(0.0001); // Avoid division by zero
-                
-                if dist < self.smoothing_radius {
-                    // Pressure force
-                    let pressure_force = -(pi.pressure + pj.pressure) / (2.0 * pj.density);
-                    let q = dist / self.smoothing_radius;
-                    let dw_dr = if q < 1.0 {
-                        -3.0 * q + 2.25 * q * q
-                    } else if q < 2.0 {
-                        -0.75 * (2.0 - q) * (2.0 - q)
-                    } else {
-                        0.0
-                    };
-                    
-                    fx += pressure_force * self.mass * dw_dr * (dx / dist);
-                    fy += pressure_force * self.mass * dw_dr * (dy / dist);
-                    
-                    // Viscosity force
-                    let dvx = pi.vx - pj.vx;
-                    let dvy = pi.vy - pj.vy;
-                    let laplacian_w = if q < 1.0 {
-                        3.0 - 4.5 * q
-                    } else if q < 2.0 {
-                        1.5 * (2.0 - q)
-                    } else {
-                        0.0
-                    };
-                    
-                    fx += self.viscosity * self.mass * laplacian_w * dvx / pj.density;
-                    fy += self.viscosity * self.mass * laplacian_w * dvy / pj.density;
-                }
-            }
-            
-            // Update velocity
-            host_particles[i].vx += fx * dt;
-            host_particles[i].vy += fy * dt;
-            
-            // Update position
-            host_particles[i].x += host_particles[i].vx * dt;
-            host_particles[i].y += host_particles[i].vy * dt;
-            
-            // Boundary conditions (bounce)
-            if host_particles[i].x < 0.0 || host_particles[i].x > 1.0 {
-                host_particles[i].vx *= -0.5;
-                host_particles[i].x = host_particles[i].x.clamp(0.0, 1.0);
-            }
-            if host_particles[i].y < 0.0 || host_particles[i].y > 1.0 {
-                host_particles[i].vy *= -0.5;
-                host_particles[i].y = host_particles[i].y.clamp(0.0, 1.0);
-            }
+        if let Some(list) = self.neighbor_list.as_mut() {
+            let positions: Vec<(f32, f32)> = host_particles.iter().map(|p| (p.x, p.y)).collect();
+            list.update(&positions, self.fluid.smoothing_radius);
         }
+        cpu_step(&mut host_particles, &self.fluid, dt, self.neighbor_list.as_ref());
         
+        self.reset_if_unstable(&mut host_particles);
+
         // Copy back to device
         self.particles.copy_from(&host_particles[..])
             .map_err(|e| anyhow::anyhow!("Failed to copy particles back: {:?}", e))?;
+        self.last_used_cuda = false;
         
+        Ok(Accelerator::Cpu)
+    }
+
+    /// Put the fluid back to its initial layout if any position or velocity went
+    /// non-finite; returns whether it did
+    fn reset_if_unstable(&mut self, particles: &mut [Particle]) -> bool {
+        if all_finite(particles) {
+            return false;
+        }
+        self.resets += 1;
+        tracing::warn!(
+            "SPH fluid went non-finite (reset #{}); restoring the initial layout",
+            self.resets
+        );
+        particles.copy_from_slice(&initial_layout(self.num_particles, self.seed));
+        true
+    }
+
+    /// False once any step has produced non-finite values (and been reset)
+    pub fn is_stable(&self) -> bool {
+        self.resets == 0
+    }
+
+    /// Times the fluid has been reset after going non-finite
+    pub fn resets(&self) -> u32 {
+        self.resets
+    }
+
+    /// Constant acceleration added to every particle each step
+    pub fn set_gravity(&mut self, gravity: (f32, f32)) {
+        self.fluid.gravity = gravity;
+    }
+
+    pub fn gravity(&self) -> (f32, f32) {
+        self.fluid.gravity
+    }
+
+    /// Apply the fluid properties in `params`; omitted ones keep their value.
+    /// Nothing changes if any value is out of range.
+    pub fn set_params(&mut self, params: &SphParams) -> Result<()> {
+        params.validate()?;
+        let fluid = &mut self.fluid;
+        fluid.gravity = params.gravity.unwrap_or(fluid.gravity);
+        fluid.rest_density = params.rest_density.unwrap_or(fluid.rest_density);
+        fluid.gas_constant = params.gas_constant.unwrap_or(fluid.gas_constant);
+        fluid.viscosity = params.viscosity.unwrap_or(fluid.viscosity);
+        fluid.surface_tension = params.surface_tension.unwrap_or(fluid.surface_tension);
+        fluid.smoothing_radius = params.smoothing_radius.unwrap_or(fluid.smoothing_radius);
+        fluid.mass = params.mass.unwrap_or(fluid.mass);
+        fluid.vorticity_confinement = params.vorticity_confinement.unwrap_or(fluid.vorticity_confinement);
+        Ok(())
+    }
+
+    /// Fluid properties every step uses
+    pub fn fluid_params(&self) -> FluidParams {
+        self.fluid
+    }
+
+    /// Split each `step` into `substeps` steps of `dt / substeps`
+    pub fn set_substeps(&mut self, substeps: u32) -> Result<()> {
+        substeps::check_substeps(substeps)?;
+        self.substeps = substeps;
         Ok(())
     }
 
+    /// Seed the initial velocity noise came from, if any; `with_seed` with it gives the same start
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Whether the last `step` ran the CUDA kernels
+    pub fn used_cuda(&self) -> bool {
+        self.last_used_cuda
+    }
+
+    /// Where the last `step` actually ran
+    pub fn accelerator(&self) -> Accelerator {
+        Accelerator::from_used_cuda(self.last_used_cuda)
+    }
+
+    /// Force the CPU path even when the kernels are available (for comparison)
+    pub fn set_force_cpu(&mut self, force_cpu: bool) {
+        self.force_cpu = force_cpu;
+    }
+
+    /// Use Verlet neighbour lists on the CPU path (the default) or scan all pairs
+    pub fn set_neighbor_list(&mut self, enabled: bool) {
+        self.neighbor_list = enabled.then(VerletList::default);
+    }
+
     pub fn get_particles(&self) -> Result<Vec<f32>> {
         // Copy particles back to host
         let mut host_particles = vec![Particle::default(); self.num_particles];
@@ -208,8 +758,10 @@ impl SphSimulation {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::init_cuda_in_thread;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         // Initialize CUDA in this test thread and keep context alive
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
@@ -220,30 +772,256 @@ mod tests {
         (Arc::new(CudaContext::new().expect("Failed to create CUDA context")), context_obj)
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sph_initialization() {
         let (context, _context_guard) = setup_test_context();
-        let sim = SphSimulation::new(&context);
+        let sim = SphSimulation::new(&context, DEFAULT_NUM_PARTICLES);
         if let Err(e) = &sim {
             eprintln!("SPH initialization error: {:?}", e);
         }
         assert!(sim.is_ok(), "SPH simulation should initialize");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sph_step() {
         let (context, _context_guard) = setup_test_context();
-        let mut sim = SphSimulation::new(&context).unwrap();
+        let mut sim = SphSimulation::new(&context, DEFAULT_NUM_PARTICLES).unwrap();
         let result = sim.step(0.016); // ~60 FPS
         assert!(result.is_ok(), "SPH step should succeed");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_sph_particle_count() {
         let (context, _context_guard) = setup_test_context();
-        let sim = SphSimulation::new(&context).unwrap();
+        let sim = SphSimulation::new(&context, DEFAULT_NUM_PARTICLES).unwrap();
         let particles = sim.get_particles().unwrap();
         // Should return 4 values per particle (x, y, vx, vy)
         assert_eq!(particles.len(), 1000 * 4, "Should return particle data");
     }
+
+    #[test]
+    fn test_initial_layout_scales_with_count() {
+        // The default count is the original single ring of radius 0.3
+        let ring = initial_layout(DEFAULT_NUM_PARTICLES, None);
+        for p in &ring {
+            let r = ((p.x - 0.5).powi(2) + (p.y - 0.5).powi(2)).sqrt();
+            assert!((r - 0.3).abs() < 1e-5);
+            // Without a seed the swirl is exactly tangential at 0.1
+            assert!(((p.vx.powi(2) + p.vy.powi(2)).sqrt() - 0.1).abs() < 1e-6);
+        }
+
+        let disc = initial_layout(4500, None);
+        assert_eq!(disc.len(), 4500);
+        let mut positions: Vec<(u32, u32)> =
+            disc.iter().map(|p| (p.x.to_bits(), p.y.to_bits())).collect();
+        positions.sort();
+        positions.dedup();
+        assert_eq!(positions.len(), 4500, "Particles must not overlap");
+        assert!(disc.iter().all(|p| ((p.x - 0.5).powi(2) + (p.y - 0.5).powi(2)).sqrt() <= 0.3 + 1e-5));
+
+        let a = initial_layout(50, Some(3));
+        let b = initial_layout(50, Some(3));
+        assert!(a.iter().zip(&b).all(|(p, q)| (p.vx, p.vy) == (q.vx, q.vy)), "Same seed, same start");
+        let c = initial_layout(50, Some(4));
+        assert!(a.iter().zip(&c).any(|(p, q)| p.vx != q.vx));
+
+        assert!(check_num_particles(0).is_err());
+        assert!(check_num_particles(MAX_NUM_PARTICLES).is_ok());
+        assert!(check_num_particles(MAX_NUM_PARTICLES + 1).is_err());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_sph_gravity_settles_at_bottom() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = SphSimulation::new_seeded(&context, 400, 1).unwrap();
+        sim.set_force_cpu(true);
+        sim.set_gravity((0.0, -9.8));
+        for _ in 0..300 {
+            sim.step(0.016).unwrap();
+        }
+        let particles = sim.get_particles().unwrap();
+        for p in particles.chunks(4) {
+            assert!(p.iter().all(|v| v.is_finite()));
+            assert!((0.0..=1.0).contains(&p[0]) && (0.0..=1.0).contains(&p[1]));
+            assert!((p[2] * p[2] + p[3] * p[3]).sqrt() <= MAX_SPEED + 1e-4);
+        }
+        let mean_y = particles.chunks(4).map(|p| p[1]).sum::<f32>() / 400.0;
+        assert!(mean_y < 0.1, "fluid should pool at the bottom, mean y {}", mean_y);
+
+        assert!(SphParams { gravity: Some((0.0, -9.8)), ..Default::default() }.validate().is_ok());
+        assert!(SphParams { gravity: Some((f32::NAN, 0.0)), ..Default::default() }.validate().is_err());
+        assert!(SphParams { gravity: Some((0.0, -1000.0)), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_all_finite() {
+        let mut particles = initial_layout(10, Some(0));
+        assert!(all_finite(&particles));
+        particles[3].vy = f32::NAN;
+        assert!(!all_finite(&particles));
+        particles[3].vy = 0.0;
+        particles[7].x = f32::INFINITY;
+        assert!(!all_finite(&particles));
+    }
+
+    #[test]
+    fn test_params_validation() {
+        let honey = SphParams { viscosity: Some(0.5), surface_tension: Some(2.0), ..Default::default() };
+        assert!(honey.validate().is_ok());
+        assert!(SphParams { viscosity: Some(0.0), ..Default::default() }.validate().is_err());
+        assert!(SphParams { mass: Some(-1.0), ..Default::default() }.validate().is_err());
+        assert!(SphParams { surface_tension: Some(-0.1), ..Default::default() }.validate().is_err());
+        assert!(SphParams { smoothing_radius: Some(0.01), ..Default::default() }.validate().is_err());
+        assert!(SphParams { rest_density: Some(f32::NAN), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_density_normalized_across_smoothing_radii() {
+        // Uniform lattice: the continuum density is the same whatever the radius
+        let spacing = 0.01;
+        let particles: Vec<Particle> = (0..100)
+            .flat_map(|i| (0..100).map(move |j| (i, j)))
+            .map(|(i, j)| Particle {
+                x: (i as f32 + 0.5) * spacing,
+                y: (j as f32 + 0.5) * spacing,
+                ..Default::default()
+            })
+            .collect();
+        let density = |h: f32| {
+            let fluid = FluidParams { smoothing_radius: h, ..Default::default() };
+            density_at(&particles, 0.5, 0.5, &fluid)
+        };
+        let reference = density(DEFAULT_SMOOTHING_RADIUS);
+        for h in [0.05, 0.2] {
+            let ratio = density(h) / reference;
+            assert!((ratio - 1.0).abs() < 0.05, "radius {} changed density by {}", h, ratio);
+        }
+    }
+
+    /// Angular momentum per particle about the centroid
+    fn spin(particles: &[Particle]) -> f32 {
+        let n = particles.len() as f32;
+        let cx = particles.iter().map(|p| p.x).sum::<f32>() / n;
+        let cy = particles.iter().map(|p| p.y).sum::<f32>() / n;
+        particles.iter().map(|p| (p.x - cx) * p.vy - (p.y - cy) * p.vx).sum::<f32>() / n
+    }
+
+    #[test]
+    fn test_limits_only_apply_with_gravity() {
+        assert_eq!(FluidParams::default().limits(), (f32::NEG_INFINITY, f32::INFINITY));
+        let fluid = FluidParams { gravity: (0.0, -9.8), ..Default::default() };
+        assert_eq!(fluid.limits(), (0.0, MAX_SPEED));
+    }
+
+    #[test]
+    fn test_vorticity_confinement_keeps_a_blob_spinning() {
+        // A disc in solid-body rotation, which viscosity slowly spins down
+        let spacing = 0.015;
+        let blob: Vec<Particle> = (-8..=8)
+            .flat_map(|i| (-8..=8).map(move |j| (i as f32 * spacing, j as f32 * spacing)))
+            .filter(|&(dx, dy)| dx * dx + dy * dy <= 0.12 * 0.12)
+            .map(|(dx, dy)| Particle { x: 0.5 + dx, y: 0.5 + dy, vx: -3.0 * dy, vy: 3.0 * dx, ..Default::default() })
+            .collect();
+        let run = |strength: f32| {
+            // A little gravity turns on the pressure floor that keeps the blob from clumping
+            let fluid = FluidParams {
+                viscosity: 0.2,
+                vorticity_confinement: strength,
+                gravity: (0.0, -1.0),
+                ..Default::default()
+            };
+            let mut particles = blob.clone();
+            for _ in 0..100 {
+                cpu_step(&mut particles, &fluid, 0.004, None);
+            }
+            assert!(all_finite(&particles));
+            spin(&particles)
+        };
+        let (plain, confined) = (run(0.0), run(0.5));
+        assert!(plain < spin(&blob), "viscosity should slow the blob");
+        assert!(confined > plain * 1.05, "confined spin {} vs plain {}", confined, plain);
+
+        assert!(SphParams { vorticity_confinement: Some(0.5), ..Default::default() }.validate().is_ok());
+        assert!(SphParams { vorticity_confinement: Some(-0.1), ..Default::default() }.validate().is_err());
+        assert!(SphParams { vorticity_confinement: Some(5.0), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_neighbor_list_matches_brute_force() {
+        // With gravity's pressure floor, so particles stay put long enough to reuse lists
+        let fluid = FluidParams { gravity: (0.0, -1.0), ..Default::default() };
+        let mut brute = initial_layout(DEFAULT_NUM_PARTICLES, Some(3));
+        let mut listed = brute.clone();
+        let mut list = VerletList::default();
+        for _ in 0..30 {
+            cpu_step(&mut brute, &fluid, 0.005, None);
+            let positions: Vec<(f32, f32)> = listed.iter().map(|p| (p.x, p.y)).collect();
+            list.update(&positions, fluid.smoothing_radius);
+            cpu_step(&mut listed, &fluid, 0.005, Some(&list));
+        }
+        assert!(list.rebuilds() < 30, "lists are reused between steps");
+        for (a, b) in brute.iter().zip(&listed) {
+            assert!((a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4, "({}, {}) vs ({}, {})", a.x, a.y, b.x, b.y);
+            assert!((a.density - b.density).abs() <= 1e-3 * a.density.abs().max(1.0));
+        }
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_set_params_reports_effective_values() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = SphSimulation::new_seeded(&context, 100, 1).unwrap();
+        let params = SphParams { viscosity: Some(0.5), smoothing_radius: Some(0.05), ..Default::default() };
+        sim.set_params(&params).unwrap();
+        let fluid = sim.fluid_params();
+        assert_eq!((fluid.viscosity, fluid.smoothing_radius), (0.5, 0.05));
+        assert_eq!(fluid.rest_density, FluidParams::default().rest_density, "Omitted params keep their value");
+
+        assert!(sim.set_params(&SphParams { mass: Some(0.0), ..Default::default() }).is_err());
+        assert_eq!(sim.fluid_params(), fluid, "Rejected params change nothing");
+        sim.step(0.016).unwrap();
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_non_finite_fluid_is_reset() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = SphSimulation::new(&context, DEFAULT_NUM_PARTICLES).unwrap();
+        let mut particles = initial_layout(DEFAULT_NUM_PARTICLES, None);
+        particles[0].x = f32::NAN;
+        sim.particles.copy_from(&particles[..]).unwrap();
+        // The GPU path only checks every STABILITY_CHECK_INTERVAL steps
+        for _ in 0..STABILITY_CHECK_INTERVAL {
+            sim.step(0.016).unwrap();
+        }
+        assert_eq!(sim.resets(), 1);
+        assert!(!sim.is_stable());
+        assert!(sim.get_particles().unwrap().iter().all(|v| v.is_finite()));
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_sph_cuda_matches_cpu() {
+        let (context, _context_guard) = setup_test_context();
+        let mut gpu = SphSimulation::new_seeded(&context, DEFAULT_NUM_PARTICLES, 1).unwrap();
+        let mut cpu = SphSimulation::new_seeded(&context, DEFAULT_NUM_PARTICLES, 1).unwrap();
+        cpu.set_force_cpu(true);
+        for _ in 0..5 {
+            gpu.step(0.016).unwrap();
+            cpu.step(0.016).unwrap();
+        }
+        assert!(!cpu.used_cuda());
+        if !gpu.used_cuda() {
+            eprintln!("SPH kernel not built (no nvcc); skipping comparison");
+            return;
+        }
+        let (a, b) = (gpu.get_particles().unwrap(), cpu.get_particles().unwrap());
+        for (x, y) in a.iter().zip(&b) {
+            assert!((x - y).abs() < 1e-3, "GPU {} vs CPU {}", x, y);
+        }
+    }
 }
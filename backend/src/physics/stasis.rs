@@ -0,0 +1,92 @@
+// Flock "heat death" detection: the whole flock has come to rest
+// Turns a vague "the boids are stuck" report into a concrete, monitorable flag
+use super::boids::Boid;
+
+/// Simulated seconds between stasis checks
+pub const CHECK_INTERVAL: f32 = 0.5;
+/// Simulated seconds the flock must stay still before stasis is reported
+pub const DEFAULT_WINDOW: f32 = 3.0;
+/// Mean boid speed below which the flock counts as still, whatever its size
+pub const DEFAULT_THRESHOLD: f32 = 1e-4;
+
+/// Mean boid speed, our proxy for the flock's kinetic energy (0 for an empty flock)
+pub fn mean_speed(boids: &[Boid]) -> f32 {
+    if boids.is_empty() {
+        return 0.0;
+    }
+    boids.iter().map(|b| (b.vx * b.vx + b.vy * b.vy).sqrt()).sum::<f32>() / boids.len() as f32
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StasisDetector {
+    pub threshold: f32,
+    pub window: f32,
+    // Simulated time the mean speed has stayed below the threshold
+    still_for: f32,
+    stasis: bool,
+}
+
+impl Default for StasisDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD, DEFAULT_WINDOW)
+    }
+}
+
+impl StasisDetector {
+    pub fn new(threshold: f32, window: f32) -> Self {
+        Self {
+            threshold,
+            window,
+            still_for: 0.0,
+            stasis: false,
+        }
+    }
+
+    /// Record the mean speed measured `elapsed` simulated seconds after the previous check.
+    /// Returns the new state when it changes.
+    pub fn observe(&mut self, mean_speed: f32, elapsed: f32) -> Option<bool> {
+        // NaN speeds mean the flock blew up, not that it stopped
+        if mean_speed < self.threshold {
+            self.still_for += elapsed;
+        } else {
+            self.still_for = 0.0;
+        }
+        let stasis = self.still_for >= self.window;
+        if stasis == self.stasis {
+            return None;
+        }
+        self.stasis = stasis;
+        Some(stasis)
+    }
+
+    pub fn in_stasis(&self) -> bool {
+        self.stasis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_only_after_sustained_stillness() {
+        let mut detector = StasisDetector::new(1e-3, 1.0);
+        assert_eq!(detector.observe(0.0, 0.5), None);
+        assert_eq!(detector.observe(5.0, 0.5), None, "Movement resets the window");
+        assert_eq!(detector.observe(0.0, 0.5), None);
+        assert_eq!(detector.observe(0.0, 0.5), Some(true));
+        assert!(detector.in_stasis());
+        assert_eq!(detector.observe(0.0, 0.5), None);
+        assert_eq!(detector.observe(f32::NAN, 0.5), Some(false));
+        assert!(!detector.in_stasis());
+    }
+
+    #[test]
+    fn test_mean_speed_ignores_flock_size() {
+        let slow = Boid { vx: 3e-5, vy: 4e-5, ..Boid::default() };
+        assert!((mean_speed(&[slow]) - 5e-5).abs() < 1e-9);
+        // A big flock of near-still boids is as still as a small one
+        assert!((mean_speed(&vec![slow; 100_000]) - 5e-5).abs() < 1e-7);
+        assert_eq!(mean_speed(&[]), 0.0);
+    }
+}
@@ -0,0 +1,267 @@
+// Aggregate statistics over a flock, for plotting how it evolves over time
+use super::boids::Boid;
+use super::genetics::GeneStats;
+use super::predators;
+use super::spatial_grid::SpatialGrid;
+use super::thumbnail;
+use serde::Serialize;
+
+/// Density grid side when the request doesn't give one
+pub const DEFAULT_DENSITY_RESOLUTION: usize = 64;
+/// Largest density grid side accepted
+pub const MAX_DENSITY_RESOLUTION: usize = 512;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BoidStats {
+    pub num_boids: usize,
+    pub mean_speed: f32,
+    /// Population variance of the speed
+    pub speed_variance: f32,
+    /// Sum of `mass * speed² / 2`
+    pub kinetic_energy: f32,
+    /// Mean distance from each boid to its nearest neighbour of any species;
+    /// `None` with fewer than two boids
+    pub mean_nearest_neighbor_distance: Option<f32>,
+    /// One entry per species present, in ascending species order
+    pub species: Vec<SpeciesStats>,
+    /// Only while genetics is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genes: Option<GeneStats>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SpeciesStats {
+    /// Species id; predators are `predators::PREDATOR_SPECIES`
+    pub species: u8,
+    pub count: usize,
+    pub centroid: (f32, f32),
+}
+
+/// Statistics over `boids`; `grid` is only scratch space for the neighbour search
+pub fn compute(boids: &[Boid], grid: &mut SpatialGrid) -> BoidStats {
+    let n = boids.len();
+    // f64 sums so 100K-boid flocks don't lose precision
+    let speeds: Vec<f64> = boids
+        .iter()
+        .map(|b| ((b.vx * b.vx + b.vy * b.vy) as f64).sqrt())
+        .collect();
+    let mean = if n > 0 { speeds.iter().sum::<f64>() / n as f64 } else { 0.0 };
+    let variance = if n > 0 {
+        speeds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64
+    } else {
+        0.0
+    };
+    let kinetic_energy: f64 = boids
+        .iter()
+        .zip(&speeds)
+        .map(|(b, s)| 0.5 * b.mass as f64 * s * s)
+        .sum();
+
+    let mut sums = [(0usize, 0.0f64, 0.0f64); 256];
+    for b in boids {
+        let entry = &mut sums[b.species as usize];
+        entry.0 += 1;
+        entry.1 += b.x as f64;
+        entry.2 += b.y as f64;
+    }
+    let species = sums
+        .iter()
+        .enumerate()
+        .filter(|(_, (count, _, _))| *count > 0)
+        .map(|(species, &(count, x, y))| SpeciesStats {
+            species: species as u8,
+            count,
+            centroid: ((x / count as f64) as f32, (y / count as f64) as f32),
+        })
+        .collect();
+
+    BoidStats {
+        num_boids: n,
+        mean_speed: mean as f32,
+        speed_variance: variance as f32,
+        kinetic_energy: kinetic_energy as f32,
+        mean_nearest_neighbor_distance: mean_nearest_neighbor_distance(boids, grid),
+        species,
+        genes: None,
+    }
+}
+
+/// Bins at about twice the typical spacing; a neighbour found within a cell width
+/// is exact, and the rare boid with none that close falls back to a full scan
+fn mean_nearest_neighbor_distance(boids: &[Boid], grid: &mut SpatialGrid) -> Option<f32> {
+    let n = boids.len();
+    if n < 2 {
+        return None;
+    }
+    let cell_size = 2.0 / (n as f32).sqrt();
+    grid.rebuild(boids, cell_size);
+    let dist_sq = |a: &Boid, b: &Boid| (a.x - b.x).powi(2) + (a.y - b.y).powi(2);
+
+    let mut near = Vec::new();
+    let mut total = 0.0f64;
+    for (i, bi) in boids.iter().enumerate() {
+        grid.near(i, &mut near);
+        let mut best = near
+            .iter()
+            .map(|&j| dist_sq(bi, &boids[j]))
+            .fold(f32::INFINITY, f32::min);
+        // Anyone closer than a cell width is always in `near`, so only a miss needs the scan
+        if best > cell_size * cell_size {
+            best = boids
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, bj)| dist_sq(bi, bj))
+                .fold(f32::INFINITY, f32::min);
+        }
+        total += (best as f64).sqrt();
+    }
+    Some((total / n as f64) as f32)
+}
+
+/// How the flock splits across species, with a colour per species to draw it in
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SpeciesSummary {
+    /// Prey species the flock was configured with
+    pub count: u8,
+    /// Prey per species id, `count` entries
+    pub populations: Vec<usize>,
+    pub predators: usize,
+    /// `#rrggbb` per species id, matching the flock thumbnails
+    pub colors: Vec<String>,
+    pub predator_color: String,
+}
+
+pub fn species_summary(boids: &[Boid], num_species: u8) -> SpeciesSummary {
+    let mut populations = vec![0; num_species as usize];
+    let mut predators = 0;
+    for b in boids {
+        if predators::is_predator(b) {
+            predators += 1;
+        } else if let Some(count) = populations.get_mut(b.species as usize) {
+            *count += 1;
+        }
+    }
+    SpeciesSummary {
+        count: num_species,
+        populations,
+        predators,
+        colors: (0..num_species).map(thumbnail::species_hex_color).collect(),
+        predator_color: thumbnail::species_hex_color(predators::PREDATOR_SPECIES),
+    }
+}
+
+pub fn check_density_resolution(resolution: usize) -> anyhow::Result<()> {
+    if !(1..=MAX_DENSITY_RESOLUTION).contains(&resolution) {
+        anyhow::bail!("res must be 1..={}, got {}", MAX_DENSITY_RESOLUTION, resolution);
+    }
+    Ok(())
+}
+
+/// Row-major `resolution`² histogram of boid positions over the `world_size` square,
+/// with row 0 at y = 0. Boids outside the square land in the nearest edge cell.
+pub fn density_grid(boids: &[Boid], resolution: usize, world_size: f32) -> Vec<u32> {
+    let mut grid = vec![0u32; resolution * resolution];
+    let scale = resolution as f32 / world_size;
+    let cell = |v: f32| ((v * scale).max(0.0) as usize).min(resolution - 1);
+    for b in boids.iter().filter(|b| b.x.is_finite() && b.y.is_finite()) {
+        grid[cell(b.y) * resolution + cell(b.x)] += 1;
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::rng::SimRng;
+
+    fn boid(x: f32, y: f32, vx: f32, vy: f32, species: u8) -> Boid {
+        Boid { x, y, vx, vy, species, ..Boid::default() }
+    }
+
+    #[test]
+    fn test_species_summary() {
+        let mut boids = vec![boid(0.1, 0.1, 0.0, 0.0, 0), boid(0.2, 0.1, 0.0, 0.0, 2), boid(0.3, 0.1, 0.0, 0.0, 2)];
+        boids.push(boid(0.4, 0.1, 0.0, 0.0, predators::PREDATOR_SPECIES));
+        let summary = species_summary(&boids, 4);
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.populations, vec![1, 0, 2, 0]);
+        assert_eq!(summary.predators, 1);
+        assert_eq!(summary.colors.len(), 4);
+        assert_eq!(summary.colors[0], "#00c8ff");
+        assert_eq!(summary.predator_color, "#ff2828");
+        let distinct: std::collections::HashSet<_> = species_summary(&[], 8).colors.into_iter().collect();
+        assert_eq!(distinct.len(), 8, "every species gets its own colour");
+    }
+
+    #[test]
+    fn test_stats_of_small_flock() {
+        let boids = [
+            boid(0.1, 0.1, 0.3, 0.4, 0),
+            boid(0.3, 0.1, 0.0, 0.0, 0),
+            boid(0.9, 0.8, 0.0, 1.0, 2),
+        ];
+        let stats = compute(&boids, &mut SpatialGrid::default());
+        assert_eq!(stats.num_boids, 3);
+        assert!((stats.mean_speed - 0.5).abs() < 1e-6);
+        assert!((stats.speed_variance - (0.0 + 0.25 + 0.25) / 3.0).abs() < 1e-6);
+        assert!((stats.kinetic_energy - 0.5 * (0.25 + 1.0)).abs() < 1e-6);
+        assert_eq!(stats.species.len(), 2);
+        assert_eq!(stats.species[0].count, 2);
+        assert!((stats.species[0].centroid.0 - 0.2).abs() < 1e-6);
+        assert_eq!(stats.species[1].species, 2);
+
+        // 0.2, 0.2, and the far boid's nearest is (0.3, 0.1)
+        let far = (0.6f32 * 0.6 + 0.7 * 0.7).sqrt();
+        let expected = (0.2 + 0.2 + far) / 3.0;
+        assert!((stats.mean_nearest_neighbor_distance.unwrap() - expected).abs() < 1e-5);
+
+        let empty = compute(&[], &mut SpatialGrid::default());
+        assert_eq!(empty.mean_nearest_neighbor_distance, None);
+        assert!(empty.species.is_empty());
+    }
+
+    #[test]
+    fn test_grid_nearest_neighbor_matches_brute_force() {
+        let mut rng = SimRng::new(9);
+        let boids: Vec<Boid> = (0..800)
+            .map(|_| boid(rng.next_f32(), rng.next_f32(), 0.0, 0.0, 0))
+            .collect();
+        let fast = mean_nearest_neighbor_distance(&boids, &mut SpatialGrid::default()).unwrap();
+        let brute: f32 = boids
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                boids
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, b)| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt())
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .sum::<f32>()
+            / boids.len() as f32;
+        assert!((fast - brute).abs() < 1e-5, "grid {} vs brute force {}", fast, brute);
+    }
+
+    #[test]
+    fn test_density_grid_bins_positions() {
+        let boids = [
+            boid(0.1, 0.1, 0.0, 0.0, 0),
+            boid(0.2, 0.1, 0.0, 0.0, 0),
+            boid(0.9, 0.6, 0.0, 0.0, 1),
+            boid(1.0, 1.2, 0.0, 0.0, 0),
+            boid(f32::NAN, 0.5, 0.0, 0.0, 0),
+        ];
+        let grid = density_grid(&boids, 4, 1.0);
+        assert_eq!(grid.len(), 16);
+        assert_eq!(grid[0], 2, "Both boids near the origin share a cell");
+        assert_eq!(grid[2 * 4 + 3], 1);
+        assert_eq!(grid[15], 1, "Out-of-range boids clamp to the edge");
+        assert_eq!(grid.iter().sum::<u32>(), 4, "Non-finite boids are skipped");
+        let scaled: Vec<Boid> = boids.iter().map(|b| Boid { x: b.x * 10.0, y: b.y * 10.0, ..*b }).collect();
+        assert_eq!(density_grid(&scaled, 4, 10.0), grid, "Binned relative to the world size");
+        assert!(check_density_resolution(0).is_err());
+        assert!(check_density_resolution(MAX_DENSITY_RESOLUTION + 1).is_err());
+    }
+}
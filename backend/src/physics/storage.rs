@@ -0,0 +1,267 @@
+// Storage abstraction over simulation buffers
+// Lets the physics code run against device memory or plain host vectors
+use anyhow::Result;
+#[cfg(not(feature = "no-cuda"))]
+use anyhow::Context as AnyhowContext;
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::memory::{DeviceBuffer, DeviceCopy};
+#[cfg(not(feature = "no-cuda"))]
+use rustacuda::prelude::*;
+
+/// What the buffers hold: types the driver can copy to the device, or any `Copy`
+/// type in `no-cuda` builds
+#[cfg(not(feature = "no-cuda"))]
+pub trait Element: DeviceCopy + Copy + 'static {}
+#[cfg(not(feature = "no-cuda"))]
+impl<T: DeviceCopy + Copy + 'static> Element for T {}
+#[cfg(feature = "no-cuda")]
+pub trait Element: Copy + 'static {}
+#[cfg(feature = "no-cuda")]
+impl<T: Copy + 'static> Element for T {}
+
+/// A fixed-length buffer the simulations copy their state through. Not `Send`:
+/// `DeviceBuffer` holds a raw device pointer, so owners vouch for it themselves
+/// (see `unsafe impl Send for BoidsSimulation`)
+pub trait Storage<T> {
+    fn len(&self) -> usize;
+    fn copy_to(&self, dst: &mut [T]) -> Result<()>;
+    fn copy_from(&mut self, src: &[T]) -> Result<()>;
+    /// Copy out the first `dst.len()` elements
+    fn copy_prefix_to(&self, dst: &mut [T]) -> Result<()>;
+    /// Overwrite the first `src.len()` elements
+    fn copy_prefix_from(&mut self, src: &[T]) -> Result<()>;
+}
+
+fn check_prefix(len: usize, prefix: usize) -> Result<()> {
+    if prefix > len {
+        anyhow::bail!("prefix of {} is longer than the buffer ({})", prefix, len);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "no-cuda"))]
+impl<T: DeviceCopy> Storage<T> for DeviceBuffer<T> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn copy_to(&self, dst: &mut [T]) -> Result<()> {
+        (**self)
+            .copy_to(dst)
+            .map_err(|e| anyhow::anyhow!("device->host copy failed: {:?}", e))
+    }
+
+    fn copy_from(&mut self, src: &[T]) -> Result<()> {
+        (**self)
+            .copy_from(src)
+            .map_err(|e| anyhow::anyhow!("host->device copy failed: {:?}", e))
+    }
+
+    fn copy_prefix_to(&self, dst: &mut [T]) -> Result<()> {
+        check_prefix((**self).len(), dst.len())?;
+        (**self)[..dst.len()]
+            .copy_to(dst)
+            .map_err(|e| anyhow::anyhow!("device->host copy failed: {:?}", e))
+    }
+
+    fn copy_prefix_from(&mut self, src: &[T]) -> Result<()> {
+        check_prefix((**self).len(), src.len())?;
+        (**self)[..src.len()]
+            .copy_from(src)
+            .map_err(|e| anyhow::anyhow!("host->device copy failed: {:?}", e))
+    }
+}
+
+/// Host-memory stand-in for `DeviceBuffer`, used when no GPU is present
+pub struct HostBuffer<T> {
+    data: Vec<T>,
+}
+
+impl<T: Copy> HostBuffer<T> {
+    /// Fallible like `DeviceBuffer::from_slice`, so `SimBuffer` callers work with either
+    pub fn from_slice(src: &[T]) -> Result<Self> {
+        Ok(Self { data: src.to_vec() })
+    }
+}
+
+fn check_len(expected: usize, actual: usize) -> Result<()> {
+    if expected != actual {
+        anyhow::bail!("buffer length mismatch: {} != {}", expected, actual);
+    }
+    Ok(())
+}
+
+impl<T: Copy> Storage<T> for HostBuffer<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn copy_to(&self, dst: &mut [T]) -> Result<()> {
+        // Same contract as DeviceBuffer: lengths must match exactly
+        check_len(self.data.len(), dst.len())?;
+        dst.copy_from_slice(&self.data);
+        Ok(())
+    }
+
+    fn copy_from(&mut self, src: &[T]) -> Result<()> {
+        check_len(self.data.len(), src.len())?;
+        self.data.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn copy_prefix_to(&self, dst: &mut [T]) -> Result<()> {
+        check_prefix(self.data.len(), dst.len())?;
+        dst.copy_from_slice(&self.data[..dst.len()]);
+        Ok(())
+    }
+
+    fn copy_prefix_from(&mut self, src: &[T]) -> Result<()> {
+        check_prefix(self.data.len(), src.len())?;
+        self.data[..src.len()].copy_from_slice(src);
+        Ok(())
+    }
+}
+
+/// Room to allocate for `len` elements of a buffer that is growing, so that a steadily
+/// growing one reallocates only now and then
+pub fn grown_capacity(len: usize) -> usize {
+    len.saturating_add(len / 2)
+}
+
+/// `Storage` whose length can change: the elements sit at the front of an allocation
+/// that is only replaced when they outgrow it
+pub struct Resizable<T> {
+    buffer: Box<dyn Storage<T>>,
+    len: usize,
+}
+
+impl<T: Element + Default> Resizable<T> {
+    pub fn upload<B: Backend>(backend: &B, src: &[T]) -> Result<Self> {
+        Ok(Self { buffer: backend.upload(src)?, len: src.len() })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Replace the contents with `src`, reallocating through `backend` only if it doesn't fit
+    pub fn assign<B: Backend>(&mut self, backend: &B, src: &[T]) -> Result<()> {
+        if src.len() > self.capacity() {
+            let mut padded = src.to_vec();
+            padded.resize(grown_capacity(src.len()), T::default());
+            self.buffer = backend.upload(&padded)?;
+        } else {
+            self.buffer.copy_prefix_from(src)?;
+        }
+        self.len = src.len();
+        Ok(())
+    }
+}
+
+impl<T> Storage<T> for Resizable<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn copy_to(&self, dst: &mut [T]) -> Result<()> {
+        check_len(self.len, dst.len())?;
+        self.buffer.copy_prefix_to(dst)
+    }
+
+    fn copy_from(&mut self, src: &[T]) -> Result<()> {
+        check_len(self.len, src.len())?;
+        self.buffer.copy_prefix_from(src)
+    }
+
+    fn copy_prefix_to(&self, dst: &mut [T]) -> Result<()> {
+        check_prefix(self.len, dst.len())?;
+        self.buffer.copy_prefix_to(dst)
+    }
+
+    fn copy_prefix_from(&mut self, src: &[T]) -> Result<()> {
+        check_prefix(self.len, src.len())?;
+        self.buffer.copy_prefix_from(src)
+    }
+}
+
+/// Where a simulation keeps its buffers
+pub trait Backend {
+    /// Whether buffers live on the GPU (and kernels may be launched on them)
+    fn is_device(&self) -> bool;
+    fn upload<T: Element>(&self, src: &[T]) -> Result<Box<dyn Storage<T>>>;
+}
+
+/// Buffers in CUDA device memory; requires a current context
+#[cfg(not(feature = "no-cuda"))]
+pub struct CudaBackend;
+
+#[cfg(not(feature = "no-cuda"))]
+impl Backend for CudaBackend {
+    fn is_device(&self) -> bool {
+        true
+    }
+
+    fn upload<T: Element>(&self, src: &[T]) -> Result<Box<dyn Storage<T>>> {
+        let buffer = DeviceBuffer::from_slice(src)
+            .context("Failed to allocate device buffer")?;
+        Ok(Box::new(buffer))
+    }
+}
+
+/// Buffers in host memory; no CUDA calls are made
+pub struct HostBackend;
+
+impl Backend for HostBackend {
+    fn is_device(&self) -> bool {
+        false
+    }
+
+    fn upload<T: Element>(&self, src: &[T]) -> Result<Box<dyn Storage<T>>> {
+        Ok(Box::new(HostBuffer::from_slice(src)?))
+    }
+}
+
+/// Buffer type for simulations that launch kernels straight on their state: device
+/// memory normally, host memory in `no-cuda` builds (where no kernel is ever loaded)
+#[cfg(not(feature = "no-cuda"))]
+pub type SimBuffer<T> = DeviceBuffer<T>;
+#[cfg(feature = "no-cuda")]
+pub type SimBuffer<T> = HostBuffer<T>;
+
+/// Backend for simulations built from a `CudaContext`
+#[cfg(not(feature = "no-cuda"))]
+pub use CudaBackend as DefaultBackend;
+#[cfg(feature = "no-cuda")]
+pub use HostBackend as DefaultBackend;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_buffer_round_trip() {
+        let mut buffer = HostBackend.upload(&[1.0f32, 2.0, 3.0]).unwrap();
+        assert_eq!(buffer.len(), 3);
+        buffer.copy_from(&[4.0, 5.0, 6.0]).unwrap();
+        let mut out = [0.0f32; 3];
+        buffer.copy_to(&mut out).unwrap();
+        assert_eq!(out, [4.0, 5.0, 6.0]);
+        assert!(buffer.copy_to(&mut [0.0f32; 2]).is_err());
+    }
+
+    #[test]
+    fn test_resizable_keeps_its_allocation_while_it_fits() {
+        let mut buffer = Resizable::upload(&HostBackend, &[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        buffer.assign(&HostBackend, &[5.0, 6.0]).unwrap();
+        assert_eq!((buffer.len(), buffer.capacity()), (2, 4));
+        let mut out = [0.0f32; 2];
+        buffer.copy_to(&mut out).unwrap();
+        assert_eq!(out, [5.0, 6.0]);
+        assert!(buffer.copy_to(&mut [0.0f32; 4]).is_err(), "only the live elements are readable");
+
+        buffer.assign(&HostBackend, &[1.0; 6]).unwrap();
+        assert_eq!((buffer.len(), buffer.capacity()), (6, 9));
+        buffer.assign(&HostBackend, &[2.0; 8]).unwrap();
+        assert_eq!(buffer.capacity(), 9, "growing within the headroom reuses it");
+    }
+}
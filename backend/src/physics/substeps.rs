@@ -0,0 +1,58 @@
+// Integration substeps: one client-visible step split into several smaller ones
+// Stiff parameters that blow up at dt = 0.016 often stay stable at dt / 4
+use super::accelerator::Accelerator;
+use anyhow::Result;
+
+/// Substeps when a request doesn't say; one step per frame, as before
+pub const DEFAULT_SUBSTEPS: u32 = 1;
+/// Most substeps accepted; each costs a full step, so frame time grows linearly
+pub const MAX_SUBSTEPS: u32 = 64;
+
+pub fn check_substeps(substeps: u32) -> Result<()> {
+    if !(1..=MAX_SUBSTEPS).contains(&substeps) {
+        anyhow::bail!(
+            "substeps must be 1..={}, got {}: more substeps keep stiff parameters stable \
+             but each one costs a full step, so frame time grows with the count",
+            MAX_SUBSTEPS,
+            substeps
+        );
+    }
+    Ok(())
+}
+
+/// Run `step` `substeps` times with `dt / substeps`; returns where the last one ran
+pub fn run(
+    substeps: u32,
+    dt: f32,
+    mut step: impl FnMut(f32) -> Result<Accelerator>,
+) -> Result<Accelerator> {
+    let sub_dt = dt / substeps as f32;
+    let mut accelerator = Accelerator::Cpu;
+    for _ in 0..substeps {
+        accelerator = step(sub_dt)?;
+    }
+    Ok(accelerator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substeps_split_dt() {
+        let mut dts = Vec::new();
+        let accelerator = run(4, 0.016, |dt| {
+            dts.push(dt);
+            Ok(Accelerator::Cuda)
+        })
+        .unwrap();
+        assert_eq!(accelerator, Accelerator::Cuda);
+        assert_eq!(dts, [0.004; 4]);
+
+        assert!(check_substeps(DEFAULT_SUBSTEPS).is_ok());
+        assert!(check_substeps(MAX_SUBSTEPS).is_ok());
+        let err = check_substeps(0).unwrap_err().to_string();
+        assert!(err.contains("frame time"), "{}", err);
+        assert!(check_substeps(MAX_SUBSTEPS + 1).is_err());
+    }
+}
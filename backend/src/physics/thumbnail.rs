@@ -0,0 +1,102 @@
+// Rasterized preview of a flock: one coloured dot per boid on black,
+// the same row-major RGBA layout `sdf::render_cpu` produces
+use super::boids::Boid;
+use super::predators::PREDATOR_SPECIES;
+use anyhow::Result;
+
+/// Canvas side used when the request doesn't give one
+pub const DEFAULT_THUMBNAIL_SIZE: usize = 512;
+/// Largest accepted canvas width or height
+pub const MAX_THUMBNAIL_SIZE: usize = 2048;
+
+// Prey colours, one per species up to `boids::MAX_SPECIES`
+const SPECIES_COLORS: [[u8; 3]; 8] = [
+    [0, 200, 255],
+    [255, 160, 0],
+    [80, 220, 100],
+    [220, 80, 220],
+    [255, 230, 60],
+    [150, 110, 255],
+    [255, 120, 160],
+    [160, 240, 230],
+];
+const PREDATOR_COLOR: [u8; 3] = [255, 40, 40];
+
+pub fn check_size(width: usize, height: usize) -> Result<()> {
+    if !(1..=MAX_THUMBNAIL_SIZE).contains(&width) || !(1..=MAX_THUMBNAIL_SIZE).contains(&height) {
+        anyhow::bail!(
+            "image size must be 1..={} per side, got {}x{}",
+            MAX_THUMBNAIL_SIZE,
+            width,
+            height
+        );
+    }
+    Ok(())
+}
+
+pub fn species_color(species: u8) -> [u8; 3] {
+    if species == PREDATOR_SPECIES {
+        PREDATOR_COLOR
+    } else {
+        SPECIES_COLORS[species as usize % SPECIES_COLORS.len()]
+    }
+}
+
+/// `species_color` as `#rrggbb`, for clients drawing the flock themselves
+pub fn species_hex_color(species: u8) -> String {
+    let [r, g, b] = species_color(species);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Splat `boids` onto a `width`x`height` RGBA canvas. The `world_size` square maps
+/// onto the whole canvas with y growing downwards, like the frontend draws it; dots
+/// grow by a pixel per 256 of the shorter side and boids outside are skipped.
+pub fn splat(boids: &[Boid], width: usize, height: usize, world_size: f32) -> Vec<u8> {
+    let mut out = [0, 0, 0, 255].repeat(width * height);
+    let radius = (width.min(height) / 256) as i64;
+    for b in boids {
+        if !(b.x.is_finite() && b.y.is_finite()) {
+            continue;
+        }
+        let (x, y) = (b.x / world_size, b.y / world_size);
+        let (cx, cy) = ((x * width as f32) as i64, (y * height as f32) as i64);
+        let [r, g, bl] = species_color(b.species);
+        for y in cy - radius..=cy + radius {
+            for x in cx - radius..=cx + radius {
+                let inside = (x - cx).pow(2) + (y - cy).pow(2) <= radius * radius;
+                if inside && (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                    let idx = (y as usize * width + x as usize) * 4;
+                    out[idx..idx + 4].copy_from_slice(&[r, g, bl, 255]);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splat_colors_by_species() {
+        let boids = [
+            Boid { x: 0.25, y: 0.5, species: 1, ..Boid::default() },
+            Boid { x: 0.75, y: 0.0, species: PREDATOR_SPECIES, ..Boid::default() },
+            Boid { x: 1.5, y: 0.5, ..Boid::default() },
+        ];
+        let rgba = splat(&boids, 8, 4, 1.0);
+        assert_eq!(rgba.len(), 8 * 4 * 4);
+        let pixel = |x: usize, y: usize| &rgba[(y * 8 + x) * 4..(y * 8 + x) * 4 + 4];
+        assert_eq!(pixel(2, 2), &[255, 160, 0, 255]);
+        assert_eq!(pixel(6, 0), &[255, 40, 40, 255]);
+        let lit = rgba.chunks(4).filter(|p| p[..3] != [0, 0, 0]).count();
+        assert_eq!(lit, 2, "off-canvas boids are skipped");
+        let scaled: Vec<Boid> = boids.iter().map(|b| Boid { x: b.x * 10.0, y: b.y * 10.0, ..*b }).collect();
+        assert_eq!(splat(&scaled, 8, 4, 10.0), rgba, "The world square fills the canvas");
+
+        assert!(check_size(512, 512).is_ok());
+        assert!(check_size(0, 512).is_err());
+        assert!(check_size(512, MAX_THUMBNAIL_SIZE + 1).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+// Verlet neighbour list for the SPH CPU path
+// Lists every pair within `cutoff + skin` and reuses the lists across steps, rebuilding
+// only every few steps or once a particle may have crossed the skin
+use std::collections::HashMap;
+
+/// Skin as a fraction of the cutoff; wider skins rebuild less often but list more pairs
+pub const DEFAULT_SKIN_FRACTION: f32 = 0.3;
+/// Steps a list is reused for at most, even when nothing moved far
+pub const DEFAULT_REBUILD_INTERVAL: u32 = 10;
+
+pub struct VerletList {
+    skin_fraction: f32,
+    rebuild_interval: u32,
+    // Cutoff and skin of the current lists; 0 before the first build
+    cutoff: f32,
+    skin: f32,
+    // Neighbours of particle i are items[start[i]..start[i + 1]]
+    start: Vec<u32>,
+    items: Vec<u32>,
+    // Positions at the last build, to measure displacement against
+    anchors: Vec<(f32, f32)>,
+    steps_since_build: u32,
+    rebuilds: u64,
+}
+
+impl Default for VerletList {
+    fn default() -> Self {
+        Self::new(DEFAULT_SKIN_FRACTION, DEFAULT_REBUILD_INTERVAL)
+    }
+}
+
+impl VerletList {
+    pub fn new(skin_fraction: f32, rebuild_interval: u32) -> Self {
+        Self {
+            skin_fraction,
+            rebuild_interval: rebuild_interval.max(1),
+            cutoff: 0.0,
+            skin: 0.0,
+            start: Vec::new(),
+            items: Vec::new(),
+            anchors: Vec::new(),
+            steps_since_build: 0,
+            rebuilds: 0,
+        }
+    }
+
+    /// Make the lists valid for `positions` and `cutoff`, rebuilding when they are stale.
+    /// No pair can come within `cutoff` unseen while every particle has moved less than
+    /// half the skin, so that is the displacement that forces a rebuild.
+    pub fn update(&mut self, positions: &[(f32, f32)], cutoff: f32) -> bool {
+        let half_skin_sq = (self.skin * 0.5).powi(2);
+        let moved_too_far = positions.iter().zip(&self.anchors).any(|(&(x, y), &(ax, ay))| {
+            // NaN positions count as moved
+            let d2 = (x - ax).powi(2) + (y - ay).powi(2);
+            d2 > half_skin_sq || d2.is_nan()
+        });
+        self.steps_since_build += 1;
+        let stale = positions.len() != self.anchors.len()
+            || cutoff != self.cutoff
+            || self.steps_since_build >= self.rebuild_interval
+            || moved_too_far;
+        if stale {
+            self.build(positions, cutoff);
+        }
+        stale
+    }
+
+    /// Particles listed near `i` as of the last build; a superset of those within the cutoff
+    pub fn neighbors(&self, i: usize) -> &[u32] {
+        &self.items[self.start[i] as usize..self.start[i + 1] as usize]
+    }
+
+    /// Times the lists have been built
+    pub fn rebuilds(&self) -> u64 {
+        self.rebuilds
+    }
+
+    fn build(&mut self, positions: &[(f32, f32)], cutoff: f32) {
+        self.cutoff = cutoff;
+        self.skin = cutoff * self.skin_fraction;
+        let reach = cutoff + self.skin;
+        let reach_sq = reach * reach;
+        let cell_of = |x: f32, y: f32| ((x / reach).floor() as i32, (y / reach).floor() as i32);
+
+        let mut cells: HashMap<(i32, i32), Vec<u32>> = HashMap::new();
+        for (i, &(x, y)) in positions.iter().enumerate() {
+            cells.entry(cell_of(x, y)).or_default().push(i as u32);
+        }
+
+        self.start.clear();
+        self.items.clear();
+        self.start.push(0);
+        for (i, &(x, y)) in positions.iter().enumerate() {
+            let (cx, cy) = cell_of(x, y);
+            for ny in cy - 1..=cy + 1 {
+                for nx in cx - 1..=cx + 1 {
+                    let Some(cell) = cells.get(&(nx, ny)) else {
+                        continue;
+                    };
+                    self.items.extend(cell.iter().copied().filter(|&j| {
+                        let (px, py) = positions[j as usize];
+                        j as usize != i && (x - px).powi(2) + (y - py).powi(2) < reach_sq
+                    }));
+                }
+            }
+            self.start.push(self.items.len() as u32);
+        }
+        self.anchors.clear();
+        self.anchors.extend_from_slice(positions);
+        self.steps_since_build = 0;
+        self.rebuilds += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::rng::SimRng;
+
+    #[test]
+    fn test_lists_cover_every_pair_within_the_cutoff() {
+        let mut rng = SimRng::new(2);
+        let mut positions: Vec<(f32, f32)> = (0..400).map(|_| (rng.next_f32(), rng.next_f32())).collect();
+        let cutoff = 0.08;
+        let mut list = VerletList::default();
+        assert!(list.update(&positions, cutoff), "first update builds");
+        assert!(!list.update(&positions, cutoff), "nothing moved");
+
+        // Drift by less than half the skin each step; the lists must stay complete
+        let drift = cutoff * DEFAULT_SKIN_FRACTION * 0.05;
+        for _ in 0..4 {
+            for p in positions.iter_mut() {
+                p.0 += rng.range_f32(-drift, drift);
+                p.1 += rng.range_f32(-drift, drift);
+            }
+            list.update(&positions, cutoff);
+            for (i, &(x, y)) in positions.iter().enumerate() {
+                for (j, &(px, py)) in positions.iter().enumerate() {
+                    if i != j && (x - px).powi(2) + (y - py).powi(2) < cutoff * cutoff {
+                        assert!(list.neighbors(i).contains(&(j as u32)), "{} missed {}", i, j);
+                    }
+                }
+            }
+        }
+        assert_eq!(list.rebuilds(), 1, "small drifts reuse the lists");
+
+        positions[0].0 += cutoff;
+        assert!(list.update(&positions, cutoff), "a big move rebuilds");
+        assert!(list.update(&positions, cutoff * 1.5), "a new cutoff rebuilds");
+    }
+
+    #[test]
+    fn test_lists_expire_after_the_interval() {
+        let positions = [(0.1, 0.1), (0.12, 0.1)];
+        let mut list = VerletList::new(0.3, 3);
+        let built: Vec<bool> = (0..7).map(|_| list.update(&positions, 0.05)).collect();
+        assert_eq!(built, [true, false, false, true, false, false, true]);
+        assert_eq!(list.neighbors(0), [1]);
+    }
+}
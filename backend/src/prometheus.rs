@@ -0,0 +1,123 @@
+// Prometheus text exposition for `GET /metrics`
+use crate::gpu_stats::GpuStats;
+use crate::metrics::{DisconnectReason, ServerMetrics};
+use crate::simulation_engine::FrameStats;
+use std::fmt::Write;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Render one scrape. GPU gauges are left out when `gpu` (or a field of it) is unavailable.
+pub fn render(frames: &FrameStats, server: &ServerMetrics, gpu: Option<&GpuStats>) -> String {
+    let mut out = String::new();
+    metric(&mut out, "physics_frames_total", "counter", "Simulation steps taken", frames.frame_count as f64);
+    metric(&mut out, "physics_achieved_fps", "gauge", "Simulation steps per second", frames.achieved_fps as f64);
+    metric(&mut out, "physics_target_fps", "gauge", "Simulation loop target rate", frames.target_fps as f64);
+    metric(&mut out, "physics_time_scale", "gauge", "Multiplier on each step's dt", frames.time_scale as f64);
+    metric(
+        &mut out,
+        "physics_broadcast_encode_failures_total",
+        "counter",
+        "Broadcast frames that failed to encode",
+        server.encode_failures() as f64,
+    );
+    metric(
+        &mut out,
+        "physics_websocket_connections",
+        "gauge",
+        "Open /ws and SSE connections",
+        server.active_connections() as f64,
+    );
+    metric(
+        &mut out,
+        "physics_websocket_connections_total",
+        "counter",
+        "/ws and SSE connections accepted",
+        server.total_connections() as f64,
+    );
+
+    metric(
+        &mut out,
+        "physics_websocket_lagged_frames_total",
+        "counter",
+        "Broadcasts /ws and SSE clients skipped after falling behind",
+        server.lagged_frames() as f64,
+    );
+
+    header(&mut out, "physics_websocket_disconnects_total", "counter", "/ws and SSE disconnects by reason");
+    for reason in DisconnectReason::ALL {
+        let _ = writeln!(
+            out,
+            "physics_websocket_disconnects_total{{reason=\"{}\"}} {}",
+            reason.as_str(),
+            server.disconnects(reason)
+        );
+    }
+
+    if let Some(util) = gpu.and_then(|g| g.gpu_utilization) {
+        metric(&mut out, "physics_gpu_utilization_percent", "gauge", "GPU utilization", util as f64);
+    }
+    if let Some(temp) = gpu.and_then(|g| g.temperature_c) {
+        metric(&mut out, "physics_gpu_temperature_celsius", "gauge", "GPU temperature", temp as f64);
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: f64) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_is_valid_exposition_format() {
+        let frames = FrameStats {
+            frame_count: 42,
+            avg_frame_ms: 2.0,
+            p99_frame_ms: 3.0,
+            used_cuda: false,
+            target_fps: 500.0,
+            achieved_fps: 480.5,
+            time_scale: 1.0,
+        };
+        let server = ServerMetrics::new();
+        server.record_encode_failure();
+        server.record_lagged(7);
+        let gpu = GpuStats {
+            gpu_utilization: Some(73),
+            memory_utilization: None,
+            memory_used_mb: None,
+            memory_total_mb: None,
+            temperature_c: None,
+            timestamp: 0,
+        };
+        let text = render(&frames, &server, Some(&gpu));
+
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                assert!(comment.starts_with("HELP ") || comment.starts_with("TYPE "), "{}", line);
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').expect(line);
+            let name = series.split('{').next().unwrap();
+            assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "{}", line);
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+        }
+        assert!(text.contains("physics_frames_total 42\n"));
+        assert!(text.contains("physics_achieved_fps 480.5\n"));
+        assert!(text.contains("physics_broadcast_encode_failures_total 1\n"));
+        assert!(text.contains("physics_websocket_connections 0\n"));
+        assert!(text.contains("physics_websocket_lagged_frames_total 7\n"));
+        assert!(text.contains("physics_websocket_disconnects_total{reason=\"send_error\"} 0\n"));
+        assert!(text.contains("physics_gpu_utilization_percent 73\n"));
+        assert!(!text.contains("temperature"), "Unavailable GPU fields are omitted");
+    }
+}
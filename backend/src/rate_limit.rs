@@ -0,0 +1,135 @@
+// Per-client token buckets for the synchronous `/api/simulate/*` runs and benchmarks,
+// each of which occupies a CUDA worker; streams and everything else are not limited
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_RATE_PER_SEC: f32 = 2.0;
+pub const DEFAULT_BURST: u32 = 10;
+/// Idle (full) buckets are dropped once this many clients are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f32,
+    updated: Instant,
+}
+
+/// Token bucket per client IP: `burst` requests at once, refilled at `rate_per_sec`
+#[derive(Clone)]
+pub struct RateLimiter {
+    rate_per_sec: f32,
+    burst: f32,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate_per_sec: f32, burst: u32) -> Self {
+        Self {
+            rate_per_sec,
+            burst: burst.max(1) as f32,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Take a token for `client`, or say how long until one is available
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let (rate, burst) = (self.rate_per_sec, self.burst);
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f32() * rate < burst);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: self.burst, updated: now });
+        let refill = now.saturating_duration_since(bucket.updated).as_secs_f32() * self.rate_per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f32((1.0 - bucket.tokens) / self.rate_per_sec))
+        }
+    }
+}
+
+/// `from_fn_with_state` middleware: 429 with `Retry-After` once the client's bucket is empty.
+/// Without connect info every request shares one bucket.
+pub async fn limit(State(limiter): State<RateLimiter>, request: Request, next: Next) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |ConnectInfo(addr)| addr.ip());
+    match limiter.check(client, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f32().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                format!("Rate limit exceeded, retry in {}s", retry_after),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_bucket_refills_at_the_rate() {
+        let limiter = RateLimiter::new(2.0, 3);
+        let (client, other) = ([10, 0, 0, 1].into(), [10, 0, 0, 2].into());
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check(client, start).is_ok());
+        }
+        let wait = limiter.check(client, start).unwrap_err();
+        assert!((wait.as_secs_f32() - 0.5).abs() < 1e-3);
+        assert!(limiter.check(other, start).is_ok(), "clients have separate buckets");
+
+        assert!(limiter.check(client, start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check(client, start + Duration::from_millis(500)).is_err());
+        assert!(limiter.check(client, start + Duration::from_secs(60)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_burst_of_requests_gets_429() {
+        let limiter = RateLimiter::new(0.5, 5);
+        let app = Router::new().route(
+            "/api/simulate/boids",
+            post(|| async { "ok" }).route_layer(axum::middleware::from_fn_with_state(limiter, limit)),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap()
+        });
+
+        let mut responses = Vec::new();
+        for _ in 0..8 {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(b"POST /api/simulate/boids HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            responses.push(response);
+        }
+        let limited: Vec<&String> = responses.iter().filter(|r| r.starts_with("HTTP/1.1 429")).collect();
+        assert_eq!(limited.len(), 3, "the first 5 fit the burst");
+        assert!(responses[..5].iter().all(|r| r.starts_with("HTTP/1.1 200")));
+        assert!(limited[0].to_ascii_lowercase().contains("retry-after: 2"));
+    }
+}
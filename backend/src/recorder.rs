@@ -0,0 +1,439 @@
+// Capture the broadcast stream to disk and play it back over `/ws?replay=<file>`,
+// so frontend rendering issues can be reproduced without a GPU.
+// File layout: `MAGIC`, then per frame [elapsed ms u64][timestamp u64][num_boids u32]
+// [num_ids u32][force bytes u32][16 bytes per boid][ids u32 each][forces]
+use crate::broadcast::{self, BroadcastState};
+use anyhow::Result;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast as tokio_broadcast, watch};
+use tracing::{info, warn};
+
+pub const MAGIC: &[u8; 8] = b"BOIDREC1";
+pub const EXTENSION: &str = "rec";
+// Frames queued for the writer thread before new ones are dropped
+const QUEUE_FRAMES: usize = 64;
+const MAX_NAME_LEN: usize = 64;
+/// Largest file a recording grows to before later frames are skipped
+pub const MAX_RECORDING_BYTES: u64 = 1 << 30;
+/// Longest a recording captures frames for
+pub const MAX_RECORDING_DURATION: Duration = Duration::from_secs(30 * 60);
+// Per-frame header: elapsed ms, timestamp, num_boids, num_ids, force bytes
+const FRAME_HEADER_BYTES: u64 = 28;
+
+#[derive(Debug)]
+pub enum RecordError {
+    AlreadyRecording(String),
+    NotRecording,
+    InvalidName(String),
+    NotFound(String),
+    Io(anyhow::Error),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::AlreadyRecording(file) => write!(f, "already recording to {}", file),
+            RecordError::NotRecording => write!(f, "no recording in progress"),
+            RecordError::InvalidName(name) => write!(
+                f,
+                "recording names are 1..={} letters, digits, '-', '_' or '.', not starting with '.'; got {:?}",
+                MAX_NAME_LEN, name
+            ),
+            RecordError::NotFound(file) => write!(f, "no recording {}", file),
+            RecordError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// What `stop` reports about the finished file
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RecordingSummary {
+    pub file: String,
+    pub frames: u64,
+    /// Frames skipped because the disk couldn't keep up
+    pub dropped: u64,
+    pub duration_ms: u64,
+    /// Whether the size or duration cap ended capture before `stop`
+    pub capped: bool,
+}
+
+struct Active {
+    file: String,
+    started: Instant,
+    frames: mpsc::SyncSender<(u64, BroadcastState)>,
+    dropped: u64,
+    // Bytes queued for the file so far, headers included
+    bytes: u64,
+    capped: bool,
+    writer: JoinHandle<Result<u64>>,
+}
+
+pub struct Recorder {
+    dir: PathBuf,
+    active: Mutex<Option<Active>>,
+    max_bytes: u64,
+    max_duration: Duration,
+}
+
+impl Recorder {
+    /// Recordings are written to and replayed from `dir`, created on first use
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            active: Mutex::new(None),
+            max_bytes: MAX_RECORDING_BYTES,
+            max_duration: MAX_RECORDING_DURATION,
+        }
+    }
+
+    /// Stop capturing once a file reaches `max_bytes` or has run for `max_duration`
+    pub fn with_limits(mut self, max_bytes: u64, max_duration: Duration) -> Self {
+        self.max_bytes = max_bytes;
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Start appending broadcast frames to `<name>.rec` (default: a timestamped name),
+    /// replacing any earlier file of that name. Returns the file name.
+    pub fn start(&self, name: Option<&str>) -> Result<String, RecordError> {
+        let mut active = self.active.lock().unwrap();
+        if let Some(current) = active.as_ref() {
+            return Err(RecordError::AlreadyRecording(current.file.clone()));
+        }
+        let file = match name {
+            Some(name) => file_name(name)?,
+            None => {
+                let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                format!("recording-{}.{}", secs, EXTENSION)
+            }
+        };
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| RecordError::Io(anyhow::anyhow!("create {}: {:?}", self.dir.display(), e)))?;
+        let path = self.dir.join(&file);
+        let mut out = File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|e| RecordError::Io(anyhow::anyhow!("create {}: {:?}", path.display(), e)))?;
+
+        let (frames, queue) = mpsc::sync_channel::<(u64, BroadcastState)>(QUEUE_FRAMES);
+        let writer = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || -> Result<u64> {
+                out.write_all(MAGIC)?;
+                let mut written = 0;
+                // Ends once `stop` drops the sender and the queue drains
+                for (elapsed_ms, state) in queue {
+                    write_frame(&mut out, elapsed_ms, &state)?;
+                    written += 1;
+                }
+                out.flush()?;
+                Ok(written)
+            })
+            .map_err(|e| RecordError::Io(anyhow::anyhow!("spawn recorder: {:?}", e)))?;
+
+        info!("Recording broadcast frames to {}", path.display());
+        *active = Some(Active {
+            file: file.clone(),
+            started: Instant::now(),
+            frames,
+            dropped: 0,
+            bytes: MAGIC.len() as u64,
+            capped: false,
+            writer,
+        });
+        Ok(file)
+    }
+
+    /// Queue `state` for the file; a no-op unless recording and under the caps
+    pub fn record(&self, state: &BroadcastState) {
+        let mut active = self.active.lock().unwrap();
+        let Some(active) = active.as_mut() else { return };
+        if active.capped {
+            return;
+        }
+        let elapsed = active.started.elapsed();
+        let bytes = active.bytes + frame_len(state);
+        if elapsed > self.max_duration || bytes > self.max_bytes {
+            warn!("Recording {} reached its size or duration cap; capturing no more frames", active.file);
+            active.capped = true;
+            return;
+        }
+        let elapsed_ms = elapsed.as_millis() as u64;
+        if active.frames.try_send((elapsed_ms, state.clone())).is_ok() {
+            active.bytes = bytes;
+        } else {
+            if active.dropped == 0 {
+                warn!("Recorder can't keep up; dropping frames");
+            }
+            active.dropped += 1;
+        }
+    }
+
+    pub fn recording(&self) -> Option<String> {
+        self.active.lock().unwrap().as_ref().map(|a| a.file.clone())
+    }
+
+    /// Flush and close the file. Blocks until queued frames are written.
+    pub fn stop(&self) -> Result<RecordingSummary, RecordError> {
+        let Active { file, started, frames, dropped, capped, writer, .. } =
+            self.active.lock().unwrap().take().ok_or(RecordError::NotRecording)?;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        drop(frames);
+        let frames = writer
+            .join()
+            .map_err(|_| RecordError::Io(anyhow::anyhow!("recorder thread panicked")))?
+            .map_err(|e| RecordError::Io(anyhow::anyhow!("write {}: {:?}", file, e)))?;
+        info!("Recorded {} frames to {} ({} dropped)", frames, file, dropped);
+        Ok(RecordingSummary { file, frames, dropped, duration_ms, capped })
+    }
+
+    /// Open a recording in this recorder's directory for playback
+    pub fn open_replay(&self, name: &str) -> Result<Replay, RecordError> {
+        let file = file_name(name)?;
+        Replay::open(&self.dir.join(&file)).map_err(|e| match e {
+            ReplayOpenError::Missing => RecordError::NotFound(file),
+            ReplayOpenError::Other(e) => RecordError::Io(e),
+        })
+    }
+}
+
+/// `name` with the `.rec` extension, rejecting anything that could leave the directory
+fn file_name(name: &str) -> Result<String, RecordError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(RecordError::InvalidName(name.to_string()));
+    }
+    let suffix = format!(".{}", EXTENSION);
+    Ok(if name.ends_with(&suffix) { name.to_string() } else { format!("{}{}", name, suffix) })
+}
+
+/// Bytes `write_frame` writes for `state`
+fn frame_len(state: &BroadcastState) -> u64 {
+    FRAME_HEADER_BYTES + (state.data.len() + state.ids.len() * 4 + state.forces.len()) as u64
+}
+
+fn write_frame(out: &mut impl Write, elapsed_ms: u64, state: &BroadcastState) -> std::io::Result<()> {
+    out.write_all(&elapsed_ms.to_le_bytes())?;
+    out.write_all(&state.timestamp.to_le_bytes())?;
+    out.write_all(&(state.num_boids as u32).to_le_bytes())?;
+    out.write_all(&(state.ids.len() as u32).to_le_bytes())?;
+    out.write_all(&(state.forces.len() as u32).to_le_bytes())?;
+    out.write_all(&state.data)?;
+    out.write_all(&state.encode_ids())?;
+    out.write_all(&state.forces)
+}
+
+enum ReplayOpenError {
+    Missing,
+    Other(anyhow::Error),
+}
+
+/// A recording being read back frame by frame
+pub struct Replay {
+    input: BufReader<File>,
+    // Bytes left in the file, which no frame header may claim more than
+    remaining: u64,
+    // Whether frames get speed bytes, which only `?withspeed=1` clients read
+    speeds: bool,
+}
+
+impl Replay {
+    fn open(path: &Path) -> Result<Self, ReplayOpenError> {
+        let file = File::open(path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => ReplayOpenError::Missing,
+            _ => ReplayOpenError::Other(anyhow::anyhow!("open {}: {:?}", path.display(), e)),
+        })?;
+        let len = file
+            .metadata()
+            .map_err(|e| ReplayOpenError::Other(anyhow::anyhow!("stat {}: {:?}", path.display(), e)))?
+            .len();
+        let mut input = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        input
+            .read_exact(&mut magic)
+            .ok()
+            .filter(|_| &magic == MAGIC)
+            .ok_or_else(|| ReplayOpenError::Other(anyhow::anyhow!("{} is not a recording", path.display())))?;
+        Ok(Self { input, remaining: len.saturating_sub(MAGIC.len() as u64), speeds: false })
+    }
+
+    /// Compute speed bytes for each frame
+    pub fn with_speeds(mut self, speeds: bool) -> Self {
+        self.speeds = speeds;
+        self
+    }
+
+    /// The next frame and when it was recorded (ms after the start), or `None` at the end
+    pub fn next_frame(&mut self) -> Result<Option<(u64, BroadcastState)>> {
+        let mut header = [0u8; FRAME_HEADER_BYTES as usize];
+        match self.input.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(anyhow::anyhow!("read frame header: {:?}", e)),
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap()) as usize;
+        let (elapsed_ms, timestamp) = (u64_at(0), u64_at(8));
+        let (num_boids, num_ids, force_bytes) = (u32_at(16), u32_at(20), u32_at(24));
+        self.remaining = self.remaining.saturating_sub(FRAME_HEADER_BYTES);
+
+        // Check the lengths before allocating, so a corrupt header can't ask for gigabytes
+        if num_ids > num_boids || force_bytes > num_boids * 4 {
+            anyhow::bail!("corrupt frame header: {} boids, {} ids, {} force bytes", num_boids, num_ids, force_bytes);
+        }
+        let body_len = (num_boids * 16 + num_ids * 4 + force_bytes) as u64;
+        if body_len > self.remaining {
+            anyhow::bail!("truncated frame: needs {} bytes, {} left", body_len, self.remaining);
+        }
+        self.remaining -= body_len;
+        let mut body = vec![0u8; body_len as usize];
+        self.input
+            .read_exact(&mut body)
+            .map_err(|e| anyhow::anyhow!("truncated frame: {:?}", e))?;
+        let forces = body.split_off(num_boids * 16 + num_ids * 4);
+        let ids = body
+            .split_off(num_boids * 16)
+            .chunks_exact(4)
+            .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+            .collect();
+        let values = BroadcastState::decode(&body)?;
+        let occupancy = broadcast::occupancy_grid(&values, broadcast::OCCUPANCY_GRID_SIZE);
+        // Speed caps aren't recorded, so replayed speeds are relative to the default cap
+        let speeds = if self.speeds { broadcast::speed_bytes(&values, &[]) } else { Vec::new() };
+        let state = BroadcastState {
+            timestamp,
+            encode_ms: 0,
+            num_boids,
+            data: body,
+            ids,
+            forces,
+            speeds,
+            occupancy,
+            previous: Vec::new(),
+            history_frames: 0,
+        };
+        Ok(Some((elapsed_ms, state)))
+    }
+
+    /// Send every frame to `tx` at the pace it was recorded. Blocking; returns at
+    /// the end of the file, on shutdown or once nobody is listening.
+    pub fn play(mut self, tx: tokio_broadcast::Sender<BroadcastState>, shutdown: watch::Receiver<bool>) {
+        let start = Instant::now();
+        loop {
+            let (elapsed_ms, state) = match self.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Replay stopped: {:?}", e);
+                    break;
+                }
+            };
+            let due = start + Duration::from_millis(elapsed_ms);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            if *shutdown.borrow() || tx.send(state).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(num_boids: usize) -> BroadcastState {
+        let data = (0..num_boids * 4).flat_map(|i| (i as f32 / 100.0).to_le_bytes()).collect();
+        BroadcastState {
+            timestamp: 3,
+            encode_ms: 0,
+            num_boids,
+            data,
+            ids: (10..10 + num_boids as u32).collect(),
+            forces: vec![1; num_boids * 4],
+            speeds: vec![0; num_boids],
+            occupancy: Vec::new(),
+            previous: Vec::new(),
+            history_frames: 0,
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay_round_trip() {
+        let dir = std::env::temp_dir().join(format!("recorder-test-{}", std::process::id()));
+        let recorder = Recorder::new(&dir);
+        assert!(matches!(recorder.stop(), Err(RecordError::NotRecording)));
+
+        recorder.record(&state(2));
+        assert_eq!(recorder.start(Some("run")).unwrap(), "run.rec");
+        assert!(matches!(recorder.start(None), Err(RecordError::AlreadyRecording(_))));
+        recorder.record(&state(2));
+        recorder.record(&state(5));
+        let summary = recorder.stop().unwrap();
+        assert_eq!((summary.file.as_str(), summary.frames, summary.dropped), ("run.rec", 2, 0));
+        assert!(!summary.capped);
+
+        let mut replay = recorder.open_replay("run.rec").unwrap();
+        let (_, first) = replay.next_frame().unwrap().unwrap();
+        assert_eq!((first.num_boids, first.timestamp), (2, 3));
+        assert_eq!(first.data, state(2).data);
+        assert_eq!(first.ids, vec![10, 11]);
+        assert_eq!(first.forces, state(2).forces);
+        assert_eq!(first.occupancy.iter().map(|&c| c as usize).sum::<usize>(), 2);
+        let (_, second) = replay.next_frame().unwrap().unwrap();
+        assert_eq!(second.num_boids, 5);
+        assert!(replay.next_frame().unwrap().is_none());
+
+        assert!(matches!(recorder.open_replay("missing"), Err(RecordError::NotFound(_))));
+        assert!(matches!(recorder.open_replay("../etc/passwd"), Err(RecordError::InvalidName(_))));
+        assert!(matches!(recorder.start(Some(".hidden")), Err(RecordError::InvalidName(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recording_stops_at_size_cap() {
+        let dir = std::env::temp_dir().join(format!("recorder-cap-test-{}", std::process::id()));
+        let two_frames = MAGIC.len() as u64 + 2 * frame_len(&state(3));
+        let recorder = Recorder::new(&dir).with_limits(two_frames, MAX_RECORDING_DURATION);
+        recorder.start(Some("capped")).unwrap();
+        for _ in 0..5 {
+            recorder.record(&state(3));
+        }
+        let summary = recorder.stop().unwrap();
+        assert_eq!((summary.frames, summary.capped), (2, true));
+        assert_eq!(std::fs::metadata(dir.join("capped.rec")).unwrap().len(), two_frames);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_rejects_oversized_frame_header() {
+        let dir = std::env::temp_dir().join(format!("recorder-corrupt-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[0u8; 16]);
+        // A billion boids in a file a few bytes long
+        bytes.extend_from_slice(&1_000_000_000u32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 8]);
+        std::fs::write(dir.join("bad.rec"), &bytes).unwrap();
+        let mut replay = Recorder::new(&dir).open_replay("bad.rec").unwrap();
+        let error = replay.next_frame().err().unwrap().to_string();
+        assert!(error.contains("truncated frame"), "{}", error);
+
+        // More ids than boids is corrupt however long the file is
+        bytes.truncate(MAGIC.len() + 16);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 4 + 16 + 8]);
+        std::fs::write(dir.join("bad.rec"), &bytes).unwrap();
+        let mut replay = Recorder::new(&dir).open_replay("bad.rec").unwrap();
+        assert!(replay.next_frame().err().unwrap().to_string().contains("corrupt frame header"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,41 +1,192 @@
 // Persistent GPU simulation engine that runs continuously
+use crate::broadcast::{SubscriberGuard, Subscribers};
 use crate::cuda::CudaContext;
-use crate::physics::BoidsSimulation;
+use crate::physics::emitter::EmitterConfig;
+use crate::physics::obstacles::Obstacle;
+use crate::physics::boids::{BoundaryMode, NeighborGraph};
+use crate::physics::checkpoint::FlockState;
+use crate::physics::stats::{BoidStats, SpeciesSummary};
+use crate::physics::{BoidsParams, BoidsSimulation};
 use anyhow::Result;
+use serde::Deserialize;
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{info, warn};
-use rustacuda::prelude::*;
+
+/// Range `set_target_fps` and `set_min_fps` clamp to
+pub const MIN_TARGET_FPS: f32 = 30.0;
+pub const MAX_TARGET_FPS: f32 = 1000.0;
+/// Floor the adaptive timer won't lower the target FPS past, unless changed with `set_min_fps`
+pub const DEFAULT_MIN_FPS: f32 = 100.0;
+/// GPU temperature the loop starts throttling at, unless changed with `set_thermal_limit`
+pub const DEFAULT_THERMAL_LIMIT_C: u32 = 85;
+/// How far below the limit the GPU must cool before the saved FPS is restored
+const THERMAL_HYSTERESIS_C: u32 = 5;
+/// Fraction of the target FPS kept while throttled
+const THERMAL_THROTTLE_FACTOR: f32 = 0.5;
+const THERMAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Range `set_time_scale` clamps to; past 4x a step can carry boids through each other
+pub const MIN_TIME_SCALE: f32 = 0.1;
+pub const MAX_TIME_SCALE: f32 = 4.0;
+
+/// How the engine advances
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EngineMode {
+    /// Background loop stepping at the target FPS
+    #[default]
+    Continuous,
+    /// No background loop; each client `tick` advances one fixed step
+    Pull,
+}
+
+impl std::str::FromStr for EngineMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "continuous" => Ok(EngineMode::Continuous),
+            "pull" => Ok(EngineMode::Pull),
+            other => anyhow::bail!("engine mode must be continuous|pull, got {:?}", other),
+        }
+    }
+}
+
+/// Least time between snapshots the loop publishes for broadcasting: well under the
+/// 16ms broadcast interval, without copying the flock back on every one of up to
+/// 1000 steps a second
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(4);
+
+/// Longest a running, unpaused loop may go without completing a step before
+/// `liveness` reports it stalled
+pub const MAX_STEP_AGE: Duration = Duration::from_secs(2);
+
+/// Whether the background loop is alive, for `/health`
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    pub running: bool,
+    pub paused: bool,
+    /// Time since the last step finished
+    pub last_update_age: Duration,
+    /// What's wrong, or `None` when healthy
+    pub problem: Option<String>,
+}
+
+/// Latest published snapshot and the frame it follows
+type LatestSnapshot = Mutex<(u64, Arc<EngineSnapshot>)>;
+
+/// Per-boid state read under one lock, all in the same boid order
+pub struct EngineSnapshot {
+    /// [x, y, vx, vy] per boid
+    pub state: Vec<f32>,
+    pub ids: Vec<u32>,
+    /// Steering force magnitude applied in the last step; empty unless requested
+    pub forces: Vec<f32>,
+    /// Each boid's speed cap, for normalizing speeds
+    pub max_speeds: Vec<f32>,
+    /// Side length of the world `state` positions are in
+    pub world_size: f32,
+    /// Wall-clock time the state was read from the simulation
+    pub produced_at: SystemTime,
+}
+
+impl EngineSnapshot {
+    /// Reading `forces` costs a device copy, so it's skipped unless asked for
+    pub fn of(sim: &mut BoidsSimulation, forces: bool) -> Result<Self> {
+        Ok(Self {
+            state: sim.get_boids()?,
+            ids: sim.ids(),
+            forces: if forces { sim.force_magnitudes()? } else { Vec::new() },
+            max_speeds: sim.max_speeds(),
+            world_size: sim.world_size(),
+            produced_at: SystemTime::now(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub frame_count: u64,
+    pub avg_frame_ms: f32,
+    pub p99_frame_ms: f32,
+    pub used_cuda: bool,
+    /// Current loop rate; the adaptive timer lowers it when steps fall behind
+    pub target_fps: f32,
+    /// Steps per wall-clock second over the recent history window
+    pub achieved_fps: f32,
+    /// Multiplier on each step's `dt`, independent of the loop rate
+    pub time_scale: f32,
+}
 
 pub struct SimulationEngine {
     simulation: Arc<Mutex<BoidsSimulation>>,
     context: Arc<CudaContext>,
     running: Arc<Mutex<bool>>,
+    // While set the loop keeps its timer but skips stepping, freezing the flock
+    paused: Arc<Mutex<bool>>,
     target_fps: Arc<Mutex<f32>>, // Make mutable for adaptive timing
+    min_fps: Arc<Mutex<f32>>,
+    time_scale: Arc<Mutex<f32>>,
+    // Temperature limit and GPU stats cache age the loop polls with; read by `start`
+    thermal_limit: Mutex<Option<(u32, Duration)>>,
     last_update: Arc<Mutex<Instant>>,
     frame_count: Arc<Mutex<u64>>,
     // Performance tracking
     frame_times: Arc<Mutex<Vec<Duration>>>, // Track last N frame times
+    frame_ends: Arc<Mutex<VecDeque<Instant>>>, // When each of the last N frames finished
     consecutive_delays: Arc<Mutex<u32>>, // Count consecutive frames that exceeded target
+    // Last snapshot published by the stepping thread and the frame it follows, so
+    // broadcasting never locks the live simulation or needs a CUDA context
+    latest: Arc<LatestSnapshot>,
+    // `?forces=1` clients; published snapshots only carry forces while there are any
+    force_subscribers: Arc<Subscribers>,
+    // Background loop started by `start`, taken by `stop_and_join`
+    thread: Mutex<Option<std::thread::JoinHandle<()>>>,
 }
 
 impl SimulationEngine {
     pub fn new(context: &Arc<CudaContext>, num_boids: usize) -> Result<Self> {
-        info!("Initializing simulation engine with {} boids", num_boids);
-        
-        let simulation = Arc::new(Mutex::new(
-            BoidsSimulation::new(context, num_boids)?
-        ));
+        Self::with_species(context, num_boids, crate::physics::boids::NUM_SPECIES as u8)
+    }
+
+    pub fn with_species(context: &Arc<CudaContext>, num_boids: usize, num_species: u8) -> Result<Self> {
+        info!("Initializing simulation engine with {} boids, {} species", num_boids, num_species);
+        Self::from_simulation(context, BoidsSimulation::new_with_species(context, num_boids, num_species)?)
+    }
+
+    /// Like `with_species`, but halves the boid count until the flock fits in GPU memory,
+    /// down to `min_boids`. `num_boids()` reports the count actually created.
+    pub fn best_effort(context: &Arc<CudaContext>, num_boids: usize, min_boids: usize, num_species: u8) -> Result<Self> {
+        info!("Initializing simulation engine with up to {} boids, {} species", num_boids, num_species);
+        Self::from_simulation(context, BoidsSimulation::new_best_effort(context, num_boids, min_boids, num_species)?)
+    }
+
+    fn from_simulation(context: &Arc<CudaContext>, mut sim: BoidsSimulation) -> Result<Self> {
+        // Published up front so there is a state to broadcast before the first step
+        let latest = Arc::new(Mutex::new((0, Arc::new(EngineSnapshot::of(&mut sim, false)?))));
+        let simulation = Arc::new(Mutex::new(sim));
         
         Ok(Self {
             simulation,
             context: Arc::clone(context),
             running: Arc::new(Mutex::new(false)),
+            paused: Arc::new(Mutex::new(false)),
             target_fps: Arc::new(Mutex::new(500.0)), // 500 Hz internal update rate
+            min_fps: Arc::new(Mutex::new(DEFAULT_MIN_FPS)),
+            time_scale: Arc::new(Mutex::new(1.0)),
+            thermal_limit: Mutex::new(Some((
+                DEFAULT_THERMAL_LIMIT_C,
+                Duration::from_millis(crate::gpu_stats::DEFAULT_CACHE_INTERVAL_MS),
+            ))),
             last_update: Arc::new(Mutex::new(Instant::now())),
             frame_count: Arc::new(Mutex::new(0)),
             frame_times: Arc::new(Mutex::new(Vec::new())),
+            frame_ends: Arc::new(Mutex::new(VecDeque::new())),
             consecutive_delays: Arc::new(Mutex::new(0)),
+            latest,
+            force_subscribers: Arc::new(Subscribers::default()),
+            thread: Mutex::new(None),
         })
     }
     
@@ -56,28 +207,29 @@ impl SimulationEngine {
         let simulation = Arc::clone(&self.simulation);
         let context = Arc::clone(&self.context);
         let running_flag = Arc::clone(&self.running);
+        let paused = Arc::clone(&self.paused);
         let target_fps = Arc::clone(&self.target_fps);
+        let min_fps = Arc::clone(&self.min_fps);
+        let time_scale = Arc::clone(&self.time_scale);
         let last_update = Arc::clone(&self.last_update);
         let frame_count = Arc::clone(&self.frame_count);
         let frame_times = Arc::clone(&self.frame_times);
+        let frame_ends = Arc::clone(&self.frame_ends);
         let consecutive_delays = Arc::clone(&self.consecutive_delays);
+        let latest = Arc::clone(&self.latest);
+        let force_subscribers = Arc::clone(&self.force_subscribers);
+        let thermal_limit = *self.thermal_limit.lock().unwrap();
         
         // Spawn simulation loop in background thread
-        std::thread::spawn(move || {
+        let handle = std::thread::spawn(move || {
             // Initialize CUDA in this thread
             if let Err(e) = crate::cuda::init_cuda_in_thread() {
                 warn!("Failed to initialize CUDA in simulation thread: {:?}", e);
                 return;
             }
             
-            // Create and keep context alive for this thread
-            // Get device from the context
-            let device = Device::get_device(0).expect("Failed to get CUDA device");
-            
-            let _cuda_context = match rustacuda::prelude::Context::create_and_push(
-                rustacuda::prelude::ContextFlags::MAP_HOST | rustacuda::prelude::ContextFlags::SCHED_AUTO,
-                device
-            ) {
+            // Create and keep context alive for this thread (no-cuda builds have no device)
+            let _cuda_context = match context.push_context() {
                 Ok(ctx) => ctx,
                 Err(e) => {
                     warn!("Failed to create CUDA context in simulation thread: {:?}", e);
@@ -85,13 +237,12 @@ impl SimulationEngine {
                 }
             };
             
-            const FRAME_TIME_HISTORY_SIZE: usize = 100;
             const ADAPTIVE_THRESHOLD: u32 = 50; // Reduce FPS after 50 consecutive delays
-            const MIN_FPS: f32 = 100.0; // Minimum FPS to prevent too slow simulation
+            let mut last_published = Instant::now();
+            let mut thermal = thermal_limit.map(|(limit_c, max_age)| (ThermalThrottle::new(limit_c), max_age));
+            let mut last_thermal_poll = Instant::now();
             
             loop {
-                let start = Instant::now();
-                
                 // Check if we should stop
                 {
                     let running_guard = running_flag.lock().unwrap();
@@ -101,44 +252,59 @@ impl SimulationEngine {
                     }
                 }
                 
+                // Thermal throttling: poll the (cached) GPU temperature now and then
+                if let Some((throttle, max_age)) = thermal.as_mut() {
+                    if last_thermal_poll.elapsed() >= THERMAL_POLL_INTERVAL {
+                        last_thermal_poll = Instant::now();
+                        let temperature = crate::gpu_stats::get_gpu_stats(context.device(), *max_age)
+                            .ok()
+                            .and_then(|stats| stats.temperature_c);
+                        if let Some(temperature) = temperature {
+                            let mut fps_guard = target_fps.lock().unwrap();
+                            if let Some(new_fps) = throttle.update(temperature, *fps_guard) {
+                                if throttle.is_throttled() {
+                                    warn!(
+                                        "GPU at {}°C (limit {}°C), throttling simulation FPS {:.1} -> {:.1} Hz",
+                                        temperature, throttle.limit_c, *fps_guard, new_fps
+                                    );
+                                } else {
+                                    info!("GPU cooled to {}°C, restoring simulation FPS to {:.1} Hz", temperature, new_fps);
+                                }
+                                *fps_guard = new_fps;
+                            }
+                        }
+                    }
+                }
+                
                 // Get current target FPS
                 let current_target_fps = {
                     let fps_guard = target_fps.lock().unwrap();
                     *fps_guard
                 };
                 
-                let dt = 1.0 / current_target_fps;
-                let target_duration = Duration::from_secs_f32(dt);
-                
-                // Run simulation step
-                let step_result = {
-                    let mut sim = simulation.lock().unwrap();
-                    sim.step(dt)
-                };
-                
-                if let Err(e) = step_result {
-                    warn!("Simulation step error: {:?}", e);
+                let target_duration = Duration::from_secs_f32(1.0 / current_target_fps);
+                let dt = *time_scale.lock().unwrap() / current_target_fps;
+
+                if *paused.lock().unwrap() {
+                    std::thread::sleep(target_duration);
+                    continue;
                 }
                 
-                // Update frame tracking
-                let elapsed = start.elapsed();
-                {
-                    let mut count = frame_count.lock().unwrap();
-                    *count += 1;
+                let publish = last_published.elapsed() >= SNAPSHOT_INTERVAL;
+                if publish {
+                    last_published = Instant::now();
                 }
-                
-                {
-                    let mut last = last_update.lock().unwrap();
-                    *last = Instant::now();
-                }
-                
-                // Track frame times for adaptive timing
-                {
-                    let mut times = frame_times.lock().unwrap();
-                    times.push(elapsed);
-                    if times.len() > FRAME_TIME_HISTORY_SIZE {
-                        times.remove(0);
-                    }
+                let (step_result, elapsed) = run_tick(
+                    &simulation,
+                    &frame_count,
+                    &last_update,
+                    &frame_times,
+                    &frame_ends,
+                    publish.then_some((&*latest, force_subscribers.any())),
+                    dt,
+                );
+                if let Err(e) = step_result {
+                    warn!("Simulation step error: {:?}", e);
                 }
                 
                 // Adaptive timing: reduce FPS if consistently falling behind
@@ -149,7 +315,9 @@ impl SimulationEngine {
                     // If consistently falling behind, reduce target FPS
                     if *delays >= ADAPTIVE_THRESHOLD {
                         let mut fps_guard = target_fps.lock().unwrap();
-                        let new_fps = (*fps_guard * 0.9).max(MIN_FPS);
+                        // Never raise a target that was set below the floor
+                        let floor = min_fps.lock().unwrap().min(*fps_guard);
+                        let new_fps = (*fps_guard * 0.9).max(floor);
                         if (new_fps - *fps_guard).abs() > 1.0 {
                             *fps_guard = new_fps;
                             info!("Reducing simulation FPS to {:.1} Hz due to performance issues", new_fps);
@@ -189,18 +357,80 @@ impl SimulationEngine {
                 }
             }
         });
+        *self.thread.lock().unwrap() = Some(handle);
         
         Ok(())
     }
     
+    /// Advance exactly one step of `time_scale / target_fps` from the caller's thread.
+    /// For pull mode, where no background loop runs; returns the new frame count.
+    pub fn tick(&self) -> Result<u64> {
+        if self.is_running() {
+            anyhow::bail!("engine is running continuously; tick is only available in pull mode");
+        }
+        self.context.ensure_context()?;
+        let dt = self.time_scale() / *self.target_fps.lock().unwrap();
+        let (result, _) = run_tick(
+            &self.simulation,
+            &self.frame_count,
+            &self.last_update,
+            &self.frame_times,
+            &self.frame_ends,
+            Some((&self.latest, self.force_subscribers.any())),
+            dt,
+        );
+        result?;
+        Ok(self.get_frame_count())
+    }
+
+    /// Freeze the flock in place; the loop keeps running so `resume` continues
+    /// from the same state
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap() = true;
+        info!("Pausing simulation engine");
+    }
+
+    pub fn resume(&self) {
+        *self.paused.lock().unwrap() = false;
+        info!("Resuming simulation engine");
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.lock().unwrap()
+    }
+
     #[allow(dead_code)]
     pub fn stop(&self) {
         let mut running = self.running.lock().unwrap();
         *running = false;
         info!("Stopping simulation engine");
     }
+
+    /// Stop the loop and wait for its thread to finish the current step.
+    /// Returns false if the thread panicked; a no-op when the loop never ran.
+    pub fn stop_and_join(&self) -> bool {
+        self.stop();
+        let handle = self.thread.lock().unwrap().take();
+        match handle {
+            Some(handle) => handle.join().is_ok(),
+            None => true,
+        }
+    }
     
     pub fn get_state(&self) -> Result<Vec<f32>> {
+        self.snapshot().map(|snapshot| snapshot.state)
+    }
+
+    /// The state most recently published by the stepping thread (at most
+    /// `SNAPSHOT_INTERVAL` behind while running) and the frame it was taken after.
+    /// Never touches the simulation or the device, so any thread may call it.
+    pub fn latest_snapshot(&self) -> (u64, Arc<EngineSnapshot>) {
+        let latest = self.latest.lock().unwrap();
+        (latest.0, Arc::clone(&latest.1))
+    }
+
+    /// Current state, stable ids and force magnitudes, read under one lock
+    pub fn snapshot(&self) -> Result<EngineSnapshot> {
         // Ensure CUDA context is available in current thread
         // Retry logic for async tasks that might run on different threads
         let mut retries = 3;
@@ -219,14 +449,232 @@ impl SimulationEngine {
         }
         
         let mut sim = self.simulation.lock().unwrap();
-        sim.get_boids()
+        EngineSnapshot::of(&mut sim, true)
+    }
+
+    /// Include force magnitudes in published snapshots until the guard is dropped
+    pub fn subscribe_forces(&self) -> SubscriberGuard {
+        self.force_subscribers.subscribe()
     }
     
     pub fn num_boids(&self) -> usize {
         let sim = self.simulation.lock().unwrap();
         sim.num_boids()
     }
+
+    /// Apply flocking parameters to the running simulation; returns any warnings
+    pub fn set_params(&self, params: &BoidsParams) -> Result<Vec<String>> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        let warnings = sim.set_params(params)?;
+        self.publish(&mut sim)?;
+        Ok(warnings)
+    }
     
+    /// Re-seed the running flock in place; returns the seed used so the run can be replayed
+    pub fn reset(&self, seed: Option<u64>) -> Result<u64> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.reset(seed)?;
+        self.publish(&mut sim)?;
+        Ok(sim.seed())
+    }
+
+    /// Replace the running flock with boids at the given positions
+    pub fn reset_positions(&self, positions: &[(f32, f32)]) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.reset_positions(positions)?;
+        self.publish(&mut sim)
+    }
+
+    /// Grow or shrink the running flock to `num_boids`, keeping the boids that remain
+    pub fn resize(&self, num_boids: usize) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.resize(num_boids)?;
+        self.publish(&mut sim)
+    }
+
+    /// Serialize the running flock with `BoidsSimulation::serialize_state`
+    pub fn checkpoint(&self) -> Result<Vec<u8>> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.serialize_state()
+    }
+
+    /// Replace the running flock with a checkpoint from `decode_checkpoint`
+    pub fn restore(&self, state: FlockState) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.restore_checkpoint(state)?;
+        self.publish(&mut sim)
+    }
+
+    // Republish after changes made outside a step, which a paused loop wouldn't pick up
+    fn publish(&self, sim: &mut BoidsSimulation) -> Result<()> {
+        publish_snapshot(&self.latest, self.get_frame_count(), sim, self.force_subscribers.any())
+    }
+
+    /// Neighbour graph of the running flock, or `None` if it has more than `max_boids`
+    pub fn neighbor_graph(&self, max_boids: usize) -> Result<Option<NeighborGraph>> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        if sim.num_boids() > max_boids {
+            return Ok(None);
+        }
+        sim.neighbor_graph().map(Some)
+    }
+
+    /// Aggregate statistics over the running flock
+    pub fn statistics(&self) -> Result<BoidStats> {
+        self.context.ensure_context()?;
+        self.simulation.lock().unwrap().statistics()
+    }
+
+    /// Histogram of the running flock's positions on a `resolution`² grid
+    pub fn density_grid(&self, resolution: usize) -> Result<Vec<u32>> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.get_boids()?;
+        Ok(sim.density_grid(resolution))
+    }
+
+    /// Boids per species in the running flock, with a display colour for each
+    pub fn species_summary(&self) -> Result<SpeciesSummary> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.get_boids()?;
+        Ok(sim.species_summary())
+    }
+
+    /// RGBA preview of the running flock
+    pub fn render_thumbnail(&self, width: usize, height: usize) -> Result<Vec<u8>> {
+        self.context.ensure_context()?;
+        self.simulation.lock().unwrap().render_thumbnail(width, height)
+    }
+
+    /// Change boundary handling; takes effect on the next step
+    pub fn set_boundary_mode(&self, mode: BoundaryMode) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.set_boundary_mode(mode)?;
+        self.publish(&mut sim)
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        let sim = self.simulation.lock().unwrap();
+        sim.boundary_mode()
+    }
+
+    pub fn set_obstacles(&self, obstacles: Vec<Obstacle>) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.set_obstacles(obstacles)?;
+        self.publish(&mut sim)
+    }
+
+    pub fn obstacles(&self) -> Vec<Obstacle> {
+        let sim = self.simulation.lock().unwrap();
+        sim.obstacles().to_vec()
+    }
+
+    /// The live flock, for callers (like `/ws` commands) that edit it between steps
+    pub fn simulation(&self) -> Arc<Mutex<BoidsSimulation>> {
+        Arc::clone(&self.simulation)
+    }
+
+    /// Configure the continuous emitter (`None` disables it)
+    pub fn set_emitter(&self, config: Option<EmitterConfig>) -> Result<()> {
+        self.context.ensure_context()?;
+        let mut sim = self.simulation.lock().unwrap();
+        sim.set_emitter(config)?;
+        self.publish(&mut sim)
+    }
+
+    pub fn emitter_config(&self) -> Option<EmitterConfig> {
+        let sim = self.simulation.lock().unwrap();
+        sim.emitter_config().cloned()
+    }
+    
+    /// Frame timing over the recent history window
+    pub fn frame_stats(&self) -> FrameStats {
+        let mut times: Vec<Duration> = self.frame_times.lock().unwrap().clone();
+        times.sort();
+        let avg_frame_ms = if times.is_empty() {
+            0.0
+        } else {
+            times.iter().sum::<Duration>().as_secs_f32() / times.len() as f32 * 1000.0
+        };
+        // Nearest-rank percentile
+        let p99_frame_ms = times
+            .get(((times.len() * 99).div_ceil(100)).saturating_sub(1))
+            .map_or(0.0, |t| t.as_secs_f32() * 1000.0);
+        let used_cuda = self.simulation.lock().unwrap().used_cuda();
+        let achieved_fps = achieved_fps(&self.frame_ends.lock().unwrap(), Instant::now());
+        FrameStats {
+            frame_count: self.get_frame_count(),
+            avg_frame_ms,
+            p99_frame_ms,
+            used_cuda,
+            target_fps: *self.target_fps.lock().unwrap(),
+            achieved_fps,
+            time_scale: self.time_scale(),
+        }
+    }
+
+    /// Whether the flock has been at rest long enough to count as stuck
+    pub fn in_stasis(&self) -> bool {
+        self.simulation.lock().unwrap().in_stasis()
+    }
+
+    /// Change the internal update rate, clamped to `MIN_TARGET_FPS..=MAX_TARGET_FPS`;
+    /// the adaptive timer may lower it under load. Returns the value applied.
+    pub fn set_target_fps(&self, fps: f32) -> Result<f32> {
+        let fps = clamp_fps(fps)?;
+        *self.target_fps.lock().unwrap() = fps;
+        Ok(fps)
+    }
+
+    /// Change the floor the adaptive timer stops lowering the target FPS at,
+    /// clamped like `set_target_fps`. Returns the value applied.
+    pub fn set_min_fps(&self, fps: f32) -> Result<f32> {
+        let fps = clamp_fps(fps)?;
+        *self.min_fps.lock().unwrap() = fps;
+        Ok(fps)
+    }
+
+    /// Throttle the background loop while the GPU is at or above `limit_c`, reading
+    /// temperatures through the GPU stats cache with `stats_max_age`; `None` disables it.
+    /// Takes effect on the next `start`.
+    pub fn set_thermal_limit(&self, limit_c: Option<u32>, stats_max_age: Duration) {
+        *self.thermal_limit.lock().unwrap() = limit_c.map(|limit| (limit, stats_max_age));
+    }
+
+    pub fn target_fps(&self) -> f32 {
+        *self.target_fps.lock().unwrap()
+    }
+
+    pub fn min_fps(&self) -> f32 {
+        *self.min_fps.lock().unwrap()
+    }
+
+    /// Run the flock faster or slower without changing the update rate: each step's `dt`
+    /// is multiplied by `scale`, clamped to `MIN_TIME_SCALE..=MAX_TIME_SCALE`.
+    /// Returns the value applied.
+    pub fn set_time_scale(&self, scale: f32) -> Result<f32> {
+        if !scale.is_finite() {
+            anyhow::bail!("time_scale must be a finite number, got {}", scale);
+        }
+        let scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        *self.time_scale.lock().unwrap() = scale;
+        Ok(scale)
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        *self.time_scale.lock().unwrap()
+    }
+
     #[allow(dead_code)]
     pub fn is_running(&self) -> bool {
         *self.running.lock().unwrap()
@@ -241,6 +689,158 @@ impl SimulationEngine {
     pub fn get_last_update(&self) -> Instant {
         *self.last_update.lock().unwrap()
     }
+
+    /// Whether the loop is still stepping. An engine that was never started (pull
+    /// mode) is healthy, since clients drive it; a paused one only needs its thread alive.
+    pub fn liveness(&self) -> Liveness {
+        let running = self.is_running();
+        let paused = self.is_paused();
+        let last_update_age = self.get_last_update().elapsed();
+        let thread_finished = self.thread.lock().unwrap().as_ref().map(|h| h.is_finished());
+        Liveness {
+            running,
+            paused,
+            last_update_age,
+            problem: liveness_problem(running, thread_finished, paused, last_update_age),
+        }
+    }
+}
+
+/// `thread_finished` is `None` when no loop was started
+fn liveness_problem(
+    running: bool,
+    thread_finished: Option<bool>,
+    paused: bool,
+    last_update_age: Duration,
+) -> Option<String> {
+    let finished = thread_finished?;
+    if !running {
+        return Some("simulation loop is stopped".to_string());
+    }
+    if finished {
+        return Some("simulation thread exited unexpectedly".to_string());
+    }
+    if !paused && last_update_age > MAX_STEP_AGE {
+        return Some(format!(
+            "no simulation step for {:.1}s (limit {}s)",
+            last_update_age.as_secs_f32(),
+            MAX_STEP_AGE.as_secs()
+        ));
+    }
+    None
+}
+
+const FRAME_TIME_HISTORY_SIZE: usize = 100;
+
+fn clamp_fps(fps: f32) -> Result<f32> {
+    if !fps.is_finite() {
+        anyhow::bail!("fps must be a finite number, got {}", fps);
+    }
+    Ok(fps.clamp(MIN_TARGET_FPS, MAX_TARGET_FPS))
+}
+
+/// Lowers the target FPS while the GPU is at or above `limit_c` and restores the
+/// previous value once it has cooled `THERMAL_HYSTERESIS_C` below the limit
+struct ThermalThrottle {
+    limit_c: u32,
+    // Target FPS before throttling and the one throttling set; set while throttled
+    saved_fps: Option<(f32, f32)>,
+}
+
+impl ThermalThrottle {
+    fn new(limit_c: u32) -> Self {
+        Self { limit_c, saved_fps: None }
+    }
+
+    fn is_throttled(&self) -> bool {
+        self.saved_fps.is_some()
+    }
+
+    /// The target FPS to switch to when `temperature_c` crosses the limit either way.
+    /// A target changed while throttled (e.g. through the API) is kept on cooling.
+    fn update(&mut self, temperature_c: u32, current_fps: f32) -> Option<f32> {
+        match self.saved_fps {
+            None if temperature_c >= self.limit_c => {
+                let throttled = (current_fps * THERMAL_THROTTLE_FACTOR).max(MIN_TARGET_FPS.min(current_fps));
+                self.saved_fps = Some((current_fps, throttled));
+                Some(throttled)
+            }
+            Some((saved, throttled)) if temperature_c + THERMAL_HYSTERESIS_C < self.limit_c => {
+                self.saved_fps = None;
+                (current_fps == throttled).then_some(saved)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One simulation step plus frame bookkeeping; shared by the background loop and `tick`.
+/// Returns the step result and how long it took.
+fn run_tick(
+    simulation: &Mutex<BoidsSimulation>,
+    frame_count: &Mutex<u64>,
+    last_update: &Mutex<Instant>,
+    frame_times: &Mutex<Vec<Duration>>,
+    frame_ends: &Mutex<VecDeque<Instant>>,
+    latest: Option<(&LatestSnapshot, bool)>,
+    dt: f32,
+) -> (Result<()>, Duration) {
+    let start = Instant::now();
+    let mut sim = simulation.lock().unwrap();
+    let mut result = sim.step(dt).and_then(|_| sim.run_emitter(dt).map(|_| ()));
+    let elapsed = start.elapsed();
+
+    let frame = {
+        let mut count = frame_count.lock().unwrap();
+        *count += 1;
+        *count
+    };
+    // Still under the simulation lock, so the snapshot is exactly this frame
+    if let Some((latest, forces)) = latest {
+        result = result.and_then(|_| publish_snapshot(latest, frame, &mut sim, forces));
+    }
+    drop(sim);
+    *last_update.lock().unwrap() = Instant::now();
+
+    // Track frame times for adaptive timing
+    let mut times = frame_times.lock().unwrap();
+    times.push(elapsed);
+    if times.len() > FRAME_TIME_HISTORY_SIZE {
+        times.remove(0);
+    }
+    let mut ends = frame_ends.lock().unwrap();
+    ends.push_back(Instant::now());
+    if ends.len() > FRAME_TIME_HISTORY_SIZE {
+        ends.pop_front();
+    }
+    (result, elapsed)
+}
+
+fn publish_snapshot(
+    latest: &LatestSnapshot,
+    frame: u64,
+    sim: &mut BoidsSimulation,
+    forces: bool,
+) -> Result<()> {
+    let snapshot = Arc::new(EngineSnapshot::of(sim, forces)?);
+    *latest.lock().unwrap() = (frame, snapshot);
+    Ok(())
+}
+
+/// Frames per second over the window from the oldest recorded frame end to `now`,
+/// so the rate decays toward zero once steps stop
+fn achieved_fps(frame_ends: &VecDeque<Instant>, now: Instant) -> f32 {
+    match frame_ends.front() {
+        Some(&first) => {
+            let window = now.saturating_duration_since(first).as_secs_f32();
+            if window > 0.0 {
+                frame_ends.len() as f32 / window
+            } else {
+                0.0
+            }
+        }
+        None => 0.0,
+    }
 }
 
 unsafe impl Send for SimulationEngine {}
@@ -249,10 +849,13 @@ unsafe impl Sync for SimulationEngine {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::{CudaContext, init_cuda_in_thread};
+    #[cfg(not(feature = "no-cuda"))]
     use std::sync::Arc;
     use std::time::Duration;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -267,6 +870,38 @@ mod tests {
         )
     }
 
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_tick_advances_one_frame_without_loop() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        let before = engine.get_state().unwrap();
+        assert_eq!(engine.tick().unwrap(), 1);
+        assert_eq!(engine.tick().unwrap(), 2);
+        assert_ne!(engine.get_state().unwrap(), before, "Each tick moves the flock");
+
+        // Nothing advances between ticks
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(engine.get_frame_count(), 2);
+
+        engine.start().unwrap();
+        assert!(engine.tick().is_err(), "Tick is refused while the loop runs");
+        engine.stop();
+    }
+
+    #[test]
+    fn test_achieved_fps_over_window() {
+        let now = Instant::now();
+        assert_eq!(achieved_fps(&VecDeque::new(), now), 0.0);
+        let ends: VecDeque<Instant> =
+            (1..=50).rev().map(|i| now - Duration::from_millis(i * 10)).collect();
+        let fps = achieved_fps(&ends, now);
+        assert!((fps - 100.0).abs() < 1.0, "50 frames over 500ms, got {}", fps);
+        // Stalled loop: the same frames spread over a longer window
+        assert!(achieved_fps(&ends, now + Duration::from_secs(1)) < 40.0);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_initialization() {
         let (context, _context_guard) = setup_test_context();
@@ -274,6 +909,7 @@ mod tests {
         assert!(engine.is_ok(), "Simulation engine should initialize");
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_start_stop() {
         let (context, _context_guard) = setup_test_context();
@@ -294,6 +930,140 @@ mod tests {
         // But stop() should have been called
     }
 
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_stop_and_join_waits_for_loop() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        assert!(engine.stop_and_join(), "Joining a loop that never ran is a no-op");
+
+        engine.start().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(engine.stop_and_join());
+        assert!(!engine.is_running());
+
+        // No step runs after the join returns
+        let frames = engine.get_frame_count();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(engine.get_frame_count(), frames);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_pause_freezes_state_until_resume() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        engine.start().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        engine.pause();
+        assert!(engine.is_paused());
+        // Let a step that was already in flight finish
+        std::thread::sleep(Duration::from_millis(20));
+        let frames = engine.get_frame_count();
+        let state = engine.get_state().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(engine.get_frame_count(), frames);
+        assert_eq!(engine.get_state().unwrap(), state);
+        assert!(engine.is_running(), "Pausing keeps the loop alive");
+
+        engine.resume();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(engine.get_frame_count() > frames);
+        engine.stop_and_join();
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_latest_snapshot_tracks_steps() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        let (frame, snapshot) = engine.latest_snapshot();
+        assert_eq!(frame, 0);
+        assert_eq!(snapshot.state, engine.get_state().unwrap(), "Available before the first step");
+
+        engine.tick().unwrap();
+        let (frame, snapshot) = engine.latest_snapshot();
+        assert_eq!(frame, 1);
+        assert_eq!(snapshot.state, engine.get_state().unwrap());
+
+        engine.reset(Some(3)).unwrap();
+        assert_eq!(engine.latest_snapshot().1.state, engine.get_state().unwrap());
+
+        // Other edits are visible without a step too, e.g. a new world size
+        engine.set_params(&BoidsParams { world_size: Some(2.0), ..Default::default() }).unwrap();
+        assert_eq!(engine.latest_snapshot().1.world_size, 2.0);
+    }
+
+    #[test]
+    fn test_liveness_problem() {
+        let fresh = Duration::from_millis(10);
+        let stale = MAX_STEP_AGE + Duration::from_secs(1);
+        assert_eq!(liveness_problem(false, None, false, stale), None, "Pull mode has no loop");
+        assert_eq!(liveness_problem(true, Some(false), false, fresh), None);
+        assert_eq!(liveness_problem(true, Some(false), true, stale), None, "Paused loops don't step");
+        assert!(liveness_problem(true, Some(false), false, stale).unwrap().contains("no simulation step"));
+        assert!(liveness_problem(true, Some(true), false, fresh).unwrap().contains("exited"));
+        assert!(liveness_problem(false, Some(true), false, fresh).unwrap().contains("stopped"));
+    }
+
+    #[test]
+    fn test_thermal_throttle_hysteresis() {
+        let mut throttle = ThermalThrottle::new(85);
+        assert_eq!(throttle.update(70, 500.0), None);
+        assert_eq!(throttle.update(85, 500.0), Some(250.0));
+        assert!(throttle.is_throttled());
+        // Still hot, or cooled but inside the hysteresis band: hold
+        assert_eq!(throttle.update(90, 250.0), None);
+        assert_eq!(throttle.update(81, 250.0), None);
+        assert_eq!(throttle.update(79, 250.0), Some(500.0));
+        assert!(!throttle.is_throttled());
+        // Never throttled below the minimum target FPS
+        assert_eq!(throttle.update(95, 40.0), Some(MIN_TARGET_FPS));
+        assert_eq!(throttle.update(70, MIN_TARGET_FPS), Some(40.0));
+
+        // A target set while throttled isn't overwritten on cooling
+        assert_eq!(throttle.update(90, 500.0), Some(250.0));
+        assert_eq!(throttle.update(70, 120.0), None);
+        assert!(!throttle.is_throttled());
+    }
+
+    #[test]
+    fn test_clamp_fps() {
+        assert_eq!(clamp_fps(240.0).unwrap(), 240.0);
+        assert_eq!(clamp_fps(5.0).unwrap(), MIN_TARGET_FPS);
+        assert_eq!(clamp_fps(1e6).unwrap(), MAX_TARGET_FPS);
+        assert!(clamp_fps(f32::NAN).is_err());
+        assert!(clamp_fps(f32::INFINITY).is_err());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_target_fps_setters_report_clamped_values() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        assert_eq!(engine.set_target_fps(5000.0).unwrap(), MAX_TARGET_FPS);
+        assert_eq!(engine.frame_stats().target_fps, MAX_TARGET_FPS);
+        assert_eq!(engine.min_fps(), DEFAULT_MIN_FPS);
+        assert_eq!(engine.set_min_fps(10.0).unwrap(), MIN_TARGET_FPS);
+        assert_eq!(engine.min_fps(), MIN_TARGET_FPS);
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_time_scale_is_clamped_and_reported() {
+        let (context, _context_guard) = setup_test_context();
+        let engine = SimulationEngine::new(&context, 100).unwrap();
+        assert_eq!(engine.frame_stats().time_scale, 1.0);
+        assert_eq!(engine.set_time_scale(0.5).unwrap(), 0.5);
+        assert_eq!(engine.set_time_scale(100.0).unwrap(), MAX_TIME_SCALE);
+        assert_eq!(engine.set_time_scale(0.0).unwrap(), MIN_TIME_SCALE);
+        assert!(engine.set_time_scale(f32::NAN).is_err());
+        assert_eq!(engine.frame_stats().time_scale, MIN_TIME_SCALE);
+        engine.tick().unwrap();
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_get_state() {
         let (context, _context_guard) = setup_test_context();
@@ -313,6 +1083,7 @@ mod tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_num_boids() {
         let (context, _context_guard) = setup_test_context();
@@ -320,6 +1091,7 @@ mod tests {
         assert_eq!(engine.num_boids(), 500);
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_frame_count() {
         let (context, _context_guard) = setup_test_context();
@@ -337,6 +1109,7 @@ mod tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_double_start() {
         let (context, _context_guard) = setup_test_context();
@@ -349,6 +1122,7 @@ mod tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_persistent_running() {
         let (context, _context_guard) = setup_test_context();
@@ -0,0 +1,121 @@
+// Combined GPU + simulation telemetry for monitoring dashboards
+// Pushed as JSON over `/ws/telemetry`, separate from the binary `/ws` position stream
+use crate::gpu_stats::GpuStats;
+use crate::physics::Accelerator;
+use crate::simulation_engine::FrameStats;
+use anyhow::Result;
+use serde::Serialize;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Frames per second sent when the client doesn't pass `rate`
+pub const DEFAULT_RATE_HZ: f32 = 2.0;
+/// GPU stats are cached for 500ms, so faster rates mostly resend the same values
+pub const MAX_RATE_HZ: f32 = 20.0;
+pub const MIN_RATE_HZ: f32 = 0.1;
+
+/// Send interval for a requested rate in Hz
+pub fn interval_for_rate(rate: Option<f32>) -> Result<Duration> {
+    let rate = rate.unwrap_or(DEFAULT_RATE_HZ);
+    if !rate.is_finite() || !(MIN_RATE_HZ..=MAX_RATE_HZ).contains(&rate) {
+        anyhow::bail!("rate must be {}..={} Hz, got {}", MIN_RATE_HZ, MAX_RATE_HZ, rate);
+    }
+    Ok(Duration::from_secs_f64(1.0 / rate as f64))
+}
+
+/// Engine frames per second between successive samples
+pub struct FpsMeter {
+    last_frames: u64,
+    last_tick: Instant,
+}
+
+impl FpsMeter {
+    pub fn new(frame_count: u64) -> Self {
+        Self {
+            last_frames: frame_count,
+            last_tick: Instant::now(),
+        }
+    }
+
+    pub fn sample(&mut self, frame_count: u64) -> f32 {
+        let elapsed = self.last_tick.elapsed().as_secs_f32();
+        let fps = if elapsed > 0.0 {
+            frame_count.saturating_sub(self.last_frames) as f32 / elapsed
+        } else {
+            0.0
+        };
+        self.last_frames = frame_count;
+        self.last_tick = Instant::now();
+        fps
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct TelemetryFrame {
+    pub timestamp_ms: u64,
+    pub fps: f32,
+    pub avg_frame_ms: f32,
+    pub p99_frame_ms: f32,
+    pub frame_count: u64,
+    pub num_boids: usize,
+    pub accelerator: &'static str,
+    pub connections: usize,
+    /// `None` when GPU stats couldn't be read
+    pub gpu: Option<GpuStats>,
+}
+
+impl TelemetryFrame {
+    pub fn new(
+        stats: &FrameStats,
+        fps: f32,
+        num_boids: usize,
+        connections: usize,
+        gpu: Option<GpuStats>,
+    ) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            fps,
+            avg_frame_ms: stats.avg_frame_ms,
+            p99_frame_ms: stats.p99_frame_ms,
+            frame_count: stats.frame_count,
+            num_boids,
+            accelerator: Accelerator::from_used_cuda(stats.used_cuda).as_str(),
+            connections,
+            gpu,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_bounds() {
+        assert_eq!(interval_for_rate(None).unwrap(), Duration::from_millis(500));
+        assert_eq!(interval_for_rate(Some(10.0)).unwrap(), Duration::from_millis(100));
+        assert!(interval_for_rate(Some(0.0)).is_err());
+        assert!(interval_for_rate(Some(MAX_RATE_HZ + 1.0)).is_err());
+        assert!(interval_for_rate(Some(f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_frame_serializes_without_gpu() {
+        let stats = FrameStats {
+            frame_count: 42,
+            avg_frame_ms: 1.5,
+            p99_frame_ms: 4.0,
+            used_cuda: false,
+            target_fps: 500.0,
+            achieved_fps: 480.0,
+            time_scale: 1.0,
+        };
+        let json = serde_json::to_value(TelemetryFrame::new(&stats, 500.0, 1000, 3, None)).unwrap();
+        assert_eq!(json["frame_count"], 42);
+        assert_eq!(json["accelerator"], "cpu");
+        assert_eq!(json["connections"], 3);
+        assert!(json["gpu"].is_null());
+    }
+}
@@ -1,12 +1,18 @@
 // Integration tests for WebSocket and end-to-end functionality
 #[cfg(test)]
 mod integration_tests {
+    #[cfg(not(feature = "no-cuda"))]
     use crate::cuda::{CudaContext, init_cuda_in_thread};
+    #[cfg(not(feature = "no-cuda"))]
     use crate::simulation_engine;
+    #[cfg(not(feature = "no-cuda"))]
     use crate::broadcast;
+    #[cfg(not(feature = "no-cuda"))]
     use std::sync::Arc;
+    #[cfg(not(feature = "no-cuda"))]
     use std::time::Duration;
 
+    #[cfg(not(feature = "no-cuda"))]
     fn setup_test_context() -> (Arc<CudaContext>, rustacuda::context::Context) {
         init_cuda_in_thread().expect("Failed to init CUDA in test thread");
         let context_obj = rustacuda::prelude::Context::create_and_push(
@@ -21,6 +27,7 @@ mod integration_tests {
         )
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_broadcast_integration() {
         let (context, _context_guard) = setup_test_context();
@@ -34,7 +41,7 @@ mod integration_tests {
         let states: Vec<_> = (0..5)
             .map(|_| {
                 std::thread::sleep(std::time::Duration::from_millis(20));
-                broadcast::BroadcastState::encode(&engine).unwrap()
+                broadcast::BroadcastState::encode(&engine, false).unwrap()
             })
             .collect();
         
@@ -51,6 +58,7 @@ mod integration_tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_performance() {
         let (context, _context_guard) = setup_test_context();
@@ -60,7 +68,7 @@ mod integration_tests {
         // Measure encoding performance
         let start = std::time::Instant::now();
         for _ in 0..10 {
-            let _state = broadcast::BroadcastState::encode(&engine).unwrap();
+            let _state = broadcast::BroadcastState::encode(&engine, false).unwrap();
         }
         let duration = start.elapsed();
         
@@ -70,6 +78,7 @@ mod integration_tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_simulation_engine_consistency() {
         let (context, _context_guard) = setup_test_context();
@@ -88,6 +97,21 @@ mod integration_tests {
         engine.stop();
     }
 
+    #[cfg(not(feature = "no-cuda"))]
+    #[test]
+    fn test_sph_simulation_consistency() {
+        let (context, _context_guard) = setup_test_context();
+        let mut sim = crate::physics::SphSimulation::new_seeded(&context, 200, 1).unwrap();
+        for _ in 0..20 {
+            sim.step(0.016).unwrap();
+            let particles = sim.get_particles().unwrap();
+            assert_eq!(particles.len(), 200 * 4);
+            assert!(particles.iter().all(|&x| x.is_finite()), "All values should be finite");
+        }
+        assert!(sim.is_stable());
+    }
+
+    #[cfg(not(feature = "no-cuda"))]
     #[test]
     fn test_broadcast_state_timestamp() {
         let (context, _context_guard) = setup_test_context();
@@ -96,10 +120,108 @@ mod integration_tests {
         
         std::thread::sleep(std::time::Duration::from_millis(100));
         
-        let state = broadcast::BroadcastState::encode(&engine).unwrap();
-        // Timestamp should be reasonable (encoding time in ms)
-        assert!(state.timestamp < 1000, "Encoding should be fast");
+        let before = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let state = broadcast::BroadcastState::encode(&engine, false).unwrap();
+        // Wall-clock time of the published snapshot, at most a step or two old
+        assert!(state.timestamp <= before + 1000 && state.timestamp + 1000 >= before);
+        assert!(state.encode_ms < 1000, "Encoding should be fast");
         
         engine.stop();
     }
+
+    #[test]
+    fn test_round_values() {
+        let mut values = vec![0.123_456_79_f32, -1.987654, 0.5];
+        crate::round_values(&mut values, 2);
+        assert_eq!(values, vec![0.12, -1.99, 0.5]);
+
+        // Rounded values serialize compactly
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(json, "[0.12,-1.99,0.5]");
+    }
+
+    #[test]
+    fn test_ws_params_flags() {
+        use axum::extract::Query;
+        let uri: axum::http::Uri = "/ws?ids=1".parse().unwrap();
+        let Query(params) = Query::<crate::WsParams>::try_from_uri(&uri).unwrap();
+        assert!(params.ids);
+
+        let uri: axum::http::Uri = "/ws".parse().unwrap();
+        let Query(params) = Query::<crate::WsParams>::try_from_uri(&uri).unwrap();
+        assert!(!params.ids && !params.occupancy);
+
+        let uri: axum::http::Uri = "/ws?occupancy=true".parse().unwrap();
+        let Query(params) = Query::<crate::WsParams>::try_from_uri(&uri).unwrap();
+        assert!(params.occupancy);
+
+        let uri: axum::http::Uri = "/ws?ids=maybe".parse().unwrap();
+        assert!(Query::<crate::WsParams>::try_from_uri(&uri).is_err());
+    }
+
+    #[test]
+    fn test_ws_params_region() {
+        use axum::extract::Query;
+        let region = |q: &str| {
+            let uri: axum::http::Uri = format!("/ws{}", q).parse().unwrap();
+            Query::<crate::WsParams>::try_from_uri(&uri).unwrap().0.region()
+        };
+        assert_eq!(region("").unwrap(), None);
+        let r = region("?xmin=0.2&xmax=0.5&ymin=0.1&ymax=0.4").unwrap().unwrap();
+        assert!(r.contains(0.3, 0.2) && !r.contains(0.6, 0.2));
+        assert!(region("?xmin=0.5").unwrap().unwrap().contains(7.0, -3.0));
+        assert!(region("?xmin=0.5&xmax=0.2").is_err());
+    }
+
+    #[test]
+    fn test_response_encoding_negotiation() {
+        use crate::ResponseEncoding;
+        let parse = |uri: &str, accept: Option<&str>| {
+            ResponseEncoding::parse(&uri.parse().unwrap(), accept)
+        };
+        assert_eq!(parse("/api/simulate/grayscott", None), Ok(ResponseEncoding::Json));
+        assert_eq!(parse("/api/simulate/grayscott?encoding=binary", None), Ok(ResponseEncoding::Binary));
+        assert_eq!(
+            parse("/api/simulate/grayscott", Some("text/html, application/octet-stream;q=0.9")),
+            Ok(ResponseEncoding::Binary)
+        );
+        // The query wins over the header
+        assert_eq!(
+            parse("/api/simulate/grayscott?encoding=json", Some("application/octet-stream")),
+            Ok(ResponseEncoding::Json)
+        );
+        assert_eq!(parse("/api/simulate/grayscott", Some("application/json")), Ok(ResponseEncoding::Json));
+        assert!(parse("/api/simulate/grayscott?encoding=msgpack", None).is_err());
+    }
+
+    #[test]
+    fn test_ws_params_json_format() {
+        use axum::extract::Query;
+        let params = |q: &str| {
+            let uri: axum::http::Uri = format!("/ws{}", q).parse().unwrap();
+            Query::<crate::WsParams>::try_from_uri(&uri).map(|p| p.0)
+        };
+        let binary = params("").unwrap();
+        assert_eq!(binary.format, crate::broadcast::FrameFormat::Binary);
+        assert_eq!(binary.json_stride().unwrap(), crate::broadcast::DEFAULT_JSON_STRIDE);
+
+        let json = params("?format=json&stride=3").unwrap();
+        assert_eq!(json.format, crate::broadcast::FrameFormat::Json);
+        assert_eq!(json.json_stride().unwrap(), 3);
+        assert!(params("?format=json&stride=0").unwrap().json_stride().is_err());
+        assert!(params("?format=xml").is_err());
+    }
+
+    #[test]
+    fn test_stream_interval_bounds_frames() {
+        assert_eq!(crate::stream_interval(1_000, None), crate::DEFAULT_STREAM_EVERY);
+        assert_eq!(crate::stream_interval(1_000, Some(10)), 10);
+        // Asking for every step of a long run is widened to the frame cap
+        let every = crate::stream_interval(1_000_000, Some(1));
+        assert!(1_000_000 / every <= crate::MAX_STREAM_FRAMES);
+        assert_eq!(crate::stream_interval(5, Some(0)), 1);
+    }
 }
@@ -12,6 +12,8 @@ interface SimulationResponse {
     num_particles: number
     computation_time_ms: number
     accelerator?: 'cpu' | 'cuda'
+    /** Boids only: `data` holds 4 floats per boid in 2D (x, y, vx, vy), 6 in 3D (x, y, z, vx, vy, vz) */
+    dimensions?: 2 | 3
   }
   error?: string
 }
@@ -20,6 +22,12 @@ export interface GpuInfo {
   gpu: string
   status?: string
   cuda_context?: boolean
+  compute_capability_major?: number | null
+  compute_capability_minor?: number | null
+  multiprocessor_count?: number | null
+  total_memory_bytes?: number | null
+  max_threads_per_block?: number | null
+  warp_size?: number | null
 }
 
 export interface GpuStats {
@@ -29,6 +37,19 @@ export interface GpuStats {
   memory_total_mb: number | null
   temperature_c: number | null
   timestamp: number
+  achieved_fps?: number
+  avg_frame_time_ms?: number
+  target_fps?: number
+}
+
+/** Flocking tunables for `/api/simulate/boids`; omitted fields keep their current value */
+export interface BoidsParams {
+  separation_radius?: number
+  alignment_radius?: number
+  cohesion_radius?: number
+  max_speed?: number
+  /** Must not exceed `max_speed` */
+  max_force?: number
 }
 
 export interface SimulationRun {
@@ -137,6 +158,9 @@ export const fetchGrayScottSimulation = async (options?: {
 export const runBoidsSimulation = async (options?: {
   steps?: number
   numParticles?: number
+  params?: BoidsParams
+  /** 3 runs the separate 3D flock */
+  dimensions?: 2 | 3
 }): Promise<SimulationRun> => {
   const response = await requestJson<SimulationResponse>(`/api/simulate/boids`, {
     method: 'POST',
@@ -144,6 +168,8 @@ export const runBoidsSimulation = async (options?: {
       simulation_type: 'boids',
       steps: options?.steps ?? 6,
       num_particles: options?.numParticles ?? 180,
+      params: options?.params,
+      dimensions: options?.dimensions,
     }),
   })
   if (!response.success || !response.data) throw new Error(response.error ?? 'No data')
@@ -37,11 +37,37 @@ function buildWebSocketUrl(): string {
   return `${wsBase}/ws`
 }
 
+// First byte of every frame identifies its layout
+const FRAME_FULL = 0
+const FRAME_OCCUPANCY = 1
+// Per-boid changes since the previous frame, requested with ?delta=1
+const FRAME_DELTA = 2
+// Set on a full or delta frame's kind byte when force magnitudes follow the boid data
+const FRAME_FLAG_FORCES = 0x80
+// Set on a full frame's kind byte when earlier positions follow (?interp=1)
+const FRAME_FLAG_INTERP = 0x40
+// Delta frames hold i16 changes in 1/32768 units; this marker means absolute f32s follow
+const DELTA_SCALE = 32768
+const DELTA_ESCAPE = -32768
+
+export interface OccupancyGrid {
+  // Row-major size x size boid counts (saturating at 255)
+  cells: Uint8Array
+  size: number
+  numBoids: number
+  timestamp: number
+}
+
 export interface StreamedBoidState {
   x: number
   y: number
   vx: number
   vy: number
+  // Steering force magnitude, present when the stream was opened with ?forces=1
+  force?: number
+  // Position a few broadcasts earlier, present when the stream was opened with ?interp=1
+  prevX?: number
+  prevY?: number
   timestamp: number
 }
 
@@ -51,11 +77,14 @@ export class SimulationStream {
   private maxReconnectAttempts = 10
   private reconnectDelay = 1000
   private onStateCallback: ((states: StreamedBoidState[]) => void) | null = null
+  private onOccupancyCallback: ((grid: OccupancyGrid) => void) | null = null
   private onErrorCallback: ((error: Error) => void) | null = null
   private onConnectionStatusCallback: ((connected: boolean) => void) | null = null
   private isConnecting = false
   private shouldReconnect = true
   private connectionStatus: 'disconnected' | 'connecting' | 'connected' = 'disconnected'
+  // Last decoded boids, the base that delta frames apply to
+  private lastStates: StreamedBoidState[] | null = null
 
   constructor() {
     // Auto-reconnect on close
@@ -69,7 +98,7 @@ export class SimulationStream {
     this.isConnecting = true
 
     return new Promise((resolve, reject) => {
-      const wsUrl = buildWebSocketUrl()
+      const wsUrl = `${buildWebSocketUrl()}?delta=1`
       
       console.log(`[SimulationStream] Connecting to: ${wsUrl}`)
       
@@ -108,6 +137,8 @@ export class SimulationStream {
         
         ws.onclose = (event) => {
           this.ws = null
+          // The server starts every connection with a full frame
+          this.lastStates = null
           this.isConnecting = false
           this.connectionStatus = 'disconnected'
           console.log(`[SimulationStream] WebSocket closed (code: ${event.code}, reason: ${event.reason || 'none'})`)
@@ -139,6 +170,13 @@ export class SimulationStream {
     const view = new DataView(data)
     let offset = 0
     
+    // Read frame kind (u8); the high bits flag trailing forces and earlier positions
+    const kindByte = view.getUint8(offset)
+    const kind = kindByte & ~(FRAME_FLAG_FORCES | FRAME_FLAG_INTERP)
+    const hasForces = (kindByte & FRAME_FLAG_FORCES) !== 0
+    const hasInterp = (kindByte & FRAME_FLAG_INTERP) !== 0
+    offset += 1
+    
     // Read timestamp (u64 = 8 bytes)
     const timestamp = Number(view.getBigUint64(offset, true))
     offset += 8
@@ -147,6 +185,23 @@ export class SimulationStream {
     const numBoids = view.getUint32(offset, true)
     offset += 4
     
+    if (kind === FRAME_OCCUPANCY) {
+      const size = view.getUint16(offset, true)
+      offset += 2
+      const cells = new Uint8Array(data, offset, size * size)
+      if (this.onOccupancyCallback) {
+        this.onOccupancyCallback({ cells, size, numBoids, timestamp })
+      }
+      return
+    }
+    if (kind === FRAME_DELTA) {
+      this.handleDeltaFrame(view, offset, numBoids, timestamp, hasForces)
+      return
+    }
+    if (kind !== FRAME_FULL) {
+      return
+    }
+    
     // Read boid data (each boid is 4 floats = 16 bytes)
     const states: StreamedBoidState[] = []
     
@@ -162,7 +217,71 @@ export class SimulationStream {
       
       states.push({ x, y, vx, vy, timestamp: Number(timestamp) })
     }
+
+    if (hasForces) {
+      for (let i = 0; i < numBoids; i++) {
+        states[i].force = view.getFloat32(offset, true)
+        offset += 4
+      }
+    }
+
+    if (hasInterp) {
+      // Skip the history depth; ids are never requested by this client
+      offset += 4
+      for (let i = 0; i < numBoids; i++) {
+        states[i].prevX = view.getFloat32(offset, true)
+        states[i].prevY = view.getFloat32(offset + 4, true)
+        offset += 8
+      }
+    }
     
+    this.lastStates = states
+    if (this.onStateCallback) {
+      this.onStateCallback(states)
+    }
+  }
+
+  private handleDeltaFrame(
+    view: DataView,
+    offset: number,
+    numBoids: number,
+    timestamp: number,
+    hasForces: boolean
+  ): void {
+    const previous = this.lastStates
+    if (!previous || previous.length !== numBoids) {
+      // Nothing to apply the delta to; the next full frame resyncs us
+      return
+    }
+
+    const states: StreamedBoidState[] = previous.map((prev) => {
+      if (view.getInt16(offset, true) === DELTA_ESCAPE) {
+        // Boid sent as absolute values (wrap-around, large changes)
+        const x = view.getFloat32(offset + 2, true)
+        const y = view.getFloat32(offset + 6, true)
+        const vx = view.getFloat32(offset + 10, true)
+        const vy = view.getFloat32(offset + 14, true)
+        offset += 18
+        return { x, y, vx, vy, timestamp }
+      }
+      // Round each sum to f32 so we hold exactly what the server expects us to
+      const d = (i: number) => view.getInt16(offset + i * 2, true) / DELTA_SCALE
+      const x = Math.fround(prev.x + d(0))
+      const y = Math.fround(prev.y + d(1))
+      const vx = Math.fround(prev.vx + d(2))
+      const vy = Math.fround(prev.vy + d(3))
+      offset += 8
+      return { x, y, vx, vy, timestamp }
+    })
+
+    if (hasForces) {
+      for (let i = 0; i < numBoids; i++) {
+        states[i].force = view.getFloat32(offset, true)
+        offset += 4
+      }
+    }
+
+    this.lastStates = states
     if (this.onStateCallback) {
       this.onStateCallback(states)
     }
@@ -172,6 +291,10 @@ export class SimulationStream {
     this.onStateCallback = callback
   }
 
+  onOccupancy(callback: (grid: OccupancyGrid) => void): void {
+    this.onOccupancyCallback = callback
+  }
+
   onError(callback: (error: Error) => void): void {
     this.onErrorCallback = callback
   }